@@ -1,20 +1,22 @@
+use crate::config::SavedServer;
 use crate::setup;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use indexmap::{IndexMap, IndexSet};
 use raphy_client::managed::{ClientReader, ClientWriter};
 use raphy_client::ClientMode;
 use raphy_protocol::config::resolved::{ConfigMask, ResolvedConfig};
-use raphy_protocol::{Config, Operation};
+use raphy_protocol::{Config, CurrentConfig, ErrorKind, Operation, SerdeError};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tauri::{
     http, App, AppHandle, Emitter, Manager, State, UriSchemeContext, UriSchemeResponder, Wry,
 };
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
 use raphy_common::ConfigLike;
 
 pub struct AppState {
@@ -28,6 +30,9 @@ pub struct AppState {
 pub struct Server {
     pub addresses: IndexSet<IpAddr>,
     pub port: u16,
+
+    /// the port players connect to, which is distinct from the port raphy is controlled through
+    pub game_port: Option<u16>,
 }
 
 impl Server {
@@ -38,10 +43,104 @@ impl Server {
     }
 }
 
+/// how many discovered servers are probed at once
+const MAX_CONCURRENT_PROBES: usize = 8;
+
+/// how long probing a single server may take, including connecting to it
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProbeResult {
+    pub full_name: String,
+
+    /// the round trip time of a ping, if the server could be reached
+    pub rtt: Option<Duration>,
+
+    /// why the server couldn't be reached
+    pub error: Option<String>,
+}
+
+async fn probe_server(server: &Server) -> anyhow::Result<Duration> {
+    let socket_addresses: Vec<_> = server.socket_addresses().collect();
+    let (_client_reader, client_writer) =
+        raphy_client::managed::from_tcp(socket_addresses.as_slice())
+            .await
+            .context("Failed to connect to the server.")?;
+
+    let start = Instant::now();
+    client_writer
+        .ping()
+        .await
+        .context("Failed to ping the server.")?;
+    Ok(start.elapsed())
+}
+
+/// checks which of the discovered servers are reachable, and how quickly they respond. each
+/// result is emitted as a `server-probed` event as soon as it's known.
+#[tauri::command]
+pub async fn probe_servers(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<Vec<ProbeResult>> {
+    tracing::debug!("lock servers structure");
+    let servers = state.servers.lock().await.clone();
+    tracing::info!(count = servers.len(), "probe servers");
+
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_PROBES));
+    let mut probes = JoinSet::new();
+    for (full_name, server) in servers {
+        let semaphore = Arc::clone(&semaphore);
+        let app_handle = app_handle.clone();
+        probes.spawn_on(
+            async move {
+                let _permit = semaphore.acquire_owned().await.ok();
+                let result = match tokio::time::timeout(PROBE_TIMEOUT, probe_server(&server)).await
+                {
+                    Ok(Ok(rtt)) => ProbeResult {
+                        full_name,
+                        rtt: Some(rtt),
+                        error: None,
+                    },
+                    Ok(Err(error)) => ProbeResult {
+                        full_name,
+                        rtt: None,
+                        error: Some(format!("{error:#}")),
+                    },
+                    Err(_) => ProbeResult {
+                        full_name,
+                        rtt: None,
+                        error: Some(format!(
+                            "The server did not respond within {} seconds.",
+                            PROBE_TIMEOUT.as_secs()
+                        )),
+                    },
+                };
+
+                tracing::debug!(?result, "server probed");
+                if let Err(error) = app_handle.emit("server-probed", result.clone()) {
+                    tracing::warn!(?error, "failed to emit the probe result: {error}");
+                }
+                result
+            },
+            state.runtime.handle(),
+        );
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        results.push(result.context("A server probe panicked.")?);
+    }
+
+    Ok(results)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ConnectToServerBy {
     FullName(String),
     SocketAddress(SocketAddr),
+
+    /// the name of a saved server
+    SavedName(String),
 }
 
 #[tauri::command]
@@ -55,14 +154,23 @@ pub async fn connect_to_server(
     tracing::debug!("lock servers structure");
     let servers = state.servers.lock().await;
 
-    let socket_addresses = match by {
+    let socket_addresses = match &by {
         ConnectToServerBy::FullName(full_name) => {
             let server = servers
-                .get(&full_name)
+                .get(full_name)
                 .context("The specified server does not exist.")?;
             server.socket_addresses().collect()
         }
-        ConnectToServerBy::SocketAddress(socket_address) => vec![socket_address],
+        ConnectToServerBy::SocketAddress(socket_address) => vec![*socket_address],
+        ConnectToServerBy::SavedName(name) => state
+            .config
+            .lock()
+            .await
+            .saved_servers
+            .get(name)
+            .context("The specified saved server does not exist.")?
+            .socket_addresses
+            .clone(),
     };
     tracing::debug!(?socket_addresses, "evaluated socket addresses");
 
@@ -88,6 +196,12 @@ pub async fn connect_to_server(
    
     let mut config = state.config.lock().await;
     config.last_remote_client = Some(socket_addresses);
+
+    if let ConnectToServerBy::SavedName(name) = &by {
+        if let Some(saved_server) = config.saved_servers.get_mut(name) {
+            saved_server.last_used = Some(SystemTime::now());
+        }
+    }
     
     if let Err(error) = config.dump().await {
         tracing::warn!(?error, "failed to save the config: {error:#}");
@@ -98,6 +212,68 @@ pub async fn connect_to_server(
     Ok(())
 }
 
+#[tauri::command]
+pub async fn list_saved_servers(
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<IndexMap<String, SavedServer>> {
+    tracing::debug!("lock config structure");
+    Ok(state.config.lock().await.saved_servers.clone())
+}
+
+#[tauri::command]
+pub async fn add_saved_server(
+    state: State<'_, AppState>,
+    name: String,
+    socket_addresses: Vec<SocketAddr>,
+) -> anyhow_tauri::TAResult<()> {
+    tracing::info!(?name, ?socket_addresses, "add saved server");
+
+    if socket_addresses.is_empty() {
+        return Err(anyhow!("A saved server needs at least one address.").into());
+    }
+
+    tracing::debug!("lock config structure");
+    let mut config = state.config.lock().await;
+    let last_used = config
+        .saved_servers
+        .get(&name)
+        .and_then(|saved_server| saved_server.last_used);
+    config.saved_servers.insert(
+        name,
+        SavedServer {
+            socket_addresses,
+            last_used,
+        },
+    );
+
+    config
+        .dump()
+        .await
+        .context("Failed to save the client config.")?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_saved_server(
+    state: State<'_, AppState>,
+    name: String,
+) -> anyhow_tauri::TAResult<()> {
+    tracing::info!(?name, "remove saved server");
+
+    tracing::debug!("lock config structure");
+    let mut config = state.config.lock().await;
+    config
+        .saved_servers
+        .shift_remove(&name)
+        .context("The specified saved server does not exist.")?;
+
+    config
+        .dump()
+        .await
+        .context("Failed to save the client config.")?;
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn client_connection_active(
     state: State<'_, AppState>,
@@ -109,6 +285,35 @@ pub async fn client_connection_active(
     Ok(is_connected)
 }
 
+#[tauri::command]
+pub fn client_build_version() -> &'static str {
+    raphy_protocol::BUILD_VERSION
+}
+
+#[tauri::command]
+pub async fn get_server_info(
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<raphy_protocol::ServerInfo> {
+    tracing::debug!("lock client structure");
+    let client = state.client.lock().await;
+    let client_writer = client
+        .as_ref()
+        .context("Not connected to a server.")?
+        .1
+        .clone();
+    drop(client);
+
+    tracing::debug!("get server info");
+    let server_info = client_writer
+        .get_server_info()
+        .await
+        .context("Failed to get the server info.")?;
+
+    tracing::debug!("server info retrieved");
+
+    Ok(server_info)
+}
+
 #[tauri::command]
 pub fn client_mode(state: State<'_, ClientMode>) -> ClientMode {
     *state
@@ -128,12 +333,27 @@ pub async fn get_server_config(
     drop(client);
 
     tracing::debug!("get server config");
-    let config = client_writer
+    let config = match client_writer
         .get_config()
         .await
         .context("Failed to get the server config.")?
-        .map(|c| c.resolve().context("Failed to resolve the server config."))
-        .transpose()?;
+    {
+        CurrentConfig::Saved(config) => Some(
+            config
+                .resolve()
+                .context("Failed to resolve the server config.")?,
+        ),
+
+        // the template only pre-fills the form, so it's left out if it can't be resolved, e.g.
+        // when java can't be auto-detected
+        CurrentConfig::Template(template) => match template.resolve() {
+            Ok(template) => Some(template),
+            Err(error) => {
+                tracing::debug!(?error, "failed to resolve the config template: {error:#}");
+                None
+            }
+        },
+    };
 
     tracing::debug!("server config retrieved");
 
@@ -142,6 +362,7 @@ pub async fn get_server_config(
 
 #[tauri::command]
 pub async fn update_config(
+    app_handle: AppHandle,
     state: State<'_, AppState>,
     config: ResolvedConfig,
     mask: ConfigMask,
@@ -154,10 +375,24 @@ pub async fn update_config(
         .clone();
     drop(client);
 
-    client_writer
+    let result = client_writer
         .update_config(Config::from_resolved(config, mask))
-        .await
-        .context("Failed to update the configuration.")?;
+        .await;
+
+    // the problems are emitted separately, so that the ui can show each next to its field
+    let kind = result
+        .as_ref()
+        .err()
+        .and_then(|error| error.chain().find_map(|error| error.downcast_ref::<SerdeError>()))
+        .and_then(SerdeError::kind);
+    if let Some(ErrorKind::InvalidConfig(problems)) = kind {
+        let emitted = app_handle.emit("config-invalid", problems);
+        if let Err(error) = emitted {
+            tracing::warn!(?error, "failed to emit the config problems: {error}");
+        }
+    }
+
+    result.context("Failed to update the configuration.")?;
     Ok(())
 }
 
@@ -224,6 +459,78 @@ pub async fn get_server_state(
     Ok(server_state)
 }
 
+#[tauri::command]
+pub async fn get_connection_info(
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<raphy_protocol::ConnectionInfo> {
+    tracing::debug!("lock client structure");
+    let client = state.client.lock().await;
+    let client_writer = client
+        .as_ref()
+        .context("Not connected to a server.")?
+        .1
+        .clone();
+    drop(client);
+
+    tracing::debug!("get connection info");
+    let connection_info = client_writer
+        .get_connection_info()
+        .await
+        .context("Failed to get the connection info.")?;
+
+    tracing::debug!("connection info retrieved");
+
+    Ok(connection_info)
+}
+
+#[tauri::command]
+pub async fn get_diagnostics(
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<raphy_protocol::Diagnostics> {
+    tracing::debug!("lock client structure");
+    let client = state.client.lock().await;
+    let client_writer = client
+        .as_ref()
+        .context("Not connected to a server.")?
+        .1
+        .clone();
+    drop(client);
+
+    tracing::debug!("get diagnostics");
+    let diagnostics = client_writer
+        .get_diagnostics()
+        .await
+        .context("Failed to get the diagnostics.")?;
+
+    tracing::debug!("diagnostics retrieved");
+
+    Ok(diagnostics)
+}
+
+#[tauri::command]
+pub async fn get_resolved_paths(
+    state: State<'_, AppState>,
+) -> anyhow_tauri::TAResult<raphy_protocol::ResolvedPaths> {
+    tracing::debug!("lock client structure");
+    let client = state.client.lock().await;
+    let client_writer = client
+        .as_ref()
+        .context("Not connected to a server.")?
+        .1
+        .clone();
+    drop(client);
+
+    tracing::debug!("get resolved paths");
+    let resolved_paths = client_writer
+        .get_resolved_paths()
+        .await
+        .context("Failed to get the resolved paths.")?;
+
+    tracing::debug!("resolved paths retrieved");
+
+    Ok(resolved_paths)
+}
+
 async fn real_stdin(state: &AppState, input: Vec<u8>) -> anyhow::Result<()> {
     let client = state.client.lock().await;
     let client_writer = client
@@ -1,13 +1,33 @@
 use std::net::SocketAddr;
+use std::time::SystemTime;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use raphy_common::ConfigLike;
 
+/// a remote server remembered by the user, so that it can be reconnected to by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedServer {
+    pub socket_addresses: Vec<SocketAddr>,
+
+    /// when the server was last connected to, if ever
+    pub last_used: Option<SystemTime>,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Config {
     pub last_remote_client: Option<Vec<SocketAddr>>,
+
+    /// saved remote servers, keyed by name
+    #[serde(default)]
+    pub saved_servers: IndexMap<String, SavedServer>,
+
+    /// the proxy remote servers are connected through, e.g. `socks5://proxy:1080`. takes
+    /// precedence over `RAPHY_PROXY`.
+    #[serde(default)]
+    pub proxy: Option<String>,
 }
 
 impl ConfigLike for Config {
     const ENV_VAR: &'static str = "RAPHY_CLIENT_APP_CONFIG_PATH";
     const CONFIG_PATH_NAME: &'static str = "client.json";
-}
\ No newline at end of file
+}
@@ -10,22 +10,35 @@ use setup::setup;
 use std::env;
 use tokio::runtime::Runtime;
 
-pub fn run(client_mode: ClientMode, data: Option<(managed::ClientReader, managed::ClientWriter, Runtime)>) -> tauri::Result<()> {
+pub fn run(
+    client_mode: ClientMode,
+    runtime: Runtime,
+    client: Option<(managed::ClientReader, managed::ClientWriter)>,
+) -> tauri::Result<()> {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             commands::connect_to_server,
+            commands::probe_servers,
+            commands::list_saved_servers,
+            commands::add_saved_server,
+            commands::remove_saved_server,
             commands::client_connection_active,
             commands::client_mode,
+            commands::client_build_version,
+            commands::get_server_info,
             commands::get_server_config,
             commands::update_config,
             commands::start_server,
             commands::stop_server,
             commands::restart_server,
             commands::get_server_state,
+            commands::get_connection_info,
+            commands::get_diagnostics,
+            commands::get_resolved_paths,
         ])
         .register_asynchronous_uri_scheme_protocol("stdin", commands::stdin)
         .manage(client_mode)
-        .setup(setup(client_mode, data))
+        .setup(setup(client_mode, runtime, client))
         .run(tauri::generate_context!())
 }
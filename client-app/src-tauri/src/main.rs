@@ -51,14 +51,14 @@ mod client_mode {
         Ok(())
     }
 
-    fn real_infer_client_mode() -> anyhow::Result<(
+    fn real_infer_client_mode(
+        runtime: &Runtime,
+    ) -> anyhow::Result<(
         ClientMode,
-        Option<(managed::ClientReader, managed::ClientWriter, Runtime)>,
+        Option<(managed::ClientReader, managed::ClientWriter)>,
     )> {
-        let runtime = Runtime::new().context("Failed to build the Tokio runtime.")?;
-
         match runtime.block_on(attempt_connection_via_unix(false)) {
-            Ok((cr, cw)) => return Ok((ClientMode::Local, Some((cr, cw, runtime)))),
+            Ok((cr, cw)) => return Ok((ClientMode::Local, Some((cr, cw)))),
             Err(error) => {
                 tracing::debug!(
                 ?error,
@@ -77,7 +77,7 @@ mod client_mode {
                     Ok((ClientMode::Remote, None))
                 } else {
                     match runtime.block_on(attempt_connection_via_unix(true)) {
-                        Ok((cr, cw)) => Ok((ClientMode::Local, Some((cr, cw, runtime)))),
+                        Ok((cr, cw)) => Ok((ClientMode::Local, Some((cr, cw)))),
                         Err(error) => {
                             tracing::warn!(
                             ?error,
@@ -93,24 +93,26 @@ mod client_mode {
     }
 
     fn infer_client_mode(
-        data: &mut Option<(managed::ClientReader, managed::ClientWriter, Runtime)>,
+        runtime: &Runtime,
+        client: &mut Option<(managed::ClientReader, managed::ClientWriter)>,
     ) -> anyhow::Result<ClientMode> {
-        real_infer_client_mode().map(|(mode, result)| {
-            *data = result;
+        real_infer_client_mode(runtime).map(|(mode, result)| {
+            *client = result;
             mode
         })
     }
 
     pub fn client_mode(
-        data: &mut Option<(managed::ClientReader, managed::ClientWriter, Runtime)>,
+        runtime: &Runtime,
+        client: &mut Option<(managed::ClientReader, managed::ClientWriter)>,
     ) -> anyhow::Result<ClientMode> {
         match env::var("RAPHY_CLIENT_APP_CLIENT_MODE") {
             Ok(mode) => match mode.as_str() {
                 "local" => Ok(ClientMode::Local),
                 "remote" => Ok(ClientMode::Remote),
-                _ => infer_client_mode(data),
+                _ => infer_client_mode(runtime, client),
             },
-            Err(_) => infer_client_mode(data),
+            Err(_) => infer_client_mode(runtime, client),
         }
     }
 
@@ -128,10 +130,19 @@ use tokio::runtime::Runtime;
 fn main() -> ExitCode {
     raphy_common::init_logging("RAPHY_CLIENT_APP_TOKIO_CONSOLE_ENABLED");
 
-    let mut data = None;
+    // the connection made while inferring the client mode runs on this runtime, so the app has to
+    // keep using it rather than building its own
+    let runtime = match raphy_client_app_lib::utils::build_runtime() {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            tracing::error!(?error, "failed to build the runtime: {error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut client = None;
     
     #[cfg(unix)]
-    let client_mode = match client_mode::client_mode(&mut data) {
+    let client_mode = match client_mode::client_mode(&runtime, &mut client) {
         Ok(mode) => mode,
         Err(error) => {
             tracing::error!(?error, "failed to determine the client mode");
@@ -142,7 +153,7 @@ fn main() -> ExitCode {
     #[cfg(not(unix))]
     let client_mode = ClientMode::Remote;
     
-    if let Err(error) = raphy_client_app_lib::run(client_mode, data) {
+    if let Err(error) = raphy_client_app_lib::run(client_mode, runtime, client) {
         tracing::error!(?error, "failed to run the client app: {error}");
         ExitCode::FAILURE
     } else {
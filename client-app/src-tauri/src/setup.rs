@@ -41,7 +41,9 @@ pub fn emit_message_on_connection_failure(runtime: &Runtime, writer: ClientWrite
             };
 
             if did_fail {
-                app.emit("connection-failure", ()).unwrap();
+                if let Err(error) = app.emit("connection-failure", ()) {
+                    tracing::warn!(?error, "failed to emit the connection failure: {error}");
+                }
                 break;
             } else {
                 interval.tick().await;
@@ -54,7 +56,7 @@ pub fn emit_message_on_connection_failure(runtime: &Runtime, writer: ClientWrite
 pub fn emit_message_on_s2c(runtime: &Runtime, mut reader: ClientReader, app: AppHandle) {
     runtime.spawn(async move {
         while let Some(message) = reader.recv().await {
-            match message {
+            let result = match message {
                 ServerToClientMessage::ConfigUpdated(config, _) => {
                     let config = match config.resolve() {
                         Ok(config) => config,
@@ -63,32 +65,48 @@ pub fn emit_message_on_s2c(runtime: &Runtime, mut reader: ClientReader, app: App
                             continue;
                         }
                     };
-                    app.emit("config-updated", config).unwrap();
+                    app.emit("config-updated", config)
                 }
                 ServerToClientMessage::OperationRequested(op, id) => {
-                    app.emit("operation-requested", (op, id)).unwrap()
+                    app.emit("operation-requested", (op, id))
                 }
                 ServerToClientMessage::OperationPerformed(op, id, _) => {
-                    app.emit("operation-performed", (op, id)).unwrap()
+                    app.emit("operation-performed", (op, id))
+                }
+                ServerToClientMessage::OperationFailed(op, id, error, _) => {
+                    app.emit("operation-failed", (op, id, error.to_string()))
                 }
-                ServerToClientMessage::OperationFailed(op, id, error, _) => app
-                    .emit("operation-failed", (op, id, error.to_string()))
-                    .unwrap(),
                 ServerToClientMessage::ServerStateUpdated(state) => {
-                    app.emit("server-state-updated", state).unwrap()
+                    app.emit("server-state-updated", state)
                 }
+                ServerToClientMessage::ServerCrashed(status) => app.emit("server-crashed", status),
                 ServerToClientMessage::Stdout(buf) => {
-                    app.emit("stdout", String::from_utf8_lossy(&buf)).unwrap()
+                    app.emit("stdout", String::from_utf8_lossy(&buf))
                 }
                 ServerToClientMessage::Stderr(buf) => {
-                    app.emit("stderr", String::from_utf8_lossy(&buf)).unwrap()
+                    app.emit("stderr", String::from_utf8_lossy(&buf))
+                }
+                ServerToClientMessage::WatchdogTriggered(action, reason) => {
+                    app.emit("watchdog-triggered", (action, reason))
                 }
+                ServerToClientMessage::ConfigEditingStarted(editor) => {
+                    app.emit("config-editing-started", editor)
+                }
+                ServerToClientMessage::ConfigEditingStopped(editor) => {
+                    app.emit("config-editing-stopped", editor)
+                }
+                ServerToClientMessage::Reconnect(reason) => app.emit("reconnect-requested", reason),
                 ServerToClientMessage::FatalError(error) => {
-                    app.emit("fatal-error", error.to_string()).unwrap()
+                    app.emit("fatal-error", error.to_string())
                 }
-                ServerToClientMessage::Error(error, _) => app.emit("error", error).unwrap(),
-                ServerToClientMessage::ShuttingDown => app.emit("shutting-down", ()).unwrap(),
+                ServerToClientMessage::Error(error, _) => app.emit("error", error),
+                ServerToClientMessage::ShuttingDown => app.emit("shutting-down", ()),
                 _ => continue,
+            };
+
+            // the window can be gone while the app is closing, which shouldn't crash it
+            if let Err(error) = result {
+                tracing::warn!(?error, "failed to emit a server message: {error}");
             }
         }
     });
@@ -121,6 +139,9 @@ fn browse_for_raphy_servers(
                             Server {
                                 addresses: info.get_addresses().clone().into_iter().collect(),
                                 port: info.get_port(),
+                                game_port: info
+                                    .get_property_val_str("game_port")
+                                    .and_then(|port| port.parse().ok()),
                             },
                         );
                         true
@@ -134,9 +155,10 @@ fn browse_for_raphy_servers(
                 };
 
                 if services_updated {
-                    app_handle
-                        .emit("servers-updated", servers.lock().await.clone())
-                        .unwrap();
+                    let servers = servers.lock().await.clone();
+                    if let Err(error) = app_handle.emit("servers-updated", servers) {
+                        tracing::warn!(?error, "failed to emit the updated servers: {error}");
+                    }
                 }
             }
         }
@@ -148,16 +170,10 @@ fn browse_for_raphy_servers(
 fn real_setup(
     app: &mut App<Wry>,
     client_mode: ClientMode,
-    data: Option<(ClientReader, ClientWriter, Runtime)>,
+    runtime: Runtime,
+    mut client: Option<(ClientReader, ClientWriter)>,
 ) -> anyhow::Result<()> {
     let servers = Arc::new(Mutex::new(IndexMap::new()));
-    let (runtime, mut client) = match data {
-        Some((cr, cw, runtime)) => (runtime, Some((cr, cw))),
-        None => {
-            let runtime = Runtime::new().context("Failed to build the Tokio runtime.")?;
-            (runtime, None)
-        }
-    };
     let config = runtime
         .block_on(Config::load())
         .map(|c| c.unwrap_or_default())
@@ -166,6 +182,12 @@ fn real_setup(
             Config::default()
         });
 
+    if let Some(url) = &config.proxy {
+        let proxy = raphy_client::proxy::Proxy::parse(url)
+            .with_context(|| format!("The configured proxy '{url}' is invalid."))?;
+        raphy_client::proxy::set_proxy(Some(proxy));
+    }
+
     match client_mode {
         ClientMode::Remote => {
             if let Some(socket_addr) = &config.last_remote_client {
@@ -209,11 +231,15 @@ fn real_setup(
 
 pub fn setup(
     client_mode: ClientMode,
-    data: Option<(ClientReader, ClientWriter, Runtime)>,
+    runtime: Runtime,
+    client: Option<(ClientReader, ClientWriter)>,
 ) -> impl Fn(&mut App<Wry>) -> Result<(), Box<dyn Error>> {
-    let data = Cell::new(data);
+    let data = Cell::new(Some((runtime, client)));
     move |app| {
-        let result = real_setup(app, client_mode, data.take());
+        let Some((runtime, client)) = data.take() else {
+            return Err("the setup hook ran more than once".into());
+        };
+        let result = real_setup(app, client_mode, runtime, client);
 
         // the reason why we handle errors here is because `tauri` panics when the setup hook fails, so
         // if we handled it in the main function, this dialog would never be shown.
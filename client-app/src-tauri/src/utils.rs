@@ -1,9 +1,11 @@
 use anyhow::Context;
 use raphy_client::managed;
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tokio::io;
 use tokio::net::ToSocketAddrs;
+use tokio::runtime::Runtime;
 use raphy_protocol::UNIX_SOCKET_PATH;
 
 pub async fn attempt_connection<F>(
@@ -57,3 +59,16 @@ pub async fn attempt_connection_via_tcp(
     )
     .await
 }
+
+/// whether the runtime of the client app was built yet
+static RUNTIME_BUILT: AtomicBool = AtomicBool::new(false);
+
+/// builds the runtime which backs the connection to the server. there must only be one, since the
+/// connection's tasks run on the runtime which made it, so this panics when called twice.
+pub fn build_runtime() -> anyhow::Result<Runtime> {
+    assert!(
+        !RUNTIME_BUILT.swap(true, Ordering::SeqCst),
+        "the client app's runtime was built twice"
+    );
+    Runtime::new().context("Failed to build the Tokio runtime.")
+}
@@ -113,7 +113,7 @@ export const restartServer = async (): Promise<void> => {
 export type ServerState = "Started" | StoppedServerState;
 
 export interface StoppedServerState {
-    Stopped?: ExitStatus;
+    Stopped?: [ExitStatus | null, StopCause | null];
 }
 
 export enum ExitStatus {
@@ -121,6 +121,11 @@ export enum ExitStatus {
     Failure = 'Failure'
 }
 
+export enum StopCause {
+    Requested = 'Requested',
+    Unexpected = 'Unexpected'
+}
+
 export type ServerStateKind = "Started" | "Stopped";
 
 export function getServerStateKind(state: ServerState): ServerStateKind {
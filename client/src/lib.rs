@@ -1,27 +1,46 @@
 pub mod managed;
+pub mod proxy;
 
 pub use managed::manage;
 use std::env;
 
 use anyhow::Context as _;
-use raphy_protocol::{ClientToServerMessage, Config, Operation, ServerToClientMessage, TaskId};
+use raphy_protocol::config::{ConfigPatch, User};
+use raphy_protocol::{
+    ClientIdentity, ClientToServerMessage, Config, HandshakeOptions, IdempotencyKey, MAX_FRAME_LEN,
+    MaintenanceStep, Operation, OperationId, OutputDelivery, OutputMode, PROTOCOL_VERSION,
+    ServerToClientMessage, TaskId,
+};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 use tokio::io;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
-use tokio::net::{TcpStream, ToSocketAddrs, tcp};
+use tokio::net::{TcpStream, ToSocketAddrs, lookup_host, tcp};
 
 #[cfg(unix)]
 use tokio::net::{UnixStream, unix};
 
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use tokio_rustls::client::TlsStream;
+
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
+
 enum OwnedReadHalf {
     Tcp(tcp::OwnedReadHalf),
     
     #[cfg(unix)]
     Unix(unix::OwnedReadHalf),
+
+    #[cfg(feature = "tls")]
+    Tls(io::ReadHalf<TlsStream<TcpStream>>),
 }
 
 impl AsyncRead for OwnedReadHalf {
@@ -35,6 +54,9 @@ impl AsyncRead for OwnedReadHalf {
 
             #[cfg(unix)]
             Self::Unix(half) => Pin::new(half).poll_read(cx, buf),
+
+            #[cfg(feature = "tls")]
+            Self::Tls(half) => Pin::new(half).poll_read(cx, buf),
         }
     }
 }
@@ -44,6 +66,9 @@ enum OwnedWriteHalf {
 
     #[cfg(unix)]
     Unix(unix::OwnedWriteHalf),
+
+    #[cfg(feature = "tls")]
+    Tls(io::WriteHalf<TlsStream<TcpStream>>),
 }
 
 impl AsyncWrite for OwnedWriteHalf {
@@ -57,6 +82,9 @@ impl AsyncWrite for OwnedWriteHalf {
 
             #[cfg(unix)]
             Self::Unix(half) => Pin::new(half).poll_write(cx, buf),
+
+            #[cfg(feature = "tls")]
+            Self::Tls(half) => Pin::new(half).poll_write(cx, buf),
         }
     }
 
@@ -66,6 +94,9 @@ impl AsyncWrite for OwnedWriteHalf {
 
             #[cfg(unix)]
             Self::Unix(half) => Pin::new(half).poll_flush(cx),
+
+            #[cfg(feature = "tls")]
+            Self::Tls(half) => Pin::new(half).poll_flush(cx),
         }
     }
 
@@ -75,6 +106,9 @@ impl AsyncWrite for OwnedWriteHalf {
 
             #[cfg(unix)]
             Self::Unix(half) => Pin::new(half).poll_shutdown(cx),
+
+            #[cfg(feature = "tls")]
+            Self::Tls(half) => Pin::new(half).poll_shutdown(cx),
         }
     }
 }
@@ -86,17 +120,41 @@ pub enum RecvMessageError {
 
     #[error("bincode decode error")]
     Bincode(#[from] bincode::error::DecodeError),
+
+    #[error("the server sent a frame of {0} bytes, which is more than allowed")]
+    FrameTooLarge(usize),
 }
 
-pub struct ClientReader(OwnedReadHalf);
+pub struct ClientReader {
+    half: OwnedReadHalf,
+    max_frame_len: usize,
+}
 
 impl ClientReader {
+    fn new(half: OwnedReadHalf) -> Self {
+        Self {
+            half,
+            max_frame_len: MAX_FRAME_LEN,
+        }
+    }
+
+    /// sets the longest frame this reader accepts, which defaults to [`MAX_FRAME_LEN`].
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
     pub async fn recv(&mut self) -> Result<ServerToClientMessage, RecvMessageError> {
         let mut len = [0; 4];
-        self.0.read_exact(&mut len).await?;
+        self.half.read_exact(&mut len).await?;
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > self.max_frame_len {
+            return Err(RecvMessageError::FrameTooLarge(len));
+        }
 
-        let mut buf = vec![0; u32::from_le_bytes(len) as usize];
-        self.0.read_exact(&mut buf).await?;
+        let mut buf = vec![0; len];
+        self.half.read_exact(&mut buf).await?;
 
         bincode::decode_from_slice(&buf, bincode::config::standard())
             .map(|(m, _)| m)
@@ -105,7 +163,7 @@ impl ClientReader {
     
     pub fn is_unix(&self) -> bool {
         #[cfg(unix)]
-        let ret = matches!(&self.0, OwnedReadHalf::Unix(_));
+        let ret = matches!(&self.half, OwnedReadHalf::Unix(_));
         
         #[cfg(not(unix))]
         let ret = false;
@@ -113,8 +171,14 @@ impl ClientReader {
         ret
     }
 
+    /// whether this reads from a tcp stream, including one wrapped in tls.
     pub fn is_tcp(&self) -> bool {
-        matches!(&self.0, OwnedReadHalf::Tcp(_))
+        let ret = matches!(&self.half, OwnedReadHalf::Tcp(_));
+
+        #[cfg(feature = "tls")]
+        let ret = ret || matches!(&self.half, OwnedReadHalf::Tls(_));
+
+        ret
     }
 }
 
@@ -158,8 +222,11 @@ impl ClientWriter {
 
     pub async fn update_config(&mut self, config: Config) -> Result<TaskId, SendMessageError> {
         let task_id = TaskId::generate();
-        self.send_message(ClientToServerMessage::UpdateConfig(task_id, config))
-            .await?;
+        self.send_message(ClientToServerMessage::UpdateConfig(
+            task_id,
+            Box::new(config),
+        ))
+        .await?;
         Ok(task_id)
     }
     
@@ -173,9 +240,319 @@ impl ClientWriter {
     pub async fn perform_operation(
         &mut self,
         operation: Operation,
+        idempotency_key: Option<IdempotencyKey>,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::PerformOperation(
+            task_id,
+            operation,
+            idempotency_key,
+        ))
+        .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_operation_result(
+        &mut self,
+        operation_id: OperationId,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetOperationResult(task_id, operation_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_active_operation(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetActiveOperation(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn run_maintenance(
+        &mut self,
+        steps: Vec<MaintenanceStep>,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::RunMaintenance(task_id, steps))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn save_world(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SaveWorld(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn backup_world(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::BackupWorld(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn reload_server(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::ReloadServer(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn update_jar(
+        &mut self,
+        relative_path: PathBuf,
+        back_up_previous: bool,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::UpdateJar(
+            task_id,
+            relative_path,
+            back_up_previous,
+        ))
+        .await?;
+        Ok(task_id)
+    }
+
+    pub async fn list_argument_presets(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::ListArgumentPresets(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn apply_argument_preset(
+        &mut self,
+        name: String,
+        memory_mb: u32,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::ApplyArgumentPreset(task_id, name, memory_mb))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_recent_output(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetRecentOutput(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_startup_log(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetStartupLog(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_lifecycle_events(
+        &mut self,
+        since: Option<SystemTime>,
     ) -> Result<TaskId, SendMessageError> {
         let task_id = TaskId::generate();
-        self.send_message(ClientToServerMessage::PerformOperation(task_id, operation))
+        self.send_message(ClientToServerMessage::GetLifecycleEvents(task_id, since))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_operation_history(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetOperationHistory(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_server_info(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetServerInfo(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn list_instances(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::ListInstances(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_connection_info(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetConnectionInfo(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_client_count(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetClientCount(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_diagnostics(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetDiagnostics(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_resolved_paths(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetResolvedPaths(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn refresh_java_detection(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::RefreshJavaDetection(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_java_version(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetJavaVersion(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_backup_retention(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetBackupRetention(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_backup_retention(
+        &mut self,
+        retention: Option<usize>,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetBackupRetention(task_id, retention))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn patch_config(&mut self, patch: ConfigPatch) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::PatchConfig(task_id, patch))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn test_user(&mut self, user: User) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::TestUser(task_id, user))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_metrics_text(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetMetricsText(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_resource_usage(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetResourceUsage(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn validate_tls(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::ValidateTls(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_protocol_stats(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::GetProtocolStats(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn disconnect_all_remote(
+        &mut self,
+        reason: String,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::DisconnectAllRemote(task_id, reason))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn subscribe_metrics(
+        &mut self,
+        interval: Duration,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SubscribeMetrics(task_id, interval))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn unsubscribe_metrics(&mut self) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::UnsubscribeMetrics(task_id))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn identify(
+        &mut self,
+        identity: ClientIdentity,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::Identify(task_id, identity))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_editing_config(&mut self, editing: bool) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetEditingConfig(task_id, editing))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_output_mode(&mut self, mode: OutputMode) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetOutputMode(task_id, mode))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_output_delivery(
+        &mut self,
+        delivery: OutputDelivery,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetOutputDelivery(task_id, delivery))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_accepting_clients(
+        &mut self,
+        accepting: bool,
+    ) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetAcceptingClients(task_id, accepting))
+            .await?;
+        Ok(task_id)
+    }
+
+    pub async fn set_log_level(&mut self, level: String) -> Result<TaskId, SendMessageError> {
+        let task_id = TaskId::generate();
+        self.send_message(ClientToServerMessage::SetLogLevel(task_id, level))
             .await?;
         Ok(task_id)
     }
@@ -187,6 +564,14 @@ impl ClientWriter {
     pub async fn shutdown(&mut self) -> Result<(), SendMessageError> {
         self.send_message(ClientToServerMessage::Shutdown).await
     }
+
+    /// flushes what was written and shuts down the write half of the connection, so the server
+    /// sees the end of the stream rather than an abrupt disconnect. the reader can still receive
+    /// what the server sends until it closes its side.
+    pub async fn close(mut self) -> io::Result<()> {
+        self.0.flush().await?;
+        self.0.shutdown().await
+    }
 }
 
 impl ClientWriter {
@@ -200,36 +585,168 @@ impl ClientWriter {
         ret
     }
 
+    /// whether this writes to a tcp stream, including one wrapped in tls.
     pub fn is_tcp(&self) -> bool {
-        matches!(&self.0, OwnedWriteHalf::Tcp(_))
+        let ret = matches!(&self.0, OwnedWriteHalf::Tcp(_));
+
+        #[cfg(feature = "tls")]
+        let ret = ret || matches!(&self.0, OwnedWriteHalf::Tls(_));
+
+        ret
+    }
+}
+
+/// exchanges protocol versions with the server, which has to succeed before any other messages are
+/// sent. a server built from another version is reported as an [`io::ErrorKind::InvalidData`] error.
+async fn handshake(
+    reader: &mut ClientReader,
+    writer: &mut ClientWriter,
+    options: HandshakeOptions,
+) -> io::Result<()> {
+    writer
+        .send_message(ClientToServerMessage::Handshake(PROTOCOL_VERSION, options))
+        .await
+        .map_err(|error| match error {
+            SendMessageError::Io(error) => error,
+            SendMessageError::Bincode(error) => io::Error::new(io::ErrorKind::InvalidData, error),
+        })?;
+
+    let response = reader.recv().await.map_err(|error| match error {
+        RecvMessageError::Io(error) => error,
+        RecvMessageError::Bincode(error) => io::Error::new(io::ErrorKind::InvalidData, error),
+        error @ RecvMessageError::FrameTooLarge(_) => {
+            io::Error::new(io::ErrorKind::InvalidData, error)
+        }
+    })?;
+    match response {
+        ServerToClientMessage::HandshakeAccepted => Ok(()),
+        ServerToClientMessage::VersionMismatch { expected, got } => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the server speaks protocol version {expected}, but this client speaks {got}; \
+                 update the client and server to the same version"
+            ),
+        )),
+        message => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the server answered the handshake with {}, it's likely from an older version",
+                message.name()
+            ),
+        )),
     }
 }
 
-pub async fn from_tcp(addrs: impl ToSocketAddrs) -> io::Result<(ClientReader, ClientWriter)> {
+/// connects to the first of `addrs` which accepts, through the proxy if one is set. see
+/// [`proxy::proxy`].
+async fn connect_tcp(addrs: impl ToSocketAddrs) -> io::Result<TcpStream> {
+    let Some(proxy) = proxy::proxy()? else {
+        return TcpStream::connect(addrs).await;
+    };
+
+    let mut last_error = None;
+    for addr in lookup_host(addrs).await? {
+        match proxy.connect(&proxy::Target::Addr(addr)).await {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+    }))
+}
+
+/// connects to `host`, which a proxy that resolves host names is handed as it is. see
+/// [`proxy::Proxy::resolves_hosts`].
+async fn connect_tcp_host(host: &str, port: u16) -> io::Result<TcpStream> {
+    match proxy::proxy()? {
+        Some(proxy) if proxy.resolves_hosts() => {
+            proxy
+                .connect(&proxy::Target::Host(host.to_owned(), port))
+                .await
+        }
+        _ => connect_tcp((host, port)).await,
+    }
+}
+
+pub async fn from_tcp(
+    addrs: impl ToSocketAddrs,
+    options: HandshakeOptions,
+) -> io::Result<(ClientReader, ClientWriter)> {
     tracing::debug!("tcp stream connect");
-    let stream = TcpStream::connect(addrs).await?;
+    let stream = connect_tcp(addrs).await?;
+    tracing::debug!("tcp stream connected");
+
+    from_tcp_stream(stream, options).await
+}
+
+/// like [`from_tcp`], but connects to a host name, which is left for the proxy to resolve if it
+/// resolves host names
+pub async fn from_tcp_host(
+    host: &str,
+    port: u16,
+    options: HandshakeOptions,
+) -> io::Result<(ClientReader, ClientWriter)> {
+    tracing::debug!(host, port, "tcp stream connect");
+    let stream = connect_tcp_host(host, port).await?;
     tracing::debug!("tcp stream connected");
 
+    from_tcp_stream(stream, options).await
+}
+
+async fn from_tcp_stream(
+    stream: TcpStream,
+    options: HandshakeOptions,
+) -> io::Result<(ClientReader, ClientWriter)> {
     let (read_half, write_half) = stream.into_split();
+    let mut reader = ClientReader::new(OwnedReadHalf::Tcp(read_half));
+    let mut writer = ClientWriter(OwnedWriteHalf::Tcp(write_half));
+    handshake(&mut reader, &mut writer, options).await?;
+
+    Ok((reader, writer))
+}
 
-    Ok((
-        ClientReader(OwnedReadHalf::Tcp(read_half)),
-        ClientWriter(OwnedWriteHalf::Tcp(write_half)),
-    ))
+/// connects to a server whose tcp listener is served over tls. `server_name` is the name its
+/// certificate is checked against, and `config` holds the certificates to trust.
+#[cfg(feature = "tls")]
+pub async fn from_tcp_tls(
+    addrs: impl ToSocketAddrs,
+    server_name: rustls::pki_types::ServerName<'static>,
+    config: Arc<rustls::ClientConfig>,
+    options: HandshakeOptions,
+) -> io::Result<(ClientReader, ClientWriter)> {
+    tracing::debug!("tcp stream connect");
+    let stream = connect_tcp(addrs).await?;
+    tracing::debug!("tcp stream connected, tls handshake");
+    let stream = tokio_rustls::TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await?;
+    tracing::debug!("tls handshake done");
+
+    let (read_half, write_half) = io::split(stream);
+    let mut reader = ClientReader::new(OwnedReadHalf::Tls(read_half));
+    let mut writer = ClientWriter(OwnedWriteHalf::Tls(write_half));
+    handshake(&mut reader, &mut writer, options).await?;
+
+    Ok((reader, writer))
 }
 
 #[cfg(unix)]
-pub async fn from_unix(addr: impl AsRef<Path>) -> io::Result<(ClientReader, ClientWriter)> {
+pub async fn from_unix(
+    addr: impl AsRef<Path>,
+    options: HandshakeOptions,
+) -> io::Result<(ClientReader, ClientWriter)> {
     tracing::debug!("unix stream connect");
     let stream = UnixStream::connect(addr).await?;
     tracing::debug!("unix stream connected");
 
     let (read_half, write_half) = stream.into_split();
+    let mut reader = ClientReader::new(OwnedReadHalf::Unix(read_half));
+    let mut writer = ClientWriter(OwnedWriteHalf::Unix(write_half));
+    handshake(&mut reader, &mut writer, options).await?;
 
-    Ok((
-        ClientReader(OwnedReadHalf::Unix(read_half)),
-        ClientWriter(OwnedWriteHalf::Unix(write_half)),
-    ))
+    Ok((reader, writer))
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -256,3 +773,52 @@ impl ClientMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// splits what a client sent into its messages
+    fn decode_frames(mut buf: &[u8]) -> Vec<ClientToServerMessage> {
+        let mut messages = Vec::new();
+        while !buf.is_empty() {
+            let len = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
+            let (message, _) =
+                bincode::decode_from_slice(&buf[4..4 + len], bincode::config::standard()).unwrap();
+            messages.push(message);
+            buf = &buf[4 + len..];
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn the_server_sees_a_clean_end_of_stream_after_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let response = bincode::encode_to_vec(
+                ServerToClientMessage::HandshakeAccepted,
+                bincode::config::standard(),
+            )
+            .unwrap();
+            stream.write_u32_le(response.len() as u32).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+
+            // only a clean end of the stream lets this finish without an error
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            decode_frames(&buf)
+        });
+
+        let (_reader, mut writer) = from_tcp(addr, HandshakeOptions::default()).await.unwrap();
+        let task_id = writer.ping().await.unwrap();
+        writer.close().await.unwrap();
+
+        let messages = server.await.unwrap();
+        assert!(matches!(messages[0], ClientToServerMessage::Handshake(..)));
+        assert!(matches!(messages[1], ClientToServerMessage::Ping(id) if id == task_id));
+        assert_eq!(messages.len(), 2);
+    }
+}
@@ -1,7 +1,19 @@
 use anyhow::Context;
-use raphy_protocol::{Config, Operation, ServerState, ServerToClientMessage};
+use raphy_protocol::config::{Arguments, ConfigPatch, JavaPath, User};
+use raphy_protocol::{
+    ActiveOperation, ArgumentPresetInfo, BackupProgress, ClientIdentity, Config, ConfigEditor,
+    ConnectionInfo, CurrentConfig, Diagnostics, ErrorKind, HandshakeOptions, IdempotencyKey,
+    JavaVersion, LifecycleEvent, MaintenanceReport, MaintenanceStep, Operation, OperationId,
+    OperationOutcome, OperationRecord, OutputDelivery, OutputMode, OutputStream, ProtocolStats,
+    ReloadResult, ResolvedPaths, ResourceUsage, SaveResult, SerdeError, ServerInfo, ServerState,
+    ServerToClientMessage, StartupLog, TlsReport, UserTestOutcome,
+};
+use std::collections::{BTreeMap, VecDeque};
 use std::io;
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 use tokio::net::ToSocketAddrs;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
@@ -38,22 +50,249 @@ impl ClientReader {
     }
 }
 
+impl ClientReader {
+    /// waits until the server broadcasts a state matching `predicate`.
+    pub async fn wait_for_state(
+        &mut self,
+        mut predicate: impl FnMut(&ServerState) -> bool,
+    ) -> Option<ServerState> {
+        match self
+            .expect(|m| matches!(m, ServerToClientMessage::ServerStateUpdated(state) if predicate(state)))
+            .await?
+        {
+            ServerToClientMessage::ServerStateUpdated(state) => Some(state),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ClientReader {
+    /// keeps the last `capacity` lines of the server's output from now on, for consumers which
+    /// don't want to run their own receive loop. lines are collected in the background until the
+    /// returned tail and all of its clones are dropped.
+    pub fn tail(&self, capacity: usize) -> OutputTail {
+        let buffer = Arc::new(Mutex::new(TailBuffer {
+            capacity,
+            lines: VecDeque::new(),
+            partial_stdout: Vec::new(),
+            partial_stderr: Vec::new(),
+        }));
+        tokio::spawn(collect_tail(self.0.resubscribe(), Arc::downgrade(&buffer)));
+
+        OutputTail(buffer)
+    }
+}
+
 impl Clone for ClientReader {
     fn clone(&self) -> Self {
         Self(self.0.resubscribe())
     }
 }
 
+struct TailBuffer {
+    capacity: usize,
+    lines: VecDeque<String>,
+
+    /// the unfinished last line of each stream, which is kept until its newline arrives
+    partial_stdout: Vec<u8>,
+    partial_stderr: Vec<u8>,
+}
+
+impl TailBuffer {
+    fn push_line(lines: &mut VecDeque<String>, capacity: usize, line: String) {
+        lines.push_back(line);
+        while lines.len() > capacity {
+            lines.pop_front();
+        }
+    }
+
+    fn push(&mut self, stream: OutputStream, output: &[u8]) {
+        let Self {
+            capacity,
+            lines,
+            partial_stdout,
+            partial_stderr,
+        } = self;
+        let partial = match stream {
+            OutputStream::Stdout => partial_stdout,
+            OutputStream::Stderr => partial_stderr,
+        };
+
+        partial.extend_from_slice(output);
+        while let Some(end) = partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            Self::push_line(lines, *capacity, line.trim_end_matches('\r').to_owned());
+        }
+    }
+}
+
+/// the last lines of the server's output, as collected by [`ClientReader::tail`]. lines from
+/// stdout and stderr are kept in the order they arrived.
+#[derive(Clone)]
+pub struct OutputTail(Arc<Mutex<TailBuffer>>);
+
+impl OutputTail {
+    /// the collected lines, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().lines.iter().cloned().collect()
+    }
+
+    /// takes the collected lines, oldest first, so that the next call only returns newer ones.
+    pub fn drain(&self) -> Vec<String> {
+        self.0.lock().unwrap().lines.drain(..).collect()
+    }
+}
+
+async fn collect_tail(
+    mut s2c_rx: broadcast::Receiver<ServerToClientMessage>,
+    buffer: Weak<Mutex<TailBuffer>>,
+) {
+    loop {
+        let result = s2c_rx.recv().await;
+        let Some(buffer) = buffer.upgrade() else {
+            break;
+        };
+        let mut buffer = buffer.lock().unwrap();
+
+        match result {
+            Ok(ServerToClientMessage::Stdout(output)) => buffer.push(OutputStream::Stdout, &output),
+            Ok(ServerToClientMessage::Stderr(output)) => buffer.push(OutputStream::Stderr, &output),
+            Ok(ServerToClientMessage::Log(log)) => buffer.push(log.stream, &log.text()),
+            Ok(_) => {}
+
+            // the missed output can't be recovered, but the gap is marked so that it isn't mistaken
+            // for the server having been quiet
+            Err(broadcast::error::RecvError::Lagged(amount)) => {
+                tracing::warn!(?amount, "output tail lagged");
+                let capacity = buffer.capacity;
+                TailBuffer::push_line(
+                    &mut buffer.lines,
+                    capacity,
+                    format!("… {amount} messages missed …"),
+                );
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("not a local client")]
 pub struct NotALocalClient;
 
+#[derive(Debug, Clone)]
+pub enum OperationPhase {
+    /// the operation was sent to the server
+    Requested,
+
+    /// the server's state changed while the operation was being performed
+    StateChanged(ServerState),
+}
+
+#[derive(Debug)]
+pub enum OperationUpdate {
+    Progress(OperationPhase),
+    Done(OperationId),
+    Failed(anyhow::Error),
+}
+
+/// the updates of an operation started by [`ClientWriter::perform_operation_with_progress`]
+pub struct OperationProgress(UnboundedReceiver<OperationUpdate>);
+
+impl OperationProgress {
+    /// receives the next update, or `None` once the final update has been received.
+    pub async fn recv(&mut self) -> Option<OperationUpdate> {
+        self.0.recv().await
+    }
+}
+
+#[derive(Debug)]
+pub enum MaintenanceUpdate {
+    StepStarted(MaintenanceStep),
+
+    /// the final update, after which no steps are run anymore
+    Finished(MaintenanceReport),
+}
+
+/// the updates of a maintenance cycle started by [`ClientWriter::run_maintenance`]
+pub struct MaintenanceProgress(UnboundedReceiver<MaintenanceUpdate>);
+
+impl MaintenanceProgress {
+    /// receives the next update, or `None` once the final update has been received or the
+    /// connection failed before it arrived.
+    pub async fn recv(&mut self) -> Option<MaintenanceUpdate> {
+        self.0.recv().await
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupUpdate {
+    Progress(BackupProgress),
+
+    /// the final update, with the path of the archive on the server's machine
+    Finished(anyhow::Result<PathBuf>),
+}
+
+/// the updates of a world backup started by [`ClientWriter::backup_world`]
+pub struct WorldBackupProgress(UnboundedReceiver<BackupUpdate>);
+
+impl WorldBackupProgress {
+    /// receives the next update, or `None` once the final update has been received or the
+    /// connection failed before it arrived.
+    pub async fn recv(&mut self) -> Option<BackupUpdate> {
+        self.0.recv().await
+    }
+}
+
 enum ClientToServerMessage {
     Ping(oneshot::Sender<()>),
-    GetConfig(oneshot::Sender<Option<Config>>),
-    UpdateConfig(Config, oneshot::Sender<()>),
+    GetConfig(oneshot::Sender<CurrentConfig>),
+    UpdateConfig(Box<Config>, oneshot::Sender<anyhow::Result<()>>),
     GetServerState(oneshot::Sender<ServerState>),
-    PerformOperation(Operation, oneshot::Sender<anyhow::Result<()>>),
+    PerformOperation(
+        Operation,
+        Option<IdempotencyKey>,
+        oneshot::Sender<(OperationId, anyhow::Result<()>)>,
+    ),
+    GetOperationResult(OperationId, oneshot::Sender<Option<OperationOutcome>>),
+    GetActiveOperation(oneshot::Sender<Option<ActiveOperation>>),
+    RunMaintenance(Vec<MaintenanceStep>, UnboundedSender<MaintenanceUpdate>),
+    SaveWorld(oneshot::Sender<anyhow::Result<SaveResult>>),
+    BackupWorld(UnboundedSender<BackupUpdate>),
+    ReloadServer(oneshot::Sender<anyhow::Result<ReloadResult>>),
+    UpdateJar(PathBuf, bool, oneshot::Sender<anyhow::Result<Config>>),
+    ListArgumentPresets(oneshot::Sender<Vec<ArgumentPresetInfo>>),
+    ApplyArgumentPreset(String, u32, oneshot::Sender<anyhow::Result<Config>>),
+    GetStartupLog(oneshot::Sender<StartupLog>),
+    GetRecentOutput(oneshot::Sender<Vec<u8>>),
+    GetLifecycleEvents(Option<SystemTime>, oneshot::Sender<Vec<LifecycleEvent>>),
+    GetOperationHistory(oneshot::Sender<Vec<OperationRecord>>),
+    GetServerInfo(oneshot::Sender<ServerInfo>),
+    ListInstances(oneshot::Sender<anyhow::Result<Vec<Option<String>>>>),
+    GetConnectionInfo(oneshot::Sender<ConnectionInfo>),
+    GetClientCount(oneshot::Sender<usize>),
+    GetDiagnostics(oneshot::Sender<anyhow::Result<Diagnostics>>),
+    GetResolvedPaths(oneshot::Sender<anyhow::Result<ResolvedPaths>>),
+    RefreshJavaDetection(oneshot::Sender<Option<PathBuf>>),
+    GetJavaVersion(oneshot::Sender<anyhow::Result<JavaVersion>>),
+    GetBackupRetention(oneshot::Sender<Option<usize>>),
+    SetBackupRetention(Option<usize>, oneshot::Sender<anyhow::Result<Config>>),
+    PatchConfig(ConfigPatch, oneshot::Sender<anyhow::Result<Config>>),
+    GetMetricsText(oneshot::Sender<anyhow::Result<String>>),
+    GetResourceUsage(oneshot::Sender<anyhow::Result<ResourceUsage>>),
+    ValidateTls(oneshot::Sender<anyhow::Result<TlsReport>>),
+    TestUser(User, oneshot::Sender<UserTestOutcome>),
+    GetProtocolStats(oneshot::Sender<anyhow::Result<ProtocolStats>>),
+    DisconnectAllRemote(String, oneshot::Sender<anyhow::Result<usize>>),
+    SubscribeMetrics(Duration, oneshot::Sender<Duration>),
+    UnsubscribeMetrics(oneshot::Sender<()>),
+    Identify(ClientIdentity, oneshot::Sender<bool>),
+    SetEditingConfig(bool, oneshot::Sender<anyhow::Result<Vec<ConfigEditor>>>),
+    SetOutputMode(OutputMode, oneshot::Sender<()>),
+    SetOutputDelivery(OutputDelivery, oneshot::Sender<()>),
+    SetAcceptingClients(bool, oneshot::Sender<anyhow::Result<()>>),
+    SetLogLevel(String, oneshot::Sender<anyhow::Result<()>>),
     Input(Vec<u8>),
     Shutdown(oneshot::Sender<Result<(), NotALocalClient>>),
 }
@@ -70,7 +309,7 @@ impl ClientWriter {
         rx.await.context("tx dropped")
     }
     
-    pub async fn get_config(&self) -> anyhow::Result<Option<Config>> {
+    pub async fn get_config(&self) -> anyhow::Result<CurrentConfig> {
         let (tx, rx) = oneshot::channel();
         self.0
             .send(ClientToServerMessage::GetConfig(tx))
@@ -78,14 +317,20 @@ impl ClientWriter {
         rx.await.context("tx dropped")
     }
 
+    /// fails if the server rejects the config as invalid, in which case the error's
+    /// [`SerdeError::kind`] is [`ErrorKind::InvalidConfig`]
     pub async fn update_config(&self, config: Config) -> anyhow::Result<()> {
         let (tx, rx) = oneshot::channel();
         self.0
-            .send(ClientToServerMessage::UpdateConfig(config, tx))
+            .send(ClientToServerMessage::UpdateConfig(Box::new(config), tx))
             .context("c2s channel closed")?;
-        rx.await.context("tx dropped")
+        rx.await
+            .context("tx dropped")?
+            .context("failed to update the config")
     }
-    
+
+    /// the server's current state, as opposed to [`ClientReader::wait_for_state`] which waits for
+    /// the state to change.
     pub async fn get_server_state(&self) -> anyhow::Result<ServerState> {
         let (tx, rx) = oneshot::channel();
         self.0
@@ -94,153 +339,1605 @@ impl ClientWriter {
         rx.await.context("tx dropped")
     }
 
+    /// performs `operation`, waiting until it finishes. prefer
+    /// [`ClientWriter::perform_operation_with_progress`] when the operation's progress should be
+    /// shown.
     pub async fn perform_operation(&self, operation: Operation) -> anyhow::Result<()> {
         let (tx, rx) = oneshot::channel();
         self.0
-            .send(ClientToServerMessage::PerformOperation(operation, tx))
+            .send(ClientToServerMessage::PerformOperation(operation, None, tx))
             .context("c2s channel closed")?;
         rx.await
             .context("tx dropped")?
+            .1
+            .context("failed to perform operation")
+    }
+
+    /// like [`ClientWriter::perform_operation`], but safe to retry: resending `operation` with the
+    /// same `idempotency_key`, e.g. after a timeout, returns the result of the first attempt rather
+    /// than performing it again.
+    pub async fn perform_operation_idempotent(
+        &self,
+        operation: Operation,
+        idempotency_key: IdempotencyKey,
+    ) -> anyhow::Result<OperationId> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::PerformOperation(
+                operation,
+                Some(idempotency_key),
+                tx,
+            ))
+            .context("c2s channel closed")?;
+        let (operation_id, result) = rx.await.context("tx dropped")?;
+        result
             .context("failed to perform operation")
+            .map(|()| operation_id)
+    }
+
+    /// performs `operation`, reporting the server state changes it causes as it goes. the last
+    /// update is always either [`OperationUpdate::Done`] or [`OperationUpdate::Failed`], including
+    /// when the connection is lost or the operation doesn't finish within `timeout`.
+    pub fn perform_operation_with_progress(
+        &self,
+        reader: &ClientReader,
+        operation: Operation,
+        timeout: Duration,
+    ) -> OperationProgress {
+        // subscribe before performing the operation so that no state update can be missed
+        let mut reader = reader.clone();
+        let c2s_tx = self.0.clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (result_tx, result_rx) = oneshot::channel();
+            if let Err(error) = c2s_tx
+                .send(ClientToServerMessage::PerformOperation(operation, None, result_tx))
+                .context("c2s channel closed")
+            {
+                tx.send(OperationUpdate::Failed(error)).ok();
+                return;
+            }
+
+            tx.send(OperationUpdate::Progress(OperationPhase::Requested))
+                .ok();
+
+            let forward_states = async {
+                while let Some(message) = reader.recv().await {
+                    if let ServerToClientMessage::ServerStateUpdated(state) = message {
+                        tx.send(OperationUpdate::Progress(OperationPhase::StateChanged(state)))
+                            .ok();
+                    }
+                }
+
+                // a closed reader means the connection is gone, which the result will reflect too
+                std::future::pending::<()>().await
+            };
+
+            let update = tokio::select! {
+                result = tokio::time::timeout(timeout, result_rx) => match result {
+                    Ok(Ok((operation_id, Ok(())))) => OperationUpdate::Done(operation_id),
+                    Ok(Ok((_, Err(error)))) => OperationUpdate::Failed(
+                        error.context("failed to perform operation"),
+                    ),
+                    Ok(Err(_)) => OperationUpdate::Failed(anyhow::anyhow!(
+                        "lost connection to the server while performing the operation"
+                    )),
+                    Err(_) => OperationUpdate::Failed(anyhow::anyhow!(
+                        "operation did not finish within {timeout:?}"
+                    )),
+                },
+                () = forward_states => unreachable!(),
+            };
+            tx.send(update).ok();
+        });
+
+        OperationProgress(rx)
     }
     
-    pub async fn input(&self, input: Vec<u8>) -> anyhow::Result<()> {
+    pub async fn get_operation_result(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<Option<OperationOutcome>> {
+        let (tx, rx) = oneshot::channel();
         self.0
-            .send(ClientToServerMessage::Input(input))
-            .context("c2s channel closed")
+            .send(ClientToServerMessage::GetOperationResult(operation_id, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
     }
 
-    pub async fn shutdown(&self) -> anyhow::Result<()> {
+    /// the operation currently being performed, if any, regardless of which client requested it.
+    pub async fn get_active_operation(&self) -> anyhow::Result<Option<ActiveOperation>> {
         let (tx, rx) = oneshot::channel();
         self.0
-            .send(ClientToServerMessage::Shutdown(tx))
+            .send(ClientToServerMessage::GetActiveOperation(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// runs the steps on the server in order, stopping at the first one which fails, e.g.
+    /// [`MaintenanceStep::CYCLE`]. the cycle carries on on the server even if the progress is
+    /// dropped or the connection fails.
+    pub fn run_maintenance(&self, steps: Vec<MaintenanceStep>) -> MaintenanceProgress {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        // if the channel is closed, so is the progress, which the caller notices through `None`
+        self.0
+            .send(ClientToServerMessage::RunMaintenance(steps, tx))
+            .ok();
+        MaintenanceProgress(rx)
+    }
+
+    pub async fn save_world(&self) -> anyhow::Result<SaveResult> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SaveWorld(tx))
             .context("c2s channel closed")?;
         rx.await
             .context("tx dropped")?
-            .context("failed to shutdown")
+            .context("failed to save the world")
     }
-}
 
-async fn client_reader_task(
-    mut reader: crate::ClientReader,
-    s2c_tx: broadcast::Sender<ServerToClientMessage>,
-    cancel_token: CancellationToken,
-) -> anyhow::Result<()> {
-    tracing::debug!("begin client reader task");
+    /// archives the world on the server, see
+    /// [`raphy_protocol::ClientToServerMessage::BackupWorld`]. the backup carries on on the server
+    /// even if the progress is dropped or the connection fails.
+    pub fn backup_world(&self) -> WorldBackupProgress {
+        let (tx, rx) = mpsc::unbounded_channel();
 
-    loop {
-        tokio::select! {
-            result = reader.recv() => match result {
-                Ok(value) => {
-                    s2c_tx.send(value).ok();
-                }
-                Err(error) => {
-                    tracing::error!(?error, "failed to receive message from client");
-                    cancel_token.cancel()
-                }
-            },
-            () = cancel_token.cancelled() => break Ok(()),
-        }
+        // if the channel is closed, so is the progress, which the caller notices through `None`
+        self.0.send(ClientToServerMessage::BackupWorld(tx)).ok();
+        WorldBackupProgress(rx)
     }
-}
 
-async fn client_writer_task_handle_message(
-    message: ClientToServerMessage,
-    writer: &mut crate::ClientWriter,
-    reader: &mut ClientReader,
-) -> anyhow::Result<()> {
-    match message {
-        ClientToServerMessage::Ping(rx) => {
-            tracing::debug!("receive ping");
-            let task_id = writer.ping().await.context("failed to send ping message")?;
-            let ServerToClientMessage::Pong(..) = reader
+    /// see [`raphy_protocol::ClientToServerMessage::ReloadServer`] for the risks of reloading.
+    pub async fn reload_server(&self) -> anyhow::Result<ReloadResult> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::ReloadServer(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to reload the server")
+    }
+
+    /// switches the server over to another jar in its working directory, returning the updated
+    /// config. if `back_up_previous` is set, the previous jar is first copied into the backup
+    /// directory.
+    pub async fn update_jar(
+        &self,
+        relative_path: impl Into<PathBuf>,
+        back_up_previous: bool,
+    ) -> anyhow::Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::UpdateJar(
+                relative_path.into(),
+                back_up_previous,
+                tx,
+            ))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to update the server jar")
+    }
+
+    pub async fn list_argument_presets(&self) -> anyhow::Result<Vec<ArgumentPresetInfo>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::ListArgumentPresets(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// replaces the java arguments with the preset of the given name, for a heap of `memory_mb`
+    /// megabytes, returning the updated config.
+    pub async fn apply_argument_preset(
+        &self,
+        name: impl Into<String>,
+        memory_mb: u32,
+    ) -> anyhow::Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::ApplyArgumentPreset(name.into(), memory_mb, tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to apply the argument preset")
+    }
+
+    pub async fn get_startup_log(&self) -> anyhow::Result<StartupLog> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetStartupLog(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the newest output of the server, stdout and stderr interleaved. meant to fill the console of
+    /// a client which just connected, since output from before that isn't sent to it otherwise.
+    pub async fn get_recent_output(&self) -> anyhow::Result<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetRecentOutput(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the server's lifecycle events, oldest first, optionally only those after `since`.
+    pub async fn get_lifecycle_events(
+        &self,
+        since: Option<SystemTime>,
+    ) -> anyhow::Result<Vec<LifecycleEvent>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetLifecycleEvents(since, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the most recent operations which finished, oldest first, along with who requested them
+    pub async fn get_operation_history(&self) -> anyhow::Result<Vec<OperationRecord>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetOperationHistory(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    pub async fn get_server_info(&self) -> anyhow::Result<ServerInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetServerInfo(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the instances on the server's machine, `None` being the default one. local clients can
+    /// use [`raphy_protocol::local_instances`] instead, without being connected to any.
+    pub async fn list_instances(&self) -> anyhow::Result<Vec<Option<String>>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::ListInstances(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to list the instances")
+    }
+
+    pub async fn get_connection_info(&self) -> anyhow::Result<ConnectionInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetConnectionInfo(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// how many clients are connected to the server, including this one. cheaper than listing
+    /// them, e.g. for polling.
+    pub async fn get_client_count(&self) -> anyhow::Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetClientCount(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    pub async fn get_diagnostics(&self) -> anyhow::Result<Diagnostics> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetDiagnostics(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get diagnostics")
+    }
+
+    pub async fn get_resolved_paths(&self) -> anyhow::Result<ResolvedPaths> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetResolvedPaths(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get the resolved paths")
+    }
+
+    /// detects java again on the server, returning the path found, if any.
+    pub async fn refresh_java_detection(&self) -> anyhow::Result<Option<PathBuf>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::RefreshJavaDetection(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the version of the java the server is launched with, e.g. to warn when it's older than
+    /// what a server jar requires.
+    pub async fn get_java_version(&self) -> anyhow::Result<JavaVersion> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetJavaVersion(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get the java version")
+    }
+
+    /// how many backups are kept after each backup, or `None` if every backup is kept.
+    pub async fn get_backup_retention(&self) -> anyhow::Result<Option<usize>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetBackupRetention(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// changes how many backups are kept after each backup, returning the updated config. the
+    /// backups beyond it are deleted after the next backup.
+    pub async fn set_backup_retention(&self, retention: Option<usize>) -> anyhow::Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetBackupRetention(retention, tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to set the backup retention")
+    }
+
+    /// changes a single field of the server's current config, returning the updated config. unlike
+    /// [`Self::update_config`], changes other clients make to other fields at the same time aren't
+    /// overwritten. only the changed field is validated.
+    pub async fn patch_config(&self, patch: ConfigPatch) -> anyhow::Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::PatchConfig(patch, tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to patch the config")
+    }
+
+    pub async fn set_java_path(&self, java_path: JavaPath) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::JavaPath(java_path)).await
+    }
+
+    pub async fn set_server_jar_path(&self, server_jar_path: PathBuf) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::ServerJarPath(server_jar_path))
+            .await
+    }
+
+    pub async fn set_java_arguments(&self, java_arguments: Arguments) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::JavaArguments(java_arguments))
+            .await
+    }
+
+    pub async fn set_server_arguments(
+        &self,
+        server_arguments: Arguments,
+    ) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::ServerArguments(server_arguments))
+            .await
+    }
+
+    pub async fn set_user(&self, user: User) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::User(user)).await
+    }
+
+    /// the most memory the server may use, or `None` for no limit
+    pub async fn set_memory_limit_mb(
+        &self,
+        memory_limit_mb: Option<u64>,
+    ) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::MemoryLimitMb(memory_limit_mb))
+            .await
+    }
+
+    pub async fn set_env(&self, env: BTreeMap<String, String>) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::Env(env)).await
+    }
+
+    pub async fn set_working_dir(&self, working_dir: Option<PathBuf>) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::WorkingDir(working_dir)).await
+    }
+
+    pub async fn set_output_timestamp(&self, format: Option<String>) -> anyhow::Result<Config> {
+        self.patch_config(ConfigPatch::OutputTimestamp(format)).await
+    }
+
+    /// checks whether the server could be launched as `user`, without starting it.
+    pub async fn test_user(&self, user: User) -> anyhow::Result<UserTestOutcome> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::TestUser(user, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// the server's metrics in the prometheus text format. fails unless the server was built with
+    /// its `prometheus` feature.
+    pub async fn get_metrics_text(&self) -> anyhow::Result<String> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetMetricsText(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get the metrics text")
+    }
+
+    /// the memory and cpu usage of the server process. takes a moment, since the cpu usage is
+    /// sampled.
+    pub async fn get_resource_usage(&self) -> anyhow::Result<ResourceUsage> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetResourceUsage(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get the resource usage")
+    }
+
+    /// checks the tls certificate and private key the server is configured with. problems with
+    /// them are listed in the report; an error means they couldn't be checked at all.
+    pub async fn validate_tls(&self) -> anyhow::Result<TlsReport> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::ValidateTls(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")?.context("failed to validate tls")
+    }
+
+    pub async fn get_protocol_stats(&self) -> anyhow::Result<ProtocolStats> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::GetProtocolStats(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to get protocol statistics")
+    }
+
+    /// tells every remote client to reconnect, returning how many there were.
+    pub async fn disconnect_all_remote(&self, reason: impl Into<String>) -> anyhow::Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::DisconnectAllRemote(reason.into(), tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to disconnect the remote clients")
+    }
+
+    /// streams `ServerToClientMessage::Metrics` to the reader on the interval, returning the
+    /// interval actually used, which may be longer.
+    pub async fn subscribe_metrics(&self, interval: Duration) -> anyhow::Result<Duration> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SubscribeMetrics(interval, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    pub async fn unsubscribe_metrics(&self) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::UnsubscribeMetrics(tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// ties the connection to `identity`, returning whether the server restored the subscriptions
+    /// of a previous connection with it. the same identity should be used for every connection of
+    /// the client, see [`ReconnectPolicy::identity`].
+    pub async fn identify(&self, identity: ClientIdentity) -> anyhow::Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::Identify(identity, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// tells the other clients that this client opened or closed its config editor, returning the
+    /// other clients which are editing the config too, e.g. to warn before their edits are
+    /// clobbered. they're told about later changes with `ConfigEditingStarted` and
+    /// `ConfigEditingStopped`.
+    pub async fn set_editing_config(&self, editing: bool) -> anyhow::Result<Vec<ConfigEditor>> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetEditingConfig(editing, tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to set whether the config is being edited")
+    }
+
+    /// starts the server if it isn't already running and waits until it has started.
+    pub async fn start_and_wait_ready(
+        &self,
+        reader: &ClientReader,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        // subscribe before starting so that the state update can't be missed
+        let mut reader = reader.clone();
+
+        if let ServerState::Started = self.get_server_state().await? {
+            return Ok(());
+        }
+
+        if let Err(error) = self.perform_operation(Operation::Start).await {
+            // someone else started the server in the meantime
+            let already_started = error.downcast_ref::<SerdeError>().and_then(SerdeError::kind)
+                == Some(&ErrorKind::AlreadyInState(ServerState::Started));
+            return if already_started { Ok(()) } else { Err(error) };
+        }
+
+        let state = tokio::time::timeout(timeout, reader.wait_for_state(|_| true))
+            .await
+            .with_context(|| format!("server did not start within {timeout:?}"))?
+            .context("s2c channel closed")?;
+
+        match state {
+            ServerState::Started => Ok(()),
+            ServerState::Stopped(status, _) => {
+                anyhow::bail!("server stopped before it was ready (exit status: {status:?})")
+            }
+        }
+    }
+
+    pub async fn set_output_mode(&self, mode: OutputMode) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetOutputMode(mode, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// chooses whether the server's stdout and stderr arrive separately or merged into `Log`
+    /// messages.
+    pub async fn set_output_delivery(&self, delivery: OutputDelivery) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetOutputDelivery(delivery, tx))
+            .context("c2s channel closed")?;
+        rx.await.context("tx dropped")
+    }
+
+    /// toggles whether the server accepts new remote clients, e.g. to drain it before maintenance.
+    pub async fn set_accepting_clients(&self, accepting: bool) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetAcceptingClients(accepting, tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to change whether new clients are accepted")
+    }
+
+    /// changes which of the server's own logs are printed, e.g. to debug a live server. `level`
+    /// uses the same syntax as `RUST_LOG`.
+    pub async fn set_log_level(&self, level: impl Into<String>) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::SetLogLevel(level.into(), tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to change the log level")
+    }
+
+    pub async fn input(&self, input: Vec<u8>) -> anyhow::Result<()> {
+        self.0
+            .send(ClientToServerMessage::Input(input))
+            .context("c2s channel closed")
+    }
+
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(ClientToServerMessage::Shutdown(tx))
+            .context("c2s channel closed")?;
+        rx.await
+            .context("tx dropped")?
+            .context("failed to shutdown")
+    }
+}
+
+async fn client_reader_task(
+    mut reader: crate::ClientReader,
+    s2c_tx: broadcast::Sender<ServerToClientMessage>,
+    cancel_token: CancellationToken,
+) -> anyhow::Result<()> {
+    tracing::debug!("begin client reader task");
+
+    loop {
+        tokio::select! {
+            // compressed output is inflated here, so that it arrives as plain `Stdout` and `Stderr`
+            result = reader.recv() => match result.map(ServerToClientMessage::decompressed) {
+                Ok(Ok(value)) => {
+                    s2c_tx.send(value).ok();
+                }
+                Ok(Err(error)) => {
+                    tracing::warn!(?error, "failed to decompress output from the server: {error}");
+                }
+                Err(error) => {
+                    tracing::error!(?error, "failed to receive message from client");
+                    cancel_token.cancel()
+                }
+            },
+            () = cancel_token.cancelled() => break Ok(()),
+        }
+    }
+}
+
+/// the writer of a connection. it's only locked while a message is written rather than until its
+/// response arrives, so that the keepalive can ping in between.
+type SharedWriter = tokio::sync::Mutex<crate::ClientWriter>;
+
+async fn client_writer_task_handle_message(
+    message: ClientToServerMessage,
+    writer: &SharedWriter,
+    reader: &mut ClientReader,
+) -> anyhow::Result<()> {
+    match message {
+        ClientToServerMessage::Ping(rx) => {
+            tracing::debug!("receive ping");
+            let task_id = writer
+                .lock()
+                .await
+                .ping()
+                .await
+                .context("failed to send ping message")?;
+            let ServerToClientMessage::Pong(..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive pong message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected Pong");
+            };
+            rx.send(()).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetConfig(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_config()
+                .await
+                .context("failed to send get config message")?;
+            let ServerToClientMessage::CurrentConfig(config, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive current config message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected CurrentConfig");
+            };
+            rx.send(config).ok();
+            Ok(())
+        }
+        ClientToServerMessage::UpdateConfig(config, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .update_config(*config)
+                .await
+                .context("failed to send update config message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config updated message")?;
+
+            match message {
+                ServerToClientMessage::ConfigUpdated(..) => {
+                    rx.send(Ok(())).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetServerState(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_server_state()
+                .await
+                .context("failed to send get server state message")?;
+            let ServerToClientMessage::CurrentServerState(state, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive current server state message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected CurrentServerState");
+            };
+            rx.send(state).ok();
+            Ok(())
+        }
+        ClientToServerMessage::PerformOperation(operation, idempotency_key, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .perform_operation(operation, idempotency_key)
+                .await
+                .context("failed to send perform operation message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive operation performed message")?;
+
+            match message {
+                ServerToClientMessage::OperationPerformed(_, operation_id, _) => {
+                    rx.send((operation_id, Ok(()))).ok();
+                }
+                ServerToClientMessage::OperationFailed(_, operation_id, error, _) => {
+                    rx.send((operation_id, Err(error.into()))).ok();
+                }
+                _ => {
+                    anyhow::bail!(
+                        "got unexpected s2c message, expected OperationPerformed or OperationFailed"
+                    );
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetOperationResult(operation_id, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_operation_result(operation_id)
+                .await
+                .context("failed to send get operation result message")?;
+            let ServerToClientMessage::OperationResult(outcome, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive operation result message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected OperationResult");
+            };
+            rx.send(outcome).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetActiveOperation(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_active_operation()
+                .await
+                .context("failed to send get active operation message")?;
+            let ServerToClientMessage::ActiveOperation(active, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive active operation message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected ActiveOperation");
+            };
+            rx.send(active).ok();
+            Ok(())
+        }
+        ClientToServerMessage::RunMaintenance(steps, tx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .run_maintenance(steps)
+                .await
+                .context("failed to send run maintenance message")?;
+
+            loop {
+                let message = reader
+                    .expect(|m| m.task_id() == Some(task_id))
+                    .await
+                    .context("failed to receive maintenance message")?;
+
+                match message {
+                    ServerToClientMessage::MaintenanceStepStarted(step, _) => {
+                        tx.send(MaintenanceUpdate::StepStarted(step)).ok();
+                    }
+                    ServerToClientMessage::MaintenanceFinished(report, _) => {
+                        tx.send(MaintenanceUpdate::Finished(report)).ok();
+                        break Ok(());
+                    }
+                    _ => {
+                        anyhow::bail!(
+                            "got unexpected s2c message, expected MaintenanceStepStarted or MaintenanceFinished"
+                        );
+                    }
+                }
+            }
+        }
+        ClientToServerMessage::BackupWorld(tx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .backup_world()
+                .await
+                .context("failed to send backup world message")?;
+
+            loop {
+                let message = reader
+                    .expect(|m| m.task_id() == Some(task_id))
+                    .await
+                    .context("failed to receive backup message")?;
+
+                match message {
+                    ServerToClientMessage::BackupProgress(progress, _) => {
+                        tx.send(BackupUpdate::Progress(progress)).ok();
+                    }
+                    ServerToClientMessage::WorldBackedUp(archive, _) => {
+                        tx.send(BackupUpdate::Finished(Ok(archive))).ok();
+                        break Ok(());
+                    }
+                    ServerToClientMessage::Error(error, _) => {
+                        tx.send(BackupUpdate::Finished(Err(error.into()))).ok();
+                        break Ok(());
+                    }
+                    _ => {
+                        anyhow::bail!(
+                            "got unexpected s2c message, expected BackupProgress, WorldBackedUp or Error"
+                        );
+                    }
+                }
+            }
+        }
+        ClientToServerMessage::SaveWorld(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .save_world()
+                .await
+                .context("failed to send save world message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive save result message")?;
+
+            match message {
+                ServerToClientMessage::SaveResult(result, _) => {
+                    rx.send(Ok(result)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected SaveResult or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::ReloadServer(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .reload_server()
+                .await
+                .context("failed to send reload server message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive reload result message")?;
+
+            match message {
+                ServerToClientMessage::ReloadResult(result, _) => {
+                    rx.send(Ok(result)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ReloadResult or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::UpdateJar(relative_path, back_up_previous, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .update_jar(relative_path, back_up_previous)
+                .await
+                .context("failed to send update jar message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config updated message")?;
+
+            match message {
+                ServerToClientMessage::ConfigUpdated(config, _) => {
+                    rx.send(Ok(config)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::ListArgumentPresets(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .list_argument_presets()
+                .await
+                .context("failed to send list argument presets message")?;
+            let ServerToClientMessage::ArgumentPresets(presets, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive argument presets message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected ArgumentPresets");
+            };
+            rx.send(presets).ok();
+            Ok(())
+        }
+        ClientToServerMessage::ApplyArgumentPreset(name, memory_mb, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .apply_argument_preset(name, memory_mb)
+                .await
+                .context("failed to send apply argument preset message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config updated message")?;
+
+            match message {
+                ServerToClientMessage::ConfigUpdated(config, _) => {
+                    rx.send(Ok(config)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetStartupLog(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_startup_log()
+                .await
+                .context("failed to send get startup log message")?;
+            let ServerToClientMessage::StartupLog(log, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive startup log message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected StartupLog");
+            };
+            rx.send(log).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetRecentOutput(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_recent_output()
+                .await
+                .context("failed to send get recent output message")?;
+            let ServerToClientMessage::RecentOutput(output, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive recent output message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected RecentOutput");
+            };
+            rx.send(output).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetLifecycleEvents(since, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_lifecycle_events(since)
+                .await
+                .context("failed to send get lifecycle events message")?;
+            let ServerToClientMessage::LifecycleEvents(events, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive lifecycle events message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected LifecycleEvents");
+            };
+            rx.send(events).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetOperationHistory(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_operation_history()
+                .await
+                .context("failed to send get operation history message")?;
+            let ServerToClientMessage::OperationHistory(history, _) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive operation history message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected OperationHistory");
+            };
+            rx.send(history).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetServerInfo(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_server_info()
+                .await
+                .context("failed to send get server info message")?;
+            let ServerToClientMessage::ServerInfo(info, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive server info message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected ServerInfo");
+            };
+            rx.send(info).ok();
+            Ok(())
+        }
+        ClientToServerMessage::ListInstances(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .list_instances()
+                .await
+                .context("failed to send list instances message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive instances message")?;
+
+            match message {
+                ServerToClientMessage::Instances(instances, _) => {
+                    rx.send(Ok(instances)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected Instances or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetConnectionInfo(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_connection_info()
+                .await
+                .context("failed to send get connection info message")?;
+            let ServerToClientMessage::ConnectionInfo(info, ..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive connection info message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected ConnectionInfo");
+            };
+            rx.send(info).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetClientCount(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_client_count()
+                .await
+                .context("failed to send get client count message")?;
+            let ServerToClientMessage::ClientCount(count, _) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive client count message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected ClientCount");
+            };
+            rx.send(count).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetDiagnostics(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_diagnostics()
+                .await
+                .context("failed to send get diagnostics message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive diagnostics message")?;
+
+            match message {
+                ServerToClientMessage::Diagnostics(diagnostics, _) => {
+                    rx.send(Ok(*diagnostics)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected Diagnostics or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetResolvedPaths(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_resolved_paths()
+                .await
+                .context("failed to send get resolved paths message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive resolved paths message")?;
+
+            match message {
+                ServerToClientMessage::ResolvedPaths(paths, _) => {
+                    rx.send(Ok(paths)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ResolvedPaths or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::RefreshJavaDetection(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .refresh_java_detection()
+                .await
+                .context("failed to send refresh java detection message")?;
+            let ServerToClientMessage::JavaDetected(java_path, _) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive java detected message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected JavaDetected");
+            };
+            rx.send(java_path).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetJavaVersion(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_java_version()
+                .await
+                .context("failed to send get java version message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive java version message")?;
+
+            match message {
+                ServerToClientMessage::JavaVersion(version, _) => {
+                    rx.send(Ok(version)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected JavaVersion or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetBackupRetention(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_backup_retention()
+                .await
+                .context("failed to send get backup retention message")?;
+            let ServerToClientMessage::BackupRetention(retention, _) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive backup retention message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected BackupRetention");
+            };
+            rx.send(retention).ok();
+            Ok(())
+        }
+        ClientToServerMessage::SetBackupRetention(retention, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .set_backup_retention(retention)
+                .await
+                .context("failed to send set backup retention message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config updated message")?;
+
+            match message {
+                ServerToClientMessage::ConfigUpdated(config, _) => {
+                    rx.send(Ok(config)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::PatchConfig(patch, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .patch_config(patch)
+                .await
+                .context("failed to send patch config message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config updated message")?;
+
+            match message {
+                ServerToClientMessage::ConfigUpdated(config, _) => {
+                    rx.send(Ok(config)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::TestUser(user, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .test_user(user)
+                .await
+                .context("failed to send test user message")?;
+            let ServerToClientMessage::UserTested(outcome, _) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive user tested message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected UserTested");
+            };
+            rx.send(outcome).ok();
+            Ok(())
+        }
+        ClientToServerMessage::GetResourceUsage(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_resource_usage()
+                .await
+                .context("failed to send get resource usage message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive resource usage message")?;
+
+            match message {
+                ServerToClientMessage::ResourceUsage(usage, _) => {
+                    rx.send(Ok(usage)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ResourceUsage or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::ValidateTls(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .validate_tls()
+                .await
+                .context("failed to send validate tls message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive tls validated message")?;
+
+            match message {
+                ServerToClientMessage::TlsValidated(report, _) => {
+                    rx.send(Ok(report)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected TlsValidated or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetMetricsText(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_metrics_text()
+                .await
+                .context("failed to send get metrics text message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive metrics text message")?;
+
+            match message {
+                ServerToClientMessage::MetricsText(text, _) => {
+                    rx.send(Ok(text)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected MetricsText or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::GetProtocolStats(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .get_protocol_stats()
+                .await
+                .context("failed to send get protocol stats message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive protocol stats message")?;
+
+            match message {
+                ServerToClientMessage::ProtocolStats(stats, _) => {
+                    rx.send(Ok(*stats)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ProtocolStats or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::DisconnectAllRemote(reason, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .disconnect_all_remote(reason)
+                .await
+                .context("failed to send disconnect all remote message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive remote clients disconnected message")?;
+
+            match message {
+                ServerToClientMessage::RemoteClientsDisconnected(count, _) => {
+                    rx.send(Ok(count)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!(
+                        "got unexpected s2c message, expected RemoteClientsDisconnected or Error"
+                    );
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::SubscribeMetrics(interval, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .subscribe_metrics(interval)
+                .await
+                .context("failed to send subscribe metrics message")?;
+            let ServerToClientMessage::MetricsSubscribed(interval, _) = reader
                 .expect(|m| m.task_id() == Some(task_id))
                 .await
-                .context("failed to receive pong message")?
+                .context("failed to receive metrics subscribed message")?
             else {
-                anyhow::bail!("got unexpected s2c message, expected Pong");
+                anyhow::bail!("got unexpected s2c message, expected MetricsSubscribed");
+            };
+            rx.send(interval).ok();
+            Ok(())
+        }
+        ClientToServerMessage::UnsubscribeMetrics(rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .unsubscribe_metrics()
+                .await
+                .context("failed to send unsubscribe metrics message")?;
+            let ServerToClientMessage::MetricsUnsubscribed(..) = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive metrics unsubscribed message")?
+            else {
+                anyhow::bail!("got unexpected s2c message, expected MetricsUnsubscribed");
             };
             rx.send(()).ok();
             Ok(())
         }
-        ClientToServerMessage::GetConfig(rx) => {
+        ClientToServerMessage::Identify(identity, rx) => {
             let task_id = writer
-                .get_config()
+                .lock()
                 .await
-                .context("failed to send get config message")?;
-            let ServerToClientMessage::CurrentConfig(config, ..) = reader
+                .identify(identity)
+                .await
+                .context("failed to send identify message")?;
+            let ServerToClientMessage::Identified(restored, ..) = reader
                 .expect(|m| m.task_id() == Some(task_id))
                 .await
-                .context("failed to receive current config message")?
+                .context("failed to receive identified message")?
             else {
-                anyhow::bail!("got unexpected s2c message, expected CurrentConfig");
+                anyhow::bail!("got unexpected s2c message, expected Identified");
             };
-            rx.send(config).ok();
+            rx.send(restored).ok();
             Ok(())
         }
-        ClientToServerMessage::UpdateConfig(config, rx) => {
+        ClientToServerMessage::SetEditingConfig(editing, rx) => {
             let task_id = writer
-                .update_config(config)
+                .lock()
                 .await
-                .context("failed to send update config message")?;
-            let ServerToClientMessage::ConfigUpdated(..) = reader
+                .set_editing_config(editing)
+                .await
+                .context("failed to send set editing config message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive config editors message")?;
+
+            match message {
+                ServerToClientMessage::ConfigEditors(editors, _) => {
+                    rx.send(Ok(editors)).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected ConfigEditors or Error");
+                }
+            }
+
+            Ok(())
+        }
+        ClientToServerMessage::SetOutputMode(mode, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .set_output_mode(mode)
+                .await
+                .context("failed to send set output mode message")?;
+            let ServerToClientMessage::OutputModeUpdated(..) = reader
                 .expect(|m| m.task_id() == Some(task_id))
                 .await
-                .context("failed to receive config updated message")?
+                .context("failed to receive output mode updated message")?
             else {
-                anyhow::bail!("got unexpected s2c message, expected ConfigUpdated");
+                anyhow::bail!("got unexpected s2c message, expected OutputModeUpdated");
             };
             rx.send(()).ok();
             Ok(())
         }
-        ClientToServerMessage::GetServerState(rx) => {
+        ClientToServerMessage::SetOutputDelivery(delivery, rx) => {
             let task_id = writer
-                .get_server_state()
+                .lock()
                 .await
-                .context("failed to send get server state message")?;
-            let ServerToClientMessage::CurrentServerState(state, ..) = reader
+                .set_output_delivery(delivery)
+                .await
+                .context("failed to send set output delivery message")?;
+            let ServerToClientMessage::OutputDeliveryUpdated(..) = reader
                 .expect(|m| m.task_id() == Some(task_id))
                 .await
-                .context("failed to receive current server state message")?
+                .context("failed to receive output delivery updated message")?
             else {
-                anyhow::bail!("got unexpected s2c message, expected CurrentServerState");
+                anyhow::bail!("got unexpected s2c message, expected OutputDeliveryUpdated");
             };
-            rx.send(state).ok();
+            rx.send(()).ok();
             Ok(())
         }
-        ClientToServerMessage::PerformOperation(operation, rx) => {
+        ClientToServerMessage::SetAcceptingClients(accepting, rx) => {
             let task_id = writer
-                .perform_operation(operation)
+                .lock()
                 .await
-                .context("failed to send perform operation message")?;
+                .set_accepting_clients(accepting)
+                .await
+                .context("failed to send set accepting clients message")?;
             let message = reader
                 .expect(|m| m.task_id() == Some(task_id))
                 .await
-                .context("failed to receive operation performed message")?;
+                .context("failed to receive accepting clients updated message")?;
 
             match message {
-                ServerToClientMessage::OperationPerformed(..) => {
+                ServerToClientMessage::AcceptingClientsUpdated(..) => {
                     rx.send(Ok(())).ok();
                 }
-                ServerToClientMessage::OperationFailed(_, _, error, _) => {
+                ServerToClientMessage::Error(error, _) => {
                     rx.send(Err(error.into())).ok();
                 }
                 _ => {
                     anyhow::bail!(
-                        "got unexpected s2c message, expected OperationPerformed or OperationFailed"
+                        "got unexpected s2c message, expected AcceptingClientsUpdated or Error"
                     );
                 }
             }
 
             Ok(())
         }
+        ClientToServerMessage::SetLogLevel(level, rx) => {
+            let task_id = writer
+                .lock()
+                .await
+                .set_log_level(level)
+                .await
+                .context("failed to send set log level message")?;
+            let message = reader
+                .expect(|m| m.task_id() == Some(task_id))
+                .await
+                .context("failed to receive log level updated message")?;
+
+            match message {
+                ServerToClientMessage::LogLevelUpdated(..) => {
+                    rx.send(Ok(())).ok();
+                }
+                ServerToClientMessage::Error(error, _) => {
+                    rx.send(Err(error.into())).ok();
+                }
+                _ => {
+                    anyhow::bail!("got unexpected s2c message, expected LogLevelUpdated or Error");
+                }
+            }
+
+            Ok(())
+        }
         ClientToServerMessage::Input(input) => writer
+            .lock()
+            .await
             .input(input)
             .await
             .context("failed to send input message"),
         ClientToServerMessage::Shutdown(tx) => {
-            if !writer.is_unix() {
+            if !writer.lock().await.is_unix() {
                 writer
+                    .lock()
+                    .await
                     .shutdown()
                     .await
                     .context("failed to send shutdown message")?;
@@ -254,35 +1951,401 @@ async fn client_writer_task_handle_message(
     }
 }
 
+/// why the writer task of a connection stopped
+enum WriterExit {
+    /// the managed writer was dropped, so the client isn't used anymore
+    Dropped,
+
+    /// the connection failed
+    Lost,
+}
+
 async fn client_writer_task(
-    mut writer: crate::ClientWriter,
+    writer: crate::ClientWriter,
     mut reader: ClientReader,
-    mut c2s_rx: UnboundedReceiver<ClientToServerMessage>,
+    c2s_rx: &mut UnboundedReceiver<ClientToServerMessage>,
+    keepalive: Option<KeepAlive>,
     cancel_token: CancellationToken,
-) -> anyhow::Result<()> {
+) -> WriterExit {
     tracing::debug!("begin client writer task");
 
-    loop {
+    let writer = Arc::new(SharedWriter::new(writer));
+    let keepalive = keepalive.map(|keepalive| {
+        tokio::spawn(keepalive_task(
+            Arc::clone(&writer),
+            reader.clone(),
+            keepalive,
+            cancel_token.clone(),
+        ))
+    });
+
+    let exit = loop {
         tokio::select! {
             result = c2s_rx.recv() => match result {
                 Some(message) => {
-                    if let Err(error) =
-                        client_writer_task_handle_message(message, &mut writer, &mut reader).await
-                    {
+                    // the response never arrives once the connection failed, so waiting for it is
+                    // given up along with the connection
+                    let handled =
+                        client_writer_task_handle_message(message, &writer, &mut reader);
+                    let result = tokio::select! {
+                        result = handled => result,
+                        () = cancel_token.cancelled() => break WriterExit::Lost,
+                    };
+                    if let Err(error) = result {
                         tracing::error!(?error, "failed to send message to server: {error:#}");
                         cancel_token.cancel();
                     }
                 }
-                None => cancel_token.cancel(),
+                None => {
+                    cancel_token.cancel();
+                    break WriterExit::Dropped;
+                }
             },
-            () = cancel_token.cancelled() => break Ok(()),
+            () = cancel_token.cancelled() => break WriterExit::Lost,
+        }
+    };
+
+    // the keepalive stops as soon as the connection is cancelled, which leaves the writer to this
+    // task alone
+    if let Some(keepalive) = keepalive {
+        keepalive.await.ok();
+    }
+    let writer = Arc::into_inner(writer).expect("the writer is still shared after the keepalive");
+    if let Err(error) = writer.into_inner().close().await {
+        tracing::debug!(?error, "failed to close the connection cleanly: {error}");
+    }
+
+    exit
+}
+
+/// how the managed client checks that its connection is still alive, so that a half-open connection
+/// is noticed. see [`manage_with_keepalive`].
+#[derive(Copy, Clone, Debug)]
+pub struct KeepAlive {
+    /// how long to wait between two pings
+    pub interval: Duration,
+
+    /// how long a ping may take before the connection is considered dead. pings are sent in
+    /// between the requests which are waiting for their response, so a slow request such as a
+    /// maintenance cycle doesn't hold them up.
+    pub timeout: Duration,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// pings the server directly rather than through the queue of requests, so that the ping isn't
+/// held up by the requests before it.
+async fn keepalive_ping(writer: &SharedWriter, mut reader: ClientReader) -> anyhow::Result<()> {
+    let task_id = writer
+        .lock()
+        .await
+        .ping()
+        .await
+        .context("failed to send ping message")?;
+    reader
+        .expect(|m| m.task_id() == Some(task_id))
+        .await
+        .context("failed to receive pong message")?;
+    Ok(())
+}
+
+/// pings the server periodically, cancelling the connection if a ping times out. stops once the
+/// connection is cancelled.
+async fn keepalive_task(
+    writer: Arc<SharedWriter>,
+    reader: ClientReader,
+    keepalive: KeepAlive,
+    cancel_token: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(keepalive.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            () = cancel_token.cancelled() => return,
+        }
+
+        let ping = tokio::time::timeout(keepalive.timeout, keepalive_ping(&writer, reader.clone()));
+        let result = tokio::select! {
+            result = ping => result,
+            () = cancel_token.cancelled() => return,
+        };
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                tracing::debug!(?error, "the keepalive ping failed: {error:#}");
+                return;
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "the server didn't answer a ping within {:?}, dropping the connection",
+                    keepalive.timeout
+                );
+                cancel_token.cancel();
+                return;
+            }
+        }
+    }
+}
+
+/// what the managed client asks of a remote server. it inflates compressed output before handing
+/// it on, so compressing it only saves bandwidth.
+const REMOTE_HANDSHAKE_OPTIONS: HandshakeOptions = HandshakeOptions {
+    compress_output: true,
+};
+
+/// where the managed client connects to again when its connection drops
+#[derive(Clone, Debug)]
+pub enum ReconnectTarget {
+    Tcp(Vec<SocketAddr>),
+
+    /// a host name and port, which is resolved again on every attempt. see
+    /// [`crate::from_tcp_host`].
+    TcpHost(String, u16),
+
+    #[cfg(feature = "tls")]
+    Tls(
+        Vec<SocketAddr>,
+        crate::rustls::pki_types::ServerName<'static>,
+        Arc<crate::rustls::ClientConfig>,
+    ),
+
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl ReconnectTarget {
+    async fn connect(&self) -> io::Result<(crate::ClientReader, crate::ClientWriter)> {
+        match self {
+            Self::Tcp(addrs) => crate::from_tcp(addrs.as_slice(), REMOTE_HANDSHAKE_OPTIONS).await,
+            Self::TcpHost(host, port) => {
+                crate::from_tcp_host(host, *port, REMOTE_HANDSHAKE_OPTIONS).await
+            }
+
+            #[cfg(feature = "tls")]
+            Self::Tls(addrs, server_name, config) => {
+                crate::from_tcp_tls(
+                    addrs.as_slice(),
+                    server_name.clone(),
+                    Arc::clone(config),
+                    REMOTE_HANDSHAKE_OPTIONS,
+                )
+                .await
+            }
+
+            #[cfg(unix)]
+            Self::Unix(path) => crate::from_unix(path, HandshakeOptions::default()).await,
+        }
+    }
+}
+
+/// how the managed client reconnects when its connection drops. see [`manage_with_reconnect`].
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub target: ReconnectTarget,
+
+    /// how many times connecting is attempted before giving up, or `None` to never give up
+    pub max_attempts: Option<u32>,
+
+    /// how long to wait before the first attempt, which doubles after every failed attempt
+    pub initial_backoff: Duration,
+
+    /// the longest wait between two attempts
+    pub max_backoff: Duration,
+
+    /// how every connection is checked to still be alive, so that a half-open connection is
+    /// reconnected too
+    pub keepalive: Option<KeepAlive>,
+
+    /// the identity every connection is tied to as soon as it's established, so that the server
+    /// restores the subscriptions of the previous connection if it reconnected quickly enough
+    pub identity: Option<ClientIdentity>,
+
+    /// how long the connection has to stay lost or back before that's announced as a
+    /// [`ConnectionEvent`], so that a flapping connection doesn't make a ui flicker. zero announces
+    /// every change right away.
+    pub settle: Duration,
+}
+
+impl ReconnectPolicy {
+    /// a policy which never gives up, waiting between half a second and half a minute between
+    /// attempts.
+    pub fn new(target: ReconnectTarget) -> Self {
+        Self {
+            target,
+            max_attempts: None,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            keepalive: None,
+            identity: None,
+            settle: Duration::from_secs(1),
+        }
+    }
+}
+
+/// what happened to the connection of a client from [`manage_with_reconnect`], announced once it
+/// settled. see [`ReconnectPolicy::settle`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// the connection dropped and didn't come back in time, and reconnecting goes on. requests which
+    /// were in flight failed.
+    Lost,
+
+    /// a new connection was established and held, which may follow a `Lost` or stand for an
+    /// outage too brief to announce. broadcasts sent while disconnected were missed, so state such
+    /// as the server's state should be fetched again.
+    Reconnected,
+
+    /// reconnecting was given up, and the client is closed
+    GaveUp,
+}
+
+/// tries to connect again until it succeeds, the attempts of the policy run out, or the managed
+/// writer is dropped.
+async fn reconnect(
+    policy: &ReconnectPolicy,
+    c2s_rx: &UnboundedReceiver<ClientToServerMessage>,
+) -> Option<(crate::ClientReader, crate::ClientWriter)> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempts = 0;
+
+    while policy.max_attempts.is_none_or(|max| attempts < max) {
+        tokio::time::sleep(backoff).await;
+        if c2s_rx.is_closed() {
+            return None;
+        }
+
+        attempts += 1;
+        match policy.target.connect().await {
+            Ok(connection) => return Some(connection),
+            Err(error) => {
+                tracing::warn!(?error, attempts, "failed to reconnect to the server: {error}")
+            }
+        }
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+
+    tracing::error!("gave up reconnecting to the server after {attempts} attempts");
+    None
+}
+
+async fn reconnecting_task(
+    mut connection: (crate::ClientReader, crate::ClientWriter),
+    s2c_tx: broadcast::Sender<ServerToClientMessage>,
+    mut c2s_rx: UnboundedReceiver<ClientToServerMessage>,
+    policy: ReconnectPolicy,
+    events_tx: UnboundedSender<ConnectionEvent>,
+) {
+    loop {
+        let (reader, mut writer) = connection;
+
+        // the answer is only broadcast, since nothing is waiting for it
+        if let Some(identity) = policy.identity {
+            let result = writer.identify(identity).await;
+            if let Err(error) = result {
+                tracing::warn!(?error, "failed to identify the connection: {error}");
+            }
+        }
+
+        let cancel_token = CancellationToken::new();
+        tokio::spawn(client_reader_task(reader, s2c_tx.clone(), cancel_token.clone()));
+
+        let reader = ClientReader(s2c_tx.subscribe());
+        let exit =
+            client_writer_task(writer, reader, &mut c2s_rx, policy.keepalive, cancel_token).await;
+        if let WriterExit::Dropped = exit {
+            return;
         }
+
+        tracing::warn!("lost the connection to the server, reconnecting");
+        events_tx.send(ConnectionEvent::Lost).ok();
+        let Some(new_connection) = reconnect(&policy, &c2s_rx).await else {
+            events_tx.send(ConnectionEvent::GaveUp).ok();
+            return;
+        };
+
+        tracing::info!("reconnected to the server");
+        events_tx.send(ConnectionEvent::Reconnected).ok();
+        connection = new_connection;
+    }
+}
+
+/// passes the events of [`reconnecting_task`] on once they settled, so that a connection which
+/// drops and comes back quickly, possibly several times over, is announced once rather than with
+/// every change
+async fn settle_events(
+    mut raw_rx: UnboundedReceiver<ConnectionEvent>,
+    events_tx: broadcast::Sender<ConnectionEvent>,
+    settle: Duration,
+) {
+    // the event to announce unless another comes first, and when
+    let mut pending: Option<(ConnectionEvent, tokio::time::Instant)> = None;
+
+    // whether the connection was announced as lost since it was last announced as back
+    let mut announced_lost = false;
+
+    loop {
+        let deadline = pending.as_ref().map(|(_, at)| *at);
+        let sleep = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now));
+        let event = tokio::select! {
+            event = raw_rx.recv() => match event {
+                // losing the connection again cancels the announcement of it coming back
+                Some(ConnectionEvent::Lost) if announced_lost => {
+                    pending = None;
+                    continue;
+                }
+                Some(event @ (ConnectionEvent::Lost | ConnectionEvent::Reconnected))
+                    if !settle.is_zero() =>
+                {
+                    pending = Some((event, tokio::time::Instant::now() + settle));
+                    continue;
+                }
+                Some(ConnectionEvent::GaveUp) => {
+                    events_tx.send(ConnectionEvent::GaveUp).ok();
+                    return;
+                }
+                Some(event) => event,
+                None => return,
+            },
+            () = sleep, if deadline.is_some() => pending.take().unwrap().0,
+        };
+
+        announced_lost = event == ConnectionEvent::Lost;
+        events_tx.send(event).ok();
     }
 }
 
 pub async fn manage(
     reader: crate::ClientReader,
     writer: crate::ClientWriter,
+) -> (ClientReader, ClientWriter) {
+    manage_inner(reader, writer, None)
+}
+
+/// like [`manage`], but the server is pinged in the background according to `keepalive`. if a
+/// ping times out the connection is dropped, which the returned reader notices through `None`.
+pub async fn manage_with_keepalive(
+    reader: crate::ClientReader,
+    writer: crate::ClientWriter,
+    keepalive: KeepAlive,
+) -> (ClientReader, ClientWriter) {
+    manage_inner(reader, writer, Some(keepalive))
+}
+
+fn manage_inner(
+    reader: crate::ClientReader,
+    writer: crate::ClientWriter,
+    keepalive: Option<KeepAlive>,
 ) -> (ClientReader, ClientWriter) {
     // note: this check is not enough; what if they are both the same type but come from
     // different sources?
@@ -297,22 +2360,315 @@ pub async fn manage(
 
     let client_reader = ClientReader(s2c_rx);
 
-    let (c2s_tx, c2s_rx) = mpsc::unbounded_channel();
+    let (c2s_tx, mut c2s_rx) = mpsc::unbounded_channel();
     tokio::spawn({
         let reader = client_reader.clone();
-        client_writer_task(writer, reader, c2s_rx, cancel_token)
+        async move { client_writer_task(writer, reader, &mut c2s_rx, keepalive, cancel_token).await }
     });
 
     (client_reader.clone(), ClientWriter(c2s_tx))
 }
 
+/// like [`manage`], but a new connection is established according to `policy` whenever the
+/// connection drops, so that the returned reader and writer keep working across brief outages. the
+/// returned receiver tells when the connection drops and comes back.
+pub async fn manage_with_reconnect(
+    reader: crate::ClientReader,
+    writer: crate::ClientWriter,
+    policy: ReconnectPolicy,
+) -> (ClientReader, ClientWriter, broadcast::Receiver<ConnectionEvent>) {
+    if (reader.is_unix() && writer.is_tcp()) || (reader.is_tcp() && writer.is_unix()) {
+        panic!("mismatched reader and writer");
+    }
+
+    let (s2c_tx, s2c_rx) = broadcast::channel(2048);
+    let (c2s_tx, c2s_rx) = mpsc::unbounded_channel();
+    let (raw_events_tx, raw_events_rx) = mpsc::unbounded_channel();
+    let (events_tx, events_rx) = broadcast::channel(16);
+    tokio::spawn(settle_events(raw_events_rx, events_tx, policy.settle));
+    tokio::spawn(reconnecting_task(
+        (reader, writer),
+        s2c_tx,
+        c2s_rx,
+        policy,
+        raw_events_tx,
+    ));
+
+    (ClientReader(s2c_rx), ClientWriter(c2s_tx), events_rx)
+}
+
 pub async fn from_tcp(addrs: impl ToSocketAddrs) -> io::Result<(ClientReader, ClientWriter)> {
-    let (reader, writer) = crate::from_tcp(addrs).await?;
+    let (reader, writer) = crate::from_tcp(addrs, REMOTE_HANDSHAKE_OPTIONS).await?;
+    Ok(manage(reader, writer).await)
+}
+
+pub async fn from_tcp_host(host: &str, port: u16) -> io::Result<(ClientReader, ClientWriter)> {
+    let (reader, writer) = crate::from_tcp_host(host, port, REMOTE_HANDSHAKE_OPTIONS).await?;
+    Ok(manage(reader, writer).await)
+}
+
+#[cfg(feature = "tls")]
+pub async fn from_tcp_tls(
+    addrs: impl ToSocketAddrs,
+    server_name: crate::rustls::pki_types::ServerName<'static>,
+    config: std::sync::Arc<crate::rustls::ClientConfig>,
+) -> io::Result<(ClientReader, ClientWriter)> {
+    let (reader, writer) =
+        crate::from_tcp_tls(addrs, server_name, config, REMOTE_HANDSHAKE_OPTIONS).await?;
     Ok(manage(reader, writer).await)
 }
 
 #[cfg(unix)]
 pub async fn from_unix(addr: impl AsRef<Path>) -> io::Result<(ClientReader, ClientWriter)> {
-    let (reader, writer) = crate::from_unix(addr).await?;
+    let (reader, writer) = crate::from_unix(addr, HandshakeOptions::default()).await?;
     Ok(manage(reader, writer).await)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raphy_protocol::TaskId;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn read_c2s(stream: &mut TcpStream) -> raphy_protocol::ClientToServerMessage {
+        let len = stream.read_u32_le().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+        bincode::decode_from_slice(&buf, bincode::config::standard())
+            .unwrap()
+            .0
+    }
+
+    async fn write_s2c(stream: &mut TcpStream, message: ServerToClientMessage) {
+        let buf = bincode::encode_to_vec(message, bincode::config::standard()).unwrap();
+        stream.write_u32_le(buf.len() as u32).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_server_state_answers_with_the_current_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_c2s(&mut stream).await;
+            write_s2c(&mut stream, ServerToClientMessage::HandshakeAccepted).await;
+
+            let raphy_protocol::ClientToServerMessage::GetServerState(task_id) =
+                read_c2s(&mut stream).await
+            else {
+                panic!("expected GetServerState");
+            };
+
+            // an unrelated answer first, which must not be taken for this one
+            write_s2c(
+                &mut stream,
+                ServerToClientMessage::CurrentServerState(
+                    ServerState::Stopped(None, None),
+                    TaskId::generate(),
+                ),
+            )
+            .await;
+            write_s2c(
+                &mut stream,
+                ServerToClientMessage::CurrentServerState(ServerState::Started, task_id),
+            )
+            .await;
+            stream
+        });
+
+        let (_reader, writer) = from_tcp(addr).await.unwrap();
+        let state = writer.get_server_state().await.unwrap();
+        assert!(matches!(state, ServerState::Started));
+        server.await.unwrap();
+    }
+
+    /// the patch a setter sends to the connection task, which is answered with the default config
+    async fn sent_patch(
+        set: impl AsyncFnOnce(&ClientWriter) -> anyhow::Result<Config>,
+    ) -> ConfigPatch {
+        let (c2s_tx, mut c2s_rx) = mpsc::unbounded_channel();
+        let writer = ClientWriter(c2s_tx);
+        let respond = async {
+            let Some(ClientToServerMessage::PatchConfig(patch, ret)) = c2s_rx.recv().await else {
+                panic!("the setter didn't patch the config");
+            };
+            ret.send(Ok(Config::default())).ok();
+            patch
+        };
+
+        let (result, patch) = tokio::join!(set(&writer), respond);
+        result.unwrap();
+        patch
+    }
+
+    #[tokio::test]
+    async fn set_java_path_patches_only_the_java_path() {
+        let patch = sent_patch(async |writer| {
+            writer
+                .set_java_path(JavaPath::Custom(PathBuf::from("/usr/bin/java")))
+                .await
+        })
+        .await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::JavaPath(JavaPath::Custom(path)) if path == Path::new("/usr/bin/java")
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_server_jar_path_patches_only_the_server_jar_path() {
+        let path = PathBuf::from("paper.jar");
+        let patch = sent_patch(async |writer| writer.set_server_jar_path(path).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::ServerJarPath(path) if path == Path::new("paper.jar")
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_java_arguments_patches_only_the_java_arguments() {
+        let arguments = Arguments::Parsed("-Xms1G".to_owned());
+        let patch = sent_patch(async |writer| writer.set_java_arguments(arguments).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::JavaArguments(Arguments::Parsed(arguments)) if arguments == "-Xms1G"
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_server_arguments_patches_only_the_server_arguments() {
+        let arguments = Arguments::Manual(vec!["nogui".to_owned()]);
+        let patch = sent_patch(async |writer| writer.set_server_arguments(arguments).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::ServerArguments(Arguments::Manual(arguments)) if arguments == ["nogui"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_user_patches_only_the_user() {
+        let user = User::Specific("minecraft".to_owned());
+        let patch = sent_patch(async |writer| writer.set_user(user).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::User(User::Specific(user)) if user == "minecraft"
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_memory_limit_mb_patches_only_the_memory_limit() {
+        let patch = sent_patch(async |writer| writer.set_memory_limit_mb(Some(4096)).await).await;
+        assert!(matches!(patch, ConfigPatch::MemoryLimitMb(Some(4096))));
+    }
+
+    #[tokio::test]
+    async fn set_env_patches_only_the_env() {
+        let env = BTreeMap::from([("TZ".to_owned(), "UTC".to_owned())]);
+        let patch = sent_patch(async |writer| writer.set_env(env).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::Env(env) if env.get("TZ").map(String::as_str) == Some("UTC")
+        ));
+    }
+
+    #[tokio::test]
+    async fn set_working_dir_patches_only_the_working_dir() {
+        let patch = sent_patch(async |writer| writer.set_working_dir(None).await).await;
+        assert!(matches!(patch, ConfigPatch::WorkingDir(None)));
+    }
+
+    #[tokio::test]
+    async fn set_output_timestamp_patches_only_the_output_timestamp() {
+        let format = Some("%H:%M:%S".to_owned());
+        let patch = sent_patch(async |writer| writer.set_output_timestamp(format).await).await;
+        assert!(matches!(
+            patch,
+            ConfigPatch::OutputTimestamp(Some(format)) if format == "%H:%M:%S"
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_refused_patch_is_reported() {
+        let (c2s_tx, mut c2s_rx) = mpsc::unbounded_channel();
+        let writer = ClientWriter(c2s_tx);
+        let respond = async {
+            let Some(ClientToServerMessage::PatchConfig(_, ret)) = c2s_rx.recv().await else {
+                panic!("the setter didn't patch the config");
+            };
+            ret.send(Err(anyhow::anyhow!("The memory limit can't be zero.")))
+                .ok();
+        };
+
+        let (result, ()) = tokio::join!(writer.set_memory_limit_mb(Some(0)), respond);
+        assert!(result.is_err());
+    }
+
+    /// the events announced for `raw`, each of which is followed by a pause of the given
+    /// milliseconds. changes settle after 50 milliseconds.
+    async fn settled(raw: &[(ConnectionEvent, u64)]) -> Vec<ConnectionEvent> {
+        let (raw_tx, raw_rx) = mpsc::unbounded_channel();
+        let (events_tx, mut events_rx) = broadcast::channel(16);
+        tokio::spawn(settle_events(raw_rx, events_tx, Duration::from_millis(50)));
+
+        for (event, pause) in raw {
+            raw_tx.send(event.clone()).unwrap();
+            tokio::time::sleep(Duration::from_millis(*pause)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(raw_tx);
+
+        let mut events = Vec::new();
+        while let Ok(event) = events_rx.recv().await {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn a_flapping_connection_is_announced_once_it_settles() {
+        let events = settled(&[
+            (ConnectionEvent::Lost, 10),
+            (ConnectionEvent::Reconnected, 10),
+            (ConnectionEvent::Lost, 10),
+            (ConnectionEvent::Reconnected, 10),
+        ])
+        .await;
+        assert_eq!(events, [ConnectionEvent::Reconnected]);
+    }
+
+    #[tokio::test]
+    async fn a_lasting_outage_is_announced() {
+        let events = settled(&[
+            (ConnectionEvent::Lost, 100),
+            (ConnectionEvent::Reconnected, 100),
+        ])
+        .await;
+        assert_eq!(
+            events,
+            [ConnectionEvent::Lost, ConnectionEvent::Reconnected]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_brief_reconnect_during_an_outage_is_not_announced() {
+        let events = settled(&[
+            (ConnectionEvent::Lost, 100),
+            (ConnectionEvent::Reconnected, 10),
+            (ConnectionEvent::Lost, 10),
+            (ConnectionEvent::Reconnected, 100),
+        ])
+        .await;
+        assert_eq!(
+            events,
+            [ConnectionEvent::Lost, ConnectionEvent::Reconnected]
+        );
+    }
+
+    #[tokio::test]
+    async fn giving_up_is_announced_right_away() {
+        let events = settled(&[(ConnectionEvent::Lost, 0), (ConnectionEvent::GaveUp, 0)]).await;
+        assert_eq!(events, [ConnectionEvent::GaveUp]);
+    }
+}
@@ -1,19 +1,59 @@
 #[cfg(feature = "init_logging")]
 mod init_logging {
-    use std::env;
-    use tracing_subscriber::{EnvFilter, Layer};
+    use std::collections::VecDeque;
+    use std::sync::{Mutex, OnceLock, PoisonError};
+    use std::{env, io};
+    use anyhow::Context;
+    use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
     use tracing_subscriber::filter::LevelFilter;
     use tracing_subscriber::layer::SubscriberExt;
     use tracing_subscriber::util::SubscriberInitExt;
 
+    /// swaps the filter of the log output while the program is running
+    static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+    /// how many lines of log output are kept for [`recent_logs`]
+    const MAX_RECENT_LOGS: usize = 200;
+
+    /// the most recent lines of log output, oldest first
+    static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+    /// keeps what's written to it in [`RECENT_LOGS`]
+    struct RecentLogsWriter;
+
+    impl io::Write for RecentLogsWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut logs = RECENT_LOGS.lock().unwrap_or_else(PoisonError::into_inner);
+            for line in String::from_utf8_lossy(buf).lines() {
+                if logs.len() == MAX_RECENT_LOGS {
+                    logs.pop_front();
+                }
+                logs.push_back(line.to_owned());
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     pub fn init_logging(tokio_console_var: &str) {
-        let registry = tracing_subscriber::registry().with(
-            tracing_subscriber::fmt::layer().with_filter(
-                EnvFilter::builder()
-                    .with_default_directive(LevelFilter::INFO.into())
-                    .from_env_lossy(),
-            ),
-        );
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy();
+        let (filter, handle) = reload::Layer::new(filter);
+        LOG_FILTER.set(handle).ok();
+
+        // the recent logs are for diagnostics, so they're kept regardless of the log level
+        let recent_logs = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(|| RecentLogsWriter)
+            .with_filter(LevelFilter::INFO);
+        let registry = tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_filter(filter))
+            .with(recent_logs);
 
         if env::var(tokio_console_var) == Ok("1".to_owned()) {
             registry.with(console_subscriber::spawn()).init();
@@ -22,71 +62,288 @@ mod init_logging {
             registry.init();
         }
     }
+
+    /// the most recent lines of log output at the info level or above, oldest first
+    pub fn recent_logs() -> Vec<String> {
+        let logs = RECENT_LOGS.lock().unwrap_or_else(PoisonError::into_inner);
+        logs.iter().cloned().collect()
+    }
+
+    /// the directives the log output is currently filtered with, if logging was initialized
+    pub fn log_level() -> Option<String> {
+        LOG_FILTER
+            .get()?
+            .with_current(|filter| filter.to_string())
+            .ok()
+    }
+
+    /// replaces the filter of the log output with the given directives, which use the same syntax
+    /// as `RUST_LOG`, e.g. `debug` or `raphy_server=trace,info`.
+    pub fn set_log_level(directives: &str) -> anyhow::Result<()> {
+        let handle = LOG_FILTER.get().context("Logging has not been initialized.")?;
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .parse(directives)
+            .context("The log level is invalid.")?;
+        handle
+            .reload(filter)
+            .context("Failed to change the log filter.")
+    }
 }
 
 #[cfg(feature = "config")]
 mod config {
-    use std::env;
-    use std::path::PathBuf;
+    use std::{env, io};
+    use std::path::{Path, PathBuf};
     use anyhow::Context;
     use directories::ProjectDirs;
     use serde::Serialize;
     use fs_err::tokio as fs;
     use serde::de::DeserializeOwned;
 
+    async fn write_config(path: &Path, contents: &str) -> io::Result<()> {
+        if let Some(path) = path.parent() {
+            fs::create_dir_all(path).await?;
+        }
+
+        fs::write(path, contents).await
+    }
+
+    /// renames a damaged config file, so that whatever is left of it isn't overwritten once the
+    /// config is written again
+    async fn set_aside_damaged_config(path: &Path) {
+        let mut damaged_path = path.as_os_str().to_owned();
+        damaged_path.push(".damaged");
+
+        match fs::rename(path, &damaged_path).await {
+            Ok(()) => tracing::info!(?damaged_path, "moved the damaged config file aside"),
+            Err(error) => tracing::warn!("failed to move the damaged config file aside: {error}"),
+        }
+    }
+
+    /// the per-user directory for files which only matter while raphy runs, e.g. the pid file of
+    /// the server. it's the runtime directory if there is one, otherwise the config directory.
+    pub fn runtime_dir() -> Option<PathBuf> {
+        let pd = ProjectDirs::from("", "ALinuxPerson", "raphy")?;
+        Some(pd.runtime_dir().unwrap_or(pd.config_dir()).to_path_buf())
+    }
+
+    fn is_unwritable(error: &io::Error) -> bool {
+        matches!(
+            error.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+        )
+    }
+
     #[allow(async_fn_in_trait)]
     pub trait ConfigLike: Serialize + DeserializeOwned {
         const ENV_VAR: &'static str;
         const CONFIG_PATH_NAME: &'static str;
         
-        fn path() -> anyhow::Result<PathBuf> {
-            match env::var_os(Self::ENV_VAR) {
-                Some(path) => Ok(PathBuf::from(path)),
-                None => match ProjectDirs::from("", "ALinuxPerson", "raphy") {
-                    Some(pd) => Ok(pd.config_dir().join(Self::CONFIG_PATH_NAME)),
-                    None => Ok(env::current_dir()
-                        .context("Failed to get the current directory.")?
-                        .join(Self::CONFIG_PATH_NAME)),
-                },
+        fn path() -> PathBuf {
+            if let Some(path) = env::var_os(Self::ENV_VAR) {
+                return PathBuf::from(path);
+            }
+
+            if let Some(pd) = ProjectDirs::from("", "ALinuxPerson", "raphy") {
+                return pd.config_dir().join(Self::CONFIG_PATH_NAME);
+            }
+
+            match env::current_dir() {
+                Ok(current_dir) => current_dir.join(Self::CONFIG_PATH_NAME),
+                // the directory a long-running daemon was launched from may have been removed since
+                Err(error) => {
+                    let path = Self::fallback_path();
+                    tracing::warn!(
+                        ?path,
+                        "failed to get the current directory, using the fallback config path instead: {error}"
+                    );
+                    path
+                }
+            }
+        }
+
+        /// where the config is written to when [`Self::path`] is not writable, e.g. for read-only
+        /// installs.
+        fn fallback_path() -> PathBuf {
+            ProjectDirs::from("", "ALinuxPerson", "raphy")
+                .and_then(|pd| pd.runtime_dir().map(Path::to_path_buf))
+                .unwrap_or_else(|| env::temp_dir().join("raphy"))
+                .join(Self::CONFIG_PATH_NAME)
+        }
+
+        /// the path the config is currently loaded from; this is the fallback path if it was
+        /// written to more recently than the primary path.
+        fn effective_path() -> PathBuf {
+            let path = Self::path();
+            let fallback_path = Self::fallback_path();
+            let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+
+            match (modified(&path), modified(&fallback_path)) {
+                (None, Some(_)) => fallback_path,
+                (Some(primary), Some(fallback)) if fallback > primary => fallback_path,
+                _ => path,
             }
         }
 
         async fn load() -> anyhow::Result<Option<Self>> {
-            let path = Self::path().context("Failed to get the config path.")?;
+            let path = Self::effective_path();
 
             if !path.exists() {
                 return Ok(None);
             }
 
-            let contents = fs::read_to_string(path)
+            tracing::debug!(?path, "load config");
+            let contents = fs::read_to_string(&path)
                 .await
                 .context("Failed to read the config file.")?;
-            Ok(Some(
-                serde_json::from_str(&contents).context("Failed to parse the config file.")?,
-            ))
+            match serde_json::from_str(&contents) {
+                Ok(config) => Ok(Some(config)),
+
+                // what an interrupted write leaves behind, which shouldn't keep the program from
+                // starting. a file which is complete but invalid is still an error, since it was
+                // most likely edited by hand.
+                Err(error) if contents.trim().is_empty() || error.is_eof() => {
+                    tracing::warn!(
+                        ?path,
+                        "the config file is empty or truncated, ignoring it: {error}"
+                    );
+                    set_aside_damaged_config(&path).await;
+                    Ok(None)
+                }
+                Err(error) => Err(error).context("Failed to parse the config file."),
+            }
         }
 
-        async fn dump(&self) -> anyhow::Result<()> {
-            let path = Self::path().context("Failed to get the config path.")?;
+        /// writes the config, returning the path it was written to.
+        async fn dump(&self) -> anyhow::Result<PathBuf> {
+            let path = Self::path();
+            let contents = serde_json::to_string(self).context("Failed to serialize the config.")?;
+
+            match write_config(&path, &contents).await {
+                Ok(()) => Ok(path),
+                Err(error) if is_unwritable(&error) => {
+                    let fallback_path = Self::fallback_path();
+                    tracing::warn!(
+                        ?path,
+                        ?fallback_path,
+                        "the config path is not writable, using the fallback path instead: {error}"
+                    );
 
-            if let Some(path) = path.parent() {
-                if let Err(error) = fs::create_dir_all(path).await {
-                    tracing::error!("failed to create the config directory: {error}");
+                    write_config(&fallback_path, &contents)
+                        .await
+                        .context("Failed to write the config file to the fallback path.")?;
+                    Ok(fallback_path)
                 }
+                Err(error) => Err(error).context("Failed to write the config file."),
             }
+        }
+    }
 
-            let contents = serde_json::to_string(self).context("Failed to serialize the config.")?;
-            fs::write(path, contents)
-                .await
-                .context("Failed to write the config file.")?;
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde::Deserialize;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
 
-            Ok(())
+        /// a directory for the configs of the test called `name`
+        fn test_dir(name: &str) -> PathBuf {
+            env::temp_dir().join(format!("raphy-test-{name}-{}", std::process::id()))
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct ReadOnlyConfig {
+            value: u32,
+        }
+
+        impl ConfigLike for ReadOnlyConfig {
+            const ENV_VAR: &'static str = "RAPHY_TEST_READ_ONLY_CONFIG_PATH";
+            const CONFIG_PATH_NAME: &'static str = "config.json";
+
+            fn path() -> PathBuf {
+                test_dir("read-only-config")
+                    .join("primary")
+                    .join(Self::CONFIG_PATH_NAME)
+            }
+
+            fn fallback_path() -> PathBuf {
+                test_dir("read-only-config")
+                    .join("fallback")
+                    .join(Self::CONFIG_PATH_NAME)
+            }
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct DamagedConfig {
+            value: u32,
+        }
+
+        impl ConfigLike for DamagedConfig {
+            const ENV_VAR: &'static str = "RAPHY_TEST_DAMAGED_CONFIG_PATH";
+            const CONFIG_PATH_NAME: &'static str = "config.json";
+
+            fn path() -> PathBuf {
+                test_dir("damaged-config").join(Self::CONFIG_PATH_NAME)
+            }
+
+            fn fallback_path() -> PathBuf {
+                test_dir("damaged-config").join("fallback.json")
+            }
+        }
+
+        #[tokio::test]
+        async fn an_empty_or_truncated_config_file_is_set_aside() {
+            let dir = test_dir("damaged-config");
+            fs::remove_dir_all(&dir).ok();
+            fs::create_dir_all(&dir).unwrap();
+            let path = DamagedConfig::path();
+            let damaged_path = dir.join("config.json.damaged");
+
+            for contents in ["", "  \n", r#"{"value": 1"#] {
+                fs::write(&path, contents).unwrap();
+                let loaded = DamagedConfig::load().await;
+
+                assert!(matches!(loaded, Ok(None)), "{contents:?} wasn't ignored");
+                assert!(!path.exists());
+                assert_eq!(fs::read_to_string(&damaged_path).unwrap(), contents);
+            }
+
+            // a complete file was most likely edited by hand, so it isn't ignored
+            fs::write(&path, r#"{"value": "one"}"#).unwrap();
+            let loaded = DamagedConfig::load().await;
+            fs::remove_dir_all(&dir).ok();
+            assert!(loaded.is_err());
+        }
+
+        #[tokio::test]
+        async fn an_unwritable_config_path_falls_back_to_the_fallback_path() {
+            let dir = test_dir("read-only-config");
+            let primary_dir = dir.join("primary");
+            fs::remove_dir_all(&dir).ok();
+            fs::create_dir_all(&primary_dir).unwrap();
+            fs::set_permissions(&primary_dir, fs::Permissions::from_mode(0o555)).unwrap();
+
+            // permissions aren't enforced for root, so there is nothing to fall back from
+            if fs::write(primary_dir.join("probe"), "").is_ok() {
+                fs::remove_dir_all(&dir).ok();
+                return;
+            }
+
+            let config = ReadOnlyConfig { value: 1 };
+            let path = config.dump().await.unwrap();
+            let loaded = ReadOnlyConfig::load().await.unwrap();
+
+            fs::set_permissions(&primary_dir, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::remove_dir_all(&dir).ok();
+            assert_eq!(path, ReadOnlyConfig::fallback_path());
+            assert_eq!(loaded, Some(config));
         }
     }
 }
 
 #[cfg(feature = "init_logging")]
-pub use init_logging::init_logging;
+pub use init_logging::{init_logging, log_level, recent_logs, set_log_level};
 
 #[cfg(feature = "config")]
-pub use config::ConfigLike;
\ No newline at end of file
+pub use config::{ConfigLike, runtime_dir};
\ No newline at end of file
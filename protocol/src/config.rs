@@ -1,8 +1,13 @@
 pub mod resolved {
     use crate::Config;
-    use crate::config::{JavaPath, JavaPathKind, Arguments, User, UserKind};
-    use anyhow::Context;
+    use crate::config::{
+        self, AnsiMode, Arguments, AutoRestart, ChildExitPolicy, InputPacing, JavaPath,
+        JavaPathKind, LaunchMode, PortReadinessCheck, RestartSchedule, ServerKind,
+        ShutdownCommand, StopStep, TerminalMode, User, UserKind, Watchdog,
+    };
+    use crate::OutputStream;
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
     use std::path::PathBuf;
 
     #[derive(Serialize, Deserialize, Clone)]
@@ -12,6 +17,72 @@ pub mod resolved {
         pub java_arguments: Arguments,
         pub server_arguments: Arguments,
         pub user: Option<String>,
+
+        #[serde(default = "config::default_stop_ladder")]
+        pub stop_ladder: Vec<StopStep>,
+
+        #[serde(default)]
+        pub server_kind: ServerKind,
+
+        #[serde(default = "config::default_output_buffer_budget")]
+        pub output_buffer_budget: usize,
+
+        #[serde(default)]
+        pub terminal: TerminalMode,
+
+        #[serde(default)]
+        pub ansi: AnsiMode,
+
+        #[serde(default)]
+        pub primary_output_stream: Option<OutputStream>,
+
+        #[serde(default)]
+        pub port_readiness_check: Option<PortReadinessCheck>,
+
+        #[serde(default)]
+        pub memory_limit_mb: Option<u64>,
+
+        #[serde(default)]
+        pub launch_mode: LaunchMode,
+
+        #[serde(default)]
+        pub watchdog: Option<Watchdog>,
+
+        #[serde(default)]
+        pub input_pacing: InputPacing,
+
+        #[serde(default)]
+        pub backup_command: Option<Arguments>,
+
+        #[serde(default)]
+        pub backup_dir: Option<PathBuf>,
+
+        #[serde(default)]
+        pub backup_retention: Option<usize>,
+
+        #[serde(default)]
+        pub world_dir: Option<PathBuf>,
+
+        #[serde(default)]
+        pub shutdown_command: Option<ShutdownCommand>,
+
+        #[serde(default)]
+        pub on_daemon_exit: ChildExitPolicy,
+
+        #[serde(default)]
+        pub env: BTreeMap<String, String>,
+
+        #[serde(default)]
+        pub working_dir: Option<PathBuf>,
+
+        #[serde(default)]
+        pub output_timestamp: Option<String>,
+
+        #[serde(default)]
+        pub auto_restart: Option<AutoRestart>,
+
+        #[serde(default)]
+        pub restart_schedule: Option<RestartSchedule>,
     }
 
     #[derive(Serialize, Deserialize, Copy, Clone)]
@@ -24,17 +95,41 @@ pub mod resolved {
         pub fn resolve(&self) -> anyhow::Result<(ResolvedConfig, ConfigMask)> {
             Ok((
                 ResolvedConfig {
-                    java_path: self
-                        .java_path
-                        .resolve()
-                        .map(|jp| jp.to_path_buf())
-                        .context(
-                            "Failed to get the Java path. Is Java installed in your system?",
-                        )?,
+                    java_path: match (self.java_path.resolve(), &self.launch_mode) {
+                        (Some(java_path), _) => java_path.to_path_buf(),
+
+                        // java isn't needed to run a launch script
+                        (None, LaunchMode::Script(_)) => PathBuf::new(),
+                        (None, LaunchMode::Java) => anyhow::bail!(
+                            "Failed to get the Java path. Is Java installed in your system?"
+                        ),
+                    },
                     server_jar_path: self.server_jar_path.clone(),
                     server_arguments: self.server_arguments.clone(),
                     java_arguments: self.java_arguments.clone(),
                     user: self.user.resolve().map(|u| u.to_owned()),
+                    stop_ladder: self.stop_ladder.clone(),
+                    server_kind: self.server_kind,
+                    output_buffer_budget: self.output_buffer_budget,
+                    terminal: self.terminal,
+                    ansi: self.ansi,
+                    primary_output_stream: self.primary_output_stream,
+                    port_readiness_check: self.port_readiness_check,
+                    memory_limit_mb: self.memory_limit_mb,
+                    launch_mode: self.launch_mode.clone(),
+                    watchdog: self.watchdog.clone(),
+                    input_pacing: self.input_pacing,
+                    backup_command: self.backup_command.clone(),
+                    backup_dir: self.backup_dir.clone(),
+                    backup_retention: self.backup_retention,
+                    world_dir: self.world_dir.clone(),
+                    shutdown_command: self.shutdown_command.clone(),
+                    on_daemon_exit: self.on_daemon_exit,
+                    env: self.env.clone(),
+                    working_dir: self.working_dir.clone(),
+                    output_timestamp: self.output_timestamp.clone(),
+                    auto_restart: self.auto_restart,
+                    restart_schedule: self.restart_schedule,
                 },
                 ConfigMask {
                     java_path: self.java_path.kind(),
@@ -57,17 +152,45 @@ pub mod resolved {
                     (_, UserKind::Current) => User::Current,
                     _ => panic!("invalid user configuration"),
                 },
+                stop_ladder: config.stop_ladder,
+                server_kind: config.server_kind,
+                output_buffer_budget: config.output_buffer_budget,
+                terminal: config.terminal,
+                ansi: config.ansi,
+                primary_output_stream: config.primary_output_stream,
+                port_readiness_check: config.port_readiness_check,
+                memory_limit_mb: config.memory_limit_mb,
+                launch_mode: config.launch_mode,
+                watchdog: config.watchdog,
+                input_pacing: config.input_pacing,
+                backup_command: config.backup_command,
+                backup_dir: config.backup_dir,
+                backup_retention: config.backup_retention,
+                world_dir: config.world_dir,
+                shutdown_command: config.shutdown_command,
+                on_daemon_exit: config.on_daemon_exit,
+                env: config.env,
+                working_dir: config.working_dir,
+                output_timestamp: config.output_timestamp,
+                auto_restart: config.auto_restart,
+                restart_schedule: config.restart_schedule,
             }
         }
     }
 }
 
-use crate::utils;
+use crate::{utils, ErrorKind, OutputStream};
 use anyhow::Context;
 use bincode::{Decode, Encode};
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Local, NaiveTime};
 use serde::{Deserialize, Serialize};
+use fs_err::tokio as fs;
 use std::borrow::Cow;
-use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 use tokio::process::Command;
 use raphy_common::ConfigLike;
 
@@ -86,9 +209,7 @@ pub enum JavaPath {
 impl JavaPath {
     pub fn resolve(&self) -> Option<Cow<Path>> {
         match self {
-            Self::AutoDetect => utils::auto_detect_java_from_java_home_env()
-                .or_else(utils::auto_detect_java_from_system_path)
-                .map(Cow::Owned),
+            Self::AutoDetect => utils::auto_detect_java().map(Cow::Owned),
             Self::Custom(path) => Some(Cow::Borrowed(path)),
         }
     }
@@ -175,6 +296,538 @@ impl User {
     }
 }
 
+/// how the server process is launched
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub enum LaunchMode {
+    /// java is run with the server jar and the java arguments
+    #[default]
+    Java,
+
+    /// the script is run instead, e.g. a `start.sh` which comes with the server. the java path,
+    /// java arguments and server jar are ignored; the server arguments are passed to the script.
+    Script(PathBuf),
+}
+
+/// the flavor of server software being managed, which determines the console commands used
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ServerKind {
+    #[default]
+    Vanilla,
+
+    /// Bukkit and its forks, e.g. Spigot and Paper
+    Bukkit,
+    Forge,
+    Fabric,
+}
+
+impl ServerKind {
+    /// the console command which saves the world to disk
+    pub fn save_command(&self) -> &'static str {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => "save-all flush",
+        }
+    }
+
+    /// the text the server prints once the world has been saved
+    pub fn save_confirmation(&self) -> &'static str {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => "Saved the game",
+        }
+    }
+
+    /// the console command which keeps the server from writing the world to disk, e.g. while
+    /// it's being backed up
+    pub fn save_off_command(&self) -> &'static str {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => "save-off",
+        }
+    }
+
+    /// the console command which undoes [`Self::save_off_command`]
+    pub fn save_on_command(&self) -> &'static str {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => "save-on",
+        }
+    }
+
+    /// the console command which reloads the server's datapacks, and on bukkit, its plugins
+    pub fn reload_command(&self) -> &'static str {
+        match self {
+            Self::Bukkit => "reload confirm",
+            Self::Vanilla | Self::Forge | Self::Fabric => "reload",
+        }
+    }
+
+    /// the text the server prints once it has reloaded
+    pub fn reload_confirmation(&self) -> &'static str {
+        match self {
+            Self::Bukkit => "Reload complete.",
+            Self::Vanilla | Self::Forge | Self::Fabric => "Reloading!",
+        }
+    }
+
+    /// the stream the server writes its regular output to, as opposed to its errors
+    pub fn primary_output_stream(&self) -> OutputStream {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => OutputStream::Stdout,
+        }
+    }
+
+    /// the text the server prints once it has finished starting up
+    pub fn ready_marker(&self) -> &'static str {
+        match self {
+            Self::Vanilla | Self::Bukkit | Self::Forge | Self::Fabric => "Done (",
+        }
+    }
+}
+
+/// what the server process's standard streams are connected to
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// pipes, with output passed through as-is
+    #[default]
+    Piped,
+
+    /// a pseudo-terminal, so the server behaves as if it were run interactively. stdout and stderr
+    /// are merged, and all output is reported as stdout.
+    Pseudo,
+
+    /// pipes, with the server told not to use colors and any ANSI escape sequences stripped from its
+    /// output
+    NonInteractive,
+}
+
+/// what is done with ANSI escape sequences in the server's output
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum AnsiMode {
+    /// escape sequences are passed through as-is, unless the terminal mode is
+    /// [`TerminalMode::NonInteractive`], in which case they're stripped
+    #[default]
+    Raw,
+
+    /// escape sequences are removed
+    Strip,
+
+    /// escape sequences are translated into styles, and output is sent to clients as
+    /// [`crate::ServerToClientMessage::Log`]
+    Structured,
+}
+
+/// a signal which can be sent to the server process while stopping it
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopSignal {
+    Interrupt,
+    Hangup,
+    Terminate,
+    Quit,
+    Kill,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct StopStep {
+    pub signal: StopSignal,
+
+    /// how long to wait for the server process to exit before moving on to the next step
+    pub timeout_secs: u64,
+}
+
+impl StopStep {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// a field of [`Config`], to tell which one a [`ConfigProblem`] is about
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigField {
+    JavaPath,
+    ServerJarPath,
+    JavaArguments,
+    ServerArguments,
+    Env,
+    WorkingDir,
+    OutputTimestamp,
+    AutoRestart,
+    RestartSchedule,
+    BackupDir,
+    User,
+    MemoryLimitMb,
+}
+
+/// a reason the server can't be launched with a config, see [`Config::validate`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub field: ConfigField,
+    pub message: String,
+}
+
+/// a change to a single field of [`Config`], which the server applies to its current config so
+/// that clients changing different fields at once don't overwrite each other's changes
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub enum ConfigPatch {
+    JavaPath(JavaPath),
+    ServerJarPath(PathBuf),
+    JavaArguments(Arguments),
+    ServerArguments(Arguments),
+    User(User),
+    MemoryLimitMb(Option<u64>),
+    Env(BTreeMap<String, String>),
+    WorkingDir(Option<PathBuf>),
+    OutputTimestamp(Option<String>),
+}
+
+impl ConfigPatch {
+    /// the field the patch changes
+    pub fn field(&self) -> ConfigField {
+        match self {
+            Self::JavaPath(_) => ConfigField::JavaPath,
+            Self::ServerJarPath(_) => ConfigField::ServerJarPath,
+            Self::JavaArguments(_) => ConfigField::JavaArguments,
+            Self::ServerArguments(_) => ConfigField::ServerArguments,
+            Self::User(_) => ConfigField::User,
+            Self::MemoryLimitMb(_) => ConfigField::MemoryLimitMb,
+            Self::Env(_) => ConfigField::Env,
+            Self::WorkingDir(_) => ConfigField::WorkingDir,
+            Self::OutputTimestamp(_) => ConfigField::OutputTimestamp,
+        }
+    }
+
+    /// applies the patch to `config`, failing if the patched field isn't valid. problems with other
+    /// fields are left alone.
+    pub fn apply(self, config: &Config) -> anyhow::Result<Config> {
+        let field = self.field();
+        let mut config = config.clone();
+        match self {
+            Self::JavaPath(java_path) => config.java_path = java_path,
+            Self::ServerJarPath(server_jar_path) => config.server_jar_path = server_jar_path,
+            Self::JavaArguments(java_arguments) => config.java_arguments = java_arguments,
+            Self::ServerArguments(server_arguments) => config.server_arguments = server_arguments,
+            Self::User(user) => config.user = user,
+            Self::MemoryLimitMb(memory_limit_mb) => config.memory_limit_mb = memory_limit_mb,
+            Self::Env(env) => config.env = env,
+            Self::WorkingDir(working_dir) => config.working_dir = working_dir,
+            Self::OutputTimestamp(output_timestamp) => config.output_timestamp = output_timestamp,
+        }
+
+        config.validate_field(field)?;
+        Ok(config)
+    }
+}
+
+/// what happens to a running server when raphy exits
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ChildExitPolicy {
+    /// the server is stopped through the stop ladder, and raphy waits for it to exit for up to a
+    /// minute. the shutdown command isn't written, since the server's console is closed by then.
+    #[default]
+    Stop,
+
+    /// the server keeps running, and the next run of raphy adopts it. an adopted server can be
+    /// stopped, restarted and monitored like any other, but:
+    ///
+    /// - its console output is lost while raphy isn't running. once it's adopted, only what it
+    ///   writes to `logs/latest.log` from then on is received, and input can't be written to its
+    ///   console.
+    /// - its exit status isn't known once it stops
+    /// - it's only recognized on linux, since its start time, boot and command line are read from
+    ///   `/proc` to make sure its pid wasn't reused
+    /// - a service manager may stop it along with raphy regardless, e.g. systemd does unless the
+    ///   unit's `KillMode` is `process`
+    Leave,
+}
+
+/// console input which makes the server shut down on its own, e.g. after saving the world
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ShutdownCommand {
+    /// written to the server's console as is. several commands are separated by line breaks, e.g.
+    /// `save-all\nstop`; a trailing line break is added if it's missing.
+    pub command: String,
+
+    /// how long to wait for the server process to exit before the stop ladder is started
+    pub grace_secs: u64,
+}
+
+impl ShutdownCommand {
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_secs)
+    }
+
+    /// the bytes to write to the server's stdin
+    pub fn input(&self) -> Vec<u8> {
+        let mut input = self.command.clone().into_bytes();
+        if !input.ends_with(b"\n") {
+            input.push(b'\n');
+        }
+        input
+    }
+}
+
+/// infers that the server has finished starting up once its game port accepts connections, for
+/// server software whose ready line isn't recognized
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct PortReadinessCheck {
+    /// how long to keep trying to connect before giving up
+    pub timeout_secs: u64,
+}
+
+impl PortReadinessCheck {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// what the watchdog checks to tell whether the server still responds
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub enum WatchdogProbe {
+    /// the game port from `server.properties` accepts connections
+    GamePort,
+
+    /// the server prints `expected_output` after `command` is written to its console
+    Command {
+        command: String,
+        expected_output: String,
+    },
+}
+
+/// what the watchdog does once the server is considered hung
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchdogAction {
+    Restart,
+    Stop,
+
+    /// clients are told about it, but the server is left running
+    Notify,
+}
+
+/// periodically probes the server once it has finished starting up, and acts if it stops
+/// responding
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct Watchdog {
+    pub probe: WatchdogProbe,
+
+    /// how often the server is probed, which is also how long a probe may take
+    pub interval_secs: u64,
+
+    /// how many probes in a row have to fail before the server is considered hung
+    pub failure_threshold: u32,
+    pub action: WatchdogAction,
+}
+
+impl Watchdog {
+    pub fn interval(&self) -> Duration {
+        // a zero interval would probe continuously
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+}
+
+/// the most time waited before restarting a crashed server, however often it crashed
+const MAX_AUTO_RESTART_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// how a server which exits unexpectedly is restarted
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AutoRestart {
+    /// the most restarts within the window, after which the server is left stopped rather than
+    /// restarted in a crash loop
+    pub max_restarts: u32,
+
+    /// how far back crashes count towards [`Self::max_restarts`]
+    pub window_secs: u64,
+
+    /// how long to wait before the first restart in the window. every further one waits twice as
+    /// long as the previous one.
+    pub backoff_secs: u64,
+}
+
+impl AutoRestart {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// how long to wait before restarting, given how many restarts there were within the window
+    pub fn backoff(&self, previous_restarts: u32) -> Duration {
+        Duration::from_secs(self.backoff_secs)
+            .saturating_mul(2u32.saturating_pow(previous_restarts))
+            .min(MAX_AUTO_RESTART_BACKOFF)
+    }
+}
+
+impl Default for AutoRestart {
+    fn default() -> Self {
+        Self {
+            max_restarts: 3,
+            window_secs: 10 * 60,
+            backoff_secs: 5,
+        }
+    }
+}
+
+/// a time of day in the local time zone at which the server is restarted
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RestartSchedule {
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl RestartSchedule {
+    /// the next time after `now` the server is restarted, or `None` if the time is invalid
+    pub fn next_after(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let time = NaiveTime::from_hms_opt(self.hour, self.minute, 0)?;
+        let mut date = now.date_naive();
+
+        // the time may not exist on a day the clocks are moved forward, which is skipped
+        for _ in 0..3 {
+            let at = date.and_time(time).and_local_timezone(Local).earliest();
+            if let Some(at) = at.filter(|at| *at > now) {
+                return Some(at);
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+/// how input is fed to the server's stdin, so that a large paste doesn't overwhelm it
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputPacing {
+    /// the most bytes written to stdin at once
+    pub chunk_size: usize,
+
+    /// how long to wait between the lines of input with several lines, e.g. to not trip a
+    /// server's anti-spam. zero writes the lines right after each other.
+    pub line_delay_ms: u64,
+}
+
+impl InputPacing {
+    pub fn chunk_size(&self) -> usize {
+        // a zero chunk size would never write anything
+        self.chunk_size.max(1)
+    }
+
+    pub fn line_delay(&self) -> Duration {
+        Duration::from_millis(self.line_delay_ms)
+    }
+}
+
+impl Default for InputPacing {
+    fn default() -> Self {
+        Self {
+            chunk_size: 4096,
+            line_delay_ms: 0,
+        }
+    }
+}
+
+fn check_server_jar(path: &Path) -> Result<(), String> {
+    if path.as_os_str().is_empty() {
+        return Err("No server jar is set.".to_owned());
+    }
+
+    let metadata = std::fs::metadata(path).map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => {
+            format!("The server jar at '{}' doesn't exist.", path.display())
+        }
+        _ => format!("The server jar at '{}' can't be accessed: {error}.", path.display()),
+    })?;
+    if metadata.is_dir() {
+        return Err(format!("The server jar at '{}' is a directory.", path.display()));
+    }
+
+    std::fs::File::open(path)
+        .map(drop)
+        .map_err(|error| format!("The server jar at '{}' can't be read: {error}.", path.display()))
+}
+
+fn check_java(path: &Path) -> Result<(), String> {
+    let metadata = std::fs::metadata(path).map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => format!("Java at '{}' doesn't exist.", path.display()),
+        _ => format!("Java at '{}' can't be accessed: {error}.", path.display()),
+    })?;
+    if !metadata.is_file() {
+        return Err(format!("Java at '{}' isn't a file.", path.display()));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("Java at '{}' isn't executable.", path.display()));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_working_dir(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err(format!("The working directory '{}' isn't an absolute path.", path.display()));
+    }
+
+    if !path.is_dir() {
+        return Err(format!("The working directory '{}' isn't a directory.", path.display()));
+    }
+
+    Ok(())
+}
+
+/// refuses backup directories which old backups could be pruned from alongside the server's own
+/// files
+fn check_backup_dir(backup_dir: &Path, working_dir: &Path, world_dir: &Path) -> Result<(), String> {
+    let backup_dir = normalize(backup_dir);
+    if normalize(working_dir).starts_with(&backup_dir) {
+        return Err(format!(
+            "The backup directory '{}' can't be the working directory or contain it.",
+            backup_dir.display()
+        ));
+    }
+
+    let world_dir = normalize(world_dir);
+    if world_dir.starts_with(&backup_dir) || backup_dir.starts_with(&world_dir) {
+        return Err(format!(
+            "The backup directory '{}' can't contain the world directory or be inside of it.",
+            backup_dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// resolves `.` and `..` in `path` without touching the filesystem, so that paths can be compared
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+fn check_timestamp_format(format: &str) -> Result<(), String> {
+    StrftimeItems::new(format)
+        .parse()
+        .map(drop)
+        .map_err(|_| format!("The timestamp format '{format}' isn't a valid strftime format."))
+}
+
+/// the default stop ladder: `SIGTERM`, then `SIGKILL` if the server is still running 30 seconds later
+pub fn default_stop_ladder() -> Vec<StopStep> {
+    vec![StopStep {
+        signal: StopSignal::Terminate,
+        timeout_secs: 30,
+    }]
+}
+
+/// the default output buffer budget: 4 MiB
+pub fn default_output_buffer_budget() -> usize {
+    4 * 1024 * 1024
+}
+
 #[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub java_path: JavaPath,
@@ -182,9 +835,413 @@ pub struct Config {
     pub java_arguments: Arguments,
     pub server_arguments: Arguments,
     pub user: User,
+
+    /// signals sent in order when stopping the server; `SIGKILL` is always sent after the last step
+    #[serde(default = "default_stop_ladder")]
+    pub stop_ladder: Vec<StopStep>,
+
+    #[serde(default)]
+    pub server_kind: ServerKind,
+
+    /// how many bytes of server output are kept in memory, across all output buffers combined
+    #[serde(default = "default_output_buffer_budget")]
+    pub output_buffer_budget: usize,
+
+    #[serde(default)]
+    pub terminal: TerminalMode,
+
+    #[serde(default)]
+    pub ansi: AnsiMode,
+
+    /// the stream the server writes its regular output to. some server software logs everything
+    /// to stderr, in which case its output shouldn't be treated as errors. `None` uses the
+    /// [`ServerKind`]'s default.
+    #[serde(default)]
+    pub primary_output_stream: Option<OutputStream>,
+
+    /// if set, the game port from `server.properties` is also checked to tell when the server is
+    /// ready
+    #[serde(default)]
+    pub port_readiness_check: Option<PortReadinessCheck>,
+
+    /// a hard limit on the memory of the server process, enforced by the kernel through a cgroup.
+    /// only supported on linux with cgroup v2; the server starts without a limit elsewhere.
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+
+    #[serde(default)]
+    pub launch_mode: LaunchMode,
+
+    #[serde(default)]
+    pub watchdog: Option<Watchdog>,
+
+    #[serde(default)]
+    pub input_pacing: InputPacing,
+
+    /// the command a maintenance cycle runs while the server is stopped, in the server's working
+    /// directory. its first argument is the program to run. see
+    /// [`crate::MaintenanceStep::Backup`].
+    #[serde(default)]
+    pub backup_command: Option<Arguments>,
+
+    /// where the backup command puts its backups, and where the world is archived to when backed
+    /// up by raphy itself, relative to the working directory. only archives named like raphy's
+    /// own, `<world>-<YYYY-MM-DD_HH-MM-SS>.tar.gz`, are taken to be backups, so a backup command
+    /// has to name its backups the same way for them to be pruned. it can't be the working
+    /// directory, nor contain the world or be inside of it.
+    #[serde(default)]
+    pub backup_dir: Option<PathBuf>,
+
+    /// how many of the newest backups in [`Self::backup_dir`] are kept after each backup, deleting
+    /// the older ones. `None` keeps every backup.
+    #[serde(default)]
+    pub backup_retention: Option<usize>,
+
+    /// the directory of the world which is archived when backing it up, relative to the working
+    /// directory. `None` is `world`, where a vanilla server keeps it. see [`Self::world_dir()`].
+    #[serde(default)]
+    pub world_dir: Option<PathBuf>,
+
+    /// if set, stopping the server first asks it to shut down through its console, and only
+    /// starts the stop ladder if it hasn't exited once the grace period is over
+    #[serde(default)]
+    pub shutdown_command: Option<ShutdownCommand>,
+
+    /// what happens to the server if it's still running when raphy exits
+    #[serde(default)]
+    pub on_daemon_exit: ChildExitPolicy,
+
+    /// environment variables the server is launched with, on top of raphy's own, e.g.
+    /// `JAVA_TOOL_OPTIONS` or `LANG`
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// the absolute path of the directory the server is launched in, where it keeps its world and
+    /// logs. `None` launches it in the directory containing the server jar or launch script. see
+    /// [`Self::working_dir()`].
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// if set, every line of the server's output is prefixed with the time raphy received it,
+    /// formatted with this `strftime` format, e.g. `[%H:%M:%S]`, and followed by a space. off by
+    /// default, since most server software already timestamps its lines.
+    #[serde(default)]
+    pub output_timestamp: Option<String>,
+
+    /// if set, the server is launched again when it exits without being asked to
+    #[serde(default)]
+    pub auto_restart: Option<AutoRestart>,
+
+    /// if set, the server is restarted every day at this time, e.g. to free memory. it isn't
+    /// started if it's stopped at the time.
+    #[serde(default)]
+    pub restart_schedule: Option<RestartSchedule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            java_path: JavaPath::AutoDetect,
+            server_jar_path: PathBuf::new(),
+            java_arguments: Arguments::Parsed("-Xms1G -Xmx2G".to_owned()),
+            server_arguments: Arguments::Parsed("nogui".to_owned()),
+            user: User::Current,
+            stop_ladder: default_stop_ladder(),
+            server_kind: ServerKind::default(),
+            output_buffer_budget: default_output_buffer_budget(),
+            terminal: TerminalMode::default(),
+            ansi: AnsiMode::default(),
+            primary_output_stream: None,
+            port_readiness_check: None,
+            memory_limit_mb: None,
+            launch_mode: LaunchMode::default(),
+            watchdog: None,
+            input_pacing: InputPacing::default(),
+            backup_command: None,
+            backup_dir: None,
+            backup_retention: None,
+            world_dir: None,
+            shutdown_command: None,
+            on_daemon_exit: ChildExitPolicy::default(),
+            env: BTreeMap::new(),
+            working_dir: None,
+            output_timestamp: None,
+            auto_restart: None,
+            restart_schedule: None,
+        }
+    }
+}
+
+impl Config {
+    /// points to a config file which is used as the template for unconfigured servers
+    pub const TEMPLATE_ENV_VAR: &'static str = "RAPHY_CONFIG_TEMPLATE_PATH";
+
+    /// a suggested starting point for unconfigured servers. it's read from the file at
+    /// [`Self::TEMPLATE_ENV_VAR`] if set, otherwise it's the default config.
+    pub async fn template() -> anyhow::Result<Self> {
+        let Some(path) = env::var_os(Self::TEMPLATE_ENV_VAR) else {
+            return Ok(Self::default());
+        };
+
+        let contents = fs::read_to_string(path)
+            .await
+            .context("Failed to read the config template.")?;
+        serde_json::from_str(&contents).context("Failed to parse the config template.")
+    }
+
+    /// checks that the server can be launched with this config, i.e. that the server jar can be
+    /// read, java exists and is executable, the arguments can be parsed and the environment
+    /// variables can be set. every problem found is listed in an [`ErrorKind::InvalidConfig`], so
+    /// that clients can show them next to their fields. whether the user the server runs as can
+    /// access the files is only checked when starting it.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        Self::check_problems(self.problems())
+    }
+
+    /// like [`Self::validate`], but only for the problems with `field`, e.g. so that changing it
+    /// isn't refused because of another field
+    pub fn validate_field(&self, field: ConfigField) -> anyhow::Result<()> {
+        let mut problems = self.problems();
+        problems.retain(|problem| problem.field == field);
+        Self::check_problems(problems)
+    }
+
+    fn check_problems(problems: Vec<ConfigProblem>) -> anyhow::Result<()> {
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let summary = problems
+            .iter()
+            .map(|problem| problem.message.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Err(ErrorKind::InvalidConfig(problems)).context(summary)
+    }
+
+    fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        let mut problem = |field, message| problems.push(ConfigProblem { field, message });
+
+        if self.server_arguments.resolve().is_err() {
+            problem(
+                ConfigField::ServerArguments,
+                "The server arguments can't be parsed; check their quoting.".to_owned(),
+            );
+        }
+
+        for name in self.env.keys() {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                problem(
+                    ConfigField::Env,
+                    format!("'{name}' isn't a valid environment variable name."),
+                );
+            }
+        }
+        if self.env.values().any(|value| value.contains('\0')) {
+            problem(
+                ConfigField::Env,
+                "Environment variables can't contain null characters.".to_owned(),
+            );
+        }
+
+        if let User::Specific(user) = &self.user
+            && user.is_empty()
+        {
+            problem(
+                ConfigField::User,
+                "The user to launch the server as can't be empty.".to_owned(),
+            );
+        }
+
+        if self.memory_limit_mb == Some(0) {
+            problem(
+                ConfigField::MemoryLimitMb,
+                "The memory limit can't be zero.".to_owned(),
+            );
+        }
+
+        if let Some(working_dir) = &self.working_dir
+            && let Err(message) = check_working_dir(working_dir)
+        {
+            problem(ConfigField::WorkingDir, message);
+        }
+
+        if let Some(format) = &self.output_timestamp
+            && let Err(message) = check_timestamp_format(format)
+        {
+            problem(ConfigField::OutputTimestamp, message);
+        }
+
+        if let Some(RestartSchedule { hour, minute }) = self.restart_schedule
+            && (hour >= 24 || minute >= 60)
+        {
+            problem(
+                ConfigField::RestartSchedule,
+                format!("{hour:02}:{minute:02} isn't a valid time of day to restart at."),
+            );
+        }
+
+        if let Some(backup_dir) = self.backup_dir()
+            && let Err(message) =
+                check_backup_dir(&backup_dir, self.working_dir(), &self.world_dir())
+        {
+            problem(ConfigField::BackupDir, message);
+        }
+
+        // crashes would never count towards the limit, so a crash loop would go on forever
+        if self.auto_restart.is_some_and(|auto_restart| auto_restart.window_secs == 0) {
+            problem(
+                ConfigField::AutoRestart,
+                "The window in which restarts are counted can't be zero.".to_owned(),
+            );
+        }
+
+        // a launch script takes care of java itself
+        if let LaunchMode::Java = self.launch_mode {
+            if let Err(message) = check_server_jar(&self.server_jar_path) {
+                problem(ConfigField::ServerJarPath, message);
+            }
+
+            let result = match self.java_path.resolve() {
+                Some(java_path) => check_java(&java_path),
+                None => Err("Java couldn't be found; install it or set its path.".to_owned()),
+            };
+            if let Err(message) = result {
+                problem(ConfigField::JavaPath, message);
+            }
+
+            if self.java_arguments.resolve().is_err() {
+                problem(
+                    ConfigField::JavaArguments,
+                    "The Java arguments can't be parsed; check their quoting.".to_owned(),
+                );
+            }
+        }
+
+        problems
+    }
+
+    pub fn primary_output_stream(&self) -> OutputStream {
+        self.primary_output_stream
+            .unwrap_or_else(|| self.server_kind.primary_output_stream())
+    }
+
+    /// the directory the server process is launched in, which is the configured one, or otherwise
+    /// the one containing the server jar or launch script
+    pub fn working_dir(&self) -> &Path {
+        if let Some(working_dir) = &self.working_dir {
+            return working_dir;
+        }
+
+        let launched = match &self.launch_mode {
+            LaunchMode::Java => &self.server_jar_path,
+            LaunchMode::Script(script) => script,
+        };
+
+        launched.parent().unwrap_or_else(|| Path::new("/"))
+    }
+
+    /// the absolute path of [`Self::backup_dir`], if one is configured
+    pub fn backup_dir(&self) -> Option<PathBuf> {
+        self.backup_dir
+            .as_ref()
+            .map(|backup_dir| self.working_dir().join(backup_dir))
+    }
+
+    /// the absolute path of [`Self::world_dir`]
+    pub fn world_dir(&self) -> PathBuf {
+        let world_dir = self.world_dir.as_deref().unwrap_or(Path::new("world"));
+        self.working_dir().join(world_dir)
+    }
 }
 
 impl ConfigLike for Config {
     const ENV_VAR: &'static str = "RAPHY_CONFIG_PATH";
     const CONFIG_PATH_NAME: &'static str = "config.json";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_backup_dir(backup_dir: &str) -> Config {
+        Config {
+            working_dir: Some(PathBuf::from("/srv/minecraft")),
+            backup_dir: Some(PathBuf::from(backup_dir)),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn backup_dir_outside_of_the_world_is_accepted() {
+        for backup_dir in ["backups", "./backups", "/var/backups/minecraft"] {
+            let config = config_with_backup_dir(backup_dir);
+            assert!(
+                config.validate_field(ConfigField::BackupDir).is_ok(),
+                "{backup_dir} was refused"
+            );
+        }
+    }
+
+    #[test]
+    fn backup_dir_sharing_the_server_files_is_refused() {
+        for backup_dir in [
+            ".",
+            "",
+            "..",
+            "/srv",
+            "world",
+            "world/backups",
+            "backups/../world",
+        ] {
+            let config = config_with_backup_dir(backup_dir);
+            assert!(
+                config.validate_field(ConfigField::BackupDir).is_err(),
+                "{backup_dir} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn backup_dir_containing_a_configured_world_is_refused() {
+        let config = Config {
+            world_dir: Some(PathBuf::from("worlds/survival")),
+            ..config_with_backup_dir("worlds")
+        };
+        assert!(config.validate_field(ConfigField::BackupDir).is_err());
+    }
+
+    /// the fields a refused patch was refused for
+    fn refused_fields(patch: ConfigPatch) -> Vec<ConfigField> {
+        let error = patch.apply(&Config::default()).unwrap_err();
+        let Some(ErrorKind::InvalidConfig(problems)) = error.downcast_ref() else {
+            panic!("the patch was refused without naming its problems: {error:#}");
+        };
+        problems.iter().map(|problem| problem.field).collect()
+    }
+
+    #[test]
+    fn an_empty_user_is_refused() {
+        let patch = ConfigPatch::User(User::Specific(String::new()));
+        assert_eq!(refused_fields(patch), [ConfigField::User]);
+    }
+
+    #[test]
+    fn a_zero_memory_limit_is_refused() {
+        let patch = ConfigPatch::MemoryLimitMb(Some(0));
+        assert_eq!(refused_fields(patch), [ConfigField::MemoryLimitMb]);
+    }
+
+    #[test]
+    fn a_patch_is_not_refused_for_problems_with_other_fields() {
+        // the default config has no server jar set
+        let config = Config::default();
+        assert!(config.validate().is_err());
+
+        let patched = ConfigPatch::MemoryLimitMb(Some(4096))
+            .apply(&config)
+            .unwrap();
+        assert_eq!(patched.memory_limit_mb, Some(4096));
+    }
+}
@@ -1,6 +1,55 @@
+use crate::{Permission, ServerState};
+use crate::config::ConfigProblem;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::PathBuf;
+
+/// a machine-readable classification of an error, so clients can react to it without parsing
+/// messages.
+///
+/// attach it to an error chain (e.g. `Err(ErrorKind::..).context(..)`) and [`SerdeError::kind`]
+/// will find it on the client side.
+#[derive(Clone, Debug, Deserialize, Serialize, Encode, Decode, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// the user the server runs as cannot access the given path
+    PermissionDenied(PathBuf),
+
+    /// the operation would have no effect, since the server is already in the state it leads to
+    AlreadyInState(ServerState),
+
+    /// the server can't be launched with the config, for every reason listed. see
+    /// [`crate::Config::validate`].
+    InvalidConfig(Vec<ConfigProblem>),
+
+    /// the client isn't allowed to do this, see [`crate::Permissions`]
+    NotPermitted(Permission),
+
+    /// the client already has as many streams open as it may, e.g. metrics or backup progress.
+    /// carries the limit.
+    TooManyStreams(usize),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied(path) => write!(f, "permission denied: {}", path.display()),
+            Self::AlreadyInState(ServerState::Started) => write!(f, "already running"),
+            Self::AlreadyInState(ServerState::Stopped(..)) => write!(f, "already stopped"),
+            Self::InvalidConfig(problems) => {
+                let fields = problems
+                    .iter()
+                    .map(|problem| format!("{:?}", problem.field))
+                    .collect::<Vec<_>>();
+                write!(f, "invalid config: {}", fields.join(", "))
+            }
+            Self::NotPermitted(permission) => write!(f, "not permitted: {permission:?}"),
+            Self::TooManyStreams(limit) => write!(f, "too many streams, at most {limit}"),
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {}
 
 #[derive(Clone, Deserialize, Serialize, Encode, Decode)]
 pub struct SerdeError {
@@ -8,22 +57,28 @@ pub struct SerdeError {
     alt_display: String,
     debug: String,
     alt_debug: String,
+    kind: Option<ErrorKind>,
     source: Option<Box<Self>>,
 }
 
 impl SerdeError {
-    pub fn new<T>(e: &T) -> Self
-    where
-        T: ?Sized + std::error::Error,
-    {
+    pub fn new(e: &(dyn std::error::Error + 'static)) -> Self {
         Self {
             display: e.to_string(),
             alt_display: format!("{e:#}"),
             debug: format!("{e:?}"),
             alt_debug: format!("{e:#?}"),
+            kind: e.downcast_ref::<ErrorKind>().cloned(),
             source: e.source().map(|s| Box::new(Self::new(s))),
         }
     }
+
+    /// the first [`ErrorKind`] found in this error or its sources
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        self.kind
+            .as_ref()
+            .or_else(|| self.source.as_ref().and_then(|s| s.kind()))
+    }
 }
 
 impl std::error::Error for SerdeError {
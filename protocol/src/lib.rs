@@ -1,17 +1,107 @@
 pub mod config;
 mod error;
+mod presets;
 mod utils;
 
 use bincode::{Decode, Encode};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
 pub use config::Config;
-pub use error::SerdeError;
+use config::{ConfigPatch, User, WatchdogAction};
+pub use error::{ErrorKind, SerdeError};
+pub use presets::{ArgumentPreset, ArgumentPresetInfo};
+pub use utils::{auto_detect_java, clear_java_detection_cache, java_version};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 pub const SERVICE_TYPE: &str = "_raphy._tcp.local.";
 pub const INSTANCE_NAME: &str = "Raphy";
 pub const UNIX_SOCKET_PATH: &str = "/tmp/raphy.sock";
 pub const DEFAULT_PORT: u16 = 18000;
 
+/// names the instance a daemon runs as, so that several minecraft servers can be managed on one
+/// machine by running a daemon for each. every named instance has its own unix socket and mdns
+/// advertisement, but its config path and port have to be set apart by hand. unset for the
+/// default instance.
+pub const INSTANCE_ENV_VAR: &str = "RAPHY_INSTANCE";
+
+/// the unix socket of the named instance, or of the default one.
+pub fn unix_socket_path(instance: Option<&str>) -> PathBuf {
+    match instance {
+        Some(name) => PathBuf::from(format!("/tmp/raphy-{name}.sock")),
+        None => PathBuf::from(UNIX_SOCKET_PATH),
+    }
+}
+
+/// checks that `name` can name an instance. it ends up in file names and the mdns advertisement, so
+/// only ascii letters, digits, `-` and `_` are allowed.
+pub fn validate_instance_name(name: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(!name.is_empty(), "The instance name is empty.");
+    anyhow::ensure!(
+        name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "The instance name '{name}' may only contain ASCII letters, digits, '-' and '_'."
+    );
+    Ok(())
+}
+
+/// the instances on this machine whose daemon is listening, found through their unix sockets.
+/// `None` is the default instance. a daemon which was killed may leave its socket behind, so an
+/// instance in the list isn't guaranteed to be running.
+#[cfg(unix)]
+pub fn local_instances() -> io::Result<Vec<Option<String>>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let default = unix_socket_path(None);
+    let Some(directory) = default.parent() else {
+        return Ok(Vec::new());
+    };
+
+    let mut instances = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_socket() {
+            continue;
+        }
+
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if entry.path() == default {
+            instances.push(None);
+        } else if let Some(name) = file_name
+            .strip_prefix("raphy-")
+            .and_then(|name| name.strip_suffix(".sock"))
+            .filter(|name| validate_instance_name(name).is_ok())
+        {
+            instances.push(Some(name.to_owned()));
+        }
+    }
+
+    instances.sort();
+    Ok(instances)
+}
+
+/// the default limit on the length of a single frame, which a reader refuses to allocate beyond so
+/// that a peer can't exhaust its memory by declaring a huge frame
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// output shorter than this is sent as is, since compressing it saves too little to be worth it
+pub const OUTPUT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// the version of the wire protocol, exchanged in the handshake when a client connects. this must be
+/// bumped whenever the messages change in a way that older builds can't decode.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// human-readable identification of the build, e.g. `0.1.0+1a2b3c4`
+pub const BUILD_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("RAPHY_GIT_HASH"));
+
 #[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
 pub enum Operation {
     Start,
@@ -19,7 +109,15 @@ pub enum Operation {
     Restart,
 }
 
-#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub enum OperationOutcome {
+    /// the operation was requested but has not finished yet
+    Pending(Operation),
+    Performed(Operation),
+    Failed(Operation, SerdeError),
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ExitStatus {
     Success,
     Failure,
@@ -50,7 +148,7 @@ impl Default for Id {
     }
 }
 
-#[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
 pub struct TaskId(Id);
 
 impl TaskId {
@@ -68,13 +166,173 @@ impl OperationId {
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+/// chosen by a client to mark an operation, so that the server performs it only once when the
+/// client resends it, e.g. after a timeout
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IdempotencyKey(Id);
+
+impl IdempotencyKey {
+    pub fn generate() -> Self {
+        Self(Id::generate())
+    }
+}
+
+/// chosen by a client once and presented on every connection, so that the server can give it back
+/// its subscriptions after a brief disconnect. see [`ClientToServerMessage::Identify`].
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClientIdentity(Id);
+
+impl ClientIdentity {
+    pub fn generate() -> Self {
+        Self(Id::generate())
+    }
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
 pub enum ClientToServerMessage {
+    /// the first message a client sends, carrying its [`PROTOCOL_VERSION`] and what it asks of the
+    /// server. this must stay the first variant, and the version its first field, so that the
+    /// version decodes the same across versions.
+    Handshake(u32, HandshakeOptions),
     Ping(TaskId),
     GetConfig(TaskId),
     GetServerState(TaskId),
-    UpdateConfig(TaskId, Config),
-    PerformOperation(TaskId, Operation),
+
+    /// the config is boxed, since it's much larger than any other message
+    UpdateConfig(TaskId, Box<Config>),
+
+    /// changes a single field of the current config, see [`ConfigPatch`]. fails if the server is
+    /// unconfigured. the config update is broadcast as `ConfigUpdated`.
+    PatchConfig(TaskId, ConfigPatch),
+    /// performs an operation. a request with the same key as a recent one isn't performed again,
+    /// and gets the result of the original operation instead
+    PerformOperation(TaskId, Operation, Option<IdempotencyKey>),
+    GetOperationResult(TaskId, OperationId),
+    GetActiveOperation(TaskId),
+
+    /// runs the steps in order, stopping at the first one which fails. the start of each step is
+    /// reported with [`ServerToClientMessage::MaintenanceStepStarted`], and the outcome with
+    /// [`ServerToClientMessage::MaintenanceFinished`].
+    RunMaintenance(TaskId, Vec<MaintenanceStep>),
+    SaveWorld(TaskId),
+
+    /// archives the world into the backup directory, see [`Config::world_dir`]. if the server is
+    /// running, the world is saved first and saving is turned off until the archive is written.
+    /// answered with any number of `BackupProgress`, then `WorldBackedUp` or `Error`.
+    BackupWorld(TaskId),
+
+    /// reloads the server's datapacks, and on bukkit servers, its plugins, without restarting it.
+    ///
+    /// reloading is not as safe as a restart: plugins which don't expect to be reloaded may leak
+    /// memory, lose state, or break outright, and a reload won't pick up changes to the server jar
+    /// or its startup arguments. a restart should be preferred when possible.
+    ReloadServer(TaskId),
+
+    /// switches the server over to another jar in its working directory, restarting it if it's
+    /// running. if the server fails to start with the new jar or exits shortly after, the previous
+    /// one is used again. if the flag is set, the previous jar is first copied into the backup
+    /// directory. the config update is broadcast as `ConfigUpdated`.
+    UpdateJar(TaskId, PathBuf, bool),
+
+    /// the built-in presets of java arguments, see [`ArgumentPreset`]
+    ListArgumentPresets(TaskId),
+
+    /// replaces the java arguments with the preset of the given name, for a heap of the given
+    /// megabytes. the config update is broadcast as `ConfigUpdated`.
+    ApplyArgumentPreset(TaskId, String, u32),
+    GetStartupLog(TaskId),
+
+    /// the newest output of the server, so that a client which just connected can fill its console
+    GetRecentOutput(TaskId),
+
+    /// the server's lifecycle events, oldest first. only the events after the given time are
+    /// returned, if one is given
+    GetLifecycleEvents(TaskId, Option<SystemTime>),
+
+    /// the most recent operations which finished, oldest first, along with who requested them
+    GetOperationHistory(TaskId),
+
+    GetServerInfo(TaskId),
+
+    /// the instances on the server's machine, see [`local_instances`]
+    ListInstances(TaskId),
+    GetConnectionInfo(TaskId),
+
+    /// how many clients are connected, over the unix socket and tcp alike, including this one
+    GetClientCount(TaskId),
+
+    /// operation can only be performed by a local client
+    GetDiagnostics(TaskId),
+    GetResolvedPaths(TaskId),
+
+    /// forgets the cached java auto-detection and detects java again, e.g. after it was installed
+    RefreshJavaDetection(TaskId),
+
+    /// the version of the configured java, or of the auto-detected one if none is configured
+    GetJavaVersion(TaskId),
+
+    GetBackupRetention(TaskId),
+
+    /// changes how many backups are kept, see [`Config::backup_retention`]. a retention of zero is
+    /// refused, since it would delete every backup. the config update is broadcast as
+    /// `ConfigUpdated`.
+    SetBackupRetention(TaskId, Option<usize>),
+
+    /// checks whether the server can be launched as the user by running a harmless command as them
+    TestUser(TaskId, User),
+
+    /// the server's metrics in the prometheus text format, for scraping adapters. fails unless the
+    /// server was built with its `prometheus` feature
+    GetMetricsText(TaskId),
+
+    /// the memory and cpu usage of the server process, sampled over a short moment
+    GetResourceUsage(TaskId),
+
+    /// reads the configured tls certificate and private key from disk and checks them, e.g. so a
+    /// renewed certificate can be checked before restarting the server to serve it. fails unless
+    /// the server was built with its `tls` feature and tls is configured
+    ValidateTls(TaskId),
+
+    /// changes how much of the server's output this client receives
+    SetOutputMode(TaskId, OutputMode),
+
+    /// changes whether this client receives the server's stdout and stderr separately or merged
+    SetOutputDelivery(TaskId, OutputDelivery),
+
+    /// toggles whether new remote clients are accepted; existing clients stay connected, and local
+    /// clients are always accepted so that draining can be undone.
+    ///
+    /// operation can only be performed by a local client
+    SetAcceptingClients(TaskId, bool),
+
+    /// changes which of the server's own logs are printed, using the same syntax as `RUST_LOG`,
+    /// e.g. `debug` or `raphy_server=trace,info`
+    ///
+    /// operation can only be performed by a local client
+    SetLogLevel(TaskId, String),
+
+    /// operation can only be performed by a local client
+    GetProtocolStats(TaskId),
+
+    /// disconnects every remote client, telling them to reconnect with the given reason, e.g. so
+    /// that they pick up changed settings. local clients stay connected.
+    ///
+    /// operation can only be performed by a local client
+    DisconnectAllRemote(TaskId, String),
+
+    /// streams `Metrics` to this client on the given interval, which is raised to
+    /// `MIN_METRICS_INTERVAL` if it is shorter. subscribing again changes the interval.
+    SubscribeMetrics(TaskId, Duration),
+    UnsubscribeMetrics(TaskId),
+
+    /// ties this connection to the identity. if a connection with the same identity dropped
+    /// recently, its subscriptions, i.e. its metrics subscription and output mode and delivery,
+    /// are restored, and the subscriptions of this connection are kept for a while once it drops.
+    Identify(TaskId, ClientIdentity),
+
+    /// tells the other clients that this client opened or closed its config editor, so that they
+    /// can warn about concurrent edits. the server forgets the flag when the client disconnects.
+    SetEditingConfig(TaskId, bool),
     Input(Vec<u8>),
 
     /// operation can only be performed by a local client
@@ -87,30 +345,794 @@ impl ClientToServerMessage {
             Self::GetConfig(task_id)
             | Self::GetServerState(task_id)
             | Self::UpdateConfig(task_id, _)
-            | Self::PerformOperation(task_id, _) => Some(*task_id),
+            | Self::PatchConfig(task_id, _)
+            | Self::PerformOperation(task_id, ..)
+            | Self::GetOperationResult(task_id, _)
+            | Self::GetActiveOperation(task_id)
+            | Self::RunMaintenance(task_id, _)
+            | Self::SaveWorld(task_id)
+            | Self::BackupWorld(task_id)
+            | Self::ReloadServer(task_id)
+            | Self::UpdateJar(task_id, ..)
+            | Self::ListArgumentPresets(task_id)
+            | Self::ApplyArgumentPreset(task_id, ..)
+            | Self::GetStartupLog(task_id)
+            | Self::GetRecentOutput(task_id)
+            | Self::GetLifecycleEvents(task_id, _)
+            | Self::GetOperationHistory(task_id)
+            | Self::GetServerInfo(task_id)
+            | Self::ListInstances(task_id)
+            | Self::GetConnectionInfo(task_id)
+            | Self::GetClientCount(task_id)
+            | Self::GetDiagnostics(task_id)
+            | Self::GetResolvedPaths(task_id)
+            | Self::RefreshJavaDetection(task_id)
+            | Self::GetJavaVersion(task_id)
+            | Self::GetBackupRetention(task_id)
+            | Self::SetBackupRetention(task_id, _)
+            | Self::TestUser(task_id, _)
+            | Self::GetMetricsText(task_id)
+            | Self::GetResourceUsage(task_id)
+            | Self::ValidateTls(task_id)
+            | Self::SetOutputMode(task_id, _)
+            | Self::SetOutputDelivery(task_id, _)
+            | Self::SetAcceptingClients(task_id, _)
+            | Self::SetLogLevel(task_id, _)
+            | Self::GetProtocolStats(task_id)
+            | Self::DisconnectAllRemote(task_id, _)
+            | Self::SubscribeMetrics(task_id, _)
+            | Self::UnsubscribeMetrics(task_id)
+            | Self::Identify(task_id, _)
+            | Self::SetEditingConfig(task_id, _) => Some(*task_id),
+            _ => None,
+        }
+    }
+
+    /// the name of the message's variant, e.g. for statistics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Handshake(..) => "Handshake",
+            Self::Ping(..) => "Ping",
+            Self::GetConfig(..) => "GetConfig",
+            Self::GetServerState(..) => "GetServerState",
+            Self::UpdateConfig(..) => "UpdateConfig",
+            Self::PatchConfig(..) => "PatchConfig",
+            Self::PerformOperation(..) => "PerformOperation",
+            Self::GetOperationResult(..) => "GetOperationResult",
+            Self::GetActiveOperation(..) => "GetActiveOperation",
+            Self::RunMaintenance(..) => "RunMaintenance",
+            Self::SaveWorld(..) => "SaveWorld",
+            Self::BackupWorld(..) => "BackupWorld",
+            Self::ReloadServer(..) => "ReloadServer",
+            Self::UpdateJar(..) => "UpdateJar",
+            Self::ListArgumentPresets(..) => "ListArgumentPresets",
+            Self::ApplyArgumentPreset(..) => "ApplyArgumentPreset",
+            Self::GetStartupLog(..) => "GetStartupLog",
+            Self::GetRecentOutput(..) => "GetRecentOutput",
+            Self::GetLifecycleEvents(..) => "GetLifecycleEvents",
+            Self::GetOperationHistory(..) => "GetOperationHistory",
+            Self::GetServerInfo(..) => "GetServerInfo",
+            Self::ListInstances(..) => "ListInstances",
+            Self::GetConnectionInfo(..) => "GetConnectionInfo",
+            Self::GetClientCount(..) => "GetClientCount",
+            Self::GetDiagnostics(..) => "GetDiagnostics",
+            Self::GetResolvedPaths(..) => "GetResolvedPaths",
+            Self::RefreshJavaDetection(..) => "RefreshJavaDetection",
+            Self::GetJavaVersion(..) => "GetJavaVersion",
+            Self::GetBackupRetention(..) => "GetBackupRetention",
+            Self::SetBackupRetention(..) => "SetBackupRetention",
+            Self::TestUser(..) => "TestUser",
+            Self::GetMetricsText(..) => "GetMetricsText",
+            Self::GetResourceUsage(..) => "GetResourceUsage",
+            Self::ValidateTls(..) => "ValidateTls",
+            Self::SetOutputMode(..) => "SetOutputMode",
+            Self::SetOutputDelivery(..) => "SetOutputDelivery",
+            Self::SetAcceptingClients(..) => "SetAcceptingClients",
+            Self::SetLogLevel(..) => "SetLogLevel",
+            Self::GetProtocolStats(..) => "GetProtocolStats",
+            Self::DisconnectAllRemote(..) => "DisconnectAllRemote",
+            Self::SubscribeMetrics(..) => "SubscribeMetrics",
+            Self::UnsubscribeMetrics(..) => "UnsubscribeMetrics",
+            Self::Identify(..) => "Identify",
+            Self::SetEditingConfig(..) => "SetEditingConfig",
+            Self::Input(..) => "Input",
+            Self::Shutdown => "Shutdown",
+        }
+    }
+
+    /// what the client needs to be allowed to send the message, if anything beyond viewing the
+    /// server
+    pub fn required_permission(&self) -> Option<Permission> {
+        match self {
+            Self::PerformOperation(..)
+            | Self::RunMaintenance(..)
+            | Self::SaveWorld(_)
+            | Self::BackupWorld(_)
+            | Self::ReloadServer(_)
+            | Self::Shutdown => Some(Permission::Control),
+            Self::UpdateConfig(..)
+            | Self::PatchConfig(..)
+            | Self::UpdateJar(..)
+            | Self::ApplyArgumentPreset(..)
+            | Self::SetBackupRetention(..)
+            | Self::TestUser(..)
+            | Self::SetEditingConfig(..) => Some(Permission::Config),
+            Self::Input(_) => Some(Permission::Input),
             _ => None,
         }
     }
 }
 
-#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+/// something a client may be allowed to do beyond viewing the server, e.g. its state, config and
+/// output
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Permission {
+    /// starting, stopping and restarting the server, and operations on it such as saving the world
+    Control,
+
+    /// changing the config
+    Config,
+
+    /// writing to the server's console
+    Input,
+}
+
+/// the [`Permission`]s of a client. local clients have every permission.
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    pub control: bool,
+    pub config: bool,
+    pub input: bool,
+}
+
+impl Permissions {
+    pub const ALL: Self = Self {
+        control: true,
+        config: true,
+        input: true,
+    };
+
+    /// can only view the server
+    pub const VIEW_ONLY: Self = Self {
+        control: false,
+        config: false,
+        input: false,
+    };
+
+    pub fn allows(self, permission: Permission) -> bool {
+        match permission {
+            Permission::Control => self.control,
+            Permission::Config => self.config,
+            Permission::Input => self.input,
+        }
+    }
+
+    /// parses a comma-separated list of permissions, e.g. `control,input`, or `all` or `view-only`
+    pub fn parse(permissions: &str) -> anyhow::Result<Self> {
+        match permissions.trim() {
+            "all" => return Ok(Self::ALL),
+            "view-only" | "" => return Ok(Self::VIEW_ONLY),
+            _ => {}
+        }
+
+        let mut parsed = Self::VIEW_ONLY;
+        for permission in permissions.split(',').map(str::trim) {
+            match permission {
+                "control" => parsed.control = true,
+                "config" => parsed.config = true,
+                "input" => parsed.input = true,
+                permission => anyhow::bail!(
+                    "'{permission}' isn't a permission; the permissions are control, config and \
+                     input."
+                ),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// why the server process stopped
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopCause {
+    /// the server was stopped or restarted on request, e.g. by a client or the watchdog
+    Requested,
+
+    /// the server exited by itself, e.g. because it crashed or `/stop` was run in its console
+    Unexpected,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ServerState {
     Started,
-    Stopped(Option<ExitStatus>),
+
+    /// the exit status and the cause are `None` if the server hasn't run since raphy started, or
+    /// if they aren't known
+    Stopped(Option<ExitStatus>, Option<StopCause>),
+}
+
+/// a step of a maintenance cycle, see [`ClientToServerMessage::RunMaintenance`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaintenanceStep {
+    /// stops the server gracefully, which is already done if it isn't running
+    Stop,
+
+    /// runs the configured [`Config::backup_command`], failing if there is none
+    Backup,
+    Start,
+}
+
+impl MaintenanceStep {
+    /// the usual maintenance cycle: stopping the server, backing it up, and starting it again
+    pub const CYCLE: [Self; 3] = [Self::Stop, Self::Backup, Self::Start];
+}
+
+/// how far a maintenance cycle got
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct MaintenanceReport {
+    /// the steps which succeeded, in the order they ran
+    pub completed: Vec<MaintenanceStep>,
+
+    /// the step which failed and why, after which the remaining steps weren't run. note that the
+    /// server is left as the completed steps left it, e.g. stopped if the backup failed.
+    pub failed: Option<(MaintenanceStep, SerdeError)>,
+}
+
+/// an operation which is currently being performed
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ActiveOperation {
+    pub operation: Operation,
+    pub operation_id: OperationId,
+
+    /// the connection of the client which requested the operation
+    pub started_by: ConnectionInfo,
+
+    /// how long ago the operation was requested
+    pub elapsed: Duration,
+}
+
+/// an operation which finished, see [`ClientToServerMessage::GetOperationHistory`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct OperationRecord {
+    pub operation: Operation,
+    pub operation_id: OperationId,
+
+    /// the connection of the client which requested the operation
+    pub started_by: ConnectionInfo,
+    pub requested_at: SystemTime,
+
+    /// how long the operation took
+    pub duration: Duration,
+
+    /// why the operation failed, or `None` if it was performed
+    pub error: Option<SerdeError>,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    /// one of the 256 indexed colors, where the first 16 are the standard and bright colors
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    /// `None` for the client's default color
+    pub foreground: Option<Color>,
+
+    /// `None` for the client's default color
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct StyledSpan {
+    pub text: Vec<u8>,
+    pub style: TextStyle,
+}
+
+/// server output with its ANSI escape sequences translated into styles
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct Log {
+    pub stream: OutputStream,
+
+    /// whether `stream` is the one the server writes its regular output to; if not, the output is
+    /// likely an error
+    pub primary: bool,
+    pub spans: Vec<StyledSpan>,
+}
+
+impl Log {
+    /// the output without its styles
+    pub fn text(&self) -> Vec<u8> {
+        self.spans
+            .iter()
+            .flat_map(|span| span.text.iter().copied())
+            .collect()
+    }
+}
+
+/// the config a client gets when it asks for one
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub enum CurrentConfig {
+    /// the config the server is using
+    Saved(Config),
+
+    /// the server is unconfigured, so this is a suggested starting point which hasn't been saved.
+    /// see [`Config::template`].
+    Template(Config),
+}
+
+impl CurrentConfig {
+    pub fn config(&self) -> &Config {
+        match self {
+            Self::Saved(config) | Self::Template(config) => config,
+        }
+    }
+
+    /// the saved config, if the server is configured
+    pub fn saved(self) -> Option<Config> {
+        match self {
+            Self::Saved(config) => Some(config),
+            Self::Template(_) => None,
+        }
+    }
+}
+
+/// how much of the server's output is sent to a client
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// all output is sent
+    #[default]
+    Full,
+
+    /// output is rate limited, with a summary of how many lines were suppressed sent in place of
+    /// the rest. meant for clients on slow links, which would otherwise fall behind during a flood.
+    Sampled,
+
+    /// no output is sent
+    Off,
+}
+
+/// what a client asks of the server in its handshake
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct HandshakeOptions {
+    /// whether large output may be sent compressed, which is worth it for remote clients but
+    /// leaves the client to inflate it. see [`ServerToClientMessage::compressed`].
+    pub compress_output: bool,
+}
+
+/// how the server's stdout and stderr are sent to a client
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum OutputDelivery {
+    /// plain output is sent as separate [`ServerToClientMessage::Stdout`] and
+    /// [`ServerToClientMessage::Stderr`] messages
+    #[default]
+    Split,
+
+    /// all output is sent as [`ServerToClientMessage::Log`] messages in the order it was read, so
+    /// it can be shown as one stream while still telling which stream each part came from
+    Merged,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct SaveResult {
+    /// whether the server confirmed the save; `false` means the confirmation wasn't seen in time,
+    /// not necessarily that the save failed
+    pub success: bool,
+}
+
+/// how far a world backup got, see [`ClientToServerMessage::BackupWorld`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct BackupProgress {
+    pub archived_files: u64,
+    pub total_files: u64,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct ReloadResult {
+    /// whether the server confirmed the reload; `false` means the confirmation wasn't seen in time,
+    /// not necessarily that the reload failed
+    pub success: bool,
+}
+
+/// the output of the most recent server start, from spawning the process until it became ready
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StartupLog {
+    /// stdout and stderr, interleaved in the order they were received
+    pub output: Vec<u8>,
+
+    /// whether the server finished starting up; if `false`, the server either is still starting or
+    /// stopped before it became ready
+    pub complete: bool,
+}
+
+/// a transition in the server's lifecycle
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LifecycleEventKind {
+    Started,
+
+    /// the server finished starting up and accepts players
+    Ready,
+    Stopped(Option<ExitStatus>, Option<StopCause>),
+    WatchdogTriggered(WatchdogAction),
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct LifecycleEvent {
+    pub timestamp: SystemTime,
+    pub kind: LifecycleEventKind,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ServerInfo {
+    /// the port raphy itself listens on for tcp clients
+    pub control_port: u16,
+
+    /// the port the managed server listens on for players, as read from `server.properties`
+    pub game_port: Option<u16>,
+
+    /// the [`BUILD_VERSION`] of the server
+    pub build_version: String,
+
+    /// the stream the server writes its regular output to, so that output on the other one can be
+    /// told apart as errors
+    pub primary_output_stream: OutputStream,
+
+    /// the name of the instance, or `None` for the default one. see [`INSTANCE_ENV_VAR`].
+    pub instance: Option<String>,
+
+    /// whether the config file differs from the config in use, e.g. because it was edited by
+    /// hand. the file is only read when raphy starts, so it has to be restarted to apply it.
+    pub config_dirty: bool,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// a local client connected through the unix socket
+    Unix,
+
+    /// a remote client connected over tcp
+    Tcp,
+
+    /// raphy itself, e.g. for a scheduled restart. it has no peer.
+    Internal,
+}
+
+/// how a client is connected, from the server's point of view
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct ConnectionInfo {
+    pub transport: Transport,
+    pub peer: Option<SocketAddr>,
+
+    /// what the client is allowed to do, e.g. so that a ui can hide the controls it can't use
+    pub permissions: Permissions,
+}
+
+/// a client which has its config editor open, see [`ClientToServerMessage::SetEditingConfig`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct ConfigEditor {
+    /// the identity the client identified with, if it did
+    pub identity: Option<ClientIdentity>,
+    pub connection: ConnectionInfo,
+}
+
+/// where the server's config is stored
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigSource {
+    /// the path the config was last loaded from or saved to
+    pub path: PathBuf,
+
+    /// whether a config file existed at this path when the server started; if `false`, the server
+    /// started unconfigured
+    pub loaded: bool,
+}
+
+/// memory usage of the server process and everything it started, as tracked by the kernel
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct MemoryUsage {
+    /// bytes currently in use
+    pub current: u64,
+
+    /// the limit in bytes, if any
+    pub limit: Option<u64>,
+
+    /// how many times a process was killed for going over the limit
+    pub oom_kills: u64,
+}
+
+/// the shortest interval metrics are streamed on
+pub const MIN_METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// a sample of the resources used by the server. each is `None` if it isn't known, e.g. the
+/// process metrics while the server isn't running.
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Metrics {
+    pub timestamp: SystemTime,
+    pub rss_bytes: Option<u64>,
+
+    /// the share of a single core used since the previous sample, so it can exceed 100
+    pub cpu_percent: Option<f32>,
+
+    /// bytes available on the disk the server's working directory is on
+    pub disk_free: Option<u64>,
+}
+
+/// the resources used by the server process, each `None` while the server isn't running or if it
+/// isn't known
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct ResourceUsage {
+    pub rss_bytes: Option<u64>,
+
+    /// the share of a single core used while sampling, so it can exceed 100
+    pub cpu_percent: Option<f32>,
+
+    /// the cpu time the process used since it started
+    pub cpu_time: Option<Duration>,
+}
+
+/// what was found out about the tls certificate and private key the server is configured with
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct TlsReport {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+
+    /// the distinguished name of the leaf certificate, e.g. `CN=example.com`
+    pub subject: String,
+
+    /// the dns names and ip addresses the leaf certificate is valid for
+    pub subject_alt_names: Vec<String>,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+
+    /// how many certificates the chain has, including the leaf
+    pub chain_len: usize,
+
+    /// why the certificate and key can't be served, e.g. because the certificate expired or the
+    /// key doesn't belong to it. empty if nothing is wrong with them.
+    pub problems: Vec<String>,
+}
+
+impl TlsReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
 }
 
-#[derive(Encode, Decode, Debug, Clone)]
+/// traffic counters of the server's network task, since it started
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProtocolStats {
+    pub frames_received: u64,
+    pub frames_sent: u64,
+
+    /// including the length prefixes of frames
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+
+    /// frames which couldn't be decoded; the client which sent one is disconnected
+    pub decode_errors: u64,
+
+    /// frames received, by message name
+    pub received_by_message: BTreeMap<String, u64>,
+
+    /// frames sent, by message name
+    pub sent_by_message: BTreeMap<String, u64>,
+
+    /// how many messages were waiting to be sent to each connected client, as of its last write
+    pub queue_depths: Vec<(ConnectionInfo, usize)>,
+}
+
+/// the absolute locations the config points to, as the server sees them
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ResolvedPaths {
+    pub working_dir: PathBuf,
+
+    /// `None` if the server is launched by a script
+    pub server_jar_path: Option<PathBuf>,
+
+    /// `None` if the server is launched with java
+    pub launch_script: Option<PathBuf>,
+
+    /// `None` if java couldn't be found
+    pub java_path: Option<PathBuf>,
+}
+
+/// whether raphy can launch processes as a user, as tested without starting the server
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UserTestOutcome {
+    Ok,
+    NoSuchUser,
+
+    /// sudo asks for a password, so launching the server as the user would hang
+    PasswordRequired,
+
+    /// the user raphy runs as isn't allowed to run commands as the user
+    NotPermitted,
+
+    /// sudo failed for another reason, described by the string
+    Failed(String),
+}
+
+/// the version of a java installation, as reported by `java -version`
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct JavaVersion {
+    pub path: PathBuf,
+
+    /// the full version, e.g. `21.0.2` or `1.8.0_392`
+    pub version: String,
+
+    /// the major version, e.g. `21` or `8`, to compare against what a server jar requires
+    pub major: u32,
+}
+
+/// the backups in [`Config::backup_dir`]
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct BackupUsage {
+    pub count: usize,
+
+    /// the size of every backup combined, in bytes
+    pub bytes: u64,
+}
+
+/// the report the server wrote to its `crash-reports` directory when it last crashed
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct CrashReport {
+    pub path: PathBuf,
+    pub written_at: SystemTime,
+
+    /// the start of the report, which has the description and stack trace of the crash
+    pub contents: String,
+
+    /// whether the rest of the report was left out since it's too long
+    pub truncated: bool,
+}
+
+/// everything useful for a support request, gathered in one place
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct Diagnostics {
+    pub config: Option<Config>,
+    pub config_source: ConfigSource,
+    pub java_path: Option<PathBuf>,
+
+    /// only available if java could be found and reported its version
+    pub java_version: Option<JavaVersion>,
+    pub server_state: ServerState,
+    pub recent_operations: Vec<(OperationId, OperationOutcome)>,
+
+    /// how many bytes of server output are currently buffered
+    pub output_buffer_usage: usize,
+
+    /// whether new remote clients are accepted
+    pub accepting_clients: bool,
+
+    /// only available while the server is running with a memory limit
+    pub memory_usage: Option<MemoryUsage>,
+
+    /// only available when a backup directory is configured and could be read
+    pub backups: Option<BackupUsage>,
+
+    /// the directives the server's own logs are filtered with
+    pub log_level: Option<String>,
+
+    /// the most recent lines of the server's own logs at the info level or above, oldest first
+    pub recent_logs: Vec<String>,
+
+    /// only available if the server crashed and wrote a crash report at some point
+    pub last_crash_report: Option<CrashReport>,
+    pub daemon_version: String,
+    pub os: String,
+    pub arch: String,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
 pub enum ServerToClientMessage {
+    /// the server's response to a handshake from a client with another [`PROTOCOL_VERSION`], after
+    /// which it disconnects the client. this, [`ServerToClientMessage::HandshakeAccepted`] and
+    /// [`ServerToClientMessage::UnsupportedMessage`] must stay the first variants so that they
+    /// decode the same across versions.
+    VersionMismatch { expected: u32, got: u32 },
+    HandshakeAccepted,
+
+    /// sent instead of a response when the client sent a message the server doesn't know, likely
+    /// because the client is newer. `type_hint` is the discriminant of the message's variant. the
+    /// connection stays open, so the client can fall back to something the server supports.
+    UnsupportedMessage { type_hint: u32 },
     Pong(TaskId),
-    CurrentConfig(Option<Config>, TaskId),
+    CurrentConfig(CurrentConfig, TaskId),
     CurrentServerState(ServerState, TaskId),
     ConfigUpdated(Config, Option<TaskId>),
     OperationRequested(Operation, OperationId),
     OperationPerformed(Operation, OperationId, Option<TaskId>),
     OperationFailed(Operation, OperationId, SerdeError, Option<TaskId>),
+    OperationResult(Option<OperationOutcome>, TaskId),
+    ActiveOperation(Option<ActiveOperation>, TaskId),
+    MaintenanceStepStarted(MaintenanceStep, TaskId),
+    MaintenanceFinished(MaintenanceReport, TaskId),
+    SaveResult(SaveResult, TaskId),
+    BackupProgress(BackupProgress, TaskId),
+
+    /// the world was backed up to the archive at this path
+    WorldBackedUp(PathBuf, TaskId),
+    ReloadResult(ReloadResult, TaskId),
+    StartupLog(StartupLog, TaskId),
+
+    /// stdout and stderr, interleaved in the order they were received. at most the last 500 lines
+    /// or 64 KiB are kept, less if the output buffer budget is smaller.
+    RecentOutput(Vec<u8>, TaskId),
+    ArgumentPresets(Vec<ArgumentPresetInfo>, TaskId),
+    LifecycleEvents(Vec<LifecycleEvent>, TaskId),
+    OperationHistory(Vec<OperationRecord>, TaskId),
+    ServerInfo(ServerInfo, TaskId),
+    Instances(Vec<Option<String>>, TaskId),
+    ConnectionInfo(ConnectionInfo, TaskId),
+    ClientCount(usize, TaskId),
+    Diagnostics(Box<Diagnostics>, TaskId),
+    ResolvedPaths(ResolvedPaths, TaskId),
+
+    /// the auto-detected java path, if java was found
+    JavaDetected(Option<PathBuf>, TaskId),
+    JavaVersion(JavaVersion, TaskId),
+    BackupRetention(Option<usize>, TaskId),
+    UserTested(UserTestOutcome, TaskId),
+    MetricsText(String, TaskId),
+    ResourceUsage(ResourceUsage, TaskId),
+    TlsValidated(TlsReport, TaskId),
+    OutputModeUpdated(OutputMode, TaskId),
+    OutputDeliveryUpdated(OutputDelivery, TaskId),
+    AcceptingClientsUpdated(bool, TaskId),
+    LogLevelUpdated(String, TaskId),
+    ProtocolStats(Box<ProtocolStats>, TaskId),
+
+    /// how many remote clients were disconnected
+    RemoteClientsDisconnected(usize, TaskId),
+
+    /// carries the interval metrics are streamed on, after bounding it
+    MetricsSubscribed(Duration, TaskId),
+    MetricsUnsubscribed(TaskId),
+
+    /// whether subscriptions were restored for the identity
+    Identified(bool, TaskId),
+
+    /// the other clients which are editing the config, sent in response to `SetEditingConfig`
+    ConfigEditors(Vec<ConfigEditor>, TaskId),
+    Metrics(Metrics),
     ServerStateUpdated(ServerState),
+
+    /// the server exited without being asked to, i.e. stopped with [`StopCause::Unexpected`],
+    /// carrying its exit status if it's known. sent after the `ServerStateUpdated` for the stop,
+    /// so that clients can alert on crashes without inspecting every state change.
+    ServerCrashed(Option<ExitStatus>),
     Stdout(Vec<u8>),
     Stderr(Vec<u8>),
+
+    /// `Stdout` and `Stderr` with their output deflated, which the server only sends to clients
+    /// that asked for it in their [`HandshakeOptions`]. see
+    /// [`ServerToClientMessage::decompressed`].
+    StdoutCompressed(Vec<u8>),
+    StderrCompressed(Vec<u8>),
+
+    /// sent in place of `Stdout` and `Stderr` when the server is configured to structure its output
+    Log(Log),
+
+    /// the watchdog considered the server hung, and took the action, for the given reason
+    WatchdogTriggered(WatchdogAction, String),
+
+    /// another client opened its config editor. it isn't sent to the client itself.
+    ConfigEditingStarted(ConfigEditor),
+
+    /// another client closed its config editor, or disconnected while editing
+    ConfigEditingStopped(ConfigEditor),
+
+    /// the server is closing the connection, and the client should connect again, for the given
+    /// reason. this is the last message sent on the connection.
+    Reconnect(String),
     FatalError(SerdeError),
     Error(SerdeError, Option<TaskId>),
     ShuttingDown,
@@ -121,7 +1143,43 @@ impl ServerToClientMessage {
         match self {
             Self::Pong(task_id)
             | Self::CurrentConfig(_, task_id)
-            | Self::CurrentServerState(_, task_id) => Some(*task_id),
+            | Self::CurrentServerState(_, task_id)
+            | Self::OperationResult(_, task_id)
+            | Self::ActiveOperation(_, task_id)
+            | Self::MaintenanceStepStarted(_, task_id)
+            | Self::MaintenanceFinished(_, task_id)
+            | Self::SaveResult(_, task_id)
+            | Self::BackupProgress(_, task_id)
+            | Self::WorldBackedUp(_, task_id)
+            | Self::ReloadResult(_, task_id)
+            | Self::StartupLog(_, task_id)
+            | Self::RecentOutput(_, task_id)
+            | Self::ArgumentPresets(_, task_id)
+            | Self::LifecycleEvents(_, task_id)
+            | Self::OperationHistory(_, task_id)
+            | Self::ServerInfo(_, task_id)
+            | Self::Instances(_, task_id)
+            | Self::ConnectionInfo(_, task_id)
+            | Self::ClientCount(_, task_id)
+            | Self::Diagnostics(_, task_id)
+            | Self::ResolvedPaths(_, task_id)
+            | Self::JavaDetected(_, task_id)
+            | Self::JavaVersion(_, task_id)
+            | Self::BackupRetention(_, task_id)
+            | Self::UserTested(_, task_id)
+            | Self::MetricsText(_, task_id)
+            | Self::ResourceUsage(_, task_id)
+            | Self::TlsValidated(_, task_id)
+            | Self::OutputModeUpdated(_, task_id)
+            | Self::OutputDeliveryUpdated(_, task_id)
+            | Self::AcceptingClientsUpdated(_, task_id)
+            | Self::LogLevelUpdated(_, task_id)
+            | Self::ProtocolStats(_, task_id)
+            | Self::RemoteClientsDisconnected(_, task_id)
+            | Self::MetricsSubscribed(_, task_id)
+            | Self::MetricsUnsubscribed(task_id)
+            | Self::Identified(_, task_id)
+            | Self::ConfigEditors(_, task_id) => Some(*task_id),
             Self::ConfigUpdated(_, task_id)
             | Self::OperationPerformed(_, _, task_id)
             | Self::OperationFailed(_, _, _, task_id)
@@ -138,4 +1196,185 @@ impl ServerToClientMessage {
             _ => None,
         }
     }
+
+    /// the name of the message's variant, e.g. for statistics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::VersionMismatch { .. } => "VersionMismatch",
+            Self::HandshakeAccepted => "HandshakeAccepted",
+            Self::UnsupportedMessage { .. } => "UnsupportedMessage",
+            Self::Pong(..) => "Pong",
+            Self::CurrentConfig(..) => "CurrentConfig",
+            Self::CurrentServerState(..) => "CurrentServerState",
+            Self::ConfigUpdated(..) => "ConfigUpdated",
+            Self::OperationRequested(..) => "OperationRequested",
+            Self::OperationPerformed(..) => "OperationPerformed",
+            Self::OperationFailed(..) => "OperationFailed",
+            Self::OperationResult(..) => "OperationResult",
+            Self::ActiveOperation(..) => "ActiveOperation",
+            Self::MaintenanceStepStarted(..) => "MaintenanceStepStarted",
+            Self::MaintenanceFinished(..) => "MaintenanceFinished",
+            Self::SaveResult(..) => "SaveResult",
+            Self::BackupProgress(..) => "BackupProgress",
+            Self::WorldBackedUp(..) => "WorldBackedUp",
+            Self::ReloadResult(..) => "ReloadResult",
+            Self::StartupLog(..) => "StartupLog",
+            Self::RecentOutput(..) => "RecentOutput",
+            Self::ArgumentPresets(..) => "ArgumentPresets",
+            Self::LifecycleEvents(..) => "LifecycleEvents",
+            Self::OperationHistory(..) => "OperationHistory",
+            Self::ServerInfo(..) => "ServerInfo",
+            Self::Instances(..) => "Instances",
+            Self::ConnectionInfo(..) => "ConnectionInfo",
+            Self::ClientCount(..) => "ClientCount",
+            Self::Diagnostics(..) => "Diagnostics",
+            Self::ResolvedPaths(..) => "ResolvedPaths",
+            Self::JavaDetected(..) => "JavaDetected",
+            Self::JavaVersion(..) => "JavaVersion",
+            Self::BackupRetention(..) => "BackupRetention",
+            Self::UserTested(..) => "UserTested",
+            Self::MetricsText(..) => "MetricsText",
+            Self::ResourceUsage(..) => "ResourceUsage",
+            Self::TlsValidated(..) => "TlsValidated",
+            Self::OutputModeUpdated(..) => "OutputModeUpdated",
+            Self::OutputDeliveryUpdated(..) => "OutputDeliveryUpdated",
+            Self::AcceptingClientsUpdated(..) => "AcceptingClientsUpdated",
+            Self::LogLevelUpdated(..) => "LogLevelUpdated",
+            Self::ProtocolStats(..) => "ProtocolStats",
+            Self::RemoteClientsDisconnected(..) => "RemoteClientsDisconnected",
+            Self::MetricsSubscribed(..) => "MetricsSubscribed",
+            Self::MetricsUnsubscribed(..) => "MetricsUnsubscribed",
+            Self::Identified(..) => "Identified",
+            Self::ConfigEditors(..) => "ConfigEditors",
+            Self::Metrics(..) => "Metrics",
+            Self::ServerStateUpdated(..) => "ServerStateUpdated",
+            Self::ServerCrashed(..) => "ServerCrashed",
+            Self::Stdout(..) => "Stdout",
+            Self::Stderr(..) => "Stderr",
+            Self::StdoutCompressed(..) => "StdoutCompressed",
+            Self::StderrCompressed(..) => "StderrCompressed",
+            Self::Log(..) => "Log",
+            Self::WatchdogTriggered(..) => "WatchdogTriggered",
+            Self::ConfigEditingStarted(..) => "ConfigEditingStarted",
+            Self::ConfigEditingStopped(..) => "ConfigEditingStopped",
+            Self::Reconnect(..) => "Reconnect",
+            Self::FatalError(..) => "FatalError",
+            Self::Error(..) => "Error",
+            Self::ShuttingDown => "ShuttingDown",
+        }
+    }
+
+    /// compresses `Stdout` and `Stderr` messages with at least [`OUTPUT_COMPRESSION_THRESHOLD`]
+    /// bytes of output into their compressed variants. other messages, and output which doesn't
+    /// get any smaller, are returned as they are.
+    pub fn compressed(self) -> Self {
+        type Variant = fn(Vec<u8>) -> ServerToClientMessage;
+        let (output, uncompressed, compressed): (_, Variant, Variant) = match self {
+            Self::Stdout(output) => (output, Self::Stdout, Self::StdoutCompressed),
+            Self::Stderr(output) => (output, Self::Stderr, Self::StderrCompressed),
+            message => return message,
+        };
+        if output.len() < OUTPUT_COMPRESSION_THRESHOLD {
+            return uncompressed(output);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+
+        // writing to a vec can't fail
+        encoder.write_all(&output).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        if deflated.len() < output.len() {
+            compressed(deflated)
+        } else {
+            uncompressed(output)
+        }
+    }
+
+    /// turns `StdoutCompressed` and `StderrCompressed` messages back into `Stdout` and `Stderr`.
+    /// other messages are returned as they are. fails if the output is corrupt, or would inflate
+    /// to more than [`MAX_FRAME_LEN`] bytes.
+    pub fn decompressed(self) -> io::Result<Self> {
+        let (deflated, decompressed): (_, fn(Vec<u8>) -> Self) = match self {
+            Self::StdoutCompressed(deflated) => (deflated, Self::Stdout),
+            Self::StderrCompressed(deflated) => (deflated, Self::Stderr),
+            message => return Ok(message),
+        };
+
+        let mut output = Vec::with_capacity(deflated.len() * 4);
+        DeflateDecoder::new(deflated.as_slice())
+            .take(MAX_FRAME_LEN as u64 + 1)
+            .read_to_end(&mut output)?;
+        if output.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the compressed output inflates to more than the maximum frame length",
+            ));
+        }
+
+        Ok(decompressed(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// decodes what `message` encodes to as json, encoding the result again so that it can be
+    /// compared without the messages having to be comparable
+    fn json_round_trip<T: Serialize + serde::de::DeserializeOwned>(
+        message: &T,
+    ) -> (String, String) {
+        let json = serde_json::to_string(message).unwrap();
+        let decoded: T = serde_json::from_str(&json).unwrap();
+        (json, serde_json::to_string(&decoded).unwrap())
+    }
+
+    #[test]
+    fn task_ids_round_trip_through_json() {
+        let task_id = TaskId::generate();
+        let json = serde_json::to_string(&task_id).unwrap();
+        assert_eq!(serde_json::from_str::<TaskId>(&json).unwrap(), task_id);
+    }
+
+    #[test]
+    fn client_messages_round_trip_through_json() {
+        let task_id = TaskId::generate();
+        let messages = [
+            ClientToServerMessage::Handshake(PROTOCOL_VERSION, HandshakeOptions::default()),
+            ClientToServerMessage::UpdateConfig(task_id, Box::default()),
+            ClientToServerMessage::PerformOperation(
+                task_id,
+                Operation::Restart,
+                Some(IdempotencyKey::generate()),
+            ),
+            ClientToServerMessage::PatchConfig(task_id, ConfigPatch::MemoryLimitMb(Some(4096))),
+            ClientToServerMessage::Input(b"say hello\n".to_vec()),
+        ];
+        for message in messages {
+            let (json, again) = json_round_trip(&message);
+            assert_eq!(json, again);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip_through_json() {
+        let task_id = TaskId::generate();
+        let error = anyhow::Error::new(ErrorKind::TooManyStreams(4)).context("Too many streams.");
+        let messages = [
+            ServerToClientMessage::CurrentServerState(ServerState::Started, task_id),
+            ServerToClientMessage::ConfigUpdated(Config::default(), Some(task_id)),
+            ServerToClientMessage::OperationPerformed(
+                Operation::Stop,
+                OperationId::generate(),
+                Some(task_id),
+            ),
+            ServerToClientMessage::Stdout(b"[Server thread/INFO]: Done (3.2s)!\n".to_vec()),
+            ServerToClientMessage::Error(SerdeError::new(&*error), Some(task_id)),
+        ];
+        for message in messages {
+            let (json, again) = json_round_trip(&message);
+            assert_eq!(json, again);
+        }
+    }
 }
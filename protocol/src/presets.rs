@@ -0,0 +1,163 @@
+use crate::config::Arguments;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// the least memory any preset can be applied with
+const MIN_MEMORY_MB: u32 = 512;
+
+/// the most memory any preset can be applied with, well beyond any machine a server runs on, so
+/// that a typo such as an extra zero is caught
+const MAX_MEMORY_MB: u32 = 1024 * 1024;
+
+/// above this much memory, aikar's flags give the young generation more room
+const AIKAR_LARGE_HEAP_MB: u32 = 12 * 1024;
+
+/// a built-in set of java arguments, for users who don't know which flags are good for a server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArgumentPreset {
+    /// only sets the heap size
+    Basic,
+
+    /// the g1 tuning from <https://docs.papermc.io/paper/aikars-flags>
+    Aikar,
+
+    /// the z garbage collector, which keeps pauses short on large heaps
+    Zgc,
+}
+
+/// describes an [`ArgumentPreset`] to a client, e.g. for a picker
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone)]
+pub struct ArgumentPresetInfo {
+    pub name: String,
+    pub description: String,
+
+    /// the least memory the preset can be applied with
+    pub min_memory_mb: u32,
+
+    /// the memory to suggest when the user hasn't picked any
+    pub recommended_memory_mb: u32,
+}
+
+impl ArgumentPreset {
+    pub const ALL: [Self; 3] = [Self::Basic, Self::Aikar, Self::Zgc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Basic => "Basic",
+            Self::Aikar => "Aikar's Flags",
+            Self::Zgc => "ZGC",
+        }
+    }
+
+    /// finds the preset with the given name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Basic => "Only sets the heap size, leaving everything else to the JVM.",
+            Self::Aikar => {
+                "G1 tuned for Minecraft servers, which keeps garbage collection pauses short. A \
+                 good default for most servers."
+            }
+            Self::Zgc => {
+                "The Z garbage collector, which keeps pauses short even on large heaps. Requires \
+                 Java 17 or newer."
+            }
+        }
+    }
+
+    pub fn min_memory_mb(self) -> u32 {
+        match self {
+            Self::Basic => MIN_MEMORY_MB,
+            Self::Aikar => 2 * 1024,
+            Self::Zgc => 8 * 1024,
+        }
+    }
+
+    pub fn recommended_memory_mb(self) -> u32 {
+        match self {
+            Self::Basic => 2 * 1024,
+            Self::Aikar => 6 * 1024,
+            Self::Zgc => 16 * 1024,
+        }
+    }
+
+    pub fn info(self) -> ArgumentPresetInfo {
+        ArgumentPresetInfo {
+            name: self.name().to_owned(),
+            description: self.description().to_owned(),
+            min_memory_mb: self.min_memory_mb(),
+            recommended_memory_mb: self.recommended_memory_mb(),
+        }
+    }
+
+    /// the java arguments of the preset for a heap of `memory_mb` megabytes.
+    pub fn arguments(self, memory_mb: u32) -> anyhow::Result<Arguments> {
+        anyhow::ensure!(
+            memory_mb >= self.min_memory_mb(),
+            "{} needs at least {} MB of memory, but only {memory_mb} MB were given.",
+            self.name(),
+            self.min_memory_mb()
+        );
+        anyhow::ensure!(
+            memory_mb <= MAX_MEMORY_MB,
+            "{memory_mb} MB of memory is more than the maximum of {MAX_MEMORY_MB} MB."
+        );
+
+        // the heap is allocated upfront, so that it doesn't have to grow while the server runs
+        let mut arguments = vec![format!("-Xms{memory_mb}M"), format!("-Xmx{memory_mb}M")];
+        match self {
+            Self::Basic => {}
+            Self::Aikar => {
+                // larger heaps get a larger young generation and larger regions
+                let (new_size, max_new_size, region_size, reserve, occupancy) =
+                    if memory_mb > AIKAR_LARGE_HEAP_MB {
+                        (40, 50, 16, 15, 20)
+                    } else {
+                        (30, 40, 8, 20, 15)
+                    };
+                arguments.extend(
+                    [
+                        "-XX:+UseG1GC",
+                        "-XX:+ParallelRefProcEnabled",
+                        "-XX:MaxGCPauseMillis=200",
+                        "-XX:+UnlockExperimentalVMOptions",
+                        "-XX:+DisableExplicitGC",
+                        "-XX:+AlwaysPreTouch",
+                        &format!("-XX:G1NewSizePercent={new_size}"),
+                        &format!("-XX:G1MaxNewSizePercent={max_new_size}"),
+                        &format!("-XX:G1HeapRegionSize={region_size}M"),
+                        &format!("-XX:G1ReservePercent={reserve}"),
+                        "-XX:G1HeapWastePercent=5",
+                        "-XX:G1MixedGCCountTarget=4",
+                        &format!("-XX:InitiatingHeapOccupancyPercent={occupancy}"),
+                        "-XX:G1MixedGCLiveThresholdPercent=90",
+                        "-XX:G1RSetUpdatingPauseTimePercent=5",
+                        "-XX:SurvivorRatio=32",
+                        "-XX:+PerfDisableSharedMem",
+                        "-XX:MaxTenuringThreshold=1",
+                        "-Dusing.aikars.flags=https://mcflags.emc.gs",
+                        "-Daikars.new.flags=true",
+                    ]
+                    .map(str::to_owned),
+                );
+            }
+            Self::Zgc => arguments.extend(
+                [
+                    "-XX:+UseZGC",
+                    "-XX:+AlwaysPreTouch",
+                    "-XX:+DisableExplicitGC",
+                    "-XX:+PerfDisableSharedMem",
+                ]
+                .map(str::to_owned),
+            ),
+        }
+
+        // parsed, so that the arguments can be edited as a single line afterwards
+        Ok(Arguments::Parsed(arguments.join(" ")))
+    }
+}
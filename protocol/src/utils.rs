@@ -1,5 +1,52 @@
+use crate::JavaVersion;
+use anyhow::Context;
 use std::env;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use tokio::process::Command;
+
+struct JavaDetection {
+    /// the values of `JAVA_HOME` and `PATH` the detection was performed with
+    env: (Option<OsString>, Option<OsString>),
+    java_path: Option<PathBuf>,
+}
+
+static JAVA_DETECTION_CACHE: Mutex<Option<JavaDetection>> = Mutex::new(None);
+
+/// auto-detects the java path, reusing the previous result unless `JAVA_HOME` or `PATH` changed.
+/// failed detections aren't reused, so java installed in the meantime is picked up.
+pub fn auto_detect_java() -> Option<PathBuf> {
+    let env = (env::var_os("JAVA_HOME"), env::var_os("PATH"));
+    let mut cache = JAVA_DETECTION_CACHE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    match &*cache {
+        Some(JavaDetection {
+            env: cached_env,
+            java_path: Some(java_path),
+        }) if *cached_env == env => Some(java_path.clone()),
+        _ => {
+            let java_path =
+                auto_detect_java_from_java_home_env().or_else(auto_detect_java_from_system_path);
+            *cache = Some(JavaDetection {
+                env,
+                java_path: java_path.clone(),
+            });
+            java_path
+        }
+    }
+}
+
+/// forgets the cached auto-detection result so the next detection scans the filesystem again.
+pub fn clear_java_detection_cache() {
+    JAVA_DETECTION_CACHE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .take();
+}
 
 pub fn auto_detect_java_from_java_home_env() -> Option<PathBuf> {
     env::var("JAVA_HOME")
@@ -23,3 +70,52 @@ pub fn auto_detect_java_from_system_path() -> Option<PathBuf> {
             .next()
     })
 }
+
+/// runs `java -version` with the java at `java_path` and parses the version it reports.
+pub async fn java_version(java_path: &Path) -> anyhow::Result<JavaVersion> {
+    let output = Command::new(java_path)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run `{} -version`.", java_path.display()))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`{} -version` failed with {}.",
+        java_path.display(),
+        output.status
+    );
+
+    // java prints its version to stderr, e.g. `openjdk version "21.0.2" 2024-01-16`
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (version, major) = parse_java_version(&stderr).with_context(|| {
+        format!(
+            "Failed to find the version in the output of `{} -version`.",
+            java_path.display()
+        )
+    })?;
+
+    Ok(JavaVersion {
+        path: java_path.to_owned(),
+        version: version.to_owned(),
+        major,
+    })
+}
+
+/// finds the quoted version in the output of `java -version` along with its major version. java 8
+/// and older report themselves as `1.x`, so their major version is the second component.
+fn parse_java_version(output: &str) -> Option<(&str, u32)> {
+    let version = output
+        .lines()
+        .find(|line| line.contains("version"))?
+        .split('"')
+        .nth(1)?;
+    let mut components = version.split(['.', '_', '-', '+']);
+    let major = match components.next()? {
+        "1" => components.next()?,
+        major => major,
+    };
+
+    Some((version, major.parse().ok()?))
+}
@@ -0,0 +1,241 @@
+use raphy_protocol::{Color, StyledSpan, TextStyle};
+
+/// where the state machine is within an escape sequence
+#[derive(Default, Copy, Clone)]
+enum State {
+    #[default]
+    Ground,
+
+    /// after an `ESC`
+    Escape,
+
+    /// after an `ESC` followed by intermediate bytes, e.g. `ESC ( B`
+    EscapeIntermediate,
+
+    /// within a control sequence, e.g. `ESC [ 1 ; 31 m`
+    Csi,
+
+    /// within an operating system command, e.g. `ESC ] 0 ; title BEL`
+    Osc,
+
+    /// after an `ESC` within an operating system command, which may be the start of its terminator
+    OscEscape,
+}
+
+enum Action {
+    /// the byte is text
+    Print,
+
+    /// the byte is part of an escape sequence
+    Ignore,
+
+    /// the byte is the final byte of a control sequence, whose parameters were collected
+    Csi(u8),
+}
+
+/// recognizes escape sequences one byte at a time. the state is kept between calls, so sequences
+/// which are split across reads are still recognized.
+#[derive(Default)]
+struct Machine {
+    state: State,
+    params: Vec<u8>,
+}
+
+impl Machine {
+    fn advance(&mut self, byte: u8) -> Action {
+        let (state, action) = match (self.state, byte) {
+            (State::Ground, 0x1b) => (State::Escape, Action::Ignore),
+            (State::Ground, _) => (State::Ground, Action::Print),
+            (State::Escape, b'[') => {
+                self.params.clear();
+                (State::Csi, Action::Ignore)
+            }
+            (State::Escape, b']') => (State::Osc, Action::Ignore),
+            (State::Escape | State::EscapeIntermediate, 0x20..=0x2f) => {
+                (State::EscapeIntermediate, Action::Ignore)
+            }
+            (State::Escape | State::EscapeIntermediate, _) => (State::Ground, Action::Ignore),
+            (State::Csi, 0x40..=0x7e) => (State::Ground, Action::Csi(byte)),
+            (State::Csi, _) => {
+                self.params.push(byte);
+                (State::Csi, Action::Ignore)
+            }
+            (State::Osc, 0x07) => (State::Ground, Action::Ignore),
+            (State::Osc, 0x1b) => (State::OscEscape, Action::Ignore),
+            (State::Osc, _) => (State::Osc, Action::Ignore),
+            (State::OscEscape, b'\\') => (State::Ground, Action::Ignore),
+            (State::OscEscape, _) => (State::Osc, Action::Ignore),
+        };
+
+        self.state = state;
+        action
+    }
+}
+
+/// removes ANSI escape sequences from output.
+#[derive(Default)]
+pub struct AnsiStripper {
+    machine: Machine,
+}
+
+impl AnsiStripper {
+    pub fn strip(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(input.len());
+
+        for &byte in input {
+            if let Action::Print = self.machine.advance(byte) {
+                output.push(byte);
+            }
+        }
+
+        output
+    }
+}
+
+/// translates ANSI escape sequences in output into styled spans of text. styles carry over between
+/// calls, like they would in a terminal.
+#[derive(Default)]
+pub struct AnsiParser {
+    machine: Machine,
+    style: TextStyle,
+}
+
+impl AnsiParser {
+    pub fn parse(&mut self, input: &[u8]) -> Vec<StyledSpan> {
+        let mut spans: Vec<StyledSpan> = Vec::new();
+
+        for &byte in input {
+            match self.machine.advance(byte) {
+                Action::Print => match spans.last_mut() {
+                    Some(span) if span.style == self.style => span.text.push(byte),
+                    _ => spans.push(StyledSpan {
+                        text: vec![byte],
+                        style: self.style,
+                    }),
+                },
+                Action::Csi(b'm') => self.apply_sgr(),
+                Action::Csi(_) | Action::Ignore => {}
+            }
+        }
+
+        spans
+    }
+
+    /// applies a select graphic rendition sequence, ignoring the attributes which aren't tracked.
+    fn apply_sgr(&mut self) {
+        let params = String::from_utf8_lossy(&self.machine.params);
+        let mut codes = params
+            .split([';', ':'])
+            .map(|code| code.parse::<u8>().unwrap_or(0));
+
+        while let Some(code) = codes.next() {
+            match code {
+                0 => self.style = TextStyle::default(),
+                1 => self.style.bold = true,
+                3 => self.style.italic = true,
+                4 => self.style.underline = true,
+                22 => self.style.bold = false,
+                23 => self.style.italic = false,
+                24 => self.style.underline = false,
+                30..=37 => self.style.foreground = Some(Color::Indexed(code - 30)),
+                38 => self.style.foreground = extended_color(&mut codes),
+                39 => self.style.foreground = None,
+                40..=47 => self.style.background = Some(Color::Indexed(code - 40)),
+                48 => self.style.background = extended_color(&mut codes),
+                49 => self.style.background = None,
+                90..=97 => self.style.foreground = Some(Color::Indexed(code - 90 + 8)),
+                100..=107 => self.style.background = Some(Color::Indexed(code - 100 + 8)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// parses the rest of a `38` or `48` code, i.e. `5;n` or `2;r;g;b`.
+fn extended_color(codes: &mut impl Iterator<Item = u8>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::Indexed(codes.next()?)),
+        2 => Some(Color::Rgb(codes.next()?, codes.next()?, codes.next()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORED_LINE: &[u8] = b"\x1b[1;31m[ERROR]\x1b[0m Can't keep up!\n";
+
+    /// the text and style of each span
+    fn spans(spans: &[StyledSpan]) -> Vec<(&str, TextStyle)> {
+        spans
+            .iter()
+            .map(|span| (std::str::from_utf8(&span.text).unwrap(), span.style))
+            .collect()
+    }
+
+    #[test]
+    fn stripping_removes_colors() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.strip(COLORED_LINE), b"[ERROR] Can't keep up!\n");
+    }
+
+    #[test]
+    fn stripping_handles_sequences_split_across_reads() {
+        // every possible split, including in the middle of both sequences
+        for split in 0..=COLORED_LINE.len() {
+            let mut stripper = AnsiStripper::default();
+            let (first, second) = COLORED_LINE.split_at(split);
+            let mut stripped = stripper.strip(first);
+            stripped.extend(stripper.strip(second));
+            assert_eq!(stripped, b"[ERROR] Can't keep up!\n", "split at {split}");
+        }
+    }
+
+    #[test]
+    fn stripping_removes_window_titles() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.strip(b"\x1b]0;server\x07Done\n"), b"Done\n");
+        assert_eq!(stripper.strip(b"\x1b]0;server\x1b\\Done\n"), b"Done\n");
+    }
+
+    #[test]
+    fn parsing_translates_colors_into_styles() {
+        let mut parser = AnsiParser::default();
+        let error = TextStyle {
+            foreground: Some(Color::Indexed(1)),
+            bold: true,
+            ..TextStyle::default()
+        };
+        assert_eq!(
+            spans(&parser.parse(COLORED_LINE)),
+            [
+                ("[ERROR]", error),
+                (" Can't keep up!\n", TextStyle::default())
+            ]
+        );
+    }
+
+    #[test]
+    fn parsing_handles_sequences_split_across_reads() {
+        let mut parser = AnsiParser::default();
+        assert!(parser.parse(b"\x1b[38;2;").is_empty());
+        let parsed = parser.parse(b"255;128;0mhot");
+        let hot = TextStyle {
+            foreground: Some(Color::Rgb(255, 128, 0)),
+            ..TextStyle::default()
+        };
+        assert_eq!(spans(&parsed), [("hot", hot)]);
+    }
+
+    #[test]
+    fn styles_carry_over_between_reads() {
+        let mut parser = AnsiParser::default();
+        parser.parse(b"\x1b[4m");
+        let underlined = TextStyle {
+            underline: true,
+            ..TextStyle::default()
+        };
+        assert_eq!(spans(&parser.parse(b"link")), [("link", underlined)]);
+    }
+}
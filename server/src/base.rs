@@ -1,30 +1,309 @@
-use crate::child;
 use crate::child::ServerToChildMessage;
-use raphy_protocol::{Config, Operation, ServerState};
-use std::process::ExitStatus;
+use crate::utils;
+use chrono::Local;
+use raphy_common::ConfigLike;
+use raphy_protocol::config::{ConfigPatch, LaunchMode, Watchdog, WatchdogAction, WatchdogProbe};
+use raphy_protocol::{
+    ActiveOperation, ArgumentPreset, BackupProgress, Config, ConfigSource, ConnectionInfo,
+    CurrentConfig, Diagnostics, LifecycleEvent, LifecycleEventKind, Log, Operation, OperationId,
+    OperationOutcome, OperationRecord, OutputStream, Permissions, ReloadResult, ResolvedPaths,
+    SaveResult, SerdeError, ServerInfo, ServerState, StartupLog, StopCause, Transport, config,
+};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::future;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::AbortHandle;
+use tokio::time::{Interval, MissedTickBehavior};
 use tokio_graceful_shutdown::SubsystemHandle;
-use raphy_common::ConfigLike;
 
 pub enum NetworkToServerMessage {
-    GetConfig(oneshot::Sender<Option<Config>>),
+    GetConfig(oneshot::Sender<CurrentConfig>),
     GetServerState(oneshot::Sender<ServerState>),
-    UpdateConfig(Config, oneshot::Sender<()>),
-    PerformOperation(Operation, oneshot::Sender<anyhow::Result<()>>),
+    /// fails without updating the config if it isn't valid, see [`Config::validate`]
+    UpdateConfig(Box<Config>, oneshot::Sender<anyhow::Result<()>>),
+    PerformOperation(
+        Operation,
+        OperationId,
+        ConnectionInfo,
+        oneshot::Sender<anyhow::Result<()>>,
+    ),
+    GetOperationResult(OperationId, oneshot::Sender<Option<OperationOutcome>>),
+
+    /// resolves once the operation finished, or with `None` if it isn't remembered anymore
+    AwaitOperation(OperationId, oneshot::Sender<Option<anyhow::Result<()>>>),
+    GetActiveOperation(oneshot::Sender<Option<ActiveOperation>>),
+    SaveWorld(oneshot::Sender<anyhow::Result<SaveResult>>),
+    ReloadServer(oneshot::Sender<anyhow::Result<ReloadResult>>),
+    /// carries the path of the new jar, relative to the working directory
+    /// the flag tells whether the previous jar is backed up first
+    UpdateJar(PathBuf, bool, oneshot::Sender<anyhow::Result<Config>>),
+
+    /// carries the name of the preset and the memory to apply it with, in megabytes
+    ApplyArgumentPreset(String, u32, oneshot::Sender<anyhow::Result<Config>>),
+    GetStartupLog(oneshot::Sender<StartupLog>),
+    GetRecentOutput(oneshot::Sender<Vec<u8>>),
+
+    /// the lifecycle events after the given time, or all of them
+    GetLifecycleEvents(Option<SystemTime>, oneshot::Sender<Vec<LifecycleEvent>>),
+    GetOperationHistory(oneshot::Sender<Vec<OperationRecord>>),
+    GetServerInfo(oneshot::Sender<ServerInfo>),
+    /// carries the network task's part of the diagnostics: whether new clients are accepted
+    GetDiagnostics(bool, oneshot::Sender<Diagnostics>),
+    GetMetricsSample(oneshot::Sender<MetricsSample>),
+    GetResolvedPaths(oneshot::Sender<anyhow::Result<ResolvedPaths>>),
+
+    /// runs the configured backup command, resolving once it exited and old backups were pruned
+    RunBackup(oneshot::Sender<anyhow::Result<()>>),
+
+    /// archives the world, reporting its progress until the channel closes, and resolves to the
+    /// path of the archive
+    BackupWorld(
+        UnboundedSender<BackupProgress>,
+        oneshot::Sender<anyhow::Result<PathBuf>>,
+    ),
+    SetBackupRetention(Option<usize>, oneshot::Sender<anyhow::Result<Config>>),
+    PatchConfig(ConfigPatch, oneshot::Sender<anyhow::Result<Config>>),
     Input(Vec<u8>),
     Shutdown,
 }
 
+/// the raw measurements metrics are computed from
+pub struct MetricsSample {
+    pub rss_bytes: Option<u64>,
+
+    /// the cpu time the server process used so far
+    pub cpu_time: Option<Duration>,
+    pub disk_free: Option<u64>,
+}
+
 pub enum ChildToServerMessage {
     Stdout(Vec<u8>),
     Stderr(Vec<u8>),
+    Log(Log),
     UpdateState(ServerState),
+
+    /// the server process a previous run of raphy left running was adopted
+    Adopted,
+
+    /// the server exited unexpectedly and couldn't be restarted, or crashed too often to be
+    AutoRestartFailed(anyhow::Error),
+}
+
+/// how many operation outcomes are remembered for clients that reconnect after requesting one
+const MAX_OPERATION_OUTCOMES: usize = 64;
+
+/// how many lifecycle events are remembered, dropping the oldest ones first
+const MAX_LIFECYCLE_EVENTS: usize = 256;
+
+/// how many finished operations are kept in the operation history, dropping the oldest ones first
+const MAX_OPERATION_HISTORY: usize = 256;
+
+/// the most bytes of the newest output which are kept for clients that connect later
+const MAX_RECENT_OUTPUT_LEN: usize = 64 * 1024;
+
+/// the most lines of the newest output which are kept for clients that connect later
+const MAX_RECENT_OUTPUT_LINES: usize = 500;
+
+/// how long to wait for the server to confirm a console command
+const CONFIRMATION_WINDOW: Duration = Duration::from_secs(30);
+
+/// how often the game port is checked while waiting for the server to become ready
+const PORT_READINESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// an operation which hasn't finished yet
+struct InFlightOperation {
+    operation: Operation,
+    operation_id: OperationId,
+    started_by: ConnectionInfo,
+    started_at: Instant,
+    requested_at: SystemTime,
+}
+
+/// the newest output of the server, dropping the oldest output once it's over its limits
+#[derive(Default)]
+struct RecentOutput {
+    output: VecDeque<u8>,
+
+    /// how many line breaks `output` contains
+    lines: usize,
+}
+
+impl RecentOutput {
+    fn push(&mut self, output: &[u8], max_len: usize) {
+        self.output.extend(output);
+        self.lines += output.iter().filter(|&&byte| byte == b'\n').count();
+        self.trim(max_len);
+    }
+
+    /// drops the oldest output until at most `max_len` bytes and [`MAX_RECENT_OUTPUT_LINES`] lines
+    /// are left
+    fn trim(&mut self, max_len: usize) {
+        while self.lines > MAX_RECENT_OUTPUT_LINES {
+            let Some(end) = self.output.iter().position(|&byte| byte == b'\n') else {
+                break;
+            };
+            self.output.drain(..=end);
+            self.lines -= 1;
+        }
+
+        let excess = self.output.len().saturating_sub(max_len);
+        self.lines -= self
+            .output
+            .drain(..excess)
+            .filter(|&byte| byte == b'\n')
+            .count();
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        self.output.iter().copied().collect()
+    }
+}
+
+/// a pending wait for a piece of text to show up in the server's output
+struct OutputWaiter {
+    text: Cow<'static, str>,
+    tx: oneshot::Sender<()>,
+}
+
+/// the watchdog of the running server
+struct WatchdogState {
+    watchdog: Watchdog,
+    interval: Interval,
+
+    /// the port probed by [`WatchdogProbe::GamePort`]
+    game_port: Option<u16>,
+
+    /// how many probes in a row failed
+    failures: u32,
+    probing: bool,
+}
+
+/// resolves on the watchdog's next tick, or never if there's no watchdog
+async fn watchdog_tick(watchdog: &mut Option<WatchdogState>) {
+    match watchdog {
+        Some(watchdog) => {
+            watchdog.interval.tick().await;
+        }
+        None => future::pending().await,
+    }
+}
+
+/// coalesces server state changes so that flapping doesn't re-register the mdns service on every
+/// change, while the final state is always advertised once the window passes
+struct AdvertisementDebounce {
+    min_interval: Duration,
+
+    /// whether the advertisement currently says the server is running
+    advertised: bool,
+    pending: Option<bool>,
+    deadline: Option<Instant>,
+}
+
+impl AdvertisementDebounce {
+    fn schedule(&mut self, running: bool) {
+        self.pending = Some(running);
+
+        // the deadline isn't pushed back by later changes, so a server that keeps flapping is
+        // still advertised at most once per interval
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + self.min_interval);
+        }
+    }
+
+    /// the state to advertise once the deadline passed, if it differs from the advertised one
+    fn due(&mut self) -> Option<bool> {
+        self.deadline = None;
+        self.pending
+            .take()
+            .filter(|&running| running != self.advertised)
+    }
+}
+
+/// resolves when the pending advertisement update is due, or never if there's none
+async fn advertisement_due(debounce: &AdvertisementDebounce) {
+    match debounce.deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => future::pending().await,
+    }
+}
+
+/// writes a command to the server's console without waiting for it to be confirmed. if the
+/// server isn't running, it's dropped.
+fn send_console_command(s2ch_tx: &UnboundedSender<ServerToChildMessage>, command: &str) {
+    let (tx, _) = oneshot::channel();
+    s2ch_tx
+        .send(ServerToChildMessage::Command(
+            format!("{command}\n").into_bytes(),
+            tx,
+        ))
+        .ok();
+}
+
+/// resolves when the scheduled restart is due, or never if there's none
+async fn restart_due(scheduled_restart: Option<Instant>) {
+    match scheduled_restart {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => future::pending().await,
+    }
+}
+
+/// the result of a finished operation, or `None` if it's still pending
+fn operation_result(outcome: &OperationOutcome) -> Option<anyhow::Result<()>> {
+    match outcome {
+        OperationOutcome::Pending(_) => None,
+        OperationOutcome::Performed(_) => Some(Ok(())),
+        OperationOutcome::Failed(_, error) => Some(Err(error.clone().into())),
+    }
 }
 
 pub struct ServerTask {
     config: Option<Config>,
+    config_source: ConfigSource,
+
+    /// whether the current config failed to be saved, and should be saved again before shutting down
+    config_unsaved: bool,
+    control_port: u16,
+    operation_outcomes: VecDeque<(OperationId, OperationOutcome)>,
+    in_flight_operations: Vec<InFlightOperation>,
+
+    /// requests waiting for an operation to finish, see [`NetworkToServerMessage::AwaitOperation`]
+    operation_waiters: Vec<(OperationId, oneshot::Sender<Option<anyhow::Result<()>>>)>,
+    outcome_tx: UnboundedSender<(OperationId, OperationOutcome)>,
+    outcome_rx: UnboundedReceiver<(OperationId, OperationOutcome)>,
+    output_waiters: Vec<OutputWaiter>,
+    startup_log: StartupLog,
+    capturing_startup: bool,
+    recent_output: RecentOutput,
+    lifecycle_events: VecDeque<LifecycleEvent>,
+    operation_history: VecDeque<OperationRecord>,
+
+    /// the task checking whether the game port accepts connections yet, if enabled
+    port_readiness_probe: Option<AbortHandle>,
+    ready_tx: UnboundedSender<()>,
+    ready_rx: UnboundedReceiver<()>,
+
+    /// started once the server has finished starting up, if configured
+    watchdog: Option<WatchdogState>,
+
+    /// distinguishes the probe results of the current watchdog from those of previous ones
+    watchdog_generation: u64,
+    probe_tx: UnboundedSender<(u64, bool)>,
+    probe_rx: UnboundedReceiver<(u64, bool)>,
+
+    /// jar swaps which the child task finished, along with the config using the new jar
+    jar_swapped_tx: UnboundedSender<(Config, oneshot::Sender<anyhow::Result<Config>>)>,
+    jar_swapped_rx: UnboundedReceiver<(Config, oneshot::Sender<anyhow::Result<Config>>)>,
+    advertisement: AdvertisementDebounce,
+
+    /// when the server is restarted next, if a restart schedule is configured
+    scheduled_restart: Option<Instant>,
     n2s_rx: UnboundedReceiver<NetworkToServerMessage>,
     ch2s_rx: UnboundedReceiver<ChildToServerMessage>,
     s2ch_tx: UnboundedSender<ServerToChildMessage>,
@@ -39,9 +318,45 @@ impl ServerTask {
         s2ch_tx: UnboundedSender<ServerToChildMessage>,
         global_s2c_tx: UnboundedSender<raphy_protocol::ServerToClientMessage>,
         config: Option<Config>,
+        config_source: ConfigSource,
+        control_port: u16,
     ) -> Self {
+        let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = mpsc::unbounded_channel();
+        let (probe_tx, probe_rx) = mpsc::unbounded_channel();
+        let (jar_swapped_tx, jar_swapped_rx) = mpsc::unbounded_channel();
         Self {
             config,
+            config_source,
+            config_unsaved: false,
+            control_port,
+            operation_outcomes: VecDeque::new(),
+            in_flight_operations: Vec::new(),
+            operation_waiters: Vec::new(),
+            outcome_tx,
+            outcome_rx,
+            output_waiters: Vec::new(),
+            startup_log: StartupLog::default(),
+            capturing_startup: false,
+            recent_output: RecentOutput::default(),
+            lifecycle_events: VecDeque::new(),
+            operation_history: VecDeque::new(),
+            port_readiness_probe: None,
+            ready_tx,
+            ready_rx,
+            watchdog: None,
+            watchdog_generation: 0,
+            probe_tx,
+            probe_rx,
+            jar_swapped_tx,
+            jar_swapped_rx,
+            advertisement: AdvertisementDebounce {
+                min_interval: utils::advertisement_interval(),
+                advertised: false,
+                pending: None,
+                deadline: None,
+            },
+            scheduled_restart: None,
             n2s_rx,
             ch2s_rx,
             s2ch_tx,
@@ -59,32 +374,359 @@ impl ServerTask {
     async fn handle_n2s(&mut self, message: NetworkToServerMessage) {
         match message {
             NetworkToServerMessage::GetConfig(ret) => {
-                ret.send(self.config.clone()).ok().unwrap();
+                let config = match &self.config {
+                    Some(config) => CurrentConfig::Saved(config.clone()),
+                    None => CurrentConfig::Template(Config::template().await.unwrap_or_else(
+                        |error| {
+                            tracing::warn!(?error, "failed to load the config template: {error:#}");
+                            Config::default()
+                        },
+                    )),
+                };
+                ret.send(config).ok().unwrap();
             }
             NetworkToServerMessage::GetServerState(ret) => {
                 self.s2ch_tx.send(ServerToChildMessage::ServerState(ret)).ok().unwrap();
             }
             NetworkToServerMessage::UpdateConfig(config, ret) => {
-                if let Err(error) = config.dump().await {
-                    tracing::error!(?error, "failed to save the configuration: {error:#}");
+                let result = config.validate();
+                if result.is_ok() {
+                    self.update_config(*config).await;
                 }
+                ret.send(result).ok();
+            }
+            NetworkToServerMessage::PerformOperation(operation, operation_id, started_by, ret) => {
+                self.perform_operation(operation, operation_id, started_by, ret)
+            }
+            NetworkToServerMessage::GetOperationResult(operation_id, ret) => {
+                let outcome = self
+                    .operation_outcomes
+                    .iter()
+                    .find(|(id, _)| *id == operation_id)
+                    .map(|(_, outcome)| outcome.clone());
+                ret.send(outcome).ok();
+            }
+            NetworkToServerMessage::AwaitOperation(operation_id, ret) => {
+                let outcome = self
+                    .operation_outcomes
+                    .iter()
+                    .find(|(id, _)| *id == operation_id)
+                    .map(|(_, outcome)| outcome);
+                match outcome {
+                    Some(OperationOutcome::Pending(_)) => {
+                        self.operation_waiters.push((operation_id, ret))
+                    }
+                    Some(outcome) => {
+                        ret.send(operation_result(outcome)).ok();
+                    }
+                    None => {
+                        ret.send(None).ok();
+                    }
+                }
+            }
+            NetworkToServerMessage::GetActiveOperation(ret) => {
+                // operations are performed in order, so the oldest one is the one in progress
+                let active = self.in_flight_operations.first().map(|op| ActiveOperation {
+                    operation: op.operation,
+                    operation_id: op.operation_id,
+                    started_by: op.started_by,
+                    elapsed: op.started_at.elapsed(),
+                });
+                ret.send(active).ok();
+            }
+            NetworkToServerMessage::SaveWorld(ret) => {
+                let server_kind = self
+                    .config
+                    .as_ref()
+                    .map(|c| c.server_kind)
+                    .unwrap_or_default();
+                let confirmed = self.run_console_command(
+                    server_kind.save_command(),
+                    server_kind.save_confirmation(),
+                );
+
+                tokio::spawn(async move {
+                    ret.send(confirmed.await.map(|success| SaveResult { success }))
+                        .ok();
+                });
+            }
+            NetworkToServerMessage::ReloadServer(ret) => {
+                let server_kind = self
+                    .config
+                    .as_ref()
+                    .map(|c| c.server_kind)
+                    .unwrap_or_default();
+                let confirmed = self.run_console_command(
+                    server_kind.reload_command(),
+                    server_kind.reload_confirmation(),
+                );
 
-                self.config = Some(config.clone());
+                tokio::spawn(async move {
+                    ret.send(confirmed.await.map(|success| ReloadResult { success }))
+                        .ok();
+                });
+            }
+            NetworkToServerMessage::UpdateJar(relative_path, back_up_previous, ret) => {
+                let config = match self.jar_swap_config(&relative_path) {
+                    Ok(config) => config,
+                    Err(error) => {
+                        ret.send(Err(error)).ok();
+                        return;
+                    }
+                };
+                if back_up_previous && let Err(error) = self.back_up_jar().await {
+                    ret.send(Err(error)).ok();
+                    return;
+                }
+
+                let (tx, rx) = oneshot::channel();
                 self.s2ch_tx
-                    .send(ServerToChildMessage::UpdateConfig(config))
+                    .send(ServerToChildMessage::SwapJar(config.clone(), tx))
                     .unwrap();
-                ret.send(()).unwrap()
+
+                let jar_swapped_tx = self.jar_swapped_tx.clone();
+                tokio::spawn(async move {
+                    let result = rx.await.unwrap_or_else(|_| {
+                        Err(anyhow::anyhow!(
+                            "The server stopped before the jar was swapped."
+                        ))
+                    });
+                    match result {
+                        Ok(()) => {
+                            jar_swapped_tx.send((config, ret)).ok();
+                        }
+                        Err(error) => {
+                            ret.send(Err(error)).ok();
+                        }
+                    }
+                });
             }
-            NetworkToServerMessage::PerformOperation(operation, ret) => match operation {
-                Operation::Start => self.s2ch_tx.send(ServerToChildMessage::Start(ret)).unwrap(),
-                Operation::Stop => {
-                    self.s2ch_tx.send(ServerToChildMessage::Stop(ret)).unwrap();
+            NetworkToServerMessage::ApplyArgumentPreset(name, memory_mb, ret) => {
+                match self.preset_config(&name, memory_mb) {
+                    Ok(config) => {
+                        self.update_config(config.clone()).await;
+                        ret.send(Ok(config)).ok();
+                    }
+                    Err(error) => {
+                        ret.send(Err(error)).ok();
+                    }
+                }
+            }
+            NetworkToServerMessage::GetStartupLog(ret) => {
+                ret.send(self.startup_log.clone()).ok();
+            }
+            NetworkToServerMessage::GetRecentOutput(ret) => {
+                ret.send(self.recent_output.to_vec()).ok();
+            }
+            NetworkToServerMessage::GetLifecycleEvents(since, ret) => {
+                let events = self
+                    .lifecycle_events
+                    .iter()
+                    .filter(|event| since.is_none_or(|since| event.timestamp > since))
+                    .copied()
+                    .collect();
+                ret.send(events).ok();
+            }
+            NetworkToServerMessage::GetOperationHistory(ret) => {
+                ret.send(self.operation_history.iter().cloned().collect()).ok();
+            }
+            NetworkToServerMessage::GetServerInfo(ret) => {
+                let game_port = self
+                    .config
+                    .as_ref()
+                    .and_then(|config| utils::read_game_port(config.working_dir()));
+                let primary_output_stream = self
+                    .config
+                    .as_ref()
+                    .map(Config::primary_output_stream)
+                    .unwrap_or(OutputStream::Stdout);
+                let control_port = self.control_port;
+                let config = self.config.clone();
+                tokio::spawn(async move {
+                    ret.send(ServerInfo {
+                        control_port,
+                        game_port,
+                        build_version: raphy_protocol::BUILD_VERSION.to_owned(),
+                        primary_output_stream,
+                        instance: utils::instance().map(str::to_owned),
+                        config_dirty: utils::config_changed_on_disk(config.as_ref()).await,
+                    })
+                    .ok();
+                });
+            }
+            NetworkToServerMessage::GetDiagnostics(accepting_clients, ret) => {
+                let (tx, rx) = oneshot::channel();
+                self.s2ch_tx.send(ServerToChildMessage::ServerState(tx)).unwrap();
+                let (memory_tx, memory_rx) = oneshot::channel();
+                self.s2ch_tx
+                    .send(ServerToChildMessage::MemoryUsage(memory_tx))
+                    .unwrap();
+
+                let config = self.config.clone();
+                let config_source = self.config_source.clone();
+                let java_path = config
+                    .as_ref()
+                    .and_then(|config| config.java_path.resolve().map(|p| p.into_owned()));
+                let backup_dir = config.as_ref().and_then(Config::backup_dir);
+                let working_dir = config
+                    .as_ref()
+                    .map(|config| config.working_dir().to_owned());
+                let recent_operations = self.operation_outcomes.iter().cloned().collect();
+                let output_buffer_usage = self.output_buffer_usage();
+                tokio::spawn(async move {
+                    let server_state = rx.await.unwrap();
+                    let memory_usage = memory_rx.await.unwrap();
+                    let backups = match backup_dir {
+                        Some(backup_dir) => tokio::task::spawn_blocking(move || {
+                            utils::backup_usage(&backup_dir)
+                                .inspect_err(|error| {
+                                    tracing::warn!("failed to measure the backups: {error:#}")
+                                })
+                                .ok()
+                        })
+                        .await
+                        .unwrap(),
+                        None => None,
+                    };
+                    let java_version = match &java_path {
+                        Some(java_path) => utils::java_version(java_path)
+                            .await
+                            .inspect_err(|error| {
+                                tracing::warn!("failed to get the java version: {error:#}")
+                            })
+                            .ok(),
+                        None => None,
+                    };
+                    let last_crash_report = match working_dir {
+                        Some(working_dir) => tokio::task::spawn_blocking(move || {
+                            utils::last_crash_report(&working_dir)
+                                .inspect_err(|error| {
+                                    tracing::warn!(
+                                        "failed to read the last crash report: {error:#}"
+                                    )
+                                })
+                                .ok()
+                                .flatten()
+                        })
+                        .await
+                        .unwrap(),
+                        None => None,
+                    };
+                    ret.send(Diagnostics {
+                        config,
+                        config_source,
+                        java_path,
+                        java_version,
+                        server_state,
+                        recent_operations,
+                        output_buffer_usage,
+                        accepting_clients,
+                        memory_usage,
+                        backups,
+                        log_level: raphy_common::log_level(),
+                        recent_logs: raphy_common::recent_logs(),
+                        last_crash_report,
+                        daemon_version: raphy_protocol::BUILD_VERSION.to_owned(),
+                        os: std::env::consts::OS.to_owned(),
+                        arch: std::env::consts::ARCH.to_owned(),
+                    })
+                    .ok();
+                });
+            }
+            NetworkToServerMessage::GetResolvedPaths(ret) => {
+                let paths = match &self.config {
+                    Some(config) => Ok(utils::resolved_paths(config)),
+                    None => Err(anyhow::anyhow!(
+                        "A server configuration is required to resolve its paths."
+                    )),
+                };
+                ret.send(paths).ok();
+            }
+            NetworkToServerMessage::RunBackup(ret) => {
+                let Some(config) = &self.config else {
+                    ret.send(Err(anyhow::anyhow!(
+                        "A server configuration is required to run a backup."
+                    )))
+                    .ok();
+                    return;
+                };
+                let Some(command) = config.backup_command.clone() else {
+                    ret.send(Err(anyhow::anyhow!("No backup command is configured.")))
+                        .ok();
+                    return;
+                };
+
+                let user = config.user.clone();
+                let working_dir = config.working_dir().to_path_buf();
+                let pruning = config.backup_dir().zip(config.backup_retention);
+                tokio::spawn(async move {
+                    let mut result = utils::run_backup(&command, &user, &working_dir).await;
+                    if let Some((backup_dir, retention)) = pruning.filter(|_| result.is_ok()) {
+                        result = tokio::task::spawn_blocking(move || {
+                            utils::prune_backups(&backup_dir, retention)
+                        })
+                        .await
+                        .unwrap()
+                        .map(|pruned| tracing::info!(pruned, "pruned old backups"));
+                    }
+                    ret.send(result).ok();
+                });
+            }
+            NetworkToServerMessage::BackupWorld(progress_tx, ret) => {
+                self.backup_world(progress_tx, ret).await
+            }
+            NetworkToServerMessage::SetBackupRetention(retention, ret) => {
+                match self.backup_retention_config(retention) {
+                    Ok(config) => {
+                        self.update_config(config.clone()).await;
+                        ret.send(Ok(config)).ok();
+                    }
+                    Err(error) => {
+                        ret.send(Err(error)).ok();
+                    }
+                }
+            }
+            NetworkToServerMessage::PatchConfig(patch, ret) => match self.patch_config(patch) {
+                Ok(config) => {
+                    self.update_config(config.clone()).await;
+                    ret.send(Ok(config)).ok();
+                }
+                Err(error) => {
+                    ret.send(Err(error)).ok();
                 }
-                Operation::Restart => self
-                    .s2ch_tx
-                    .send(ServerToChildMessage::Restart(ret))
-                    .unwrap(),
             },
+            NetworkToServerMessage::GetMetricsSample(ret) => {
+                let (tx, rx) = oneshot::channel();
+                self.s2ch_tx.send(ServerToChildMessage::Pid(tx)).unwrap();
+
+                let working_dir = self
+                    .config
+                    .as_ref()
+                    .map(|config| config.working_dir().to_path_buf());
+                tokio::spawn(async move {
+                    let usage = rx.await.unwrap().and_then(|pid| {
+                        utils::process_usage(pid)
+                            .inspect_err(|error| {
+                                tracing::debug!(?error, "failed to get the process usage: {error:#}")
+                            })
+                            .ok()
+                    });
+                    let disk_free = working_dir.and_then(|working_dir| {
+                        utils::disk_free(&working_dir)
+                            .inspect_err(|error| {
+                                tracing::debug!(?error, "failed to get the free disk space: {error:#}")
+                            })
+                            .ok()
+                    });
+
+                    ret.send(MetricsSample {
+                        rss_bytes: usage.map(|(rss, _)| rss),
+                        cpu_time: usage.map(|(_, cpu_time)| cpu_time),
+                        disk_free,
+                    })
+                    .ok();
+                });
+            }
             NetworkToServerMessage::Input(input) => self
                 .s2ch_tx
                 .send(ServerToChildMessage::Stdin(input))
@@ -93,24 +735,802 @@ impl ServerTask {
         }
     }
 
-    fn handle_ch2s(&self, message: ChildToServerMessage) {
+    /// archives the world into the backup directory, pausing saves while a running server would
+    /// write to it. old backups are pruned afterwards if a retention is configured.
+    async fn backup_world(
+        &mut self,
+        progress_tx: UnboundedSender<BackupProgress>,
+        ret: oneshot::Sender<anyhow::Result<PathBuf>>,
+    ) {
+        let Some(config) = &self.config else {
+            ret.send(Err(anyhow::anyhow!(
+                "A server configuration is required to back up the world."
+            )))
+            .ok();
+            return;
+        };
+        let Some(backup_dir) = config.backup_dir() else {
+            ret.send(Err(anyhow::anyhow!("No backup directory is configured.")))
+                .ok();
+            return;
+        };
+        let world_dir = config.world_dir();
+        let retention = config.backup_retention;
+        let server_kind = config.server_kind;
+
+        let (tx, rx) = oneshot::channel();
+        self.s2ch_tx.send(ServerToChildMessage::ServerState(tx)).unwrap();
+        let running = matches!(rx.await, Ok(ServerState::Started));
+
+        // a running server keeps writing to the world, which would leave the archive inconsistent
+        let saved = running.then(|| {
+            send_console_command(&self.s2ch_tx, server_kind.save_off_command());
+            self.run_console_command(server_kind.save_command(), server_kind.save_confirmation())
+        });
+        let s2ch_tx = self.s2ch_tx.clone();
+        tokio::spawn(async move {
+            let saved = match saved {
+                Some(saved) => saved.await,
+                None => Ok(true),
+            };
+            let result = match saved {
+                Ok(saved) => {
+                    if !saved {
+                        tracing::warn!(
+                            "the server didn't confirm saving the world, backing it up anyway"
+                        );
+                    }
+
+                    tokio::task::spawn_blocking(move || {
+                        let archive = utils::archive_world(&world_dir, &backup_dir, |progress| {
+                            progress_tx.send(progress).ok();
+                        })?;
+                        tracing::info!(?archive, "backed up the world");
+
+                        // the backup succeeded regardless of whether old ones could be deleted
+                        if let Some(retention) = retention {
+                            match utils::prune_backups(&backup_dir, retention) {
+                                Ok(pruned) => tracing::info!(pruned, "pruned old backups"),
+                                Err(error) => tracing::warn!(
+                                    ?error,
+                                    "failed to prune old backups: {error:#}"
+                                ),
+                            }
+                        }
+                        Ok(archive)
+                    })
+                    .await
+                    .unwrap()
+                }
+                Err(error) => Err(error.context("Failed to save the world before backing it up.")),
+            };
+
+            if running {
+                send_console_command(&s2ch_tx, server_kind.save_on_command());
+            }
+            ret.send(result).ok();
+        });
+    }
+
+    fn perform_operation(
+        &mut self,
+        operation: Operation,
+        operation_id: OperationId,
+        started_by: ConnectionInfo,
+        ret: oneshot::Sender<anyhow::Result<()>>,
+    ) {
+        self.record_operation_outcome(operation_id, OperationOutcome::Pending(operation));
+        self.in_flight_operations.push(InFlightOperation {
+            operation,
+            operation_id,
+            started_by,
+            started_at: Instant::now(),
+            requested_at: SystemTime::now(),
+        });
+
+        let (tx, rx) = oneshot::channel();
+        match operation {
+            Operation::Start => self.s2ch_tx.send(ServerToChildMessage::Start(tx)).unwrap(),
+            Operation::Stop => {
+                self.s2ch_tx.send(ServerToChildMessage::Stop(tx)).unwrap();
+            }
+            Operation::Restart => self
+                .s2ch_tx
+                .send(ServerToChildMessage::Restart(tx))
+                .unwrap(),
+        }
+
+        let outcome_tx = self.outcome_tx.clone();
+        tokio::spawn(async move {
+            let result = rx.await.unwrap();
+            let outcome = match &result {
+                Ok(()) => OperationOutcome::Performed(operation),
+                Err(error) => OperationOutcome::Failed(operation, SerdeError::new(&**error)),
+            };
+            outcome_tx.send((operation_id, outcome)).ok();
+            ret.send(result).ok();
+        });
+    }
+
+    /// works out when the server is restarted next, if a restart schedule is configured
+    fn schedule_restart(&mut self) {
+        let schedule = self.config.as_ref().and_then(|config| config.restart_schedule);
+        let now = Local::now();
+        self.scheduled_restart = schedule
+            .and_then(|schedule| schedule.next_after(now))
+            .and_then(|at| (at - now).to_std().ok())
+            .map(|delay| {
+                tracing::info!(?delay, "scheduled the next restart");
+                Instant::now() + delay
+            });
+    }
+
+    /// restarts the server as a scheduled restart is due, just like a client requesting it would
+    async fn perform_scheduled_restart(&mut self) {
+        self.schedule_restart();
+
+        let (tx, rx) = oneshot::channel();
+        self.s2ch_tx.send(ServerToChildMessage::ServerState(tx)).unwrap();
+        if !matches!(rx.await, Ok(ServerState::Started)) {
+            tracing::info!("the server isn't running, skipping the scheduled restart");
+            return;
+        }
+
+        tracing::info!("restarting the server as scheduled");
+        let operation = Operation::Restart;
+        let operation_id = OperationId::generate();
+        self.global_s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::OperationRequested(
+                operation,
+                operation_id,
+            ))
+            .ok();
+
+        let started_by = ConnectionInfo {
+            transport: Transport::Internal,
+            peer: None,
+            permissions: Permissions::ALL,
+        };
+        let (tx, rx) = oneshot::channel();
+        self.perform_operation(operation, operation_id, started_by, tx);
+
+        let global_s2c_tx = self.global_s2c_tx.clone();
+        tokio::spawn(async move {
+            let message = match rx.await.unwrap() {
+                Ok(()) => raphy_protocol::ServerToClientMessage::OperationPerformed(
+                    operation,
+                    operation_id,
+                    None,
+                ),
+                Err(error) => {
+                    tracing::error!(?error, "the scheduled restart failed: {error:#}");
+                    raphy_protocol::ServerToClientMessage::OperationFailed(
+                        operation,
+                        operation_id,
+                        SerdeError::new(&*error),
+                        None,
+                    )
+                }
+            };
+            global_s2c_tx.send(message).ok();
+        });
+    }
+
+    async fn update_config(&mut self, config: Config) {
+        self.config = Some(config.clone());
+        self.save_config().await;
+
+        let budget = self.output_buffer_budget();
+        self.startup_log.output.truncate(budget);
+        let max_len = self.recent_output_max_len();
+        self.recent_output.trim(max_len);
+
+        self.s2ch_tx
+            .send(ServerToChildMessage::UpdateConfig(config))
+            .unwrap();
+        self.schedule_restart();
+    }
+
+    fn preset_config(&self, name: &str, memory_mb: u32) -> anyhow::Result<Config> {
+        let Some(config) = &self.config else {
+            anyhow::bail!("A server configuration is required to apply an argument preset.");
+        };
+        let Some(preset) = ArgumentPreset::from_name(name) else {
+            anyhow::bail!("There is no argument preset named '{name}'.");
+        };
+
+        let java_arguments = preset.arguments(memory_mb)?;
+        tracing::info!(preset = preset.name(), memory_mb, "applying an argument preset");
+        Ok(Config {
+            java_arguments,
+            ..config.clone()
+        })
+    }
+
+    fn backup_retention_config(&self, backup_retention: Option<usize>) -> anyhow::Result<Config> {
+        let Some(config) = &self.config else {
+            anyhow::bail!("A server configuration is required to change the backup retention.");
+        };
+        anyhow::ensure!(
+            backup_retention != Some(0),
+            "At least one backup has to be kept."
+        );
+
+        tracing::info!(?backup_retention, "changing the backup retention");
+        Ok(Config {
+            backup_retention,
+            ..config.clone()
+        })
+    }
+
+    fn patch_config(&self, patch: ConfigPatch) -> anyhow::Result<Config> {
+        let Some(config) = &self.config else {
+            anyhow::bail!("A server configuration is required to change a single setting.");
+        };
+
+        tracing::info!(?patch, "patching the config");
+        patch.apply(config)
+    }
+
+    /// the current config, but using the given jar
+    fn jar_swap_config(&self, relative_path: &Path) -> anyhow::Result<Config> {
+        let Some(config) = &self.config else {
+            anyhow::bail!("A server configuration is required to update the server jar.");
+        };
+
+        if let LaunchMode::Script(_) = config.launch_mode {
+            anyhow::bail!("The server is launched by a script, so it doesn't use a jar.");
+        }
+
+        let server_jar_path = utils::resolve_jar(config.working_dir(), relative_path)?;
+        tracing::info!(?server_jar_path, "updating the server jar");
+        Ok(Config {
+            server_jar_path,
+            ..config.clone()
+        })
+    }
+
+    async fn back_up_jar(&self) -> anyhow::Result<()> {
+        let Some(config) = &self.config else {
+            anyhow::bail!("A server configuration is required to back up the server jar.");
+        };
+        let Some(backup_dir) = config.backup_dir() else {
+            anyhow::bail!("No backup directory is configured.");
+        };
+
+        let jar = config.server_jar_path.clone();
+        let backup = tokio::task::spawn_blocking(move || utils::back_up_jar(&jar, &backup_dir))
+            .await
+            .unwrap()?;
+        tracing::info!(?backup, "backed up the server jar");
+        Ok(())
+    }
+
+    async fn finish_jar_swap(
+        &mut self,
+        config: Config,
+        ret: oneshot::Sender<anyhow::Result<Config>>,
+    ) {
+        self.config = Some(config.clone());
+        self.save_config().await;
+        ret.send(Ok(config)).ok();
+    }
+
+    fn output_buffer_budget(&self) -> usize {
+        self.config
+            .as_ref()
+            .map(|c| c.output_buffer_budget)
+            .unwrap_or_else(config::default_output_buffer_budget)
+    }
+
+    fn output_buffer_usage(&self) -> usize {
+        self.startup_log.output.len() + self.recent_output.output.len()
+    }
+
+    /// the startup log takes precedence, so the recent output only gets what's left of the budget
+    fn recent_output_max_len(&self) -> usize {
+        self.output_buffer_budget()
+            .saturating_sub(self.startup_log.output.len())
+            .min(MAX_RECENT_OUTPUT_LEN)
+    }
+
+    fn remember_output(&mut self, output: &[u8]) {
+        let max_len = self.recent_output_max_len();
+        self.recent_output.push(output, max_len);
+    }
+
+    async fn save_config(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+
+        match config.dump().await {
+            Ok(path) => {
+                tracing::info!(?path, "saved the configuration");
+                self.config_source.path = path;
+                self.config_unsaved = false;
+            }
+            Err(error) => {
+                tracing::error!(?error, "failed to save the configuration: {error:#}");
+                self.config_unsaved = true;
+            }
+        }
+    }
+
+    /// makes sure the latest config is on disk before the server task exits.
+    async fn flush_config(&mut self) {
+        // a config update could have been queued right before shutdown was requested, so keep it
+        // rather than dropping it with the rest of the queue. the child task may already be gone,
+        // so it isn't forwarded there.
+        let mut updated = false;
+        while let Ok(message) = self.n2s_rx.try_recv() {
+            match message {
+                NetworkToServerMessage::UpdateConfig(config, ret) => {
+                    let result = config.validate();
+                    if result.is_ok() {
+                        self.config = Some(*config);
+                        updated = true;
+                    }
+                    ret.send(result).ok();
+                }
+                NetworkToServerMessage::PatchConfig(patch, ret) => {
+                    let result = self.patch_config(patch);
+                    if let Ok(config) = &result {
+                        self.config = Some(config.clone());
+                        updated = true;
+                    }
+                    ret.send(result).ok();
+                }
+                _ => {}
+            }
+        }
+
+        if updated || self.config_unsaved {
+            tracing::info!("saving the configuration before shutting down");
+            self.save_config().await;
+        }
+    }
+
+    fn record_lifecycle_event(&mut self, kind: LifecycleEventKind) {
+        if self.lifecycle_events.len() == MAX_LIFECYCLE_EVENTS {
+            self.lifecycle_events.pop_front();
+        }
+
+        self.lifecycle_events.push_back(LifecycleEvent {
+            timestamp: SystemTime::now(),
+            kind,
+        });
+    }
+
+    fn record_operation_outcome(&mut self, operation_id: OperationId, outcome: OperationOutcome) {
+        if !matches!(outcome, OperationOutcome::Pending(_)) {
+            let finished = self
+                .in_flight_operations
+                .extract_if(.., |op| op.operation_id == operation_id)
+                .collect::<Vec<_>>();
+            for op in finished {
+                self.record_operation_history(op, &outcome);
+            }
+
+            for (_, ret) in self
+                .operation_waiters
+                .extract_if(.., |(id, _)| *id == operation_id)
+            {
+                ret.send(operation_result(&outcome)).ok();
+            }
+        }
+
+        match self
+            .operation_outcomes
+            .iter_mut()
+            .find(|(id, _)| *id == operation_id)
+        {
+            Some((_, existing)) => *existing = outcome,
+            None => {
+                if self.operation_outcomes.len() == MAX_OPERATION_OUTCOMES {
+                    self.operation_outcomes.pop_front();
+                }
+
+                self.operation_outcomes.push_back((operation_id, outcome));
+            }
+        }
+    }
+
+    fn record_operation_history(&mut self, op: InFlightOperation, outcome: &OperationOutcome) {
+        if self.operation_history.len() == MAX_OPERATION_HISTORY {
+            self.operation_history.pop_front();
+        }
+
+        self.operation_history.push_back(OperationRecord {
+            operation: op.operation,
+            operation_id: op.operation_id,
+            started_by: op.started_by,
+            requested_at: op.requested_at,
+            duration: op.started_at.elapsed(),
+            error: match outcome {
+                OperationOutcome::Failed(_, error) => Some(error.clone()),
+                OperationOutcome::Pending(_) | OperationOutcome::Performed(_) => None,
+            },
+        });
+    }
+
+    /// writes `command` to the server's console, resolving to whether `confirmation` showed up in
+    /// the server's output within [`CONFIRMATION_WINDOW`].
+    fn run_console_command(
+        &mut self,
+        command: &str,
+        confirmation: &'static str,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send + 'static {
+        self.watch_console_command(command, confirmation.into(), CONFIRMATION_WINDOW)
+    }
+
+    /// like [`Self::run_console_command`], but waits for `confirmation` for as long as `window`.
+    fn watch_console_command(
+        &mut self,
+        command: &str,
+        confirmation: Cow<'static, str>,
+        window: Duration,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send + 'static {
+        let (waiter_tx, waiter_rx) = oneshot::channel();
+        self.output_waiters.push(OutputWaiter {
+            text: confirmation,
+            tx: waiter_tx,
+        });
+
+        let (tx, rx) = oneshot::channel();
+        self.s2ch_tx
+            .send(ServerToChildMessage::Command(
+                format!("{command}\n").into_bytes(),
+                tx,
+            ))
+            .unwrap();
+
+        async move {
+            rx.await.unwrap()?;
+            Ok(tokio::time::timeout(window, waiter_rx)
+                .await
+                .is_ok_and(|result| result.is_ok()))
+        }
+    }
+
+    fn notify_output_waiters(&mut self, output: &[u8]) {
+        if self.output_waiters.is_empty() {
+            return;
+        }
+
+        let output = String::from_utf8_lossy(output);
+        let (matched, pending) = self
+            .output_waiters
+            .drain(..)
+            .filter(|waiter| !waiter.tx.is_closed())
+            .partition::<Vec<_>, _>(|waiter| output.contains(&*waiter.text));
+
+        for waiter in matched {
+            waiter.tx.send(()).ok();
+        }
+
+        self.output_waiters = pending;
+    }
+
+    fn capture_startup_output(&mut self, output: &[u8]) {
+        if !self.capturing_startup {
+            return;
+        }
+
+        // the startup log takes precedence over the recent output, so it can get the whole budget.
+        // once it's used up, the rest of the startup output is dropped, since the beginning is the
+        // more useful part.
+        let remaining = self
+            .output_buffer_budget()
+            .saturating_sub(self.startup_log.output.len());
+        self.startup_log
+            .output
+            .extend_from_slice(&output[..output.len().min(remaining)]);
+
+        let ready_marker = self
+            .config
+            .as_ref()
+            .map(|c| c.server_kind)
+            .unwrap_or_default()
+            .ready_marker();
+        if String::from_utf8_lossy(output).contains(ready_marker) {
+            tracing::debug!("server printed its ready line");
+            self.finish_startup();
+        }
+    }
+
+    fn finish_startup(&mut self) {
+        if !self.capturing_startup {
+            return;
+        }
+
+        tracing::debug!("server became ready, finished capturing the startup log");
+        self.record_lifecycle_event(LifecycleEventKind::Ready);
+        self.startup_log.complete = true;
+        self.capturing_startup = false;
+        self.stop_port_readiness_probe();
+        self.start_watchdog();
+    }
+
+    fn start_watchdog(&mut self) {
+        self.stop_watchdog();
+
+        let Some(config) = &self.config else {
+            return;
+        };
+        let Some(watchdog) = config.watchdog.clone() else {
+            return;
+        };
+
+        let game_port = utils::read_game_port(config.working_dir());
+        if let (WatchdogProbe::GamePort, None) = (&watchdog.probe, game_port) {
+            tracing::warn!("the watchdog probes the game port, but it's unknown; not starting it");
+            return;
+        }
+
+        tracing::debug!(?watchdog, "starting the watchdog");
+        let mut interval = tokio::time::interval(watchdog.interval());
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.watchdog = Some(WatchdogState {
+            watchdog,
+            interval,
+            game_port,
+            failures: 0,
+            probing: false,
+        });
+    }
+
+    fn stop_watchdog(&mut self) {
+        self.watchdog = None;
+        self.watchdog_generation += 1;
+    }
+
+    fn probe_server(&mut self) {
+        let Some(state) = &mut self.watchdog else {
+            return;
+        };
+
+        // a probe which is still going hasn't failed yet
+        if state.probing {
+            return;
+        }
+        state.probing = true;
+
+        let timeout = state.watchdog.interval();
+        let game_port = state.game_port;
+        let probe = state.watchdog.probe.clone();
+        let generation = self.watchdog_generation;
+        let probe_tx = self.probe_tx.clone();
+        match probe {
+            WatchdogProbe::GamePort => {
+                tokio::spawn(async move {
+                    let responded = match game_port {
+                        Some(game_port) => tokio::time::timeout(
+                            timeout,
+                            TcpStream::connect((Ipv4Addr::LOCALHOST, game_port)),
+                        )
+                        .await
+                        .is_ok_and(|result| result.is_ok()),
+                        None => false,
+                    };
+                    probe_tx.send((generation, responded)).ok();
+                });
+            }
+            WatchdogProbe::Command {
+                command,
+                expected_output,
+            } => {
+                let confirmed =
+                    self.watch_console_command(&command, expected_output.into(), timeout);
+                tokio::spawn(async move {
+                    let responded = confirmed.await.unwrap_or(false);
+                    probe_tx.send((generation, responded)).ok();
+                });
+            }
+        }
+    }
+
+    fn handle_probe_result(&mut self, generation: u64, responded: bool) {
+        if generation != self.watchdog_generation {
+            return;
+        }
+
+        let Some(state) = &mut self.watchdog else {
+            return;
+        };
+        state.probing = false;
+
+        if responded {
+            state.failures = 0;
+            return;
+        }
+
+        state.failures += 1;
+        tracing::warn!(failures = state.failures, "the server didn't respond to the watchdog");
+        if state.failures < state.watchdog.failure_threshold {
+            return;
+        }
+
+        let action = state.watchdog.action;
+        let reason = format!(
+            "The server didn't respond to the watchdog {} times in a row.",
+            state.failures
+        );
+        tracing::error!(?action, "{reason}");
+        self.global_s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::WatchdogTriggered(
+                action, reason,
+            ))
+            .ok();
+
+        let (tx, rx) = oneshot::channel();
+        match action {
+            WatchdogAction::Restart => {
+                self.stop_watchdog();
+                self.s2ch_tx.send(ServerToChildMessage::Restart(tx)).unwrap();
+            }
+            WatchdogAction::Stop => {
+                self.stop_watchdog();
+                self.s2ch_tx.send(ServerToChildMessage::Stop(tx)).unwrap();
+            }
+            WatchdogAction::Notify => {
+                state.failures = 0;
+                self.record_lifecycle_event(LifecycleEventKind::WatchdogTriggered(action));
+                return;
+            }
+        }
+        self.record_lifecycle_event(LifecycleEventKind::WatchdogTriggered(action));
+
+        tokio::spawn(async move {
+            if let Ok(Err(error)) = rx.await {
+                tracing::error!(?action, ?error, "the watchdog failed to act: {error:#}");
+            }
+        });
+    }
+
+    fn stop_port_readiness_probe(&mut self) {
+        if let Some(probe) = self.port_readiness_probe.take() {
+            probe.abort();
+        }
+    }
+
+    /// starts checking the game port in the background, if enabled. the server is considered ready
+    /// once a connection succeeds; if it never does, the probe gives up after the timeout and
+    /// readiness is left to the ready line.
+    fn start_port_readiness_probe(&mut self) {
+        self.stop_port_readiness_probe();
+
+        let Some(config) = &self.config else {
+            return;
+        };
+        let Some(check) = config.port_readiness_check else {
+            return;
+        };
+        let Some(game_port) = utils::read_game_port(config.working_dir()) else {
+            tracing::warn!("the port readiness check is enabled, but the game port is unknown");
+            return;
+        };
+
+        let ready_tx = self.ready_tx.clone();
+        let probe = tokio::spawn(async move {
+            let connect = async {
+                loop {
+                    match TcpStream::connect((Ipv4Addr::LOCALHOST, game_port)).await {
+                        Ok(_) => break,
+                        Err(error) => {
+                            tracing::trace!(game_port, "game port isn't connectable yet: {error}")
+                        }
+                    }
+
+                    tokio::time::sleep(PORT_READINESS_INTERVAL).await;
+                }
+            };
+
+            match tokio::time::timeout(check.timeout(), connect).await {
+                Ok(()) => {
+                    tracing::debug!(game_port, "game port accepted a connection");
+                    ready_tx.send(()).ok();
+                }
+                Err(_) => tracing::warn!(
+                    game_port,
+                    "game port didn't accept a connection within {:?}, giving up on the port readiness check",
+                    check.timeout(),
+                ),
+            }
+        });
+        self.port_readiness_probe = Some(probe.abort_handle());
+    }
+
+    fn handle_ch2s(&mut self, message: ChildToServerMessage) {
         match message {
             ChildToServerMessage::Stdout(out) => {
+                self.notify_output_waiters(&out);
+                self.capture_startup_output(&out);
+                self.remember_output(&out);
+
                 self.global_s2c_tx
                     .send(raphy_protocol::ServerToClientMessage::Stdout(out))
                     .ok();
             }
+            ChildToServerMessage::Log(log) => {
+                let text = log.text();
+                if let OutputStream::Stdout = log.stream {
+                    self.notify_output_waiters(&text);
+                }
+                self.capture_startup_output(&text);
+                self.remember_output(&text);
+
+                self.global_s2c_tx
+                    .send(raphy_protocol::ServerToClientMessage::Log(log))
+                    .ok();
+            }
             ChildToServerMessage::Stderr(err) => {
+                self.capture_startup_output(&err);
+                self.remember_output(&err);
+
                 self.global_s2c_tx
                     .send(raphy_protocol::ServerToClientMessage::Stderr(err))
                     .ok();
             }
+            ChildToServerMessage::AutoRestartFailed(error) => {
+                self.global_s2c_tx
+                    .send(raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        None,
+                    ))
+                    .ok();
+            }
+            ChildToServerMessage::Adopted => {
+                // the server was running before raphy started, so it's assumed to be ready
+                self.handle_ch2s(ChildToServerMessage::UpdateState(ServerState::Started));
+                self.finish_startup();
+            }
             ChildToServerMessage::UpdateState(state) => {
+                match state {
+                    ServerState::Started => {
+                        self.record_lifecycle_event(LifecycleEventKind::Started);
+                        self.startup_log = StartupLog::default();
+                        self.capturing_startup = true;
+                        self.start_port_readiness_probe();
+                    }
+                    ServerState::Stopped(status, cause) => {
+                        self.record_lifecycle_event(LifecycleEventKind::Stopped(status, cause));
+                        self.capturing_startup = false;
+                        self.stop_port_readiness_probe();
+                        self.stop_watchdog();
+                    }
+                }
+
+                self.schedule_advertisement(matches!(state, ServerState::Started));
                 self.global_s2c_tx
                     .send(raphy_protocol::ServerToClientMessage::ServerStateUpdated(
                         state,
                     ))
                     .ok();
+
+                if let ServerState::Stopped(status, Some(StopCause::Unexpected)) = state {
+                    tracing::warn!(?status, "the server exited unexpectedly");
+                    self.global_s2c_tx
+                        .send(raphy_protocol::ServerToClientMessage::ServerCrashed(status))
+                        .ok();
+                }
+            }
+        }
+    }
+
+    fn schedule_advertisement(&mut self, running: bool) {
+        self.advertisement.schedule(running);
+    }
+
+    fn flush_advertisement(&mut self) {
+        let Some(running) = self.advertisement.due() else {
+            return;
+        };
+
+        match utils::update_advertisement(running) {
+            Ok(()) => self.advertisement.advertised = running,
+            Err(error) => {
+                tracing::warn!(?error, "failed to update the mdns advertisement: {error:#}")
             }
         }
     }
@@ -118,13 +1538,138 @@ impl ServerTask {
     pub async fn run(mut self, sh: SubsystemHandle<anyhow::Error>) {
         let sh = Arc::new(sh);
         self.sh = Some(Arc::clone(&sh));
+        self.schedule_restart();
 
         loop {
             tokio::select! {
                 Some(message) = self.n2s_rx.recv() => self.handle_n2s(message).await,
                 Some(message) = self.ch2s_rx.recv() => self.handle_ch2s(message),
+                Some((operation_id, outcome)) = self.outcome_rx.recv() => {
+                    self.record_operation_outcome(operation_id, outcome)
+                }
+                Some(()) = self.ready_rx.recv() => self.finish_startup(),
+                () = watchdog_tick(&mut self.watchdog) => self.probe_server(),
+                () = advertisement_due(&self.advertisement) => self.flush_advertisement(),
+                () = restart_due(self.scheduled_restart) => self.perform_scheduled_restart().await,
+                Some((generation, responded)) = self.probe_rx.recv() => {
+                    self.handle_probe_result(generation, responded)
+                }
+                Some((config, ret)) = self.jar_swapped_rx.recv() => {
+                    self.finish_jar_swap(config, ret).await
+                }
                 () = sh.on_shutdown_requested() => break,
             }
         }
+
+        self.stop_port_readiness_probe();
+        self.flush_config().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a server task without a child task or clients, and the sender for its messages
+    fn server_task(
+        config: Option<Config>,
+    ) -> (ServerTask, UnboundedSender<NetworkToServerMessage>) {
+        let (n2s_tx, n2s_rx) = mpsc::unbounded_channel();
+        let (_ch2s_tx, ch2s_rx) = mpsc::unbounded_channel();
+        let (s2ch_tx, _s2ch_rx) = mpsc::unbounded_channel();
+        let (global_s2c_tx, _global_s2c_rx) = mpsc::unbounded_channel();
+        let config_source = ConfigSource {
+            path: Config::path(),
+            loaded: config.is_some(),
+        };
+        let task = ServerTask::new(
+            n2s_rx,
+            ch2s_rx,
+            s2ch_tx,
+            global_s2c_tx,
+            config,
+            config_source,
+            0,
+        );
+        (task, n2s_tx)
+    }
+
+    #[tokio::test]
+    async fn a_config_submitted_right_before_shutdown_is_saved() {
+        let (_guard, path) = utils::lock_test_config_path().await;
+        let (mut task, n2s_tx) = server_task(Some(Config::default()));
+
+        // a launch script isn't checked, unlike java and the server jar
+        let config = Config {
+            launch_mode: LaunchMode::Script(PathBuf::from("start.sh")),
+            world_dir: Some(PathBuf::from("saved-world")),
+            ..Config::default()
+        };
+        let (tx, rx) = oneshot::channel();
+        n2s_tx
+            .send(NetworkToServerMessage::UpdateConfig(Box::new(config), tx))
+            .unwrap();
+        task.flush_config().await;
+
+        rx.await.unwrap().unwrap();
+        let saved = Config::load().await.unwrap().unwrap();
+        assert_eq!(saved.world_dir, Some(PathBuf::from("saved-world")));
+        assert_eq!(task.config_source.path, path);
+    }
+    #[tokio::test]
+    async fn a_flood_of_output_stays_within_the_budget() {
+        let budget = 4096;
+        let config = Config {
+            output_buffer_budget: budget,
+            ..Config::default()
+        };
+        let (mut task, _n2s_tx) = server_task(Some(config));
+        let flood = |task: &mut ServerTask| {
+            for _ in 0..1000 {
+                task.handle_ch2s(ChildToServerMessage::Stdout(vec![b'o'; 100]));
+                task.handle_ch2s(ChildToServerMessage::Stderr(vec![b'e'; 100]));
+                assert!(task.output_buffer_usage() <= budget);
+            }
+        };
+
+        flood(&mut task);
+        assert_eq!(task.recent_output.output.len(), budget);
+
+        // the startup log takes precedence, so the recent output makes room for it
+        task.capturing_startup = true;
+        flood(&mut task);
+        assert_eq!(task.startup_log.output.len(), budget);
+    }
+    fn debounce() -> AdvertisementDebounce {
+        AdvertisementDebounce {
+            min_interval: Duration::from_millis(50),
+            advertised: false,
+            pending: None,
+            deadline: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn rapid_toggles_result_in_a_single_final_advertisement() {
+        let mut debounce = debounce();
+        let started = Instant::now();
+        for running in [true, false, true, false, true] {
+            debounce.schedule(running);
+        }
+
+        advertisement_due(&debounce).await;
+        assert!(started.elapsed() >= debounce.min_interval);
+        assert_eq!(debounce.due(), Some(true));
+        assert_eq!(debounce.due(), None);
+    }
+
+    #[tokio::test]
+    async fn toggling_back_to_the_advertised_state_is_not_advertised() {
+        let mut debounce = debounce();
+        debounce.schedule(true);
+        debounce.schedule(false);
+
+        advertisement_due(&debounce).await;
+        assert_eq!(debounce.due(), None);
     }
 }
@@ -0,0 +1,154 @@
+/// a cgroup v2 group which caps the memory of the server process, so that the kernel kills the
+/// server rather than anything else on the host when it runs out. the group is removed when this
+/// is dropped, which only succeeds once the server has exited.
+pub struct MemoryCgroup {
+    #[cfg(target_os = "linux")]
+    path: std::path::PathBuf,
+}
+
+impl MemoryCgroup {
+    /// keeps the group rather than removing it, for a server which keeps running after raphy
+    /// exits. the next run of raphy picks it up again, since creating it tolerates that it exists.
+    pub fn leave(self) {
+        std::mem::forget(self);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::MemoryCgroup;
+    use anyhow::Context;
+    use raphy_protocol::MemoryUsage;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+    /// the name of the group, which is created within the group raphy runs in
+    const GROUP_NAME: &str = "raphy-server";
+
+    /// the group of the given instance, so that the daemons of several instances running in the
+    /// same group each limit their own server
+    fn group_name(instance: Option<&str>) -> String {
+        match instance {
+            Some(name) => format!("{GROUP_NAME}-{name}"),
+            None => GROUP_NAME.to_owned(),
+        }
+    }
+
+    fn read_u64(path: &Path) -> anyhow::Result<Option<u64>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read '{}'.", path.display()))?;
+
+        match contents.trim() {
+            "max" => Ok(None),
+            value => value
+                .parse()
+                .map(Some)
+                .with_context(|| format!("Failed to parse '{}'.", path.display())),
+        }
+    }
+
+    /// the group raphy itself runs in
+    fn own_cgroup() -> anyhow::Result<PathBuf> {
+        let contents = fs::read_to_string("/proc/self/cgroup")
+            .context("Failed to read the cgroup of the raphy server.")?;
+
+        // cgroup v2 only has the unified hierarchy, which has an id of 0 and no controllers
+        let relative = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .context("The raphy server isn't in a cgroup v2 hierarchy.")?;
+        Ok(Path::new(CGROUP_ROOT).join(relative.trim_start_matches('/')))
+    }
+
+    impl MemoryCgroup {
+        pub fn create(limit_mb: u64) -> anyhow::Result<Self> {
+            if !Path::new(CGROUP_ROOT).join("cgroup.controllers").exists() {
+                anyhow::bail!("cgroup v2 isn't available on this system.");
+            }
+
+            let parent = own_cgroup()?;
+            let controllers = fs::read_to_string(parent.join("cgroup.subtree_control"))
+                .context("Failed to read the controllers of the raphy server's cgroup.")?;
+            if !controllers.split_whitespace().any(|c| c == "memory") {
+                // this fails if the group has processes in it, which is usually the case unless
+                // the service manager delegated a subtree to raphy
+                fs::write(parent.join("cgroup.subtree_control"), "+memory").context(
+                    "Failed to enable the memory controller. Is a cgroup subtree delegated to the raphy server?",
+                )?;
+            }
+
+            let path = parent.join(group_name(crate::utils::instance()));
+            match fs::create_dir(&path) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(error) => return Err(error).context("Failed to create the cgroup."),
+            }
+
+            let limit = limit_mb.saturating_mul(1024 * 1024);
+            fs::write(path.join("memory.max"), limit.to_string())
+                .context("Failed to set the memory limit of the cgroup.")?;
+
+            tracing::debug!(?path, limit, "created memory cgroup");
+            Ok(Self { path })
+        }
+
+        /// moves the process into the group. processes it starts afterwards are in the group too.
+        pub fn add(&self, pid: u32) -> anyhow::Result<()> {
+            fs::write(self.path.join("cgroup.procs"), pid.to_string())
+                .context("Failed to move the server process into the cgroup.")
+        }
+
+        pub fn usage(&self) -> anyhow::Result<MemoryUsage> {
+            let events = fs::read_to_string(self.path.join("memory.events"))
+                .context("Failed to read the memory events of the cgroup.")?;
+            let oom_kills = events
+                .lines()
+                .find_map(|line| line.strip_prefix("oom_kill "))
+                .and_then(|count| count.trim().parse().ok())
+                .unwrap_or(0);
+
+            Ok(MemoryUsage {
+                current: read_u64(&self.path.join("memory.current"))?.unwrap_or(0),
+                limit: read_u64(&self.path.join("memory.max"))?,
+                oom_kills,
+            })
+        }
+    }
+
+    impl Drop for MemoryCgroup {
+        fn drop(&mut self) {
+            if let Err(error) = fs::remove_dir(&self.path) {
+                tracing::warn!(path = ?self.path, "failed to remove the cgroup: {error}");
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn instances_have_groups_of_their_own() {
+            assert_eq!(group_name(None), "raphy-server");
+            assert_eq!(group_name(Some("creative")), "raphy-server-creative");
+            assert_ne!(group_name(Some("creative")), group_name(Some("survival")));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl MemoryCgroup {
+    pub fn create(_limit_mb: u64) -> anyhow::Result<Self> {
+        anyhow::bail!("Memory limits are only supported on Linux.")
+    }
+
+    pub fn add(&self, _pid: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    pub fn usage(&self) -> anyhow::Result<raphy_protocol::MemoryUsage> {
+        unreachable!("memory cgroups can't be created on this platform")
+    }
+}
@@ -1,44 +1,159 @@
+use crate::ansi::{AnsiParser, AnsiStripper};
 use crate::base::ChildToServerMessage;
+use crate::cgroup::MemoryCgroup;
+use crate::utils;
 use anyhow::Context;
-use raphy_protocol::{Config, ServerState};
-use std::{io, mem};
-use std::path::Path;
-use std::process::{ExitStatus, Stdio};
-use std::sync::{Arc, Mutex};
+use nix::pty::OpenptyResult;
 use nix::sys::signal::Signal;
-use nix::unistd::Pid;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
-use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use nix::sys::termios::{self, LocalFlags, SetArg};
+use nix::unistd::{Pid, geteuid};
+use raphy_protocol::config::{
+    AnsiMode, ChildExitPolicy, InputPacing, LaunchMode, StopSignal, StopStep, TerminalMode, User,
+};
+use raphy_protocol::{
+    Config, ErrorKind, Log, MemoryUsage, OutputStream, ServerState, StopCause, StyledSpan,
+    TextStyle,
+};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::SeekFrom;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::{io, mem};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio_graceful_shutdown::{NestedSubsystem, SubsystemBuilder, SubsystemHandle};
 
 pub enum ServerToChildMessage {
     Stdin(Vec<u8>),
+
+    /// like `Stdin`, but fails if the server isn't running
+    Command(Vec<u8>, oneshot::Sender<anyhow::Result<()>>),
     Start(oneshot::Sender<anyhow::Result<()>>),
     Stop(oneshot::Sender<anyhow::Result<()>>),
     Restart(oneshot::Sender<anyhow::Result<()>>),
+
+    /// restarts the server with the given config, which only differs in its server jar. if the
+    /// server fails to start with it, the previous config is used again.
+    SwapJar(Config, oneshot::Sender<anyhow::Result<()>>),
     ServerState(oneshot::Sender<ServerState>),
+    MemoryUsage(oneshot::Sender<Option<MemoryUsage>>),
+
+    /// the pid of the server process, if it is running
+    Pid(oneshot::Sender<Option<Pid>>),
     UpdateConfig(Config),
 }
 
+/// how often an adopted server process is checked for whether it's still running, since it can't
+/// be waited on
+const ADOPTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the log file of the server relative to its working directory, which is followed for the output
+/// of an adopted server
+const ADOPTED_LOG_PATH: &str = "logs/latest.log";
+
+/// how often the log file of an adopted server is checked for new output
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// a request to the task waiting for the server process
+enum WaiterRequest {
+    /// kills the server process, which is how it's stopped if its pid isn't known
+    Kill,
+
+    /// stops waiting, leaving the server process running. see [`ChildExitPolicy::Leave`].
+    Detach,
+}
+
 enum State {
     Running {
-        std: NestedSubsystem<anyhow::Error>,
-        stdin_tx: UnboundedSender<Vec<u8>>,
+        /// the tasks handling the server's console, which an adopted server doesn't have
+        std: Option<NestedSubsystem<anyhow::Error>>,
+        stdin_tx: Option<UnboundedSender<Vec<u8>>>,
         pid: Option<Pid>,
+        waiter_tx: UnboundedSender<WaiterRequest>,
+
+        /// limits the memory of the server process, if configured and supported
+        cgroup: Option<MemoryCgroup>,
+
+        /// whether the server was asked to stop, so its exit is expected
+        stop_requested: Arc<AtomicBool>,
     },
     Stopped,
 }
 
+/// why the server process exited, given whether it was asked to stop
+fn stop_cause(stop_requested: &AtomicBool) -> StopCause {
+    if stop_requested.load(Ordering::SeqCst) {
+        StopCause::Requested
+    } else {
+        StopCause::Unexpected
+    }
+}
+
+/// a jar swap waiting for the previous server process to exit
+struct JarSwap {
+    previous: Config,
+    ret: oneshot::Sender<anyhow::Result<()>>,
+}
+
+/// how long the server has to keep running after it was started with a new jar before the swap
+/// is answered. if it exits before then, the previous jar is used again.
+const JAR_TRIAL_WINDOW: Duration = Duration::from_secs(30);
+
+/// a server which was started with a new jar, and hasn't run for [`JAR_TRIAL_WINDOW`] yet
+struct JarTrial {
+    previous: Config,
+    ret: oneshot::Sender<anyhow::Result<()>>,
+    generation: u64,
+}
+
 pub struct ChildTask {
     state: State,
     s2c_rx: UnboundedReceiver<ServerToChildMessage>,
     c2s_tx: UnboundedSender<ChildToServerMessage>,
-    dead_tx: UnboundedSender<()>,
-    dead_rx: UnboundedReceiver<()>,
-    sigterm_in_progress: bool,
+
+    /// tells the network task which stream the running server writes its regular output to
+    primary_output_tx: watch::Sender<OutputStream>,
+    dead_tx: UnboundedSender<StopCause>,
+    dead_rx: UnboundedReceiver<StopCause>,
+    escalate_tx: UnboundedSender<(u64, usize)>,
+    escalate_rx: UnboundedReceiver<(u64, usize)>,
+
+    /// index into the stop ladder of the last signal sent, if a stop is in progress
+    stop_step: Option<usize>,
+
+    /// distinguishes escalation timers of the current stop from those of previous ones
+    stop_generation: u64,
+
+    /// whether the current stop began by writing the shutdown command rather than with the stop
+    /// ladder
+    stop_wrote_command: bool,
     restart_in_progress: bool,
+    jar_swap: Option<JarSwap>,
+    jar_trial: Option<JarTrial>,
+
+    /// distinguishes the timer of the current jar trial from those of previous ones
+    jar_trial_generation: u64,
+    jar_trial_tx: UnboundedSender<u64>,
+    jar_trial_rx: UnboundedReceiver<u64>,
+
+    /// when the server was restarted after exiting unexpectedly, oldest first, so that a crash
+    /// loop can be told apart from the odd crash
+    auto_restarts: VecDeque<Instant>,
+
+    /// distinguishes the pending automatic restart from those a start or stop has superseded
+    auto_restart_generation: u64,
+    auto_restart_tx: UnboundedSender<u64>,
+    auto_restart_rx: UnboundedReceiver<u64>,
+
+    /// whether raphy is exiting and waiting for the server to stop first
+    exiting: bool,
     config: Option<Config>,
     sh: Option<Arc<SubsystemHandle<anyhow::Error>>>,
 }
@@ -47,17 +162,36 @@ impl ChildTask {
     pub fn new(
         s2c_rx: UnboundedReceiver<ServerToChildMessage>,
         c2s_tx: UnboundedSender<ChildToServerMessage>,
+        primary_output_tx: watch::Sender<OutputStream>,
         config: Option<Config>,
     ) -> Self {
         let (dead_tx, dead_rx) = mpsc::unbounded_channel();
+        let (escalate_tx, escalate_rx) = mpsc::unbounded_channel();
+        let (auto_restart_tx, auto_restart_rx) = mpsc::unbounded_channel();
+        let (jar_trial_tx, jar_trial_rx) = mpsc::unbounded_channel();
         Self {
             state: State::Stopped,
             s2c_rx,
             c2s_tx,
+            primary_output_tx,
             dead_tx,
             dead_rx,
-            sigterm_in_progress: false,
+            escalate_tx,
+            escalate_rx,
+            stop_step: None,
+            stop_generation: 0,
+            stop_wrote_command: false,
             restart_in_progress: false,
+            jar_swap: None,
+            jar_trial: None,
+            jar_trial_generation: 0,
+            jar_trial_tx,
+            jar_trial_rx,
+            auto_restarts: VecDeque::new(),
+            auto_restart_generation: 0,
+            auto_restart_tx,
+            auto_restart_rx,
+            exiting: false,
             config,
             sh: None,
         }
@@ -72,29 +206,298 @@ impl ChildTask {
     pub async fn run(mut self, sh: SubsystemHandle<anyhow::Error>) {
         let sh = Arc::new(sh);
         self.sh = Some(Arc::clone(&sh));
+        self.adopt();
 
         loop {
             tokio::select! {
                 Some(message) = self.s2c_rx.recv() => self.handle_s2c(message).await,
-                Some(()) = self.dead_rx.recv() => {
-                    self.sigterm_in_progress = false;
+                Some((generation, step)) = self.escalate_rx.recv() => {
+                    // only escalate if no further step was taken in the meantime
+                    if self.stop_generation == generation && self.stop_step == Some(step) {
+                        // the server can't have stopped in the meantime, since that resets the
+                        // stop step
+                        self.handle_s2c_stop().ok();
+                    }
+                },
+                Some(generation) = self.auto_restart_rx.recv() => {
+                    // the server may have been started or stopped by hand in the meantime
+                    if self.auto_restart_generation == generation
+                        && matches!(self.state, State::Stopped)
+                    {
+                        tracing::info!("restarting the server after it exited unexpectedly");
+                        if let Err(error) = self.handle_s2c_start() {
+                            self.report_auto_restart_failure(
+                                error.context("Failed to restart the server after it crashed."),
+                            );
+                        }
+                    }
+                },
+                Some(generation) = self.jar_trial_rx.recv() => {
+                    // the server may have exited in the meantime, which already answered the swap
+                    let trial = self.jar_trial.take_if(|trial| trial.generation == generation);
+                    if let Some(trial) = trial {
+                        tracing::info!("the server kept running with the new jar");
+                        trial.ret.send(Ok(())).ok();
+                    }
+                },
+                Some(cause) = self.dead_rx.recv() => {
+                    self.stop_step = None;
                     let state = mem::replace(&mut self.state, State::Stopped);
-                    
-                    if let State::Running { std, .. } = state {
-                        std.initiate_shutdown();
+                    utils::remove_pid_file();
+
+                    if let State::Running { std, cgroup, .. } = state {
+                        if let Some(std) = std {
+                            std.initiate_shutdown();
+                        }
+
+                        // removes the cgroup before a restart could create it again
+                        drop(cgroup);
+                    }
+
+                    if self.exiting {
+                        break;
                     }
-                    
+
                     if self.restart_in_progress {
                         if let Err(error) = self.handle_s2c_start() {
                             tracing::error!(?error, "failed to restart the server: {error:#}");
                         }
-                        
+
                         self.restart_in_progress = false;
                     }
+
+                    if let Some(JarSwap { previous, ret }) = self.jar_swap.take() {
+                        self.start_jar_trial(previous, ret);
+                    } else if let Some(JarTrial { previous, ret, .. }) = self.jar_trial.take() {
+                        // a stop which was asked for says nothing about the new jar
+                        if cause == StopCause::Unexpected {
+                            let error = anyhow::anyhow!(
+                                "The server exited within {} seconds of starting with the new jar.",
+                                JAR_TRIAL_WINDOW.as_secs()
+                            );
+                            ret.send(Err(self.roll_back_jar_swap(previous, error))).ok();
+                            continue;
+                        }
+
+                        ret.send(Ok(())).ok();
+                    }
+
+                    if cause == StopCause::Unexpected {
+                        self.schedule_auto_restart();
+                    }
                 },
-                () = sh.on_shutdown_requested() => break,
+                () = sh.on_shutdown_requested(), if !self.exiting => {
+                    if !self.handle_daemon_exit() {
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    /// applies the [`ChildExitPolicy`] as raphy exits. returns whether the server is being
+    /// stopped, in which case this task keeps running until it has exited.
+    fn handle_daemon_exit(&mut self) -> bool {
+        if let State::Stopped = self.state {
+            return false;
+        }
+
+        let policy = self
+            .config
+            .as_ref()
+            .map(|config| config.on_daemon_exit)
+            .unwrap_or_default();
+        match policy {
+            ChildExitPolicy::Stop => {
+                tracing::info!("stopping the server before exiting");
+                self.exiting = true;
+                self.restart_in_progress = false;
+
+                // a stop which is already in progress is waited for instead
+                if self.stop_step.is_some() {
+                    return true;
+                }
+
+                match self.handle_s2c_stop() {
+                    Ok(()) => true,
+                    Err(error) => {
+                        tracing::error!(?error, "failed to stop the server: {error:#}");
+                        false
+                    }
+                }
+            }
+            ChildExitPolicy::Leave => {
+                let State::Running {
+                    pid,
+                    waiter_tx,
+                    cgroup,
+                    ..
+                } = mem::replace(&mut self.state, State::Stopped)
+                else {
+                    return false;
+                };
+
+                tracing::info!(?pid, "leaving the server running, it's adopted by the next run");
+                waiter_tx.send(WaiterRequest::Detach).ok();
+                if let Some(cgroup) = cgroup {
+                    cgroup.leave();
+                }
+                false
+            }
+        }
+    }
+
+    /// checks that the process is one raphy launched, rather than trusting the pid file alone,
+    /// since it's signalled once adopted. it has to belong to this user, and have been launched
+    /// with the command the current config launches the server with. a server launched as another
+    /// user is recorded as its `sudo` process, which keeps this user as its real user.
+    fn check_adoptable(&self, identity: &utils::ProcessIdentity) -> anyhow::Result<()> {
+        let uid = utils::process_uid(identity.pid)?;
+        anyhow::ensure!(uid == geteuid(), "The process belongs to another user.");
+
+        let config = self
+            .config
+            .as_ref()
+            .context("There's no server configuration to compare its command line to.")?;
+        let command = server_command(config)?;
+        anyhow::ensure!(
+            identity.launched_with(command.as_std()),
+            "The process wasn't launched with the configured command."
+        );
+        Ok(())
+    }
+
+    /// takes over the server process a previous run of raphy left running, if there is one. that's
+    /// either because of [`ChildExitPolicy::Leave`], or because the previous run didn't get to stop
+    /// it, e.g. since it crashed.
+    fn adopt(&mut self) {
+        let identity = match utils::read_pid_file() {
+            Ok(Some(identity)) => identity,
+            Ok(None) => return,
+            Err(error) => {
+                tracing::warn!(?error, "failed to read the pid file: {error:#}");
+                utils::remove_pid_file();
+                return;
             }
+        };
+
+        let pid = identity.pid;
+        if !identity.is_running() {
+            tracing::debug!(?pid, "the server process of a previous run isn't running anymore");
+            utils::remove_pid_file();
+            return;
         }
+
+        if let Err(error) = self.check_adoptable(&identity) {
+            tracing::warn!(
+                ?pid,
+                ?error,
+                "not adopting the process in the pid file: {error:#}"
+            );
+            utils::remove_pid_file();
+            return;
+        }
+
+        tracing::info!(?pid, "adopting the server process left running by a previous run");
+        let cgroup = self
+            .config
+            .as_ref()
+            .and_then(|config| config.memory_limit_mb)
+            .and_then(|limit_mb| {
+                let cgroup = MemoryCgroup::create(limit_mb).and_then(|cgroup| {
+                    cgroup.add(pid.as_raw() as u32)?;
+                    Ok(cgroup)
+                });
+
+                cgroup
+                    .inspect_err(|error| {
+                        tracing::warn!(
+                            ?error,
+                            "failed to limit the memory of the adopted server: {error:#}"
+                        )
+                    })
+                    .ok()
+            });
+
+        let dead_tx = self.dead_tx.clone();
+        let c2s_tx = self.c2s_tx.clone();
+        let (waiter_tx, mut waiter_rx) = mpsc::unbounded_channel();
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let waiter_stop_requested = Arc::clone(&stop_requested);
+        self.sh()
+            .start(SubsystemBuilder::new("waiter", move |_| async move {
+                // the process isn't a child of this one, so it can't be waited on
+                let mut interval = tokio::time::interval(ADOPTED_POLL_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            if !identity.is_running() {
+                                break;
+                            }
+                        },
+                        Some(request) = waiter_rx.recv() => match request {
+                            WaiterRequest::Kill => {
+                                if let Err(error) = nix::sys::signal::kill(pid, Signal::SIGKILL) {
+                                    tracing::error!(
+                                        ?error,
+                                        "failed to kill the server process: {error}"
+                                    );
+                                }
+                            }
+                            WaiterRequest::Detach => return Ok(()),
+                        },
+                    }
+                }
+
+                let cause = stop_cause(&waiter_stop_requested);
+
+                // its exit status can't be known either
+                tracing::info!(?cause, "adopted server process exited");
+                c2s_tx
+                    .send(ChildToServerMessage::UpdateState(ServerState::Stopped(
+                        None,
+                        Some(cause),
+                    )))
+                    .ok();
+                dead_tx.send(cause).ok();
+
+                Ok::<_, anyhow::Error>(())
+            }));
+
+        // the server's console went away with the previous run, but its log still has its output
+        let std = self.config.as_ref().map(|config| {
+            let log_path = config.working_dir().join(ADOPTED_LOG_PATH);
+            let processor = OutputProcessor::new(
+                config.ansi,
+                config.terminal,
+                config.output_timestamp.clone(),
+                true,
+            );
+            let c2s_tx = self.c2s_tx.clone();
+            self.sh().start(SubsystemBuilder::new("log", move |sh| async move {
+                follow_log(&log_path, c2s_tx, processor, sh).await
+            }))
+        });
+
+        self.state = State::Running {
+            std,
+            stdin_tx: None,
+            pid: Some(pid),
+            waiter_tx,
+            cgroup,
+            stop_requested,
+        };
+        self.c2s_tx.send(ChildToServerMessage::Adopted).ok();
+    }
+}
+
+fn signal_from_stop_signal(signal: StopSignal) -> Signal {
+    match signal {
+        StopSignal::Interrupt => Signal::SIGINT,
+        StopSignal::Hangup => Signal::SIGHUP,
+        StopSignal::Terminate => Signal::SIGTERM,
+        StopSignal::Quit => Signal::SIGQUIT,
+        StopSignal::Kill => Signal::SIGKILL,
     }
 }
 
@@ -113,6 +516,11 @@ async fn output_subsystem(
                     break
                 }
                 Ok(n) => n,
+                // a pseudo-terminal reports EIO rather than EOF once the server's end is closed
+                Err(error) if error.raw_os_error() == Some(nix::errno::Errno::EIO as i32) => {
+                    sh.on_shutdown_requested().await;
+                    break
+                }
                 Err(error) => {
                     tracing::error!("failed to read from {std}: {error}");
                     sh.request_local_shutdown();
@@ -128,79 +536,557 @@ async fn output_subsystem(
     Ok(())
 }
 
+/// forwards what an adopted server writes to its log from now on, as if it was written to stdout
+async fn follow_log(
+    path: &Path,
+    c2s_tx: UnboundedSender<ChildToServerMessage>,
+    mut processor: OutputProcessor,
+    sh: SubsystemHandle<anyhow::Error>,
+) -> anyhow::Result<()> {
+    let mut log = match tokio::fs::File::open(path).await {
+        Ok(log) => log,
+        Err(error) => {
+            tracing::info!(?path, "not receiving the adopted server's output: {error}");
+            return Ok(());
+        }
+    };
+    log.seek(SeekFrom::End(0))
+        .await
+        .context("Failed to seek to the end of the server's log.")?;
+
+    let mut interval = tokio::time::interval(LOG_POLL_INTERVAL);
+    let mut buffer = vec![0; 1024];
+    loop {
+        tokio::select! {
+            _ = interval.tick() => loop {
+                let n = log
+                    .read(&mut buffer)
+                    .await
+                    .context("Failed to read the server's log.")?;
+                if n == 0 {
+                    break;
+                }
+
+                let output = buffer[..n].to_vec();
+                if let Some(message) = processor.process(OutputStream::Stdout, output) {
+                    c2s_tx.send(message).ok();
+                }
+            },
+            () = sh.on_shutdown_requested() => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// applies the configured [`AnsiMode`] and output timestamps to one of the server's output
+/// streams
+struct OutputProcessor {
+    ansi: AnsiProcessor,
+    timestamps: Option<Timestamps>,
+}
+
+enum AnsiProcessor {
+    Raw,
+    Strip(AnsiStripper),
+
+    /// also carries whether the stream is the primary output stream
+    Structure(AnsiParser, bool),
+}
+
+impl OutputProcessor {
+    fn new(
+        ansi: AnsiMode,
+        terminal: TerminalMode,
+        timestamp_format: Option<String>,
+        primary: bool,
+    ) -> Self {
+        let ansi = match (ansi, terminal) {
+            (AnsiMode::Raw, TerminalMode::Piped | TerminalMode::Pseudo) => AnsiProcessor::Raw,
+            (AnsiMode::Raw, TerminalMode::NonInteractive) | (AnsiMode::Strip, _) => {
+                AnsiProcessor::Strip(AnsiStripper::default())
+            }
+            (AnsiMode::Structured, _) => AnsiProcessor::Structure(AnsiParser::default(), primary),
+        };
+
+        Self {
+            ansi,
+            timestamps: timestamp_format.map(Timestamps::new),
+        }
+    }
+
+    /// turns a chunk of output into the message for it, if anything is left of it.
+    fn process(&mut self, stream: OutputStream, output: Vec<u8>) -> Option<ChildToServerMessage> {
+        let output = match &mut self.ansi {
+            AnsiProcessor::Raw => output,
+            AnsiProcessor::Strip(stripper) => stripper.strip(&output),
+            AnsiProcessor::Structure(parser, primary) => {
+                let mut spans = parser.parse(&output);
+                if let Some(timestamps) = &mut self.timestamps {
+                    spans = timestamps.prefix_spans(spans);
+                }
+
+                return (!spans.is_empty()).then_some(ChildToServerMessage::Log(Log {
+                    stream,
+                    primary: *primary,
+                    spans,
+                }));
+            }
+        };
+
+        if output.is_empty() {
+            return None;
+        }
+
+        let output = match &mut self.timestamps {
+            Some(timestamps) => timestamps.prefix(&output),
+            None => output,
+        };
+        Some(match stream {
+            OutputStream::Stdout => ChildToServerMessage::Stdout(output),
+            OutputStream::Stderr => ChildToServerMessage::Stderr(output),
+        })
+    }
+}
+
+/// prefixes every line of a stream with the time it was received, see
+/// [`Config::output_timestamp`]. output arrives in chunks which don't line up with its lines, so
+/// whether the next chunk starts a new line is remembered.
+struct Timestamps {
+    format: String,
+    at_line_start: bool,
+}
+
+impl Timestamps {
+    fn new(format: String) -> Self {
+        Self {
+            format,
+            at_line_start: true,
+        }
+    }
+
+    fn timestamp(&self) -> Vec<u8> {
+        // the format is validated with the config, but an invalid one shouldn't bring down the
+        // output of the server
+        let mut timestamp = String::new();
+        match write!(timestamp, "{} ", chrono::Local::now().format(&self.format)) {
+            Ok(()) => timestamp.into_bytes(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn prefix(&mut self, output: &[u8]) -> Vec<u8> {
+        let timestamp = self.timestamp();
+        let mut prefixed = Vec::with_capacity(output.len() + timestamp.len());
+        for line in output.split_inclusive(|&byte| byte == b'\n') {
+            if self.at_line_start {
+                prefixed.extend_from_slice(&timestamp);
+            }
+            prefixed.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
+        }
+
+        prefixed
+    }
+
+    /// like [`Self::prefix`], with the timestamps in spans of their own in the default style
+    fn prefix_spans(&mut self, spans: Vec<StyledSpan>) -> Vec<StyledSpan> {
+        let timestamp = self.timestamp();
+        let mut prefixed = Vec::with_capacity(spans.len());
+        for span in spans {
+            for line in span.text.split_inclusive(|&byte| byte == b'\n') {
+                if self.at_line_start {
+                    prefixed.push(StyledSpan {
+                        text: timestamp.clone(),
+                        style: TextStyle::default(),
+                    });
+                }
+                prefixed.push(StyledSpan {
+                    text: line.to_vec(),
+                    style: span.style,
+                });
+                self.at_line_start = line.ends_with(b"\n");
+            }
+        }
+
+        prefixed
+    }
+}
+
+/// opens a pseudo-terminal for the server process, with echo disabled since clients already show
+/// the input they send.
+fn open_pty() -> anyhow::Result<OpenptyResult> {
+    let pty = nix::pty::openpty(None, None).context("Failed to open a pseudo-terminal.")?;
+    let mut attrs = termios::tcgetattr(&pty.slave)
+        .context("Failed to get the pseudo-terminal's attributes.")?;
+    attrs.local_flags.remove(LocalFlags::ECHO);
+    termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &attrs)
+        .context("Failed to set the pseudo-terminal's attributes.")?;
+
+    Ok(pty)
+}
+
+/// writes input to the server's stdin in chunks, pausing between its lines if configured. each
+/// chunk is written before the next one, so a server that reads slowly holds the rest back.
+async fn write_input(
+    stdin: &mut (impl AsyncWrite + Unpin),
+    input: &[u8],
+    pacing: InputPacing,
+) -> io::Result<()> {
+    let line_delay = pacing.line_delay();
+
+    for (index, line) in input.split_inclusive(|&byte| byte == b'\n').enumerate() {
+        if index > 0 && !line_delay.is_zero() {
+            tokio::time::sleep(line_delay).await;
+        }
+
+        for chunk in line.chunks(pacing.chunk_size()) {
+            // flushing makes sure the input reaches a pseudo-terminal, which is written to through
+            // a buffered file
+            stdin.write_all(chunk).await?;
+            stdin.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the command the server is launched with, without its stdio and working directory
+fn server_command(config: &Config) -> anyhow::Result<Command> {
+    let server_args = config
+        .server_arguments
+        .resolve()
+        .context("Failed to get the server arguments.")?;
+    let mut command = match &config.launch_mode {
+        LaunchMode::Java => {
+            let java_path = config
+                .java_path
+                .resolve()
+                .context("Failed to get the Java path.")?;
+            let java_args = config
+                .java_arguments
+                .resolve()
+                .context("Failed to get the Java arguments.")?;
+
+            let mut command = launch_command(&config.user, &java_path, &config.env);
+            command
+                .args(java_args.iter())
+                .arg("-jar")
+                .arg(&config.server_jar_path);
+            command
+        }
+        LaunchMode::Script(script) => {
+            check_script(script)?;
+            launch_command(&config.user, script, &config.env)
+        }
+    };
+
+    command.args(server_args.iter());
+    Ok(command)
+}
+
+/// a command running `program`, as the given user.
+fn launch_command(user: &User, program: &Path, env: &BTreeMap<String, String>) -> Command {
+    match user.make_command() {
+        Some(mut command) => {
+            // sudo resets the environment, so the variables are set by `env` once running as the
+            // user instead
+            if !env.is_empty() {
+                command
+                    .arg("env")
+                    .args(env.iter().map(|(name, value)| format!("{name}={value}")));
+            }
+            command.arg(program);
+            command
+        }
+        None => {
+            let mut command = Command::new(program);
+            command.envs(env);
+            command
+        }
+    }
+}
+
+/// checks that the launch script is an executable file, since failing to spawn it wouldn't say
+/// which file is the problem.
+fn check_script(script: &Path) -> anyhow::Result<()> {
+    let metadata = std::fs::metadata(script)
+        .with_context(|| format!("The launch script '{}' does not exist.", script.display()))?;
+
+    if !metadata.is_file() {
+        anyhow::bail!("The launch script '{}' is not a file.", script.display());
+    }
+
+    if metadata.permissions().mode() & 0o111 == 0 {
+        anyhow::bail!("The launch script '{}' is not executable.", script.display());
+    }
+
+    Ok(())
+}
+
+/// checks that `user` can read the server jar (or run the launch script) and write to the working
+/// directory, so that a permission problem is reported before starting rather than as a cryptic
+/// failure midway through.
+///
+/// the check is skipped if it can't be performed, e.g. if `sudo` requires a password.
+fn check_user_permissions(user: &str, config: &Config) -> anyhow::Result<()> {
+    let launch_check = match &config.launch_mode {
+        LaunchMode::Java => ("-r", config.server_jar_path.as_path(), "read the server jar"),
+        LaunchMode::Script(script) => ("-x", script.as_path(), "run the launch script"),
+    };
+    let checks = [
+        launch_check,
+        ("-w", config.working_dir(), "write to the working directory"),
+    ];
+
+    for (flag, path, action) in checks {
+        let output = match std::process::Command::new("sudo")
+            .args(["-n", "-u", user, "test", flag])
+            .arg(path)
+            .output()
+        {
+            Ok(output) => output,
+            Err(error) => {
+                tracing::warn!(?error, "failed to check permissions as {user}, skipping the check");
+                return Ok(());
+            }
+        };
+
+        if output.status.success() {
+            continue;
+        }
+
+        // `test` prints nothing when the check fails, so anything on stderr came from `sudo`
+        if !output.stderr.is_empty() {
+            tracing::warn!(
+                stderr = %String::from_utf8_lossy(&output.stderr),
+                "failed to check permissions as {user}, skipping the check"
+            );
+            return Ok(());
+        }
+
+        return Err(ErrorKind::PermissionDenied(path.to_path_buf())).with_context(|| {
+            format!(
+                "The user '{user}' is not allowed to {action} '{}'.",
+                path.display()
+            )
+        });
+    }
+
+    Ok(())
+}
+
+/// the server's stdin, which is either a pipe or the pseudo-terminal it runs in
+type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// one of the server's output streams, which is either a pipe or the pseudo-terminal it runs in
+type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+
 impl ChildTask {
     fn handle_s2c_stdin(&mut self, input: Vec<u8>) {
-        if let State::Running { stdin_tx, .. } = &self.state {
-            stdin_tx.send(input).unwrap();
+        match &self.state {
+            State::Running {
+                stdin_tx: Some(stdin_tx),
+                ..
+            } => stdin_tx.send(input).unwrap(),
+            State::Running { stdin_tx: None, .. } => {
+                tracing::warn!("dropped input, since the adopted server has no console")
+            }
+            State::Stopped => {}
+        }
+    }
+
+    /// restarts the server after it exited unexpectedly, if configured, waiting longer the more
+    /// often it crashed within the window. once it crashed too often, it's left stopped.
+    fn schedule_auto_restart(&mut self) {
+        let Some(auto_restart) = self.config.as_ref().and_then(|config| config.auto_restart) else {
+            return;
+        };
+
+        let now = Instant::now();
+        while self
+            .auto_restarts
+            .front()
+            .is_some_and(|restarted_at| now.duration_since(*restarted_at) > auto_restart.window())
+        {
+            self.auto_restarts.pop_front();
         }
+
+        let previous_restarts = u32::try_from(self.auto_restarts.len()).unwrap_or(u32::MAX);
+        if previous_restarts >= auto_restart.max_restarts {
+            self.report_auto_restart_failure(anyhow::anyhow!(
+                "The server kept crashing after being restarted {previous_restarts} times within \
+                 {} seconds, so it's left stopped.",
+                auto_restart.window_secs
+            ));
+            return;
+        }
+
+        let backoff = auto_restart.backoff(previous_restarts);
+        tracing::warn!(
+            ?backoff,
+            previous_restarts,
+            "the server exited unexpectedly, restarting it after a delay"
+        );
+        self.auto_restarts.push_back(now);
+
+        let auto_restart_tx = self.auto_restart_tx.clone();
+        let generation = self.auto_restart_generation;
+        tokio::spawn(async move {
+            tokio::time::sleep(backoff).await;
+            auto_restart_tx.send(generation).ok();
+        });
+    }
+
+    fn report_auto_restart_failure(&self, error: anyhow::Error) {
+        tracing::error!(?error, "{error:#}");
+        self.c2s_tx
+            .send(ChildToServerMessage::AutoRestartFailed(error))
+            .ok();
     }
 
     fn handle_s2c_start(&mut self) -> anyhow::Result<()> {
+        self.auto_restart_generation += 1;
         if matches!(self.state, State::Running { .. }) {
-            return Ok(());
+            return Err(ErrorKind::AlreadyInState(ServerState::Started))
+                .context("The server is already running.");
         }
 
         let Some(config) = &self.config else {
             anyhow::bail!("A server configuration is required to start the server.");
         };
-        let java_path = config
-            .java_path
-            .resolve()
-            .context("Failed to get the Java path.")?;
-        let java_args = config
-            .java_arguments
-            .resolve()
-            .context("Failed to get the Java arguments.")?;
-        let server_args = config
-            .server_arguments
-            .resolve()
-            .context("Failed to get the server arguments.")?;
-        let mut command = match config.user.make_command() {
-            Some(mut command) => {
-                command.arg(&*java_path);
-                command
-            }
-            None => Command::new(&*java_path),
+
+        // the files may have changed since the config was updated
+        config.validate()?;
+
+        if let User::Specific(user) = &config.user {
+            check_user_permissions(user, config)?;
+        }
+
+        let mut command = server_command(config)?;
+
+        let terminal = config.terminal;
+        let ansi = config.ansi;
+        let output_timestamp = config.output_timestamp.clone();
+        let primary_output_stream = config.primary_output_stream();
+        self.primary_output_tx.send_replace(primary_output_stream);
+        let memory_limit_mb = config.memory_limit_mb;
+        let input_pacing = config.input_pacing;
+        let pty = match terminal {
+            TerminalMode::Pseudo => Some(open_pty()?),
+            TerminalMode::Piped | TerminalMode::NonInteractive => None,
         };
-        
-        let child = command
-            .current_dir(config.server_jar_path.parent().unwrap_or_else(|| Path::new("/")))
-            .args(java_args.iter())
-            .arg("-jar")
-            .arg(&config.server_jar_path)
-            .args(server_args.iter())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-        
+
+        let child = command.current_dir(config.working_dir());
+
+        match &pty {
+            Some(pty) => {
+                let slave = || {
+                    pty.slave
+                        .try_clone()
+                        .map(Stdio::from)
+                        .context("Failed to duplicate the pseudo-terminal.")
+                };
+                child.stdin(slave()?).stdout(slave()?).stderr(slave()?);
+            }
+            None => {
+                child
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+            }
+        }
+
+        if let TerminalMode::NonInteractive = terminal {
+            child.env("TERM", "dumb").env("NO_COLOR", "1");
+        }
+
+        // signals meant for raphy, e.g. from pressing ctrl-c in its terminal, shouldn't reach the
+        // server, which is stopped or left running according to the exit policy instead
+        child.process_group(0);
+
         let child_std = child.as_std();
-        tracing::debug!(program = ?child_std.get_program(), args = ?child_std.get_args(), "starting server process");
+        tracing::debug!(program = ?child_std.get_program(), args = ?child_std.get_args(), ?terminal, "starting server process");
 
         let mut child = command
             .spawn()
             .context("Failed to start the server process.")?;
 
+        // the command holds onto copies of the pseudo-terminal's server end, which have to be closed
+        // for its output to end once the server exits
+        drop(command);
+
+        // the process is moved into the cgroup right after it's spawned, so whatever it allocates
+        // before then isn't counted, which is negligible next to the server's heap
+        let cgroup = memory_limit_mb.and_then(|limit_mb| {
+            let cgroup = MemoryCgroup::create(limit_mb)
+                .and_then(|cgroup| {
+                    cgroup.add(child.id().context("The server process already exited.")?)?;
+                    Ok(cgroup)
+                });
+
+            match cgroup {
+                Ok(cgroup) => Some(cgroup),
+                Err(error) => {
+                    tracing::warn!(
+                        ?error,
+                        "failed to limit the memory of the server, starting it without a limit: {error:#}"
+                    );
+                    None
+                }
+            }
+        });
+
         let c2s_tx = self.c2s_tx.clone();
-        let mut stdin = child
-            .stdin
-            .take()
-            .expect("child did not have a handle to stdin");
-        let stdout = child
-            .stdout
-            .take()
-            .expect("child did not have a handle to stdout");
-        let stderr = child
-            .stderr
-            .take()
-            .expect("child did not have a handle to stderr");
+        let (mut stdin, stdout, stderr): (BoxedWriter, BoxedReader, Option<BoxedReader>) = match pty
+        {
+            Some(pty) => {
+                let master = std::fs::File::from(pty.master);
+                let writer = master
+                    .try_clone()
+                    .context("Failed to duplicate the pseudo-terminal.")?;
+                (
+                    Box::new(tokio::fs::File::from_std(writer)),
+                    Box::new(tokio::fs::File::from_std(master)),
+                    None,
+                )
+            }
+            None => (
+                Box::new(
+                    child
+                        .stdin
+                        .take()
+                        .expect("child did not have a handle to stdin"),
+                ),
+                Box::new(
+                    child
+                        .stdout
+                        .take()
+                        .expect("child did not have a handle to stdout"),
+                ),
+                Some(Box::new(
+                    child
+                        .stderr
+                        .take()
+                        .expect("child did not have a handle to stderr"),
+                )),
+            ),
+        };
         let (stdin_tx, mut stdin_rx) = mpsc::unbounded_channel::<Vec<u8>>();
-        let root = self.sh().start(SubsystemBuilder::new("std", |sh| async move {
+        let root = self.sh().start(SubsystemBuilder::new("std", move |sh| async move {
             sh.start(SubsystemBuilder::new("in", {
-                |sh| async move {
+                move |sh| async move {
                     loop {
                         tokio::select! {
                             Some(input) = stdin_rx.recv() => {
-                                if let Err(error) = stdin.write_all(&input).await {
+                                // a paced paste can take a while, which shouldn't hold up shutting
+                                // down
+                                let result = tokio::select! {
+                                    result = write_input(&mut stdin, &input, input_pacing) => result,
+                                    () = sh.on_shutdown_requested() => break,
+                                };
+
+                                if let Err(error) = result {
                                     tracing::error!("failed to write to stdin: {error}");
                                     sh.request_local_shutdown();
                                     break
@@ -220,18 +1106,37 @@ impl ChildTask {
             }));
 
             let (stderr_tx, mut stderr_rx) = mpsc::unbounded_channel();
-            sh.start(SubsystemBuilder::new("err", |sh| async move {
-                output_subsystem(stderr, stderr_tx, sh, "stderr").await
-            }));
+            if let Some(stderr) = stderr {
+                sh.start(SubsystemBuilder::new("err", |sh| async move {
+                    output_subsystem(stderr, stderr_tx, sh, "stderr").await
+                }));
+            }
+
+            sh.start(SubsystemBuilder::new("channel-helper", move |sh| async move {
+                let mut stdout_processor = OutputProcessor::new(
+                    ansi,
+                    terminal,
+                    output_timestamp.clone(),
+                    primary_output_stream == OutputStream::Stdout,
+                );
+                let mut stderr_processor = OutputProcessor::new(
+                    ansi,
+                    terminal,
+                    output_timestamp,
+                    primary_output_stream == OutputStream::Stderr,
+                );
 
-            sh.start(SubsystemBuilder::new("channel-helper", |sh| async move {
                 loop {
                     tokio::select! {
                         Some(buf) = stdout_rx.recv() => {
-                            c2s_tx.send(ChildToServerMessage::Stdout(buf)).ok();
+                            if let Some(message) = stdout_processor.process(OutputStream::Stdout, buf) {
+                                c2s_tx.send(message).ok();
+                            }
                         },
                         Some(buf) = stderr_rx.recv() => {
-                            c2s_tx.send(ChildToServerMessage::Stderr(buf)).ok();
+                            if let Some(message) = stderr_processor.process(OutputStream::Stderr, buf) {
+                                c2s_tx.send(message).ok();
+                            }
                         },
                         () = sh.on_shutdown_requested() => break,
                     }
@@ -242,70 +1147,294 @@ impl ChildTask {
 
             Ok::<_, anyhow::Error>(())
         }));
-        
 
         let dead_tx = self.dead_tx.clone();
         let c2s_tx = self.c2s_tx.clone();
         let pid = child.id().map(|id| Pid::from_raw(id as i32));
+        match pid {
+            Some(pid) => {
+                let result = utils::ProcessIdentity::of(pid)
+                    .and_then(|identity| utils::write_pid_file(&identity));
+                if let Err(error) = result {
+                    tracing::warn!(
+                        ?error,
+                        "failed to record the server process, it can't be adopted later: {error:#}"
+                    );
+                }
+            }
+            None => tracing::warn!(
+                "the server process has no pid, so it can only be killed rather than stopped gracefully"
+            ),
+        }
+        let (waiter_tx, mut waiter_rx) = mpsc::unbounded_channel();
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let waiter_stop_requested = Arc::clone(&stop_requested);
         self.sh()
             .start(SubsystemBuilder::new("waiter", |sh| async move {
-                match child.wait().await {
+                let result = loop {
+                    tokio::select! {
+                        result = child.wait() => break result,
+                        Some(request) = waiter_rx.recv() => match request {
+                            WaiterRequest::Kill => {
+                                if let Err(error) = child.start_kill() {
+                                    tracing::error!(
+                                        ?error,
+                                        "failed to kill the server process: {error}"
+                                    );
+                                }
+                            }
+
+                            // the process keeps running once its handle is dropped
+                            WaiterRequest::Detach => return Ok(()),
+                        },
+                    }
+                };
+                let cause = stop_cause(&waiter_stop_requested);
+
+                match result {
                     Ok(exit_status) => {
-                        tracing::info!("server process exited with status code {exit_status}");
+                        tracing::info!(?cause, "server process exited with status code {exit_status}");
 
                         c2s_tx
-                            .send(ChildToServerMessage::UpdateState(ServerState::Stopped(Some(exit_status.into()))))
+                            .send(ChildToServerMessage::UpdateState(ServerState::Stopped(Some(exit_status.into()), Some(cause))))
                             .ok();
                     }
                     Err(error) => {
                         tracing::error!("failed to wait for the server process to exit: {error}");
                         c2s_tx
-                            .send(ChildToServerMessage::UpdateState(ServerState::Stopped(None)))
+                            .send(ChildToServerMessage::UpdateState(ServerState::Stopped(
+                                None,
+                                Some(cause),
+                            )))
                             .ok();
                     }
                 }
-                
-                dead_tx.send(()).ok();
-                
+
+                dead_tx.send(cause).ok();
+
                 Ok::<_, anyhow::Error>(())
             }));
 
         self.state = State::Running {
-            std: root,
-            stdin_tx,
+            std: Some(root),
+            stdin_tx: Some(stdin_tx),
             pid,
+            waiter_tx,
+            cgroup,
+            stop_requested,
         };
-        
-        self.c2s_tx.send(ChildToServerMessage::UpdateState(ServerState::Started)).ok();
+
+        self.c2s_tx
+            .send(ChildToServerMessage::UpdateState(ServerState::Started))
+            .ok();
 
         Ok(())
     }
 
-    fn handle_s2c_stop(&mut self) {
-        if let State::Running { pid: Some(pid), .. } = &mut self.state {
-            let signal = if self.sigterm_in_progress {
-                Signal::SIGKILL
-            } else {
-                Signal::SIGTERM
-            };
-            
-            if let Err(error) = nix::sys::signal::kill(*pid, signal) {
-                tracing::error!(?error, ?pid, "failed to send SIGTERM to the server process");
+    fn stop_ladder(&self) -> Vec<StopStep> {
+        self.config
+            .as_ref()
+            .map(|config| config.stop_ladder.clone())
+            .unwrap_or_else(raphy_protocol::config::default_stop_ladder)
+    }
+
+    /// writes the shutdown command if one is configured, then sends the next signal in the stop
+    /// ladder on each call, escalating to `SIGKILL` once the ladder is exhausted.
+    fn handle_s2c_stop(&mut self) -> anyhow::Result<()> {
+        // stopping a crashed server keeps it from being restarted
+        self.auto_restart_generation += 1;
+        if let State::Running { stop_requested, .. } = &self.state {
+            stop_requested.store(true, Ordering::SeqCst);
+        }
+
+        let (pid, stdin_tx) = match &self.state {
+            State::Running {
+                pid: Some(pid),
+                stdin_tx,
+                ..
+            } => (*pid, stdin_tx.clone()),
+
+            // signals can't be sent without a pid, but the process can still be killed through
+            // its handle
+            State::Running {
+                pid: None,
+                waiter_tx,
+                ..
+            } => {
+                tracing::warn!("the server process has no pid, killing it instead of stopping it");
+                waiter_tx
+                    .send(WaiterRequest::Kill)
+                    .ok()
+                    .context("The server process couldn't be killed, since it isn't waited on.")?;
+                return Ok(());
+            }
+            State::Stopped => {
+                return Err(ErrorKind::AlreadyInState(ServerState::Stopped(None, None)))
+                    .context("The server is already stopped.");
+            }
+        };
+
+        let step = match self.stop_step {
+            Some(step) => step + 1,
+            None => {
+                self.stop_generation += 1;
+                0
             }
-            
-            self.sigterm_in_progress = true;
+        };
+
+        // the shutdown command comes before the stop ladder, so it takes up the first step. it
+        // needs the server's console, which an adopted server doesn't have and which is closed
+        // once raphy exits.
+        let shutdown_command = stdin_tx.filter(|_| step == 0 && !self.exiting).zip(
+            self.config
+                .as_ref()
+                .and_then(|config| config.shutdown_command.clone()),
+        );
+        if step == 0 {
+            self.stop_wrote_command = shutdown_command.is_some();
+        }
+
+        let timeout = match shutdown_command {
+            Some((stdin_tx, shutdown_command)) => {
+                tracing::info!(?pid, "writing the shutdown command to the server's console");
+                if stdin_tx.send(shutdown_command.input()).is_err() {
+                    tracing::warn!("the server's stdin was closed, waiting out the grace period");
+                }
+                Some(shutdown_command.grace())
+            }
+            None => {
+                let ladder_step = step - usize::from(self.stop_wrote_command);
+                let ladder = self.stop_ladder();
+                let (signal, timeout) = match ladder.get(ladder_step) {
+                    Some(stop_step) => {
+                        (signal_from_stop_signal(stop_step.signal), Some(stop_step.timeout()))
+                    }
+                    None => (Signal::SIGKILL, None),
+                };
+
+                tracing::info!(?signal, ?pid, step, "sending stop signal to the server process");
+                if let Err(error) = nix::sys::signal::kill(pid, signal) {
+                    tracing::error!(?error, ?pid, "failed to send {signal} to the server process");
+                }
+                timeout
+            }
+        };
+
+        self.stop_step = Some(step);
+
+        if let Some(timeout) = timeout {
+            let escalate_tx = self.escalate_tx.clone();
+            let generation = self.stop_generation;
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                escalate_tx.send((generation, step)).ok();
+            });
         }
+
+        Ok(())
     }
 
     fn handle_s2c_restart(&mut self) -> anyhow::Result<()> {
-        self.handle_s2c_stop();
+        // there's nothing to stop, so the server would never come back up by waiting for it to exit
+        if let State::Stopped = self.state {
+            return self.handle_s2c_start();
+        }
+
+        self.handle_s2c_stop()?;
         self.restart_in_progress = true;
         Ok(())
     }
 
+    fn handle_s2c_swap_jar(
+        &mut self,
+        config: Config,
+        ret: oneshot::Sender<anyhow::Result<()>>,
+    ) {
+        if self.restart_in_progress || self.jar_swap.is_some() || self.jar_trial.is_some() {
+            ret.send(Err(anyhow::anyhow!("The server is already restarting.")))
+                .ok();
+            return;
+        }
+
+        let Some(previous) = self.config.replace(config) else {
+            self.config = None;
+            ret.send(Err(anyhow::anyhow!(
+                "A server configuration is required to update the server jar."
+            )))
+            .ok();
+            return;
+        };
+
+        // the new jar is used once the server is started
+        if let State::Stopped = self.state {
+            ret.send(Ok(())).ok();
+            return;
+        }
+
+        match self.handle_s2c_stop() {
+            Ok(()) => self.jar_swap = Some(JarSwap { previous, ret }),
+            Err(error) => {
+                self.config = Some(previous);
+                ret.send(Err(error)).ok();
+            }
+        }
+    }
+
+    /// starts the server with the new jar. the swap is answered once the server kept running for
+    /// [`JAR_TRIAL_WINDOW`], or falls back to the previous config if it fails before then.
+    fn start_jar_trial(&mut self, previous: Config, ret: oneshot::Sender<anyhow::Result<()>>) {
+        if let Err(error) = self.handle_s2c_start() {
+            ret.send(Err(self.roll_back_jar_swap(previous, error))).ok();
+            return;
+        }
+
+        self.jar_trial_generation += 1;
+        let generation = self.jar_trial_generation;
+        self.jar_trial = Some(JarTrial {
+            previous,
+            ret,
+            generation,
+        });
+
+        let jar_trial_tx = self.jar_trial_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(JAR_TRIAL_WINDOW).await;
+            jar_trial_tx.send(generation).ok();
+        });
+    }
+
+    /// starts the server with the previous config again after it failed with the new jar,
+    /// returning the error the swap is answered with.
+    fn roll_back_jar_swap(&mut self, previous: Config, error: anyhow::Error) -> anyhow::Error {
+        tracing::error!(?error, "the server failed with the new jar: {error:#}");
+        self.config = Some(previous);
+        if let Err(error) = self.handle_s2c_start() {
+            tracing::error!(
+                ?error,
+                "failed to start the server with the previous jar: {error:#}"
+            );
+        }
+
+        error.context("The server failed with the new jar, so the previous jar is used again.")
+    }
+
     async fn handle_s2c(&mut self, message: ServerToChildMessage) {
         match message {
             ServerToChildMessage::Stdin(input) => self.handle_s2c_stdin(input),
+            ServerToChildMessage::Command(command, ret) => {
+                let result = match &self.state {
+                    State::Running {
+                        stdin_tx: Some(stdin_tx),
+                        ..
+                    } => stdin_tx.send(command).context("The server's stdin was closed."),
+                    State::Running { stdin_tx: None, .. } => Err(anyhow::anyhow!(
+                        "The server was adopted from a previous run of raphy, so its console \
+                         isn't available."
+                    )),
+                    State::Stopped => Err(anyhow::anyhow!("The server is not running.")),
+                };
+                ret.send(result).ok();
+            }
             ServerToChildMessage::Start(ret) => {
                 let result = self.handle_s2c_start();
 
@@ -316,8 +1445,13 @@ impl ChildTask {
                 ret.send(result).unwrap();
             }
             ServerToChildMessage::Stop(ret) => {
-                self.handle_s2c_stop();
-                ret.send(Ok(())).unwrap()
+                let result = self.handle_s2c_stop();
+
+                if let Err(error) = &result {
+                    tracing::error!(?error, "failed to stop the server: {error:#}")
+                }
+
+                ret.send(result).unwrap()
             }
             ServerToChildMessage::Restart(ret) => {
                 let result = self.handle_s2c_restart();
@@ -328,14 +1462,204 @@ impl ChildTask {
 
                 ret.send(result).unwrap()
             }
+            ServerToChildMessage::SwapJar(config, ret) => self.handle_s2c_swap_jar(config, ret),
             ServerToChildMessage::ServerState(ret) => {
                 let state = match &self.state {
                     State::Running { .. } => ServerState::Started,
-                    State::Stopped => ServerState::Stopped(None),
+                    State::Stopped => ServerState::Stopped(None, None),
                 };
                 ret.send(state).unwrap();
             }
+            ServerToChildMessage::MemoryUsage(ret) => {
+                let usage = match &self.state {
+                    State::Running {
+                        cgroup: Some(cgroup),
+                        ..
+                    } => cgroup
+                        .usage()
+                        .inspect_err(|error| {
+                            tracing::warn!(?error, "failed to get the memory usage: {error:#}")
+                        })
+                        .ok(),
+                    State::Running { .. } | State::Stopped => None,
+                };
+                ret.send(usage).ok();
+            }
+            ServerToChildMessage::Pid(ret) => {
+                let pid = match &self.state {
+                    State::Running { pid, .. } => *pid,
+                    State::Stopped => None,
+                };
+                ret.send(pid).ok();
+            }
             ServerToChildMessage::UpdateConfig(config) => self.config = Some(config),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// remembers every write to it separately
+    #[derive(Default)]
+    struct RecordingStdin {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl AsyncWrite for RecordingStdin {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes.push(buf.to_vec());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn child_task() -> ChildTask {
+        let (_s2ch_tx, s2ch_rx) = mpsc::unbounded_channel();
+        let (ch2s_tx, _ch2s_rx) = mpsc::unbounded_channel();
+        let (primary_output_tx, _) = watch::channel(OutputStream::Stdout);
+        ChildTask::new(s2ch_rx, ch2s_tx, primary_output_tx, None)
+    }
+
+    /// the state of a server which is running, without a process or pid behind it. the returned
+    /// receiver gets what's asked of the process' waiter.
+    fn running() -> (State, UnboundedReceiver<WaiterRequest>) {
+        let (waiter_tx, waiter_rx) = mpsc::unbounded_channel();
+        let state = State::Running {
+            std: None,
+            stdin_tx: None,
+            pid: None,
+            waiter_tx,
+            cgroup: None,
+            stop_requested: Arc::default(),
+        };
+        (state, waiter_rx)
+    }
+
+    #[test]
+    fn starting_a_running_server_fails() {
+        let mut task = child_task();
+        let (state, _waiter_rx) = running();
+        task.state = state;
+
+        let error = task.handle_s2c_start().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ErrorKind>(),
+            Some(ErrorKind::AlreadyInState(ServerState::Started))
+        ));
+    }
+
+    #[test]
+    fn stopping_a_stopped_server_fails() {
+        let mut task = child_task();
+
+        let error = task.handle_s2c_stop().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ErrorKind>(),
+            Some(ErrorKind::AlreadyInState(ServerState::Stopped(..)))
+        ));
+    }
+
+    #[test]
+    fn a_server_without_a_pid_is_killed_through_its_handle() {
+        let mut task = child_task();
+        let (state, mut waiter_rx) = running();
+        task.state = state;
+
+        task.handle_s2c_stop().unwrap();
+        assert!(matches!(waiter_rx.try_recv(), Ok(WaiterRequest::Kill)));
+    }
+
+    #[test]
+    fn a_server_without_a_pid_which_isnt_waited_on_fails_to_stop() {
+        let mut task = child_task();
+        let (state, waiter_rx) = running();
+        task.state = state;
+        drop(waiter_rx);
+
+        assert!(task.handle_s2c_stop().is_err());
+    }
+
+    /// the cause the running server's exit would be reported with
+    fn cause_of_exit(task: &ChildTask) -> StopCause {
+        let State::Running { stop_requested, .. } = &task.state else {
+            panic!("the server isn't running");
+        };
+        stop_cause(stop_requested)
+    }
+
+    #[test]
+    fn an_exit_after_a_stop_was_requested_is_expected() {
+        let mut task = child_task();
+        let (state, _waiter_rx) = running();
+        task.state = state;
+
+        task.handle_s2c_stop().unwrap();
+        assert_eq!(cause_of_exit(&task), StopCause::Requested);
+    }
+
+    #[test]
+    fn an_exit_without_a_stop_being_requested_is_unexpected() {
+        let mut task = child_task();
+        let (state, _waiter_rx) = running();
+        task.state = state;
+
+        assert_eq!(cause_of_exit(&task), StopCause::Unexpected);
+    }
+
+    fn paste(lines: usize) -> Vec<u8> {
+        (0..lines)
+            .flat_map(|line| format!("say line {line} of the paste\n").into_bytes())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_multi_line_paste_is_delivered_in_order() {
+        let input = paste(100);
+        let pacing = InputPacing {
+            chunk_size: 8,
+            line_delay_ms: 0,
+        };
+        let mut stdin = RecordingStdin::default();
+        write_input(&mut stdin, &input, pacing).await.unwrap();
+
+        assert_eq!(stdin.writes.concat(), input);
+        for write in &stdin.writes {
+            assert!(write.len() <= 8);
+
+            // a chunk never spans two lines, so the pause between lines comes after a whole line
+            assert!(!write[..write.len() - 1].contains(&b'\n'));
+        }
+    }
+
+    #[tokio::test]
+    async fn the_lines_of_a_paste_are_paced() {
+        let input = paste(3);
+        let pacing = InputPacing {
+            chunk_size: 4096,
+            line_delay_ms: 20,
+        };
+        let mut stdin = RecordingStdin::default();
+        let started = Instant::now();
+        write_input(&mut stdin, &input, pacing).await.unwrap();
+
+        // there's no pause before the first line
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert_eq!(stdin.writes.len(), 3);
+        assert_eq!(stdin.writes.concat(), input);
+    }
+}
@@ -1,18 +1,22 @@
+mod ansi;
 mod base;
+mod cgroup;
 mod child;
 mod network;
+#[cfg(feature = "prometheus")]
+mod prometheus;
 mod utils;
 
 use crate::child::ChildTask;
 use anyhow::Context;
 use native_dialog::MessageType;
-use raphy_protocol::Config;
+use raphy_protocol::{Config, ConfigSource, OutputStream};
 use std::env;
 use std::fmt::{Debug, Display};
 use std::process::ExitCode;
 use std::time::Duration;
 use auto_launch::AutoLaunch;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle, Toplevel};
 use tracing_subscriber::{EnvFilter, Layer};
 use raphy_common::ConfigLike;
@@ -34,7 +38,25 @@ fn auto_launch() -> anyhow::Result<()> {
 }
 
 
+/// removes the unix socket and mdns registration when dropped, so that they're cleaned up even if
+/// `main` unwinds from a panic and a stale socket doesn't block the next start.
+///
+/// this is best-effort: it can't run if the process is killed or aborts, and panics in spawned
+/// tasks don't unwind `main`, though those bring the server down through the graceful shutdown
+/// path when they happen in a subsystem.
+struct CleanupGuard;
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        network::remove_unix_socket();
+        utils::stop_advertising();
+    }
+}
+
 async fn real_main(sh: SubsystemHandle<anyhow::Error>) -> anyhow::Result<()> {
+    tracing::info!("raphy server {}", raphy_protocol::BUILD_VERSION);
+    utils::init_instance().context("Failed to determine the instance to run as.")?;
+
     if env::args().nth(2).as_deref() == Some("auto-launch") {
         if let Err(error) = auto_launch() {
             tracing::warn!(?error, "failed to toggle auto-launch");
@@ -43,25 +65,51 @@ async fn real_main(sh: SubsystemHandle<anyhow::Error>) -> anyhow::Result<()> {
     
     let (n2s_tx, n2s_rx) = mpsc::unbounded_channel();
     let (global_s2c_tx, global_s2c_rx) = mpsc::unbounded_channel();
-    let port = network::initialize(&sh, n2s_tx, global_s2c_rx)
+    let (primary_output_tx, primary_output_rx) = watch::channel(OutputStream::Stdout);
+    let port = network::initialize(&sh, n2s_tx, global_s2c_rx, primary_output_rx)
         .await
         .context("Failed to initialize the network subsystem.")?;
 
-    utils::start_advertising(port).context("Failed to start advertising mDNS service.")?;
-
+    let config_path = Config::effective_path();
     let config = Config::load()
         .await
         .context("Failed to load the server configuration.")?;
+    let config_source = ConfigSource {
+        path: config_path,
+        loaded: config.is_some(),
+    };
+    if config_source.loaded {
+        tracing::info!(path = ?config_source.path, "loaded the configuration");
+    } else {
+        tracing::info!(
+            path = ?config_source.path,
+            "no configuration exists yet, the server is unconfigured"
+        );
+    }
+
+    let game_port = config
+        .as_ref()
+        .and_then(|config| utils::read_game_port(config.working_dir()));
+    utils::start_advertising(port, game_port)
+        .context("Failed to start advertising mDNS service.")?;
     let (s2ch_tx, s2ch_rx) = mpsc::unbounded_channel();
     let (ch2s_tx, ch2s_rx) = mpsc::unbounded_channel();
-    let child_task = ChildTask::new(s2ch_rx, ch2s_tx, config.clone());
+    let child_task = ChildTask::new(s2ch_rx, ch2s_tx, primary_output_tx, config.clone());
 
     sh.start(SubsystemBuilder::new("child", move |sh| async move {
         child_task.run(sh).await;
         Ok::<_, anyhow::Error>(())
     }));
 
-    let server_task = base::ServerTask::new(n2s_rx, ch2s_rx, s2ch_tx, global_s2c_tx, config);
+    let server_task = base::ServerTask::new(
+        n2s_rx,
+        ch2s_rx,
+        s2ch_tx,
+        global_s2c_tx,
+        config,
+        config_source,
+        port,
+    );
     sh.start(SubsystemBuilder::new("server", move |sh| async move {
         server_task.run(sh).await;
         Ok::<_, anyhow::Error>(())
@@ -91,6 +139,7 @@ async fn handle_error(error: impl Display + Debug + Send + Sync + 'static) {
 #[tokio::main]
 async fn main() -> ExitCode {
     raphy_common::init_logging("RAPHY_SERVER_TOKIO_CONSOLE_ENABLED");
+    let _cleanup_guard = CleanupGuard;
 
     if let Err(error) = Toplevel::new(|sh| async move {
         if let Err(error) = real_main(sh).await {
@@ -1,18 +1,53 @@
-use crate::base::NetworkToServerMessage;
-use anyhow::{Context, anyhow};
-use raphy_protocol::{Config, Operation, OperationId, SerdeError, TaskId, DEFAULT_PORT, UNIX_SOCKET_PATH};
+use crate::base::{MetricsSample, NetworkToServerMessage};
+use crate::utils;
+use anyhow::{Context, anyhow, bail};
+use bincode::error::DecodeError;
+use raphy_protocol::config::{ConfigPatch, User};
+use raphy_protocol::{
+    ArgumentPreset, ClientIdentity, Config, ConfigEditor, ConnectionInfo, CurrentConfig,
+    DEFAULT_PORT, ErrorKind, HandshakeOptions, IdempotencyKey, JavaVersion, Log, MAX_FRAME_LEN,
+    MIN_METRICS_INTERVAL, MaintenanceReport, MaintenanceStep, Metrics, Operation, OperationId,
+    OutputDelivery, OutputMode, OutputStream, PROTOCOL_VERSION, Permission, Permissions,
+    ProtocolStats, ResourceUsage, SerdeError, ServerState, StyledSpan, TaskId, TextStyle,
+    TlsReport, Transport,
+};
 use slab::Slab;
+use std::borrow::Cow;
 use std::cell::OnceCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::ops::ControlFlow;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use std::{env, fmt, fs, io};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::AbortHandle;
 use tokio_graceful_shutdown::{NestedSubsystem, SubsystemBuilder, SubsystemHandle};
 
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
+
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::{
+    ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+
+#[cfg(feature = "tls")]
+use std::path::Path;
+
+/// stands in for the tls acceptor when the server is built without the `tls` feature, so there's
+/// never one to use. unlike `Infallible` it isn't `Copy`, so it's cloned the same way as the real
+/// one.
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+enum TlsAcceptor {}
+
 #[derive(Debug, Copy, Clone)]
 pub struct ClientId(usize);
 
@@ -25,7 +60,27 @@ impl fmt::Display for ClientId {
 #[derive(Debug)]
 pub struct ClientToServerMessage {
     id: ClientId,
-    data: raphy_protocol::ClientToServerMessage,
+    data: Incoming,
+}
+
+/// what was decoded from a frame a client sent
+#[derive(Debug)]
+enum Incoming {
+    Message(raphy_protocol::ClientToServerMessage),
+
+    /// a message whose variant the server doesn't know, likely because the client is newer. since
+    /// frames are length prefixed, it can be skipped without losing track of the stream. holds the
+    /// discriminant of the variant.
+    Unsupported(u32),
+}
+
+impl Incoming {
+    fn name(&self) -> &'static str {
+        match self {
+            Incoming::Message(message) => message.name(),
+            Incoming::Unsupported(_) => "Unsupported",
+        }
+    }
 }
 
 pub struct ServerToClientMessage {
@@ -33,6 +88,72 @@ pub struct ServerToClientMessage {
     data: raphy_protocol::ServerToClientMessage,
 }
 
+/// how messages are serialized on a connection. either way, each message is prefixed with its
+/// length as a little endian `u32`.
+#[derive(Copy, Clone, Debug)]
+enum Codec {
+    /// compact, and what the bundled clients speak
+    Bincode,
+
+    /// the serde representation of the messages, so third party tooling and debugging scripts can
+    /// speak the protocol. only served by the listener at `RAPHY_JSON_ADDRESS`, if it is set.
+    Json,
+}
+
+impl Codec {
+    fn decode(&self, buf: &[u8]) -> anyhow::Result<Incoming> {
+        match self {
+            // bincode encodes the discriminant of the variant first, so an unknown one is noticed
+            // before anything else is decoded. unknown variants of enums nested in a message still
+            // fail to decode, since the message can't be handled without them.
+            Codec::Bincode => match bincode::decode_from_slice(buf, bincode::config::standard()) {
+                Ok((message, _)) => Ok(Incoming::Message(message)),
+                Err(DecodeError::UnexpectedVariant {
+                    type_name: "ClientToServerMessage",
+                    found,
+                    ..
+                }) => Ok(Incoming::Unsupported(found)),
+                Err(error) => Err(error.into()),
+            },
+            Codec::Json => serde_json::from_slice(buf)
+                .map(Incoming::Message)
+                .map_err(Into::into),
+        }
+    }
+
+    /// the protocol version at the start of a handshake which doesn't decode, e.g. since it's from
+    /// a client of another version whose handshake holds other fields after it
+    fn handshake_version(&self, buf: &[u8]) -> Option<u32> {
+        match self {
+            // the discriminant of the variant comes first, then the version
+            Codec::Bincode => {
+                let ((variant, version), _): ((u32, u32), _) =
+                    bincode::decode_from_slice(buf, bincode::config::standard()).ok()?;
+                (variant == 0).then_some(version)
+            }
+
+            // a handshake with only the version holds it directly rather than in an array
+            Codec::Json => {
+                let message: serde_json::Value = serde_json::from_slice(buf).ok()?;
+                let version = match &message["Handshake"] {
+                    serde_json::Value::Array(fields) => fields.first()?,
+                    version => version,
+                };
+                version.as_u64()?.try_into().ok()
+            }
+        }
+    }
+
+    fn encode(&self, message: raphy_protocol::ServerToClientMessage) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Codec::Bincode => {
+                bincode::encode_to_vec(message, bincode::config::standard()).map_err(Into::into)
+            }
+            Codec::Json => serde_json::to_vec(&message).map_err(Into::into),
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 enum ClientKind {
     Unix,
@@ -53,24 +174,337 @@ impl ClientKind {
             ClientKind::Tcp => "tcp stream",
         }
     }
+
+    fn transport(&self) -> Transport {
+        match self {
+            ClientKind::Unix => Transport::Unix,
+            ClientKind::Tcp => Transport::Tcp,
+        }
+    }
+}
+
+/// how many output messages a client in [`OutputMode::Sampled`] receives per [`OUTPUT_SAMPLE_WINDOW`]
+const SAMPLED_OUTPUT_MESSAGES: usize = 20;
+
+const OUTPUT_SAMPLE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct ClientOutput {
+    mode: OutputMode,
+    delivery: OutputDelivery,
+
+    /// how many output messages were sent during the current sample window
+    sent: usize,
+
+    /// how many lines were suppressed during the current sample window
+    suppressed_lines: usize,
+}
+
+impl ClientOutput {
+    /// whether `output` should be sent to the client, counting it as suppressed if not.
+    fn admit(&mut self, output: &[u8]) -> bool {
+        match self.mode {
+            OutputMode::Full => true,
+            OutputMode::Sampled if self.sent < SAMPLED_OUTPUT_MESSAGES => {
+                self.sent += 1;
+                true
+            }
+            OutputMode::Sampled => {
+                self.suppressed_lines += output.iter().filter(|&&b| b == b'\n').count().max(1);
+                false
+            }
+            OutputMode::Off => false,
+        }
+    }
+
+    /// the message to send `message` as, which is a `Log` for plain output if the client wants its
+    /// output merged.
+    fn deliver(
+        &self,
+        message: &raphy_protocol::ServerToClientMessage,
+        primary_output_stream: OutputStream,
+    ) -> raphy_protocol::ServerToClientMessage {
+        let (stream, output) = match (self.delivery, message) {
+            (OutputDelivery::Merged, raphy_protocol::ServerToClientMessage::Stdout(output)) => {
+                (OutputStream::Stdout, output)
+            }
+            (OutputDelivery::Merged, raphy_protocol::ServerToClientMessage::Stderr(output)) => {
+                (OutputStream::Stderr, output)
+            }
+            _ => return message.clone(),
+        };
+
+        raphy_protocol::ServerToClientMessage::Log(Log {
+            stream,
+            primary: stream == primary_output_stream,
+            spans: vec![StyledSpan {
+                text: output.clone(),
+                style: TextStyle::default(),
+            }],
+        })
+    }
+
+    /// starts a new sample window, returning a summary of the output suppressed during the last one.
+    fn next_window(&mut self) -> Option<Vec<u8>> {
+        self.sent = 0;
+
+        match std::mem::take(&mut self.suppressed_lines) {
+            0 => None,
+            lines => Some(format!("… {lines} lines suppressed …\n").into_bytes()),
+        }
+    }
+}
+
+/// traffic counters shared by the read and write subsystems of every client
+#[derive(Default)]
+struct ProtocolCounters {
+    frames_received: AtomicU64,
+    frames_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    decode_errors: AtomicU64,
+    received_by_message: Mutex<BTreeMap<&'static str, u64>>,
+    sent_by_message: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl ProtocolCounters {
+    fn count_received(&self, name: &'static str, bytes: usize) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        *self.received_by_message.lock().unwrap().entry(name).or_default() += 1;
+    }
+
+    fn count_sent(&self, name: &'static str, bytes: usize) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        *self.sent_by_message.lock().unwrap().entry(name).or_default() += 1;
+    }
+
+    fn snapshot(&self, queue_depths: Vec<(ConnectionInfo, usize)>) -> ProtocolStats {
+        let by_message = |map: &Mutex<BTreeMap<&'static str, u64>>| {
+            map.lock()
+                .unwrap()
+                .iter()
+                .map(|(name, count)| (name.to_string(), *count))
+                .collect()
+        };
+
+        ProtocolStats {
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            received_by_message: by_message(&self.received_by_message),
+            sent_by_message: by_message(&self.sent_by_message),
+            queue_depths,
+        }
+    }
+}
+
+/// what the read and write subsystems of a connection need to know about it
+#[derive(Clone)]
+struct Connection {
+    kind: ClientKind,
+    codec: Codec,
+    counters: Arc<ProtocolCounters>,
+
+    /// the longest frame the client may send, so that it can't make the server allocate too much
+    max_frame_len: usize,
+}
+
+/// how the handshake of a new connection went
+enum HandshakeOutcome {
+    /// the connection is about to become a client
+    Accepted {
+        read_half: Box<dyn AsyncRead + Send + Unpin>,
+        write_half: Box<dyn AsyncWrite + Send + Unpin>,
+        connection: Connection,
+        peer: Option<SocketAddr>,
+        options: HandshakeOptions,
+    },
+
+    /// the connection was dropped without ever becoming a client
+    Failed { peer: Option<SocketAddr> },
+}
+
+/// the receiving ends of a client's outbound messages. control messages are written before any
+/// bulk ones, so that responses aren't stuck behind a flood of server output.
+struct Outbound {
+    control: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+    bulk: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+}
+
+impl Outbound {
+    /// the next message to write, or `None` once the client is gone.
+    async fn recv(&mut self) -> Option<raphy_protocol::ServerToClientMessage> {
+        tokio::select! {
+            biased;
+            Some(s2c) = self.control.recv() => Some(s2c),
+            Some(s2c) = self.bulk.recv() => Some(s2c),
+            else => None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.control.len() + self.bulk.len()
+    }
 }
 
 struct Client {
     s2c_tx: UnboundedSender<raphy_protocol::ServerToClientMessage>,
+
+    /// server output, which is written after anything sent through `s2c_tx`
+    bulk_tx: UnboundedSender<raphy_protocol::ServerToClientMessage>,
     kind: ClientKind,
+    peer: Option<SocketAddr>,
+    permissions: Permissions,
+    output: ClientOutput,
+
+    /// whether large output is compressed before it's sent, which the client asked for in its
+    /// handshake
+    compress_output: bool,
     subsystem: OnceCell<NestedSubsystem<anyhow::Error>>,
+
+    /// how many messages were waiting to be sent to the client, as of its last write
+    queue_depth: Arc<AtomicUsize>,
+
+    /// the task streaming metrics to the client, if it subscribed to them
+    metrics: Option<MetricsSubscription>,
+
+    /// the tasks streaming progress to the client, e.g. of a backup. see [`Client::open_streams`].
+    streams: Vec<AbortHandle>,
+
+    /// the identity the client presented, which its subscriptions are kept under once it drops
+    identity: Option<ClientIdentity>,
+
+    /// whether the client has its config editor open
+    editing_config: bool,
+}
+
+struct MetricsSubscription {
+    task: AbortHandle,
+    interval: Duration,
+}
+
+impl Client {
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            transport: self.kind.transport(),
+            peer: self.peer,
+            permissions: self.permissions,
+        }
+    }
+
+    fn config_editor(&self) -> ConfigEditor {
+        ConfigEditor {
+            identity: self.identity,
+            connection: self.connection_info(),
+        }
+    }
+
+    /// starts streaming metrics to the client, replacing its previous subscription if any
+    fn subscribe_metrics(
+        &mut self,
+        n2s_tx: UnboundedSender<NetworkToServerMessage>,
+        interval: Duration,
+    ) {
+        if let Some(metrics) = self.metrics.take() {
+            metrics.task.abort();
+        }
+
+        let task = tokio::spawn(stream_metrics(n2s_tx, self.s2c_tx.clone(), interval));
+        self.metrics = Some(MetricsSubscription {
+            task: task.abort_handle(),
+            interval,
+        });
+    }
+
+    /// how many streams the client has open, counting its metrics subscription
+    fn open_streams(&mut self) -> usize {
+        self.streams.retain(|stream| !stream.is_finished());
+        self.streams.len() + usize::from(self.metrics.is_some())
+    }
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions {
+            metrics_interval: self.metrics.as_ref().map(|metrics| metrics.interval),
+            output_mode: self.output.mode,
+            output_delivery: self.output.delivery,
+        }
+    }
 }
 
+/// what an identified client subscribed to, which is restored when it reconnects
+struct Subscriptions {
+    metrics_interval: Option<Duration>,
+    output_mode: OutputMode,
+    output_delivery: OutputDelivery,
+}
+
+/// the subscriptions of an identified client which disconnected
+struct RetainedClient {
+    identity: ClientIdentity,
+    subscriptions: Subscriptions,
+    disconnected_at: Instant,
+}
+
+/// how long the subscriptions of an identified client are kept after it disconnects, unless
+/// overridden with `RAPHY_RECONNECT_GRACE_SECS`
+const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(30);
+
+/// how many disconnected clients' subscriptions are kept at most
+const MAX_RETAINED_CLIENTS: usize = 64;
+
+/// how many streams a single client may have open at once, unless overridden with
+/// `RAPHY_MAX_STREAMS_PER_CLIENT`
+const DEFAULT_MAX_STREAMS_PER_CLIENT: usize = 4;
+
 enum NewClient {
     Unix(UnixStream),
-    Tcp(TcpStream),
+    Tcp(TcpStream, Codec),
+
+    /// a tcp client which still has to finish its tls handshake. that's part of its handshake, so
+    /// it already counts against its host's connections while it's going on.
+    #[cfg(feature = "tls")]
+    Tls(TcpStream, TlsAcceptor, Codec),
 }
 
 impl NewClient {
     pub fn kind(&self) -> ClientKind {
         match self {
             NewClient::Unix(_) => ClientKind::Unix,
-            NewClient::Tcp(_) => ClientKind::Tcp,
+            NewClient::Tcp(..) => ClientKind::Tcp,
+
+            #[cfg(feature = "tls")]
+            NewClient::Tls(..) => ClientKind::Tcp,
+        }
+    }
+
+    /// the address of the client, which unix clients don't have since they're local
+    fn peer_ip(&self) -> Option<IpAddr> {
+        match self {
+            NewClient::Unix(_) => None,
+            NewClient::Tcp(stream, _) => stream.peer_addr().ok().map(|peer| peer.ip()),
+
+            #[cfg(feature = "tls")]
+            NewClient::Tls(stream, ..) => stream.peer_addr().ok().map(|peer| peer.ip()),
+        }
+    }
+
+    /// tells the client why it was rejected and disconnects it. tls clients are only disconnected,
+    /// since telling them would mean finishing a tls handshake for a client that isn't accepted.
+    fn reject(self, reason: &'static str) {
+        match self {
+            NewClient::Unix(stream) => {
+                tokio::spawn(reject_client(stream, Codec::Bincode, reason));
+            }
+            NewClient::Tcp(stream, codec) => {
+                tokio::spawn(reject_client(stream, codec, reason));
+            }
+
+            #[cfg(feature = "tls")]
+            NewClient::Tls(stream, ..) => drop(stream),
         }
     }
 }
@@ -79,9 +513,16 @@ async fn read_subsystem_once(
     c2s_tx: &UnboundedSender<ClientToServerMessage>,
     id: ClientId,
     read_half: &mut (impl AsyncRead + Unpin),
-    kind: ClientKind,
+    connection: &Connection,
     len: &mut Option<usize>,
 ) -> ControlFlow<anyhow::Result<()>> {
+    let Connection {
+        kind,
+        codec,
+        counters,
+        max_frame_len,
+    } = connection;
+
     let mut buf = vec![0; len.unwrap_or(4)];
     match read_half
         .read_exact(&mut buf)
@@ -90,17 +531,25 @@ async fn read_subsystem_once(
     {
         Ok(_) => {
             if len.is_none() {
-                *len = Some(u32::from_le_bytes(buf.try_into().unwrap()) as usize);
+                let frame_len = u32::from_le_bytes(buf.try_into().unwrap()) as usize;
+                if frame_len > *max_frame_len {
+                    counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    return ControlFlow::Break(Err(anyhow!(
+                        "{} sent a frame of {frame_len} bytes, more than the maximum of {max_frame_len}",
+                        kind.stream_label()
+                    )));
+                }
+
+                *len = Some(frame_len);
                 return ControlFlow::Continue(());
             }
 
-            match bincode::decode_from_slice::<raphy_protocol::ClientToServerMessage, _>(
-                &buf,
-                bincode::config::standard(),
-            )
-            .with_context(|| format!("failed to decode message from {}", kind.stream_label()))
+            match codec
+                .decode(&buf)
+                .with_context(|| format!("failed to decode message from {}", kind.stream_label()))
             {
-                Ok((data, _)) => {
+                Ok(data) => {
+                    counters.count_received(data.name(), 4 + buf.len());
                     if let Err(error) = c2s_tx
                         .send(ClientToServerMessage { id, data })
                         .context("failed to send message to network task")
@@ -108,7 +557,10 @@ async fn read_subsystem_once(
                         return ControlFlow::Break(Err(error));
                     }
                 }
-                Err(error) => return ControlFlow::Break(Err(error)),
+                Err(error) => {
+                    counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    return ControlFlow::Break(Err(error));
+                }
             }
 
             *len = None;
@@ -126,64 +578,295 @@ async fn read_subsystem_once(
     ControlFlow::Continue(())
 }
 
+/// how long the read half of a client keeps reading after its write half failed
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// how many reads the read half of a client performs at most after its write half failed. each
+/// message takes two reads, one for its length and one for its contents.
+const DRAIN_READS: usize = 64;
+
 async fn read_subsystem(
     c2s_tx: UnboundedSender<ClientToServerMessage>,
     id: ClientId,
     mut read_half: impl AsyncRead + Unpin,
-    sh: SubsystemHandle<anyhow::Error>,
-    kind: ClientKind,
+    shutdown: impl Future<Output = ()>,
+    connection: Connection,
+    mut drain_rx: UnboundedReceiver<()>,
     destroy_tx: UnboundedSender<()>,
 ) {
+    tokio::pin!(shutdown);
     let mut len = None;
 
+    // set once the write half failed, after which the client is only read from until then
+    let mut drain_deadline = None;
+    let mut drain_reads = 0;
     loop {
-        tokio::select! {
-            control_flow = read_subsystem_once(&c2s_tx, id, &mut read_half, kind, &mut len) => match control_flow {
-                ControlFlow::Continue(()) => continue,
-                ControlFlow::Break(result) => {
-                    if let Err(error) = result {
-                        tracing::error!(?error, "{error:#}");
-                    }
+        // a read can't be cancelled without losing the part of the frame it read already, so the
+        // read in progress is always finished
+        let once = read_subsystem_once(&c2s_tx, id, &mut read_half, &connection, &mut len);
+        tokio::pin!(once);
 
-                    destroy_tx.send(()).ok();
-                    break;
+        let control_flow = match drain_deadline {
+            None => tokio::select! {
+                control_flow = &mut once => Some(control_flow),
+                Some(()) = drain_rx.recv() => {
+                    // the write half failed, but the client may have sent messages which haven't
+                    // been read yet, e.g. a final `Input` or `Shutdown`. those are forwarded
+                    // before the client is torn down.
+                    let deadline = tokio::time::Instant::now() + DRAIN_TIMEOUT;
+                    drain_deadline = Some(deadline);
+                    tokio::time::timeout_at(deadline, &mut once).await.ok()
                 }
+                () = &mut shutdown => return,
             },
-            () = sh.on_shutdown_requested() => break,
+            Some(deadline) if drain_reads < DRAIN_READS => {
+                drain_reads += 1;
+                tokio::time::timeout_at(deadline, &mut once).await.ok()
+            }
+            Some(_) => None,
+        };
+
+        match control_flow {
+            Some(ControlFlow::Continue(())) => {}
+            Some(ControlFlow::Break(Err(error))) if drain_deadline.is_some() => {
+                tracing::debug!("stopped draining client {id}: {error:#}");
+                break;
+            }
+            Some(ControlFlow::Break(Err(error))) => {
+                tracing::error!(?error, "{error:#}");
+                break;
+            }
+            Some(ControlFlow::Break(Ok(()))) | None => break,
+        }
+    }
+
+    destroy_tx.send(()).ok();
+}
+
+/// encodes a message, prefixed with its length.
+fn encode_frame(
+    message: raphy_protocol::ServerToClientMessage,
+    codec: Codec,
+) -> anyhow::Result<Vec<u8>> {
+    let data = codec.encode(message)?;
+    tracing::trace!(?data);
+
+    let mut buf = Vec::with_capacity(4 + data.len());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend(data);
+    Ok(buf)
+}
+
+/// tells a client that it won't be served, then disconnects it.
+async fn reject_client(mut stream: impl AsyncWrite + Unpin, codec: Codec, reason: &str) {
+    let message =
+        raphy_protocol::ServerToClientMessage::FatalError(SerdeError::new(&*anyhow!("{reason}")));
+
+    match encode_frame(message, codec) {
+        Ok(buf) => {
+            if let Err(error) = stream.write_all(&buf).await {
+                tracing::debug!("failed to tell a rejected client why it was rejected: {error}");
+            }
+        }
+        Err(error) => tracing::error!(?error, "failed to encode rejection: {error:#}"),
+    }
+
+    stream.shutdown().await.ok();
+}
+
+/// how long a new client has to finish its handshake before it's disconnected, unless overridden
+/// with `RAPHY_HANDSHAKE_TIMEOUT_SECS`
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// reads the handshake a client sends when it connects, and tells it whether its protocol version
+/// matches the server's. no other messages are read or written until this succeeded.
+async fn handshake(
+    read_half: &mut (impl AsyncRead + Unpin),
+    write_half: &mut (impl AsyncWrite + Unpin),
+    connection: &Connection,
+) -> anyhow::Result<HandshakeOptions> {
+    let Connection {
+        kind,
+        codec,
+        max_frame_len,
+        ..
+    } = connection;
+
+    let mut len = [0; 4];
+    read_half
+        .read_exact(&mut len)
+        .await
+        .with_context(|| format!("failed to read the handshake from {}", kind.stream_label()))?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > *max_frame_len {
+        anyhow::bail!(
+            "{} sent a handshake of {len} bytes, more than the maximum of {max_frame_len}",
+            kind.stream_label()
+        );
+    }
+    let mut buf = vec![0; len];
+    read_half
+        .read_exact(&mut buf)
+        .await
+        .with_context(|| format!("failed to read the handshake from {}", kind.stream_label()))?;
+
+    // a client from before the handshake existed sends some other message first, which either
+    // doesn't decode or decodes as something else
+    let (got, options) = match codec.decode(&buf) {
+        Ok(Incoming::Message(raphy_protocol::ClientToServerMessage::Handshake(
+            version,
+            options,
+        ))) => (version, options),
+        Ok(Incoming::Message(message)) => anyhow::bail!(
+            "{} sent {} instead of a handshake, it's likely from an older version",
+            kind.stream_label(),
+            message.name()
+        ),
+        Ok(Incoming::Unsupported(type_hint)) => anyhow::bail!(
+            "{} sent a message of the unknown variant {type_hint} instead of a handshake",
+            kind.stream_label()
+        ),
+        Err(error) => match codec.handshake_version(&buf) {
+            // still told that it speaks another version, rather than just being dropped
+            Some(version) if version != PROTOCOL_VERSION => (version, HandshakeOptions::default()),
+            _ => {
+                return Err(error).with_context(|| {
+                    format!(
+                        "failed to decode the handshake from {}",
+                        kind.stream_label()
+                    )
+                });
+            }
+        },
+    };
+
+    let response = if got == PROTOCOL_VERSION {
+        raphy_protocol::ServerToClientMessage::HandshakeAccepted
+    } else {
+        raphy_protocol::ServerToClientMessage::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+            got,
+        }
+    };
+    write_half
+        .write_all(&encode_frame(response, *codec)?)
+        .await
+        .with_context(|| format!("failed to answer the handshake of {}", kind.stream_label()))?;
+
+    if got != PROTOCOL_VERSION {
+        write_half.shutdown().await.ok();
+        anyhow::bail!(
+            "{} speaks protocol version {got}, but the server speaks {PROTOCOL_VERSION}",
+            kind.stream_label()
+        );
+    }
+
+    Ok(options)
+}
+
+/// performs the handshake of a new connection, dropping it if it fails or doesn't finish within
+/// `timeout`.
+async fn negotiate(
+    mut read_half: impl AsyncRead + Send + Unpin + 'static,
+    mut write_half: impl AsyncWrite + Send + Unpin + 'static,
+    connection: Connection,
+    peer: Option<SocketAddr>,
+    timeout: Duration,
+) -> HandshakeOutcome {
+    let result = tokio::time::timeout(
+        timeout,
+        handshake(&mut read_half, &mut write_half, &connection),
+    )
+    .await
+    .unwrap_or_else(|_| Err(anyhow!("the client didn't send a handshake in time")));
+
+    match result {
+        Ok(options) => HandshakeOutcome::Accepted {
+            read_half: Box::new(read_half),
+            write_half: Box::new(write_half),
+            connection,
+            peer,
+            options,
+        },
+        Err(error) => {
+            tracing::warn!(
+                ?error,
+                "new {} failed the handshake: {error:#}",
+                connection.kind.stream_label()
+            );
+            HandshakeOutcome::Failed { peer }
         }
     }
 }
 
+/// finishes the tls handshake of a new tcp client and then performs its handshake, both within
+/// `timeout`
+#[cfg(feature = "tls")]
+async fn negotiate_tls(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    connection: Connection,
+    peer: Option<SocketAddr>,
+    timeout: Duration,
+) -> HandshakeOutcome {
+    let started = Instant::now();
+    let stream = match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(error)) => {
+            tracing::info!("tls handshake with a new tcp client failed: {error}");
+            return HandshakeOutcome::Failed { peer };
+        }
+        Err(_) => {
+            tracing::info!("a new tcp client didn't finish its tls handshake in time");
+            return HandshakeOutcome::Failed { peer };
+        }
+    };
+
+    let (read_half, write_half) = tokio::io::split(stream);
+    let remaining = timeout.saturating_sub(started.elapsed());
+    negotiate(read_half, write_half, connection, peer, remaining).await
+}
+
 async fn write_subsystem_once(
     write_half: &mut (impl AsyncWrite + Unpin),
-    s2c_rx: &mut UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
-    kind: ClientKind,
+    outbound: &mut Outbound,
+    connection: &Connection,
+    queue_depth: &AtomicUsize,
 ) -> ControlFlow<anyhow::Result<()>> {
-    let Some(s2c) = s2c_rx.recv().await else {
+    let Connection {
+        kind,
+        codec,
+        counters,
+        ..
+    } = connection;
+
+    let Some(s2c) = outbound.recv().await else {
         return ControlFlow::Break(Ok(()));
     };
 
     tracing::trace!(?s2c);
+    queue_depth.store(outbound.len(), Ordering::Relaxed);
+    let name = s2c.name();
+    let last = matches!(s2c, raphy_protocol::ServerToClientMessage::Reconnect(_));
 
-    let data = match bincode::encode_to_vec(s2c, bincode::config::standard())
+    let buf = match encode_frame(s2c, *codec)
         .with_context(|| format!("failed to encode message for {}", kind.stream_label()))
     {
-        Ok(data) => data,
+        Ok(buf) => buf,
         Err(error) => return ControlFlow::Break(Err(error)),
     };
 
-    tracing::trace!(?data);
-
-    let mut buf = Vec::with_capacity(4 + data.len());
-    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
-    buf.extend(data);
-
     tracing::trace!(?buf);
 
     match write_half.write_all(&buf).await {
         Ok(_) => {
             tracing::trace!("write successful");
-            ControlFlow::Continue(())
+            counters.count_sent(name, buf.len());
+            if last {
+                ControlFlow::Break(Ok(()))
+            } else {
+                ControlFlow::Continue(())
+            }
         }
         Err(error) if matches!(error.kind(), io::ErrorKind::BrokenPipe) => {
             ControlFlow::Break(Ok(()))
@@ -196,21 +879,27 @@ async fn write_subsystem_once(
 
 async fn write_subsystem(
     mut write_half: impl AsyncWrite + Unpin,
-    mut s2c_rx: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+    mut outbound: Outbound,
     sh: SubsystemHandle<anyhow::Error>,
-    kind: ClientKind,
+    connection: Connection,
+    queue_depth: Arc<AtomicUsize>,
+    drain_tx: UnboundedSender<()>,
     destroy_tx: UnboundedSender<()>,
 ) {
     loop {
         tokio::select! {
-            control_flow = write_subsystem_once(&mut write_half, &mut s2c_rx, kind) => match control_flow {
+            control_flow = write_subsystem_once(&mut write_half, &mut outbound, &connection, &queue_depth) => match control_flow {
                 ControlFlow::Continue(()) => continue,
                 ControlFlow::Break(value) => {
                     if let Err(error) = value {
                         tracing::error!(?error, "{error:#}");
                     }
 
-                    destroy_tx.send(()).ok();
+                    // let the read half forward what it still has before the client is destroyed,
+                    // unless it already stopped
+                    if drain_tx.send(()).is_err() {
+                        destroy_tx.send(()).ok();
+                    }
                     break;
                 },
             },
@@ -219,33 +908,209 @@ async fn write_subsystem(
     }
 }
 
-struct MessageBroadcaster {
-    senders: Vec<UnboundedSender<raphy_protocol::ServerToClientMessage>>,
-    active_task: Option<(
-        TaskId,
-        UnboundedSender<raphy_protocol::ServerToClientMessage>,
-    )>,
+/// the share of a single core used between two samples of the cpu time, taken at the given times
+fn cpu_percent(
+    previous: Option<(Instant, Duration)>,
+    current: Option<(Instant, Duration)>,
+) -> Option<f32> {
+    let ((previous_at, previous_used), (current_at, current_used)) = previous.zip(current)?;
+    let elapsed = current_at.duration_since(previous_at).as_secs_f32();
+    let used = current_used.checked_sub(previous_used)?.as_secs_f32();
+    (elapsed > 0.0).then(|| used / elapsed * 100.0)
 }
 
-impl MessageBroadcaster {
-    pub fn broadcast(self, message: raphy_protocol::ServerToClientMessage) {
-        if let Some((_, tx)) = self.active_task {
-            tx.send(message.clone()).ok();
+/// the version of the java in `config`, which is auto-detected unless a path is configured
+async fn java_version(config: CurrentConfig) -> anyhow::Result<JavaVersion> {
+    let (CurrentConfig::Saved(config) | CurrentConfig::Template(config)) = config;
+    let java_path = config
+        .java_path
+        .resolve()
+        .context("Failed to get the Java path. Is Java installed in your system?")?
+        .into_owned();
+
+    utils::java_version(&java_path).await
+}
+
+/// how long the cpu time is sampled for when a client asks for the resource usage
+const RESOURCE_USAGE_WINDOW: Duration = Duration::from_millis(500);
+
+/// sends metrics to a client on the interval until it goes away or the server task stops.
+async fn stream_metrics(
+    n2s_tx: UnboundedSender<NetworkToServerMessage>,
+    s2c_tx: UnboundedSender<raphy_protocol::ServerToClientMessage>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut previous: Option<(Instant, Duration)> = None;
+
+    loop {
+        ticker.tick().await;
+
+        let (tx, rx) = oneshot::channel();
+        if n2s_tx.send(NetworkToServerMessage::GetMetricsSample(tx)).is_err() {
+            break;
         }
+        let Ok(MetricsSample {
+            rss_bytes,
+            cpu_time,
+            disk_free,
+        }) = rx.await
+        else {
+            break;
+        };
 
-        for tx in self.senders {
-            tx.send(message.clone()).ok();
+        // the cpu usage is only known relative to the previous sample
+        let now = Instant::now();
+        let current = cpu_time.map(|cpu_time| (now, cpu_time));
+        let cpu_percent = cpu_percent(previous, current);
+        previous = current;
+
+        let metrics = Metrics {
+            timestamp: SystemTime::now(),
+            rss_bytes,
+            cpu_percent,
+            disk_free,
+        };
+        if s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::Metrics(metrics))
+            .is_err()
+        {
+            break;
         }
     }
+}
 
-    pub fn broadcast_with_task_id(
-        self,
-        mut message_fn: impl FnMut(Option<TaskId>) -> raphy_protocol::ServerToClientMessage,
-    ) {
-        if let Some((task_id, tx)) = self.active_task {
-            tx.send(message_fn(Some(task_id))).ok();
-        }
+/// how long a keyed operation is remembered, so that resending it within this window returns its
+/// result rather than performing it again
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(10 * 60);
 
+/// how many keyed operations are remembered at most
+const MAX_IDEMPOTENCY_KEYS: usize = 64;
+
+/// an operation requested with an idempotency key
+struct KeyedOperation {
+    key: IdempotencyKey,
+    operation: Operation,
+    operation_id: OperationId,
+    requested_at: Instant,
+}
+
+/// the error a client receives when the server task can't handle its request, e.g. while the daemon
+/// is starting up or shutting down. retrying later may succeed.
+const SERVER_UNAVAILABLE: &str =
+    "The server is not ready to handle requests right now; please try again shortly.";
+
+fn server_unavailable(task_id: TaskId) -> raphy_protocol::ServerToClientMessage {
+    raphy_protocol::ServerToClientMessage::Error(
+        SerdeError::new(&*anyhow!(SERVER_UNAVAILABLE)),
+        Some(task_id),
+    )
+}
+
+/// runs a single step of a maintenance cycle through the server task, resolving once it finished
+async fn run_maintenance_step(
+    n2s_tx: &UnboundedSender<NetworkToServerMessage>,
+    step: MaintenanceStep,
+    started_by: ConnectionInfo,
+) -> anyhow::Result<()> {
+    let operation = match step {
+        MaintenanceStep::Stop => {
+            let (tx, rx) = oneshot::channel();
+            n2s_tx
+                .send(NetworkToServerMessage::GetServerState(tx))
+                .map_err(|_| anyhow!(SERVER_UNAVAILABLE))?;
+
+            // stopping a server which isn't running would fail, but it's what the step is after
+            if let ServerState::Stopped(..) = rx.await.map_err(|_| anyhow!(SERVER_UNAVAILABLE))? {
+                return Ok(());
+            }
+            Operation::Stop
+        }
+        MaintenanceStep::Start => Operation::Start,
+        MaintenanceStep::Backup => {
+            let (tx, rx) = oneshot::channel();
+            n2s_tx
+                .send(NetworkToServerMessage::RunBackup(tx))
+                .map_err(|_| anyhow!(SERVER_UNAVAILABLE))?;
+            return rx.await.unwrap_or_else(|_| Err(anyhow!(SERVER_UNAVAILABLE)));
+        }
+    };
+
+    let (tx, rx) = oneshot::channel();
+    n2s_tx
+        .send(NetworkToServerMessage::PerformOperation(
+            operation,
+            OperationId::generate(),
+            started_by,
+            tx,
+        ))
+        .map_err(|_| anyhow!(SERVER_UNAVAILABLE))?;
+    rx.await.unwrap_or_else(|_| Err(anyhow!(SERVER_UNAVAILABLE)))
+}
+
+struct MessageBroadcaster {
+    senders: Vec<UnboundedSender<raphy_protocol::ServerToClientMessage>>,
+    active_task: Option<(
+        TaskId,
+        UnboundedSender<raphy_protocol::ServerToClientMessage>,
+    )>,
+
+    /// the client and task which the task-id-bearing response is meant for
+    origin: Option<(ClientId, TaskId)>,
+}
+
+impl MessageBroadcaster {
+    fn log_vanished_origin(&self) {
+        if let Some((client_id, task_id)) = self.origin {
+            tracing::info!(
+                ?task_id,
+                "client {client_id} disconnected before the response to its task could be delivered"
+            );
+        }
+    }
+
+    /// sends the message only to the originating task, without broadcasting it
+    pub fn respond(self, message: raphy_protocol::ServerToClientMessage) {
+        match &self.active_task {
+            Some((_, tx)) => {
+                if tx.send(message).is_err() {
+                    self.log_vanished_origin();
+                }
+            }
+            None => self.log_vanished_origin(),
+        }
+    }
+
+    pub fn broadcast(self, message: raphy_protocol::ServerToClientMessage) {
+        match &self.active_task {
+            Some((_, tx)) => {
+                if tx.send(message.clone()).is_err() {
+                    self.log_vanished_origin();
+                }
+            }
+            None => self.log_vanished_origin(),
+        }
+
+        for tx in self.senders {
+            tx.send(message.clone()).ok();
+        }
+    }
+
+    pub fn broadcast_with_task_id(
+        self,
+        mut message_fn: impl FnMut(Option<TaskId>) -> raphy_protocol::ServerToClientMessage,
+    ) {
+        match &self.active_task {
+            Some((task_id, tx)) => {
+                if tx.send(message_fn(Some(*task_id))).is_err() {
+                    self.log_vanished_origin();
+                }
+            }
+            None => self.log_vanished_origin(),
+        }
+
+        // the remaining clients are still told about the result even if the originating client
+        // vanished
         for tx in &self.senders {
             tx.send(message_fn(None)).ok();
         }
@@ -261,17 +1126,130 @@ struct NetworkTask {
     global_s2c_rx: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
     destroy_client_tx: UnboundedSender<ClientId>,
     destroy_client_rx: UnboundedReceiver<ClientId>,
+    handshakes_tx: UnboundedSender<HandshakeOutcome>,
+    handshakes_rx: UnboundedReceiver<HandshakeOutcome>,
+
+    /// whether new remote clients are accepted
+    accepting_clients: bool,
+
+    /// how many connections a single remote host may have open at once, if limited
+    max_connections_per_ip: Option<usize>,
+
+    /// how many connections each remote host currently has open
+    connections_per_ip: HashMap<IpAddr, usize>,
+
+    /// what remote clients are allowed to do. local clients may do anything.
+    remote_permissions: Permissions,
+
+    /// how many streams a single client may have open at once
+    max_streams_per_client: usize,
+
+    /// the longest frame a client may send
+    max_frame_len: usize,
+    counters: Arc<ProtocolCounters>,
+
+    /// the most recent operations requested with an idempotency key, oldest first
+    keyed_operations: VecDeque<KeyedOperation>,
+
+    /// how long the subscriptions of a disconnected client are kept, so that they're restored if
+    /// it reconnects in time
+    reconnect_grace: Duration,
+
+    /// how long a new connection has to finish its handshake
+    handshake_timeout: Duration,
+
+    /// the identified clients which disconnected recently, oldest first
+    retained_clients: VecDeque<RetainedClient>,
+
+    /// the stream the running server writes its regular output to, which merged output is marked
+    /// with
+    primary_output_stream: watch::Receiver<OutputStream>,
+
+    /// when the network task started, reported as the uptime in the exported metrics
+    #[cfg(feature = "prometheus")]
+    started_at: Instant,
     sh: Option<Arc<SubsystemHandle<anyhow::Error>>>,
 }
 
+/// how connections are treated, as configured through the environment
+struct NetworkSettings {
+    max_connections_per_ip: Option<usize>,
+    max_frame_len: usize,
+    reconnect_grace: Duration,
+    handshake_timeout: Duration,
+    remote_permissions: Permissions,
+    max_streams_per_client: usize,
+}
+
+impl NetworkSettings {
+    fn from_env() -> anyhow::Result<Self> {
+        let max_connections_per_ip = match env::var("RAPHY_MAX_CONNECTIONS_PER_IP") {
+            Ok(max) => Some(max.parse::<usize>().with_context(|| {
+                format!("Failed to parse `RAPHY_MAX_CONNECTIONS_PER_IP` value `{max}`.")
+            })?),
+            Err(_) => None,
+        };
+        let max_frame_len = match env::var("RAPHY_MAX_FRAME_LEN") {
+            Ok(max) => max
+                .parse::<usize>()
+                .with_context(|| format!("Failed to parse `RAPHY_MAX_FRAME_LEN` value `{max}`."))?,
+            Err(_) => MAX_FRAME_LEN,
+        };
+        let reconnect_grace = match env::var("RAPHY_RECONNECT_GRACE_SECS") {
+            Ok(secs) => Duration::from_secs(secs.parse::<u64>().with_context(|| {
+                format!("Failed to parse `RAPHY_RECONNECT_GRACE_SECS` value `{secs}`.")
+            })?),
+            Err(_) => DEFAULT_RECONNECT_GRACE,
+        };
+        let handshake_timeout = match env::var("RAPHY_HANDSHAKE_TIMEOUT_SECS") {
+            Ok(secs) => Duration::from_secs(secs.parse::<u64>().with_context(|| {
+                format!("Failed to parse `RAPHY_HANDSHAKE_TIMEOUT_SECS` value `{secs}`.")
+            })?),
+            Err(_) => DEFAULT_HANDSHAKE_TIMEOUT,
+        };
+        let remote_permissions = match env::var("RAPHY_REMOTE_PERMISSIONS") {
+            Ok(permissions) => Permissions::parse(&permissions).with_context(|| {
+                format!("Failed to parse `RAPHY_REMOTE_PERMISSIONS` value `{permissions}`.")
+            })?,
+            Err(_) => Permissions::ALL,
+        };
+        let max_streams_per_client = match env::var("RAPHY_MAX_STREAMS_PER_CLIENT") {
+            Ok(max) => max.parse::<usize>().with_context(|| {
+                format!("Failed to parse `RAPHY_MAX_STREAMS_PER_CLIENT` value `{max}`.")
+            })?,
+            Err(_) => DEFAULT_MAX_STREAMS_PER_CLIENT,
+        };
+
+        Ok(Self {
+            max_connections_per_ip,
+            max_frame_len,
+            reconnect_grace,
+            handshake_timeout,
+            remote_permissions,
+            max_streams_per_client,
+        })
+    }
+}
+
 impl NetworkTask {
     pub fn new(
         new_clients_rx: UnboundedReceiver<NewClient>,
         n2s_tx: UnboundedSender<NetworkToServerMessage>,
         global_s2c_rx: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+        settings: NetworkSettings,
+        primary_output_stream: watch::Receiver<OutputStream>,
     ) -> Self {
+        let NetworkSettings {
+            max_connections_per_ip,
+            max_frame_len,
+            reconnect_grace,
+            handshake_timeout,
+            remote_permissions,
+            max_streams_per_client,
+        } = settings;
         let (c2s_tx, c2s_rx) = mpsc::unbounded_channel();
         let (destroy_client_tx, destroy_client_rx) = mpsc::unbounded_channel();
+        let (handshakes_tx, handshakes_rx) = mpsc::unbounded_channel();
         Self {
             clients: Slab::new(),
             new_clients_rx,
@@ -280,7 +1258,23 @@ impl NetworkTask {
             n2s_tx,
             destroy_client_tx,
             destroy_client_rx,
+            handshakes_tx,
+            handshakes_rx,
             global_s2c_rx,
+            accepting_clients: true,
+            max_connections_per_ip,
+            connections_per_ip: HashMap::new(),
+            remote_permissions,
+            max_streams_per_client,
+            max_frame_len,
+            counters: Arc::default(),
+            keyed_operations: VecDeque::new(),
+            reconnect_grace,
+            handshake_timeout,
+            retained_clients: VecDeque::new(),
+            primary_output_stream,
+            #[cfg(feature = "prometheus")]
+            started_at: Instant::now(),
             sh: None,
         }
     }
@@ -292,12 +1286,66 @@ impl NetworkTask {
     }
 
     fn broadcast_message(&self, message: raphy_protocol::ServerToClientMessage) {
+        if self.clients.is_empty() {
+            return;
+        }
+
         tracing::debug!(?message, "broadcast message");
         for (_, client) in &self.clients {
             client.s2c_tx.send(message.clone()).ok();
         }
     }
 
+    /// broadcasts server output, respecting each client's output mode.
+    fn broadcast_output(&mut self, message: raphy_protocol::ServerToClientMessage) {
+        // a headless server often has no clients at all, in which case the output isn't worth
+        // looking at
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let output = match &message {
+            raphy_protocol::ServerToClientMessage::Stdout(output)
+            | raphy_protocol::ServerToClientMessage::Stderr(output) => Cow::Borrowed(output),
+            raphy_protocol::ServerToClientMessage::Log(log) => Cow::Owned(log.text()),
+            _ => {
+                self.broadcast_message(message);
+                return;
+            }
+        };
+
+        let primary_output_stream = *self.primary_output_stream.borrow();
+
+        // compressed at most once, and only if a client wants it
+        let mut compressed = None;
+        for (_, client) in &mut self.clients {
+            if !client.output.admit(&output) {
+                continue;
+            }
+
+            let message = match (client.compress_output, client.output.delivery) {
+                (true, OutputDelivery::Split) => compressed
+                    .get_or_insert_with(|| message.clone().compressed())
+                    .clone(),
+                _ => client.output.deliver(&message, primary_output_stream),
+            };
+            client.bulk_tx.send(message).ok();
+        }
+    }
+
+    fn send_output_summaries(&mut self) {
+        let primary_output_stream = *self.primary_output_stream.borrow();
+        for (_, client) in &mut self.clients {
+            if let Some(summary) = client.output.next_window() {
+                let summary = raphy_protocol::ServerToClientMessage::Stdout(summary);
+                client
+                    .bulk_tx
+                    .send(client.output.deliver(&summary, primary_output_stream))
+                    .ok();
+            }
+        }
+    }
+
     fn message_broadcaster(&self, active_task: Option<(ClientId, TaskId)>) -> MessageBroadcaster {
         if let Some((client_id, task_id)) = active_task {
             let mut senders: HashMap<_, _> = self
@@ -310,19 +1358,49 @@ impl NetworkTask {
             MessageBroadcaster {
                 senders: senders.into_values().collect(),
                 active_task,
+                origin: Some((client_id, task_id)),
             }
         } else {
             MessageBroadcaster {
                 senders: self.clients.iter().map(|(_, c)| c.s2c_tx.clone()).collect(),
                 active_task: None,
+                origin: None,
             }
         }
     }
 
     fn destroy_client(&mut self, client_id: ClientId) {
+        // messages the client sent before it went away are handled while it still exists, since
+        // e.g. `Shutdown` is ignored from clients which don't
+        while let Ok(c2s) = self.c2s_rx.try_recv() {
+            self.handle_c2s(c2s);
+        }
+
         match self.clients.try_remove(client_id.0) {
             Some(client) => {
-                client.subsystem.get().unwrap().initiate_shutdown();
+                if let Some(subsystem) = client.subsystem.get() {
+                    subsystem.initiate_shutdown();
+                }
+                if let Some(identity) = client.identity {
+                    self.retain_client(identity, client.subscriptions());
+                }
+                if client.editing_config {
+                    self.broadcast_message(
+                        raphy_protocol::ServerToClientMessage::ConfigEditingStopped(
+                            client.config_editor(),
+                        ),
+                    );
+                }
+                if let Some(metrics) = client.metrics {
+                    metrics.task.abort();
+                }
+                for stream in client.streams {
+                    stream.abort();
+                }
+                if let Some(peer) = client.peer {
+                    self.release_connection(peer.ip());
+                }
+
                 tracing::info!(
                     "{} client with client id {client_id} disconnected from the server",
                     client.kind.label()
@@ -336,15 +1414,45 @@ impl NetworkTask {
         }
     }
 
+    fn prune_retained_clients(&mut self) {
+        while self
+            .retained_clients
+            .front()
+            .is_some_and(|retained| retained.disconnected_at.elapsed() > self.reconnect_grace)
+        {
+            self.retained_clients.pop_front();
+        }
+    }
+
+    fn retain_client(&mut self, identity: ClientIdentity, subscriptions: Subscriptions) {
+        if self.reconnect_grace.is_zero() {
+            return;
+        }
+
+        self.prune_retained_clients();
+        if self.retained_clients.len() == MAX_RETAINED_CLIENTS {
+            self.retained_clients.pop_front();
+        }
+        self.retained_clients.push_back(RetainedClient {
+            identity,
+            subscriptions,
+            disconnected_at: Instant::now(),
+        });
+    }
+
     pub async fn run(mut self, sh: SubsystemHandle<anyhow::Error>) {
         let sh = Arc::new(sh);
         self.sh = Some(Arc::clone(&sh));
 
+        let mut sample_window = tokio::time::interval(OUTPUT_SAMPLE_WINDOW);
+
         loop {
             tokio::select! {
                 Some(new_client) = self.new_clients_rx.recv() => self.handle_new_client(new_client),
+                Some(outcome) = self.handshakes_rx.recv() => self.handle_handshake(outcome),
                 Some(c2s) = self.c2s_rx.recv() => self.handle_c2s(c2s),
-                Some(message) = self.global_s2c_rx.recv() => self.broadcast_message(message),
+                Some(message) = self.global_s2c_rx.recv() => self.broadcast_output(message),
+                _ = sample_window.tick() => self.send_output_summaries(),
                 Some(client_id) = self.destroy_client_rx.recv() => self.destroy_client(client_id),
                 () = sh.on_shutdown_requested() => break,
             }
@@ -353,17 +1461,106 @@ impl NetworkTask {
 }
 
 impl NetworkTask {
+    /// performs the handshake of a new connection. it only becomes a client once that succeeded,
+    /// see [`NetworkTask::handle_handshake`].
     fn handle_new_stream(
         &mut self,
         read_half: impl AsyncRead + Send + Unpin + 'static,
         write_half: impl AsyncWrite + Send + Unpin + 'static,
         kind: ClientKind,
+        codec: Codec,
+        peer: Option<SocketAddr>,
+    ) {
+        let connection = self.connection(kind, codec);
+        let timeout = self.handshake_timeout;
+        self.start_handshake(
+            kind,
+            negotiate(read_half, write_half, connection, peer, timeout),
+        );
+    }
+
+    fn connection(&self, kind: ClientKind, codec: Codec) -> Connection {
+        Connection {
+            kind,
+            codec,
+            counters: Arc::clone(&self.counters),
+            max_frame_len: self.max_frame_len,
+        }
+    }
+
+    /// runs the handshake of a new connection in the background, reporting how it went to
+    /// [`NetworkTask::handle_handshake`]
+    fn start_handshake(
+        &self,
+        kind: ClientKind,
+        negotiate: impl Future<Output = HandshakeOutcome> + Send + 'static,
+    ) {
+        let handshakes_tx = self.handshakes_tx.clone();
+        self.sh().start(SubsystemBuilder::new(
+            format!("{}-handshake", kind.label()),
+            async move |sh| {
+                tokio::select! {
+                    outcome = negotiate => {
+                        handshakes_tx.send(outcome).ok();
+                    }
+                    () = sh.on_shutdown_requested() => {}
+                }
+
+                Ok::<_, anyhow::Error>(())
+            },
+        ));
+    }
+
+    fn handle_handshake(&mut self, outcome: HandshakeOutcome) {
+        match outcome {
+            HandshakeOutcome::Accepted {
+                read_half,
+                write_half,
+                connection,
+                peer,
+                options,
+            } => self.add_client(read_half, write_half, connection, peer, options),
+            HandshakeOutcome::Failed { peer } => {
+                if let Some(peer) = peer {
+                    self.release_connection(peer.ip());
+                }
+            }
+        }
+    }
+
+    fn add_client(
+        &mut self,
+        read_half: Box<dyn AsyncRead + Send + Unpin>,
+        write_half: Box<dyn AsyncWrite + Send + Unpin>,
+        connection: Connection,
+        peer: Option<SocketAddr>,
+        options: HandshakeOptions,
     ) {
-        let (s2c_tx, s2c_rx) = mpsc::unbounded_channel();
+        let Connection { kind, .. } = connection;
+        let (s2c_tx, control_rx) = mpsc::unbounded_channel();
+        let (bulk_tx, bulk_rx) = mpsc::unbounded_channel();
+        let outbound = Outbound {
+            control: control_rx,
+            bulk: bulk_rx,
+        };
+        let queue_depth = Arc::new(AtomicUsize::new(0));
         let id = ClientId(self.clients.insert(Client {
             s2c_tx,
+            bulk_tx,
             kind,
+            peer,
+            permissions: match kind {
+                ClientKind::Unix => Permissions::ALL,
+                ClientKind::Tcp => self.remote_permissions,
+            },
+            output: ClientOutput::default(),
+            compress_output: options.compress_output,
             subsystem: OnceCell::new(),
+            queue_depth: Arc::clone(&queue_depth),
+            metrics: None,
+            streams: Vec::new(),
+            identity: None,
+            editing_config: false,
         }));
         let c2s_tx = self.c2s_tx.clone();
         let destroy_client_tx = self.destroy_client_tx.clone();
@@ -371,15 +1568,35 @@ impl NetworkTask {
             format!("{}-{id}", kind.label()),
             async move |sh| {
                 let (destroy_tx, mut destroy_rx) = mpsc::unbounded_channel();
+                let (drain_tx, drain_rx) = mpsc::unbounded_channel();
                 sh.start(SubsystemBuilder::new("read", {
                     let destroy_tx = destroy_tx.clone();
+                    let connection = connection.clone();
                     move |sh| async move {
-                        read_subsystem(c2s_tx, id, read_half, sh, kind, destroy_tx).await;
+                        read_subsystem(
+                            c2s_tx,
+                            id,
+                            read_half,
+                            sh.on_shutdown_requested(),
+                            connection,
+                            drain_rx,
+                            destroy_tx,
+                        )
+                        .await;
                         Ok::<_, anyhow::Error>(())
                     }
                 }));
                 sh.start(SubsystemBuilder::new("write", move |sh| async move {
-                    write_subsystem(write_half, s2c_rx, sh, kind, destroy_tx).await;
+                    write_subsystem(
+                        write_half,
+                        outbound,
+                        sh,
+                        connection,
+                        queue_depth,
+                        drain_tx,
+                        destroy_tx,
+                    )
+                    .await;
                     Ok::<_, anyhow::Error>(())
                 }));
                 sh.start(SubsystemBuilder::new(
@@ -408,20 +1625,76 @@ impl NetworkTask {
 
     fn handle_new_unix_stream(&mut self, client: UnixStream) {
         let (read_half, write_half) = client.into_split();
-        self.handle_new_stream(read_half, write_half, ClientKind::Unix);
+        self.handle_new_stream(read_half, write_half, ClientKind::Unix, Codec::Bincode, None);
     }
 
-    fn handle_new_tcp_stream(&mut self, client: TcpStream) {
+    fn release_connection(&mut self, ip: IpAddr) {
+        if let Some(count) = self.connections_per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                self.connections_per_ip.remove(&ip);
+            }
+        }
+    }
+
+    /// whether the host already has as many connections open as it may
+    fn connection_limit_reached(&self, ip: IpAddr) -> bool {
+        self.max_connections_per_ip.is_some_and(|max| {
+            self.connections_per_ip.get(&ip).copied().unwrap_or(0) >= max
+        })
+    }
+
+    fn handle_new_tcp_stream(&mut self, client: TcpStream, codec: Codec) {
+        let peer = client.peer_addr().ok();
+        if let Some(peer) = peer {
+            *self.connections_per_ip.entry(peer.ip()).or_default() += 1;
+        }
         let (read_half, write_half) = client.into_split();
-        self.handle_new_stream(read_half, write_half, ClientKind::Tcp);
+        self.handle_new_stream(read_half, write_half, ClientKind::Tcp, codec, peer);
+    }
+
+    #[cfg(feature = "tls")]
+    fn handle_new_tls_stream(&mut self, client: TcpStream, acceptor: TlsAcceptor, codec: Codec) {
+        let peer = client.peer_addr().ok();
+        if let Some(peer) = peer {
+            *self.connections_per_ip.entry(peer.ip()).or_default() += 1;
+        }
+        let connection = self.connection(ClientKind::Tcp, codec);
+        let timeout = self.handshake_timeout;
+        self.start_handshake(
+            ClientKind::Tcp,
+            negotiate_tls(acceptor, client, connection, peer, timeout),
+        );
     }
 
     fn handle_new_client(&mut self, new_client: NewClient) {
         let kind = new_client.kind().label();
 
+        // unix clients are local, so they're always accepted
+        if !self.accepting_clients && !matches!(new_client, NewClient::Unix(_)) {
+            tracing::info!("rejected new {kind} client, since new clients aren't being accepted");
+            new_client.reject("The server is not accepting new clients right now.");
+            return;
+        }
+
+        // unix clients are local, so they aren't limited
+        if let Some(ip) = new_client
+            .peer_ip()
+            .filter(|ip| self.connection_limit_reached(*ip))
+        {
+            tracing::info!(%ip, "rejected new {kind} client, since its host has too many connections");
+            new_client.reject("Too many connections are open from your address.");
+            return;
+        }
+
         match new_client {
             NewClient::Unix(stream) => self.handle_new_unix_stream(stream),
-            NewClient::Tcp(stream) => self.handle_new_tcp_stream(stream),
+            NewClient::Tcp(stream, codec) => self.handle_new_tcp_stream(stream, codec),
+
+            #[cfg(feature = "tls")]
+            NewClient::Tls(stream, acceptor, codec) => {
+                self.handle_new_tls_stream(stream, acceptor, codec)
+            }
         }
 
         tracing::info!("new {kind} client connected to the server");
@@ -429,6 +1702,103 @@ impl NetworkTask {
 }
 
 impl NetworkTask {
+    /// returns the sender of the given client if it is a local client, otherwise the client is sent
+    /// an error explaining that it isn't allowed to `action`.
+    fn local_client_s2c_tx(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        action: &str,
+    ) -> Option<UnboundedSender<raphy_protocol::ServerToClientMessage>> {
+        let Some(client) = self.clients.get(client_id.0) else {
+            tracing::warn!("client {client_id} tried to {action}, but it doesn't exist");
+            return None;
+        };
+
+        if !matches!(client.kind, ClientKind::Unix) {
+            tracing::warn!("client {client_id} tried to {action}, but it's not a local client");
+            client
+                .s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*anyhow!("Only local clients may {action}.")),
+                    Some(task_id),
+                ))
+                .ok();
+            return None;
+        }
+
+        Some(client.s2c_tx.clone())
+    }
+
+    /// whether the client has `permission`, which `message` needs. if it doesn't, it's sent an
+    /// error saying so.
+    fn check_permission(
+        &self,
+        client_id: ClientId,
+        permission: Permission,
+        message: &raphy_protocol::ClientToServerMessage,
+    ) -> bool {
+        let Some(client) = self.clients.get(client_id.0) else {
+            tracing::warn!("client {client_id} sent {}, but it doesn't exist", message.name());
+            return false;
+        };
+        if client.permissions.allows(permission) {
+            return true;
+        }
+
+        tracing::warn!(
+            ?permission,
+            "client {client_id} sent {}, but it isn't permitted to",
+            message.name()
+        );
+        let error = anyhow::Error::new(ErrorKind::NotPermitted(permission))
+            .context(format!("This client isn't permitted to send {}.", message.name()));
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::Error(
+                SerdeError::new(&*error),
+                message.task_id(),
+            ))
+            .ok();
+        false
+    }
+
+    /// forwards a message to the server task. if the server task isn't running, the message is
+    /// dropped along with any response sender within it, so the handler waiting on the response
+    /// tells its client that the server is unavailable instead.
+    fn send_n2s(&self, message: NetworkToServerMessage) {
+        if self.n2s_tx.send(message).is_err() {
+            tracing::warn!("the server task isn't running, so a message to it was dropped");
+        }
+    }
+
+    /// whether the client may open another stream, telling it why not if it can't
+    fn admit_stream(&mut self, client_id: ClientId, task_id: TaskId) -> bool {
+        let limit = self.max_streams_per_client;
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            return false;
+        };
+        if client.open_streams() < limit {
+            return true;
+        }
+
+        tracing::warn!(
+            limit,
+            "client {client_id} tried to open more streams than it may"
+        );
+        let error = anyhow::Error::new(ErrorKind::TooManyStreams(limit)).context(format!(
+            "This client already has {limit} streams open; wait for one to finish first."
+        ));
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::Error(
+                SerdeError::new(&*error),
+                Some(task_id),
+            ))
+            .ok();
+        false
+    }
+
     fn handle_c2s_ping(&self, client_id: ClientId, task_id: TaskId) {
         let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
             tracing::warn!("client {client_id} tried to ping the server, but it doesn't exist");
@@ -447,12 +1817,13 @@ impl NetworkTask {
         };
 
         let (tx, rx) = oneshot::channel();
-        self.n2s_tx
-            .send(NetworkToServerMessage::GetConfig(tx))
-            .unwrap();
+        self.send_n2s(NetworkToServerMessage::GetConfig(tx));
 
         tokio::spawn(async move {
-            let config = rx.await.unwrap();
+            let Ok(config) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
             s2c_tx
                 .send(raphy_protocol::ServerToClientMessage::CurrentConfig(
                     config, task_id,
@@ -469,12 +1840,13 @@ impl NetworkTask {
         };
 
         let (tx, rx) = oneshot::channel();
-        self.n2s_tx
-            .send(NetworkToServerMessage::GetServerState(tx))
-            .unwrap();
+        self.send_n2s(NetworkToServerMessage::GetServerState(tx));
 
         tokio::spawn(async move {
-            let config = rx.await.unwrap();
+            let Ok(config) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
             s2c_tx
                 .send(raphy_protocol::ServerToClientMessage::CurrentServerState(
                     config, task_id,
@@ -484,41 +1856,95 @@ impl NetworkTask {
         });
     }
 
-    fn handle_c2s_update_config(&self, client_id: ClientId, task_id: TaskId, config: Config) {
+    fn handle_c2s_update_config(&self, client_id: ClientId, task_id: TaskId, config: Box<Config>) {
         let (tx, rx) = oneshot::channel();
-        self.n2s_tx
-            .send(NetworkToServerMessage::UpdateConfig(config.clone(), tx))
-            .unwrap();
+        let updated = (*config).clone();
+        self.send_n2s(NetworkToServerMessage::UpdateConfig(config, tx));
 
         let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
         tokio::spawn(async move {
-            rx.await.unwrap();
-            message_broadcaster.broadcast_with_task_id(|tid| {
-                raphy_protocol::ServerToClientMessage::ConfigUpdated(config.clone(), tid)
-            });
+            let Ok(result) = rx.await else {
+                message_broadcaster.respond(server_unavailable(task_id));
+                return;
+            };
+
+            match result {
+                Ok(()) => message_broadcaster.broadcast_with_task_id(|tid| {
+                    raphy_protocol::ServerToClientMessage::ConfigUpdated(updated.clone(), tid)
+                }),
+                Err(error) => message_broadcaster.respond(
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    ),
+                ),
+            }
             tracing::debug!(?client_id, ?task_id, "finished responding to message");
         });
     }
 
     fn handle_c2s_perform_operation(
-        &self,
+        &mut self,
         client_id: ClientId,
         task_id: TaskId,
         operation: Operation,
+        idempotency_key: Option<IdempotencyKey>,
     ) {
+        let Some(started_by) = self.clients.get(client_id.0).map(Client::connection_info) else {
+            tracing::warn!("client {client_id} tried to perform an operation, but it doesn't exist");
+            return;
+        };
+
+        while self
+            .keyed_operations
+            .front()
+            .is_some_and(|keyed| keyed.requested_at.elapsed() > IDEMPOTENCY_WINDOW)
+        {
+            self.keyed_operations.pop_front();
+        }
+
+        let resent = idempotency_key
+            .and_then(|key| self.keyed_operations.iter().find(|keyed| keyed.key == key));
+        if let Some(keyed) = resent {
+            tracing::info!(
+                operation_id = ?keyed.operation_id,
+                "client {client_id} resent an operation, responding with its original result"
+            );
+            self.respond_with_operation_result(
+                client_id,
+                task_id,
+                keyed.operation,
+                keyed.operation_id,
+            );
+            return;
+        }
+
         let op_id = OperationId::generate();
+        if let Some(key) = idempotency_key {
+            if self.keyed_operations.len() == MAX_IDEMPOTENCY_KEYS {
+                self.keyed_operations.pop_front();
+            }
+
+            self.keyed_operations.push_back(KeyedOperation {
+                key,
+                operation,
+                operation_id: op_id,
+                requested_at: Instant::now(),
+            });
+        }
         self.broadcast_message(raphy_protocol::ServerToClientMessage::OperationRequested(
             operation, op_id,
         ));
 
         let (tx, rx) = oneshot::channel();
-        self.n2s_tx
-            .send(NetworkToServerMessage::PerformOperation(operation, tx))
-            .unwrap();
+        self.send_n2s(NetworkToServerMessage::PerformOperation(
+            operation, op_id, started_by, tx,
+        ));
 
         let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
         tokio::spawn(async move {
-            match rx.await.unwrap() {
+            let result = rx.await.unwrap_or_else(|_| Err(anyhow!(SERVER_UNAVAILABLE)));
+            match result {
                 Ok(()) => message_broadcaster.broadcast_with_task_id(|tid| {
                     raphy_protocol::ServerToClientMessage::OperationPerformed(operation, op_id, tid)
                 }),
@@ -535,46 +1961,1308 @@ impl NetworkTask {
         });
     }
 
-    fn handle_c2s_input(&self, input: Vec<u8>) {
-        self.n2s_tx
-            .send(NetworkToServerMessage::Input(input))
-            .unwrap();
-        tracing::debug!("finished responding to input message");
+    /// responds to a resent operation with the result of the original one, once it finished.
+    fn respond_with_operation_result(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        operation: Operation,
+        operation_id: OperationId,
+    ) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::AwaitOperation(operation_id, tx));
+
+        tokio::spawn(async move {
+            let result = match rx.await {
+                Ok(Some(result)) => result,
+                Ok(None) => Err(anyhow!(
+                    "The original operation is no longer remembered; check the server state before retrying."
+                )),
+                Err(_) => Err(anyhow!(SERVER_UNAVAILABLE)),
+            };
+            let message = match result {
+                Ok(()) => raphy_protocol::ServerToClientMessage::OperationPerformed(
+                    operation,
+                    operation_id,
+                    Some(task_id),
+                ),
+                Err(error) => raphy_protocol::ServerToClientMessage::OperationFailed(
+                    operation,
+                    operation_id,
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(message).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
     }
 
-    fn handle_c2s_shutdown(&self, id: ClientId) {
-        let Some(client) = self.clients.get(id.0) else {
-            tracing::warn!("client {id} tried to shut down the server, but it doesn't exist",);
+    fn handle_c2s_get_operation_result(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        operation_id: OperationId,
+    ) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get an operation result, but it doesn't exist");
             return;
         };
 
-        if !matches!(client.kind, ClientKind::Unix) {
-            tracing::warn!(
-                "client {id} tried to shut down the server, but it's not a remote client",
-            );
-        }
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetOperationResult(operation_id, tx));
 
-        self.n2s_tx.send(NetworkToServerMessage::Shutdown).unwrap()
+        tokio::spawn(async move {
+            let Ok(outcome) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::OperationResult(
+                    outcome, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
     }
 
-    fn handle_c2s(&self, c2s: ClientToServerMessage) {
+    fn handle_c2s_get_active_operation(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the active operation, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetActiveOperation(tx));
+
+        tokio::spawn(async move {
+            let Ok(active) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::ActiveOperation(
+                    active, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_run_maintenance(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        steps: Vec<MaintenanceStep>,
+    ) {
+        let Some((s2c_tx, started_by)) = self
+            .clients
+            .get(client_id.0)
+            .map(|c| (c.s2c_tx.clone(), c.connection_info()))
+        else {
+            tracing::warn!("client {client_id} tried to run maintenance, but it doesn't exist");
+            return;
+        };
+
+        let n2s_tx = self.n2s_tx.clone();
+        tokio::spawn(async move {
+            let mut report = MaintenanceReport {
+                completed: Vec::with_capacity(steps.len()),
+                failed: None,
+            };
+
+            // the cycle carries on even if the client disconnects midway, since leaving the server
+            // stopped because of that would be worse
+            for step in steps {
+                tracing::info!(?step, "client {client_id} started a maintenance step");
+                s2c_tx
+                    .send(raphy_protocol::ServerToClientMessage::MaintenanceStepStarted(
+                        step, task_id,
+                    ))
+                    .ok();
+
+                match run_maintenance_step(&n2s_tx, step, started_by).await {
+                    Ok(()) => report.completed.push(step),
+                    Err(error) => {
+                        tracing::warn!(?step, ?error, "a maintenance step failed: {error:#}");
+                        report.failed = Some((step, SerdeError::new(&*error)));
+                        break;
+                    }
+                }
+            }
+
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::MaintenanceFinished(
+                    report, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_save_world(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to save the world, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::SaveWorld(tx));
+
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let message = match result {
+                Ok(result) => raphy_protocol::ServerToClientMessage::SaveResult(result, task_id),
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(message).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_backup_world(&mut self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to back up the world, but it doesn't exist");
+            return;
+        };
+        if !self.admit_stream(client_id, task_id) {
+            return;
+        }
+
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::BackupWorld(progress_tx, tx));
+
+        let stream = tokio::spawn(async move {
+            // the progress channel closes once the backup has finished
+            while let Some(progress) = progress_rx.recv().await {
+                s2c_tx
+                    .send(raphy_protocol::ServerToClientMessage::BackupProgress(
+                        progress, task_id,
+                    ))
+                    .ok();
+            }
+
+            let Ok(result) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let message = match result {
+                Ok(archive) => {
+                    raphy_protocol::ServerToClientMessage::WorldBackedUp(archive, task_id)
+                }
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(message).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+        if let Some(client) = self.clients.get_mut(client_id.0) {
+            client.streams.push(stream.abort_handle());
+        }
+    }
+
+    fn handle_c2s_reload_server(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to reload the server, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::ReloadServer(tx));
+
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let message = match result {
+                Ok(result) => raphy_protocol::ServerToClientMessage::ReloadResult(result, task_id),
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(message).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_update_jar(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        relative_path: PathBuf,
+        back_up_previous: bool,
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::UpdateJar(
+            relative_path,
+            back_up_previous,
+            tx,
+        ));
+
+        let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                message_broadcaster.respond(server_unavailable(task_id));
+                return;
+            };
+
+            match result {
+                Ok(config) => message_broadcaster.broadcast_with_task_id(|tid| {
+                    raphy_protocol::ServerToClientMessage::ConfigUpdated(config.clone(), tid)
+                }),
+                Err(error) => message_broadcaster.respond(
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    ),
+                ),
+            }
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_list_argument_presets(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to list the argument presets, but it doesn't exist");
+            return;
+        };
+
+        let presets = ArgumentPreset::ALL.map(ArgumentPreset::info).to_vec();
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::ArgumentPresets(
+                presets, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_apply_argument_preset(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        name: String,
+        memory_mb: u32,
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::ApplyArgumentPreset(name, memory_mb, tx));
+
+        let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                message_broadcaster.respond(server_unavailable(task_id));
+                return;
+            };
+
+            match result {
+                Ok(config) => message_broadcaster.broadcast_with_task_id(|tid| {
+                    raphy_protocol::ServerToClientMessage::ConfigUpdated(config.clone(), tid)
+                }),
+                Err(error) => message_broadcaster.respond(
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    ),
+                ),
+            }
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_startup_log(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the startup log, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetStartupLog(tx));
+
+        tokio::spawn(async move {
+            let Ok(log) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::StartupLog(
+                    log, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_recent_output(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!(
+                "client {client_id} tried to get the recent output, but it doesn't exist"
+            );
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetRecentOutput(tx));
+
+        tokio::spawn(async move {
+            let Ok(output) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::RecentOutput(
+                    output, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_lifecycle_events(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        since: Option<SystemTime>,
+    ) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the lifecycle events, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetLifecycleEvents(since, tx));
+
+        tokio::spawn(async move {
+            let Ok(events) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::LifecycleEvents(
+                    events, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_operation_history(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!(
+                "client {client_id} tried to get the operation history, but it doesn't exist"
+            );
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetOperationHistory(tx));
+
+        tokio::spawn(async move {
+            let Ok(history) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::OperationHistory(
+                    history, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_server_info(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the server info, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetServerInfo(tx));
+
+        tokio::spawn(async move {
+            let Ok(info) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::ServerInfo(
+                    info, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_list_instances(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to list the instances, but it doesn't exist");
+            return;
+        };
+
+        // reading the socket directory blocks
+        tokio::spawn(async move {
+            let instances = tokio::task::spawn_blocking(raphy_protocol::local_instances)
+                .await
+                .unwrap();
+            let message = match instances {
+                Ok(instances) => {
+                    raphy_protocol::ServerToClientMessage::Instances(instances, task_id)
+                }
+                Err(error) => {
+                    let error = anyhow::Error::new(error).context("Failed to list the instances.");
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    )
+                }
+            };
+            s2c_tx.send(message).ok();
+        });
+    }
+
+    fn handle_c2s_get_connection_info(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(client) = self.clients.get(client_id.0) else {
+            tracing::warn!("client {client_id} tried to get its connection info, but it doesn't exist");
+            return;
+        };
+
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::ConnectionInfo(
+                client.connection_info(),
+                task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_get_client_count(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(client) = self.clients.get(client_id.0) else {
+            tracing::warn!("client {client_id} tried to get the client count, but it doesn't exist");
+            return;
+        };
+
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::ClientCount(
+                self.clients.len(),
+                task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_get_diagnostics(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.local_client_s2c_tx(client_id, task_id, "get the diagnostics")
+        else {
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetDiagnostics(
+            self.accepting_clients,
+            tx,
+        ));
+
+        tokio::spawn(async move {
+            let Ok(diagnostics) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::Diagnostics(
+                    Box::new(diagnostics),
+                    task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_protocol_stats(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) =
+            self.local_client_s2c_tx(client_id, task_id, "get the protocol statistics")
+        else {
+            return;
+        };
+
+        let queue_depths = self
+            .clients
+            .iter()
+            .map(|(_, c)| (c.connection_info(), c.queue_depth.load(Ordering::Relaxed)))
+            .collect();
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::ProtocolStats(
+                Box::new(self.counters.snapshot(queue_depths)),
+                task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_disconnect_all_remote(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        reason: String,
+    ) {
+        let Some(s2c_tx) =
+            self.local_client_s2c_tx(client_id, task_id, "disconnect all remote clients")
+        else {
+            return;
+        };
+
+        // the write half of each client closes the connection once it sent the message, and the
+        // client is destroyed afterwards like any other one which disconnected
+        let mut disconnected = 0;
+        for (_, client) in &self.clients {
+            if matches!(client.kind, ClientKind::Tcp)
+                && client
+                    .s2c_tx
+                    .send(raphy_protocol::ServerToClientMessage::Reconnect(reason.clone()))
+                    .is_ok()
+            {
+                disconnected += 1;
+            }
+        }
+
+        tracing::info!(?reason, "client {client_id} disconnected {disconnected} remote clients");
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::RemoteClientsDisconnected(
+                disconnected,
+                task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_subscribe_metrics(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        interval: Duration,
+    ) {
+        let Some(subscribed) = self.clients.get(client_id.0).map(|c| c.metrics.is_some()) else {
+            tracing::warn!(
+                "client {client_id} tried to subscribe to metrics, but it doesn't exist"
+            );
+            return;
+        };
+
+        // changing the interval of a subscription doesn't open another stream
+        if !subscribed && !self.admit_stream(client_id, task_id) {
+            return;
+        }
+
+        let n2s_tx = self.n2s_tx.clone();
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            return;
+        };
+
+        let interval = interval.max(MIN_METRICS_INTERVAL);
+        client.subscribe_metrics(n2s_tx, interval);
+
+        tracing::debug!(?interval, "client {client_id} subscribed to metrics");
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::MetricsSubscribed(
+                interval, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_unsubscribe_metrics(&mut self, client_id: ClientId, task_id: TaskId) {
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            tracing::warn!("client {client_id} tried to unsubscribe from metrics, but it doesn't exist");
+            return;
+        };
+
+        if let Some(metrics) = client.metrics.take() {
+            metrics.task.abort();
+            tracing::debug!("client {client_id} unsubscribed from metrics");
+        }
+
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::MetricsUnsubscribed(
+                task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_get_resolved_paths(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the resolved paths, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetResolvedPaths(tx));
+
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let message = match result {
+                Ok(paths) => raphy_protocol::ServerToClientMessage::ResolvedPaths(paths, task_id),
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(message).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_refresh_java_detection(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to refresh the java detection, but it doesn't exist");
+            return;
+        };
+
+        raphy_protocol::clear_java_detection_cache();
+        let java_path = raphy_protocol::auto_detect_java();
+        tracing::info!(?java_path, "client {client_id} refreshed the java detection");
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::JavaDetected(
+                java_path, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_get_java_version(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the java version, but it doesn't exist");
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetConfig(tx));
+
+        tokio::spawn(async move {
+            let Ok(config) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let s2c = match java_version(config).await {
+                Ok(version) => raphy_protocol::ServerToClientMessage::JavaVersion(version, task_id),
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(s2c).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_get_backup_retention(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!(
+                "client {client_id} tried to get the backup retention, but it doesn't exist"
+            );
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetConfig(tx));
+
+        tokio::spawn(async move {
+            let Ok(CurrentConfig::Saved(config) | CurrentConfig::Template(config)) = rx.await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::BackupRetention(
+                    config.backup_retention,
+                    task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_set_backup_retention(
+        &self,
+        client_id: ClientId,
+        task_id: TaskId,
+        retention: Option<usize>,
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::SetBackupRetention(retention, tx));
+
+        let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                message_broadcaster.respond(server_unavailable(task_id));
+                return;
+            };
+
+            match result {
+                Ok(config) => message_broadcaster.broadcast_with_task_id(|tid| {
+                    raphy_protocol::ServerToClientMessage::ConfigUpdated(config.clone(), tid)
+                }),
+                Err(error) => message_broadcaster.respond(
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    ),
+                ),
+            }
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_patch_config(&self, client_id: ClientId, task_id: TaskId, patch: ConfigPatch) {
+        let (tx, rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::PatchConfig(patch, tx));
+
+        let message_broadcaster = self.message_broadcaster(Some((client_id, task_id)));
+        tokio::spawn(async move {
+            let Ok(result) = rx.await else {
+                message_broadcaster.respond(server_unavailable(task_id));
+                return;
+            };
+
+            match result {
+                Ok(config) => message_broadcaster.broadcast_with_task_id(|tid| {
+                    raphy_protocol::ServerToClientMessage::ConfigUpdated(config.clone(), tid)
+                }),
+                Err(error) => message_broadcaster.respond(
+                    raphy_protocol::ServerToClientMessage::Error(
+                        SerdeError::new(&*error),
+                        Some(task_id),
+                    ),
+                ),
+            }
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_test_user(&self, client_id: ClientId, task_id: TaskId, user: User) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to test a user, but it doesn't exist");
+            return;
+        };
+
+        tokio::spawn(async move {
+            let outcome = utils::test_user(&user).await;
+            tracing::info!(?user, ?outcome, "client {client_id} tested a user");
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::UserTested(
+                    outcome, task_id,
+                ))
+                .ok();
+        });
+    }
+
+    fn handle_c2s_get_resource_usage(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the resource usage, but it doesn't exist");
+            return;
+        };
+
+        let n2s_tx = self.n2s_tx.clone();
+        tokio::spawn(async move {
+            let sample = || async {
+                let (tx, rx) = oneshot::channel();
+                n2s_tx.send(NetworkToServerMessage::GetMetricsSample(tx)).ok()?;
+                let sample = rx.await.ok()?;
+                Some((Instant::now(), sample))
+            };
+
+            // the cpu usage is only known between two samples
+            let Some((first_at, first)) = sample().await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            tokio::time::sleep(RESOURCE_USAGE_WINDOW).await;
+            let Some((second_at, second)) = sample().await else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+
+            let usage = ResourceUsage {
+                rss_bytes: second.rss_bytes,
+                cpu_percent: cpu_percent(
+                    first.cpu_time.map(|cpu_time| (first_at, cpu_time)),
+                    second.cpu_time.map(|cpu_time| (second_at, cpu_time)),
+                ),
+                cpu_time: second.cpu_time,
+            };
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::ResourceUsage(
+                    usage, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    fn handle_c2s_validate_tls(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to validate tls, but it doesn't exist");
+            return;
+        };
+
+        tokio::spawn(async move {
+            // the certificate and key are read from disk
+            let result = tokio::task::spawn_blocking(validate_tls)
+                .await
+                .unwrap_or_else(|error| Err(anyhow!(error).context("Failed to validate TLS.")));
+            let s2c = match result {
+                Ok(report) => raphy_protocol::ServerToClientMessage::TlsValidated(report, task_id),
+                Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                    SerdeError::new(&*error),
+                    Some(task_id),
+                ),
+            };
+            s2c_tx.send(s2c).ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    #[cfg(feature = "prometheus")]
+    fn handle_c2s_get_metrics_text(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} tried to get the metrics text, but it doesn't exist");
+            return;
+        };
+
+        let (sample_tx, sample_rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetMetricsSample(sample_tx));
+        let (state_tx, state_rx) = oneshot::channel();
+        self.send_n2s(NetworkToServerMessage::GetServerState(state_tx));
+        let clients = self.clients.len();
+        let uptime = self.started_at.elapsed();
+
+        tokio::spawn(async move {
+            let (Ok(sample), Ok(state)) = (sample_rx.await, state_rx.await) else {
+                s2c_tx.send(server_unavailable(task_id)).ok();
+                return;
+            };
+            let text = crate::prometheus::render(&crate::prometheus::Snapshot {
+                sample,
+                state,
+                clients,
+                uptime,
+            });
+            s2c_tx
+                .send(raphy_protocol::ServerToClientMessage::MetricsText(
+                    text, task_id,
+                ))
+                .ok();
+            tracing::debug!(?client_id, ?task_id, "finished responding to message");
+        });
+    }
+
+    #[cfg(not(feature = "prometheus"))]
+    fn handle_c2s_get_metrics_text(&self, client_id: ClientId, task_id: TaskId) {
+        let Some(client) = self.clients.get(client_id.0) else {
+            tracing::warn!("client {client_id} tried to get the metrics text, but it doesn't exist");
+            return;
+        };
+
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::Error(
+                SerdeError::new(&*anyhow!(
+                    "The server was built without metrics exporting; enable its `prometheus` feature."
+                )),
+                Some(task_id),
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_input(&self, input: Vec<u8>) {
+        self.send_n2s(NetworkToServerMessage::Input(input));
+        tracing::debug!("finished responding to input message");
+    }
+
+    fn handle_c2s_shutdown(&self, id: ClientId) {
+        let Some(client) = self.clients.get(id.0) else {
+            tracing::warn!("client {id} tried to shut down the server, but it doesn't exist",);
+            return;
+        };
+
+        if !matches!(client.kind, ClientKind::Unix) {
+            tracing::warn!(
+                "client {id} tried to shut down the server, but it's not a remote client",
+            );
+        }
+
+        self.send_n2s(NetworkToServerMessage::Shutdown);
+    }
+
+    fn handle_c2s_set_accepting_clients(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        accepting: bool,
+    ) {
+        let Some(s2c_tx) = self.local_client_s2c_tx(
+            client_id,
+            task_id,
+            "change whether new clients are accepted",
+        ) else {
+            return;
+        };
+
+        tracing::info!(accepting, "client {client_id} changed whether new clients are accepted");
+        self.accepting_clients = accepting;
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::AcceptingClientsUpdated(
+                accepting, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_set_log_level(&self, client_id: ClientId, task_id: TaskId, level: String) {
+        let Some(s2c_tx) = self.local_client_s2c_tx(client_id, task_id, "change the log level")
+        else {
+            return;
+        };
+
+        let message = match raphy_common::set_log_level(&level) {
+            Ok(()) => {
+                tracing::info!(?level, "client {client_id} changed the log level");
+                raphy_protocol::ServerToClientMessage::LogLevelUpdated(level, task_id)
+            }
+            Err(error) => raphy_protocol::ServerToClientMessage::Error(
+                SerdeError::new(&*error),
+                Some(task_id),
+            ),
+        };
+        s2c_tx.send(message).ok();
+    }
+
+    fn handle_c2s_set_output_mode(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        mode: OutputMode,
+    ) {
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            tracing::warn!("client {client_id} tried to set its output mode, but it doesn't exist");
+            return;
+        };
+
+        tracing::debug!(?mode, "client {client_id} set its output mode");
+        client.output = ClientOutput {
+            mode,
+            delivery: client.output.delivery,
+            ..ClientOutput::default()
+        };
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::OutputModeUpdated(
+                mode, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_set_output_delivery(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        delivery: OutputDelivery,
+    ) {
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            tracing::warn!("client {client_id} tried to set its output delivery, but it doesn't exist");
+            return;
+        };
+
+        tracing::debug!(?delivery, "client {client_id} set its output delivery");
+        client.output.delivery = delivery;
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::OutputDeliveryUpdated(
+                delivery, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_identify(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        identity: ClientIdentity,
+    ) {
+        if !self.clients.contains(client_id.0) {
+            tracing::warn!("client {client_id} tried to identify itself, but it doesn't exist");
+            return;
+        }
+
+        // the previous connection may not have been noticed to be gone yet, in which case its
+        // subscriptions are taken over right away
+        let live = self
+            .clients
+            .iter_mut()
+            .find(|(id, client)| *id != client_id.0 && client.identity == Some(identity))
+            .map(|(_, client)| {
+                client.identity = None;
+                client.subscriptions()
+            });
+        let subscriptions = live.or_else(|| {
+            self.prune_retained_clients();
+            let index = self
+                .retained_clients
+                .iter()
+                .position(|retained| retained.identity == identity)?;
+            self.retained_clients
+                .remove(index)
+                .map(|retained| retained.subscriptions)
+        });
+
+        let n2s_tx = self.n2s_tx.clone();
+        let client = &mut self.clients[client_id.0];
+        client.identity = Some(identity);
+        let restored = subscriptions.is_some();
+        if let Some(subscriptions) = subscriptions {
+            tracing::info!("restored the subscriptions of client {client_id}");
+            client.output = ClientOutput {
+                mode: subscriptions.output_mode,
+                delivery: subscriptions.output_delivery,
+                ..ClientOutput::default()
+            };
+            if let Some(interval) = subscriptions.metrics_interval {
+                client.subscribe_metrics(n2s_tx, interval);
+            }
+        }
+
+        client
+            .s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::Identified(
+                restored, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_c2s_set_editing_config(
+        &mut self,
+        client_id: ClientId,
+        task_id: TaskId,
+        editing: bool,
+    ) {
+        let Some(client) = self.clients.get_mut(client_id.0) else {
+            tracing::warn!("client {client_id} tried to set whether it's editing the config, but it doesn't exist");
+            return;
+        };
+
+        let changed = client.editing_config != editing;
+        client.editing_config = editing;
+        let editor = client.config_editor();
+        let s2c_tx = client.s2c_tx.clone();
+
+        if changed {
+            tracing::info!(editing, "client {client_id} set whether it's editing the config");
+            let message = if editing {
+                raphy_protocol::ServerToClientMessage::ConfigEditingStarted(editor)
+            } else {
+                raphy_protocol::ServerToClientMessage::ConfigEditingStopped(editor)
+            };
+            for (id, client) in &self.clients {
+                if id != client_id.0 {
+                    client.s2c_tx.send(message.clone()).ok();
+                }
+            }
+        }
+
+        let editors = self
+            .clients
+            .iter()
+            .filter(|&(id, client)| id != client_id.0 && client.editing_config)
+            .map(|(_, client)| client.config_editor())
+            .collect();
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::ConfigEditors(
+                editors, task_id,
+            ))
+            .ok();
+    }
+
+    fn handle_unsupported_message(&self, client_id: ClientId, type_hint: u32) {
+        let Some(s2c_tx) = self.clients.get(client_id.0).map(|c| c.s2c_tx.clone()) else {
+            tracing::warn!("client {client_id} sent an unsupported message, but it doesn't exist");
+            return;
+        };
+
+        tracing::info!(
+            "client {client_id} sent a message of the unknown variant {type_hint}, it's likely from a newer version"
+        );
+        s2c_tx
+            .send(raphy_protocol::ServerToClientMessage::UnsupportedMessage { type_hint })
+            .ok();
+    }
+
+    fn handle_c2s(&mut self, c2s: ClientToServerMessage) {
         tracing::debug!(?c2s, "received new message from a client");
 
-        match c2s.data {
+        let data = match c2s.data {
+            Incoming::Message(data) => data,
+            Incoming::Unsupported(type_hint) => {
+                self.handle_unsupported_message(c2s.id, type_hint);
+                return;
+            }
+        };
+
+        if let Some(permission) = data.required_permission()
+            && !self.check_permission(c2s.id, permission, &data)
+        {
+            return;
+        }
+
+        match data {
+            raphy_protocol::ClientToServerMessage::Handshake(..) => {
+                tracing::warn!("client {} sent another handshake, ignoring it", c2s.id)
+            }
             raphy_protocol::ClientToServerMessage::Ping(task_id) => {
                 self.handle_c2s_ping(c2s.id, task_id)
             }
-            raphy_protocol::ClientToServerMessage::GetConfig(task_id) => {
-                self.handle_c2s_get_config(c2s.id, task_id)
+            raphy_protocol::ClientToServerMessage::GetConfig(task_id) => {
+                self.handle_c2s_get_config(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetServerState(task_id) => {
+                self.handle_c2s_get_server_state(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::UpdateConfig(task_id, config) => {
+                self.handle_c2s_update_config(c2s.id, task_id, config)
+            }
+            raphy_protocol::ClientToServerMessage::PerformOperation(
+                task_id,
+                operation,
+                idempotency_key,
+            ) => {
+                self.handle_c2s_perform_operation(c2s.id, task_id, operation, idempotency_key)
+            }
+            raphy_protocol::ClientToServerMessage::GetOperationResult(task_id, operation_id) => {
+                self.handle_c2s_get_operation_result(c2s.id, task_id, operation_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetActiveOperation(task_id) => {
+                self.handle_c2s_get_active_operation(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::RunMaintenance(task_id, steps) => {
+                self.handle_c2s_run_maintenance(c2s.id, task_id, steps)
+            }
+            raphy_protocol::ClientToServerMessage::BackupWorld(task_id) => {
+                self.handle_c2s_backup_world(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::SaveWorld(task_id) => {
+                self.handle_c2s_save_world(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::ReloadServer(task_id) => {
+                self.handle_c2s_reload_server(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::UpdateJar(task_id, relative_path, back_up) => {
+                self.handle_c2s_update_jar(c2s.id, task_id, relative_path, back_up)
+            }
+            raphy_protocol::ClientToServerMessage::ListArgumentPresets(task_id) => {
+                self.handle_c2s_list_argument_presets(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::ApplyArgumentPreset(
+                task_id,
+                name,
+                memory_mb,
+            ) => {
+                self.handle_c2s_apply_argument_preset(c2s.id, task_id, name, memory_mb)
+            }
+            raphy_protocol::ClientToServerMessage::GetOperationHistory(task_id) => {
+                self.handle_c2s_get_operation_history(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetLifecycleEvents(task_id, since) => {
+                self.handle_c2s_get_lifecycle_events(c2s.id, task_id, since)
+            }
+            raphy_protocol::ClientToServerMessage::GetStartupLog(task_id) => {
+                self.handle_c2s_get_startup_log(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetRecentOutput(task_id) => {
+                self.handle_c2s_get_recent_output(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::SetAcceptingClients(task_id, accepting) => {
+                self.handle_c2s_set_accepting_clients(c2s.id, task_id, accepting)
+            }
+            raphy_protocol::ClientToServerMessage::SetLogLevel(task_id, level) => {
+                self.handle_c2s_set_log_level(c2s.id, task_id, level)
+            }
+            raphy_protocol::ClientToServerMessage::SetOutputMode(task_id, mode) => {
+                self.handle_c2s_set_output_mode(c2s.id, task_id, mode)
             }
-            raphy_protocol::ClientToServerMessage::GetServerState(task_id) => {
-                self.handle_c2s_get_server_state(c2s.id, task_id)
+            raphy_protocol::ClientToServerMessage::SetOutputDelivery(task_id, delivery) => {
+                self.handle_c2s_set_output_delivery(c2s.id, task_id, delivery)
             }
-            raphy_protocol::ClientToServerMessage::UpdateConfig(task_id, config) => {
-                self.handle_c2s_update_config(c2s.id, task_id, config)
+            raphy_protocol::ClientToServerMessage::ListInstances(task_id) => {
+                self.handle_c2s_list_instances(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetServerInfo(task_id) => {
+                self.handle_c2s_get_server_info(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetConnectionInfo(task_id) => {
+                self.handle_c2s_get_connection_info(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetClientCount(task_id) => {
+                self.handle_c2s_get_client_count(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetDiagnostics(task_id) => {
+                self.handle_c2s_get_diagnostics(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetResolvedPaths(task_id) => {
+                self.handle_c2s_get_resolved_paths(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::RefreshJavaDetection(task_id) => {
+                self.handle_c2s_refresh_java_detection(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetJavaVersion(task_id) => {
+                self.handle_c2s_get_java_version(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetBackupRetention(task_id) => {
+                self.handle_c2s_get_backup_retention(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::SetBackupRetention(task_id, retention) => {
+                self.handle_c2s_set_backup_retention(c2s.id, task_id, retention)
+            }
+            raphy_protocol::ClientToServerMessage::PatchConfig(task_id, patch) => {
+                self.handle_c2s_patch_config(c2s.id, task_id, patch)
             }
-            raphy_protocol::ClientToServerMessage::PerformOperation(task_id, operation) => {
-                self.handle_c2s_perform_operation(c2s.id, task_id, operation)
+            raphy_protocol::ClientToServerMessage::TestUser(task_id, user) => {
+                self.handle_c2s_test_user(c2s.id, task_id, user)
+            }
+            raphy_protocol::ClientToServerMessage::GetResourceUsage(task_id) => {
+                self.handle_c2s_get_resource_usage(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::ValidateTls(task_id) => {
+                self.handle_c2s_validate_tls(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetMetricsText(task_id) => {
+                self.handle_c2s_get_metrics_text(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::GetProtocolStats(task_id) => {
+                self.handle_c2s_get_protocol_stats(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::DisconnectAllRemote(task_id, reason) => {
+                self.handle_c2s_disconnect_all_remote(c2s.id, task_id, reason)
+            }
+            raphy_protocol::ClientToServerMessage::SubscribeMetrics(task_id, interval) => {
+                self.handle_c2s_subscribe_metrics(c2s.id, task_id, interval)
+            }
+            raphy_protocol::ClientToServerMessage::UnsubscribeMetrics(task_id) => {
+                self.handle_c2s_unsubscribe_metrics(c2s.id, task_id)
+            }
+            raphy_protocol::ClientToServerMessage::Identify(task_id, identity) => {
+                self.handle_c2s_identify(c2s.id, task_id, identity)
+            }
+            raphy_protocol::ClientToServerMessage::SetEditingConfig(task_id, editing) => {
+                self.handle_c2s_set_editing_config(c2s.id, task_id, editing)
             }
             raphy_protocol::ClientToServerMessage::Input(input) => self.handle_c2s_input(input),
             raphy_protocol::ClientToServerMessage::Shutdown => self.handle_c2s_shutdown(c2s.id),
@@ -582,13 +3270,30 @@ impl NetworkTask {
     }
 }
 
+/// whether this process bound the unix socket, and so is the one responsible for removing it
+static UNIX_SOCKET_BOUND: AtomicBool = AtomicBool::new(false);
+
+/// removes the unix socket file if this process bound it. safe to call more than once.
+pub fn remove_unix_socket() {
+    if !UNIX_SOCKET_BOUND.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    let path = utils::unix_socket_path();
+    if let Err(error) = fs::remove_file(&path) {
+        tracing::error!("failed to remove unix socket path '{}': {error}", path.display());
+    }
+}
+
 async fn unix(
     new_clients: UnboundedSender<NewClient>,
     sh: SubsystemHandle<anyhow::Error>,
 ) -> anyhow::Result<()> {
-    let listener = UnixListener::bind(UNIX_SOCKET_PATH)
-        .with_context(|| format!("Failed to bind unix socket path '{UNIX_SOCKET_PATH}'."))?;
-    tracing::info!("listening on unix socket '{UNIX_SOCKET_PATH}'");
+    let path = utils::unix_socket_path();
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind unix socket path '{}'.", path.display()))?;
+    UNIX_SOCKET_BOUND.store(true, Ordering::SeqCst);
+    tracing::info!("listening on unix socket '{}'", path.display());
 
     loop {
         tokio::select! {
@@ -609,21 +3314,166 @@ async fn unix(
             }
             () = sh.on_shutdown_requested() => {
                 drop(listener);
-
-                if let Err(error) = fs::remove_file(UNIX_SOCKET_PATH) {
-                    tracing::error!("failed to remove unix socket path '{UNIX_SOCKET_PATH}': {error}");
-                }
-
+                remove_unix_socket();
                 return Ok(())
             }
         }
     }
 }
 
+/// the certificate chain and private key paths from `RAPHY_TLS_CERT` and `RAPHY_TLS_KEY`, or
+/// `None` if neither is set
+fn tls_paths() -> anyhow::Result<Option<(PathBuf, PathBuf)>> {
+    let cert_path = env::var_os("RAPHY_TLS_CERT");
+    let key_path = env::var_os("RAPHY_TLS_KEY");
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            Ok(Some((PathBuf::from(cert_path), PathBuf::from(key_path))))
+        }
+        (None, None) => Ok(None),
+        _ => bail!("Both `RAPHY_TLS_CERT` and `RAPHY_TLS_KEY` have to be set to enable TLS."),
+    }
+}
+
+/// reads the certificate chain and private key from their pem files
+#[cfg(feature = "tls")]
+fn load_tls_files(
+    cert_path: &Path,
+    key_path: &Path,
+) -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| {
+            format!(
+                "Failed to read the TLS certificate chain at `{}`.",
+                cert_path.display()
+            )
+        })?;
+    let key = PrivateKeyDer::from_pem_file(key_path).with_context(|| {
+        format!(
+            "Failed to read the TLS private key at `{}`.",
+            key_path.display()
+        )
+    })?;
+
+    Ok((certs, key))
+}
+
+/// loads the certificate chain and private key at `RAPHY_TLS_CERT` and `RAPHY_TLS_KEY`, which are
+/// pem files. tls is disabled if neither is set.
+#[cfg(feature = "tls")]
+fn tls_acceptor() -> anyhow::Result<Option<TlsAcceptor>> {
+    let Some((cert_path, key_path)) = tls_paths()? else {
+        return Ok(None);
+    };
+
+    let (certs, key) = load_tls_files(&cert_path, &key_path)?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Failed to configure TLS with the given certificate and private key.")?;
+
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// reads the configured certificate chain and private key again and checks whether they can be
+/// served. files which can't be read or parsed fail outright, anything else is reported as a
+/// problem.
+#[cfg(feature = "tls")]
+fn validate_tls() -> anyhow::Result<TlsReport> {
+    use x509_parser::extensions::GeneralName;
+
+    let Some((cert_path, key_path)) = tls_paths()? else {
+        bail!("TLS isn't configured; set `RAPHY_TLS_CERT` and `RAPHY_TLS_KEY` to enable it.");
+    };
+
+    let (certs, key) = load_tls_files(&cert_path, &key_path)?;
+    let leaf = certs.first().with_context(|| {
+        format!("The TLS certificate chain at `{}` is empty.", cert_path.display())
+    })?;
+    let (_, cert) = x509_parser::parse_x509_certificate(leaf)
+        .context("Failed to parse the TLS certificate.")?;
+
+    let subject_alt_names = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|extension| {
+            extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(bytes) => match bytes.len() {
+                        4 => <[u8; 4]>::try_from(*bytes).ok().map(IpAddr::from),
+                        16 => <[u8; 16]>::try_from(*bytes).ok().map(IpAddr::from),
+                        _ => None,
+                    }
+                    .map(|ip| ip.to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let validity = cert.validity();
+    let mut problems = Vec::new();
+    let now = x509_parser::time::ASN1Time::now();
+    if now < validity.not_before {
+        problems.push(format!("The certificate isn't valid until {}.", validity.not_before));
+    }
+    if now > validity.not_after {
+        problems.push(format!("The certificate expired on {}.", validity.not_after));
+    }
+
+    // rustls checks that the key belongs to the certificate, the same way it does on startup
+    if let Err(error) = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs.clone(), key)
+    {
+        problems.push(format!(
+            "TLS can't be configured with this certificate and private key: {error}."
+        ));
+    }
+
+    let timestamp = |time: x509_parser::time::ASN1Time| {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(time.timestamp()).unwrap_or(0))
+    };
+    Ok(TlsReport {
+        subject: cert.subject().to_string(),
+        subject_alt_names,
+        not_before: timestamp(validity.not_before),
+        not_after: timestamp(validity.not_after),
+        chain_len: certs.len(),
+        problems,
+        cert_path,
+        key_path,
+    })
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_acceptor() -> anyhow::Result<Option<TlsAcceptor>> {
+    if env::var_os("RAPHY_TLS_CERT").is_some() || env::var_os("RAPHY_TLS_KEY").is_some() {
+        bail!("TLS was configured, but this server was built without the `tls` feature.");
+    }
+
+    Ok(None)
+}
+
+#[cfg(not(feature = "tls"))]
+fn validate_tls() -> anyhow::Result<TlsReport> {
+    tls_paths()?
+        .context("TLS isn't configured; set `RAPHY_TLS_CERT` and `RAPHY_TLS_KEY` to enable it.")?;
+    bail!("The server was built without TLS; enable its `tls` feature.")
+}
+
 async fn tcp(
     address: String,
     new_clients: UnboundedSender<NewClient>,
-    port_tx: oneshot::Sender<u16>,
+    codec: Codec,
+    tls: Option<TlsAcceptor>,
+    port_tx: Option<oneshot::Sender<u16>>,
     sh: SubsystemHandle<anyhow::Error>,
 ) -> anyhow::Result<()> {
     let listener = TcpListener::bind(&address)
@@ -632,8 +3482,10 @@ async fn tcp(
     let local_addr = listener
         .local_addr()
         .context("Failed to get local address of TCP listener.")?;
-    tracing::info!("listening on tcp address {local_addr}");
-    port_tx.send(local_addr.port()).unwrap();
+    tracing::info!(?codec, tls = tls.is_some(), "listening on tcp address {local_addr}");
+    if let Some(port_tx) = port_tx {
+        port_tx.send(local_addr.port()).unwrap();
+    }
 
     loop {
         tokio::select! {
@@ -649,8 +3501,17 @@ async fn tcp(
                     }
                 };
 
-                new_clients.send(NewClient::Tcp(stream))
-                    .expect("failed to send new tcp client to network task");
+                match &tls {
+                    None => new_clients.send(NewClient::Tcp(stream, codec))
+                        .expect("failed to send new tcp client to network task"),
+
+                    #[cfg(feature = "tls")]
+                    Some(acceptor) => new_clients.send(NewClient::Tls(stream, acceptor.clone(), codec))
+                        .expect("failed to send new tls client to network task"),
+
+                    #[cfg(not(feature = "tls"))]
+                    Some(never) => match *never {},
+                }
             }
             () = sh.on_shutdown_requested() => break,
         }
@@ -663,11 +3524,14 @@ pub async fn initialize(
     sh: &SubsystemHandle<anyhow::Error>,
     n2s_tx: UnboundedSender<NetworkToServerMessage>,
     global_s2c_rx: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+    primary_output_stream: watch::Receiver<OutputStream>,
 ) -> anyhow::Result<u16> {
     let address = env::var("RAPHY_SERVER_ADDRESS").unwrap_or_else(|_| {
         let port = env::args().nth(1).and_then(|p| p.parse::<u16>().ok()).unwrap_or(DEFAULT_PORT);
         format!("0.0.0.0:{port}")
     });
+    let settings = NetworkSettings::from_env()?;
+    let tls = tls_acceptor().context("Failed to set up TLS for the TCP listeners.")?;
     let (new_clients_tx, new_clients_rx) = mpsc::unbounded_channel();
 
     sh.start(SubsystemBuilder::new("unix-listener", {
@@ -675,12 +3539,42 @@ pub async fn initialize(
         move |sh| unix(new_clients_tx, sh)
     }));
 
+    if let Ok(json_address) = env::var("RAPHY_JSON_ADDRESS") {
+        sh.start(SubsystemBuilder::new("tcp-json-listener", {
+            let new_clients_tx = new_clients_tx.clone();
+            let tls = tls.clone();
+            move |sh| {
+                tcp(
+                    json_address,
+                    new_clients_tx,
+                    Codec::Json,
+                    tls,
+                    None,
+                    sh,
+                )
+            }
+        }));
+    }
+
     let (port_tx, port_rx) = oneshot::channel();
     sh.start(SubsystemBuilder::new("tcp-listener", move |sh| {
-        tcp(address, new_clients_tx, port_tx, sh)
+        tcp(
+            address,
+            new_clients_tx,
+            Codec::Bincode,
+            tls,
+            Some(port_tx),
+            sh,
+        )
     }));
 
-    let network = NetworkTask::new(new_clients_rx, n2s_tx, global_s2c_rx);
+    let network = NetworkTask::new(
+        new_clients_rx,
+        n2s_tx,
+        global_s2c_rx,
+        settings,
+        primary_output_stream,
+    );
     sh.start(SubsystemBuilder::new("network", move |sh| async move {
         network.run(sh).await;
         Ok::<_, anyhow::Error>(())
@@ -688,3 +3582,687 @@ pub async fn initialize(
 
     Ok(port_rx.await.expect("port tx was dropped"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection() -> Connection {
+        Connection {
+            kind: ClientKind::Tcp,
+            codec: Codec::Bincode,
+            counters: Arc::default(),
+            max_frame_len: MAX_FRAME_LEN,
+        }
+    }
+
+    /// a message as a client writes it
+    fn client_frame(message: raphy_protocol::ClientToServerMessage) -> Vec<u8> {
+        let data = bincode::encode_to_vec(message, bincode::config::standard()).unwrap();
+        let mut frame = (data.len() as u32).to_le_bytes().to_vec();
+        frame.extend(data);
+        frame
+    }
+
+    #[tokio::test]
+    async fn draining_keeps_a_message_which_was_read_partly() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (c2s_tx, mut c2s_rx) = mpsc::unbounded_channel();
+        let (drain_tx, drain_rx) = mpsc::unbounded_channel();
+        let (destroy_tx, mut destroy_rx) = mpsc::unbounded_channel();
+        tokio::spawn(read_subsystem(
+            c2s_tx,
+            ClientId(0),
+            server,
+            std::future::pending(),
+            connection(),
+            drain_rx,
+            destroy_tx,
+        ));
+
+        // the write half fails while the contents of the message are only partly received
+        let input = raphy_protocol::ClientToServerMessage::Input(b"stop\n".to_vec());
+        let frame = client_frame(input);
+        let (first, rest) = frame.split_at(6);
+        client.write_all(first).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drain_tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        client.write_all(rest).await.unwrap();
+
+        let message = c2s_rx.recv().await.unwrap();
+        assert!(matches!(
+            message.data,
+            Incoming::Message(raphy_protocol::ClientToServerMessage::Input(input))
+                if input == b"stop\n"
+        ));
+
+        drop(client);
+        destroy_rx.recv().await.unwrap();
+    }
+
+    fn network_task() -> (NetworkTask, UnboundedReceiver<NetworkToServerMessage>) {
+        let (_, new_clients_rx) = mpsc::unbounded_channel();
+        let (n2s_tx, n2s_rx) = mpsc::unbounded_channel();
+        let (_, global_s2c_rx) = mpsc::unbounded_channel();
+        let (_, primary_output_stream) = watch::channel(OutputStream::Stdout);
+        let settings = NetworkSettings {
+            max_connections_per_ip: Some(4),
+            max_frame_len: MAX_FRAME_LEN,
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            remote_permissions: Permissions::ALL,
+            max_streams_per_client: DEFAULT_MAX_STREAMS_PER_CLIENT,
+        };
+        let network = NetworkTask::new(
+            new_clients_rx,
+            n2s_tx,
+            global_s2c_rx,
+            settings,
+            primary_output_stream,
+        );
+        (network, n2s_rx)
+    }
+
+    /// a client which was added without a connection, and what the server sends it
+    struct TestClient {
+        id: ClientId,
+        control: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+        bulk: UnboundedReceiver<raphy_protocol::ServerToClientMessage>,
+    }
+
+    fn add_test_client(network: &mut NetworkTask, kind: ClientKind) -> TestClient {
+        let (s2c_tx, control) = mpsc::unbounded_channel();
+        let (bulk_tx, bulk) = mpsc::unbounded_channel();
+        let id = ClientId(network.clients.insert(Client {
+            s2c_tx,
+            bulk_tx,
+            kind,
+            peer: None,
+            permissions: match kind {
+                ClientKind::Unix => Permissions::ALL,
+                ClientKind::Tcp => network.remote_permissions,
+            },
+            output: ClientOutput::default(),
+            compress_output: false,
+            subsystem: OnceCell::new(),
+            queue_depth: Arc::default(),
+            metrics: None,
+            streams: Vec::new(),
+            identity: None,
+            editing_config: false,
+        }));
+        TestClient { id, control, bulk }
+    }
+
+    #[tokio::test]
+    async fn a_client_which_sends_its_handshake_is_accepted() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+        let options = HandshakeOptions {
+            compress_output: true,
+        };
+        let handshake = raphy_protocol::ClientToServerMessage::Handshake(PROTOCOL_VERSION, options);
+        client.write_all(&client_frame(handshake)).await.unwrap();
+
+        let timeout = Duration::from_millis(50);
+        let outcome = negotiate(read_half, write_half, connection(), None, timeout).await;
+        assert!(matches!(
+            outcome,
+            HandshakeOutcome::Accepted { options: accepted, .. } if accepted == options
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_client_of_an_older_version_is_told_so() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+
+        // the handshake of protocol version 1, which held nothing but the version
+        let data = bincode::encode_to_vec((0u32, 1u32), bincode::config::standard()).unwrap();
+        let mut frame = (data.len() as u32).to_le_bytes().to_vec();
+        frame.extend(data);
+        client.write_all(&frame).await.unwrap();
+
+        let timeout = Duration::from_millis(50);
+        let outcome = negotiate(read_half, write_half, connection(), None, timeout).await;
+        assert!(matches!(outcome, HandshakeOutcome::Failed { .. }));
+
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        let (response, _): (raphy_protocol::ServerToClientMessage, _) =
+            bincode::decode_from_slice(&buf[4..], bincode::config::standard()).unwrap();
+        assert!(matches!(
+            response,
+            raphy_protocol::ServerToClientMessage::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                got: 1,
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_client_which_sends_nothing_is_dropped() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+
+        let timeout = Duration::from_millis(50);
+        let outcome = negotiate(read_half, write_half, connection(), None, timeout).await;
+        assert!(matches!(outcome, HandshakeOutcome::Failed { .. }));
+
+        // the connection is closed rather than kept around
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_client_which_stalls_during_its_handshake_is_dropped() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+        let handshake = raphy_protocol::ClientToServerMessage::Handshake(
+            PROTOCOL_VERSION,
+            HandshakeOptions::default(),
+        );
+        let frame = client_frame(handshake);
+        // only the length and the first byte of the handshake arrive
+        client.write_all(&frame[..5]).await.unwrap();
+
+        let timeout = Duration::from_millis(50);
+        let outcome = negotiate(read_half, write_half, connection(), None, timeout).await;
+        assert!(matches!(outcome, HandshakeOutcome::Failed { .. }));
+    }
+
+    #[tokio::test]
+    async fn a_host_may_not_open_more_connections_than_its_limit() {
+        let (mut network, _n2s_rx) = network_task();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // the connections up to the limit, counted the way they are when they're accepted
+        for _ in 0..4 {
+            *network.connections_per_ip.entry(addr.ip()).or_default() += 1;
+        }
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        network.handle_new_client(NewClient::Tcp(stream, Codec::Bincode));
+
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        let (response, _): (raphy_protocol::ServerToClientMessage, _) =
+            bincode::decode_from_slice(&buf[4..], bincode::config::standard()).unwrap();
+        let raphy_protocol::ServerToClientMessage::FatalError(error) = response else {
+            panic!("the connection wasn't rejected");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Too many connections are open from your address."
+        );
+        assert_eq!(network.connections_per_ip[&addr.ip()], 4);
+
+        // other hosts aren't affected, and the host may connect again once a connection closed
+        assert!(!network.connection_limit_reached(IpAddr::from([192, 0, 2, 1])));
+        network.release_connection(addr.ip());
+        assert!(!network.connection_limit_reached(addr.ip()));
+    }
+
+    #[test]
+    fn a_failed_handshake_never_becomes_a_client() {
+        let (mut network, _n2s_rx) = network_task();
+        let peer = SocketAddr::from(([192, 0, 2, 1], 40000));
+        network.connections_per_ip.insert(peer.ip(), 1);
+
+        network.handle_handshake(HandshakeOutcome::Failed { peer: Some(peer) });
+        assert!(network.clients.is_empty());
+        assert!(network.connections_per_ip.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_request_before_the_server_task_is_running_is_told_to_try_again() {
+        let (mut network, n2s_rx) = network_task();
+        drop(n2s_rx);
+        let mut client = add_test_client(&mut network, ClientKind::Unix);
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_get_config(client.id, task_id);
+        let Some(raphy_protocol::ServerToClientMessage::Error(error, Some(tid))) =
+            client.control.recv().await
+        else {
+            panic!("the client wasn't told that the server is unavailable");
+        };
+        assert_eq!(tid, task_id);
+        assert_eq!(error.to_string(), SERVER_UNAVAILABLE);
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_perform_operation(client.id, task_id, Operation::Start, None);
+        loop {
+            match client.control.recv().await.unwrap() {
+                raphy_protocol::ServerToClientMessage::OperationFailed(_, _, error, tid) => {
+                    assert_eq!(tid, Some(task_id));
+                    assert_eq!(error.to_string(), SERVER_UNAVAILABLE);
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn only_remote_clients_are_told_to_reconnect() {
+        let (mut network, _n2s_rx) = network_task();
+        let mut local = add_test_client(&mut network, ClientKind::Unix);
+        let mut other_local = add_test_client(&mut network, ClientKind::Unix);
+        let mut remote = add_test_client(&mut network, ClientKind::Tcp);
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_disconnect_all_remote(local.id, task_id, "rotated the token".to_owned());
+
+        let Ok(raphy_protocol::ServerToClientMessage::Reconnect(reason)) =
+            remote.control.try_recv()
+        else {
+            panic!("the remote client wasn't told to reconnect");
+        };
+        assert_eq!(reason, "rotated the token");
+
+        let Ok(raphy_protocol::ServerToClientMessage::RemoteClientsDisconnected(disconnected, tid)) =
+            local.control.try_recv()
+        else {
+            panic!("the local client wasn't told how many remote clients were disconnected");
+        };
+        assert_eq!((disconnected, tid), (1, task_id));
+        assert!(other_local.control.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn a_control_response_is_written_ahead_of_an_output_flood() {
+        let (control_tx, control) = mpsc::unbounded_channel();
+        let (bulk_tx, bulk) = mpsc::unbounded_channel();
+        let mut outbound = Outbound { control, bulk };
+        for _ in 0..10_000 {
+            bulk_tx
+                .send(raphy_protocol::ServerToClientMessage::Stdout(
+                    b"spam\n".to_vec(),
+                ))
+                .unwrap();
+        }
+        let task_id = TaskId::generate();
+        control_tx
+            .send(raphy_protocol::ServerToClientMessage::Pong(task_id))
+            .unwrap();
+
+        let mut written = Vec::new();
+        let queue_depth = AtomicUsize::new(0);
+        let control_flow =
+            write_subsystem_once(&mut written, &mut outbound, &connection(), &queue_depth).await;
+        assert!(matches!(control_flow, ControlFlow::Continue(())));
+
+        let (response, _): (raphy_protocol::ServerToClientMessage, _) =
+            bincode::decode_from_slice(&written[4..], bincode::config::standard()).unwrap();
+        assert!(matches!(
+            response,
+            raphy_protocol::ServerToClientMessage::Pong(id) if id == task_id
+        ));
+        assert_eq!(queue_depth.load(Ordering::Relaxed), 10_000);
+    }
+
+    #[test]
+    fn a_message_of_an_unknown_variant_is_answered_as_unsupported() {
+        // what a newer client would send for a variant this server doesn't have, followed by its
+        // fields
+        let mut frame = bincode::encode_to_vec(9999u32, bincode::config::standard()).unwrap();
+        frame.extend(b"fields");
+        let incoming = Codec::Bincode.decode(&frame).unwrap();
+        assert!(matches!(incoming, Incoming::Unsupported(9999)));
+
+        let (mut network, _n2s_rx) = network_task();
+        let mut client = add_test_client(&mut network, ClientKind::Tcp);
+        network.handle_c2s(ClientToServerMessage {
+            id: client.id,
+            data: incoming,
+        });
+        assert!(matches!(
+            client.control.try_recv(),
+            Ok(raphy_protocol::ServerToClientMessage::UnsupportedMessage { type_hint: 9999 })
+        ));
+    }
+
+    #[test]
+    fn each_client_gets_its_output_in_the_format_it_chose() {
+        let (mut network, _n2s_rx) = network_task();
+        let mut split = add_test_client(&mut network, ClientKind::Unix);
+        let mut merged = add_test_client(&mut network, ClientKind::Unix);
+        network.handle_c2s_set_output_delivery(
+            merged.id,
+            TaskId::generate(),
+            OutputDelivery::Merged,
+        );
+
+        network.broadcast_output(raphy_protocol::ServerToClientMessage::Stdout(
+            b"out\n".to_vec(),
+        ));
+        network.broadcast_output(raphy_protocol::ServerToClientMessage::Stderr(
+            b"err\n".to_vec(),
+        ));
+
+        assert!(matches!(
+            split.bulk.try_recv(),
+            Ok(raphy_protocol::ServerToClientMessage::Stdout(output)) if output == b"out\n"
+        ));
+        assert!(matches!(
+            split.bulk.try_recv(),
+            Ok(raphy_protocol::ServerToClientMessage::Stderr(output)) if output == b"err\n"
+        ));
+
+        for (stream, text) in [
+            (OutputStream::Stdout, "out\n"),
+            (OutputStream::Stderr, "err\n"),
+        ] {
+            let Ok(raphy_protocol::ServerToClientMessage::Log(log)) = merged.bulk.try_recv() else {
+                panic!("the merged client didn't get a log");
+            };
+            assert_eq!(log.stream, stream);
+            assert_eq!(log.primary, stream == OutputStream::Stdout);
+            assert_eq!(log.text(), text.as_bytes());
+        }
+    }
+
+    /// identifies a new client as `identity`, returning whether its subscriptions were restored
+    fn reconnect(network: &mut NetworkTask, identity: ClientIdentity) -> (TestClient, bool) {
+        let mut client = add_test_client(network, ClientKind::Tcp);
+        network.handle_c2s_identify(client.id, TaskId::generate(), identity);
+        let Ok(raphy_protocol::ServerToClientMessage::Identified(restored, _)) =
+            client.control.try_recv()
+        else {
+            panic!("the client wasn't told whether it was identified");
+        };
+        (client, restored)
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_restored_only_within_the_reconnect_grace() {
+        let (mut network, _n2s_rx) = network_task();
+        network.reconnect_grace = Duration::from_millis(50);
+        let identity = ClientIdentity::generate();
+
+        let (client, restored) = reconnect(&mut network, identity);
+        assert!(!restored);
+        network.handle_c2s_set_output_mode(client.id, TaskId::generate(), OutputMode::Sampled);
+        network.destroy_client(client.id);
+
+        let (client, restored) = reconnect(&mut network, identity);
+        assert!(restored);
+        assert!(matches!(
+            network.clients[client.id.0].output.mode,
+            OutputMode::Sampled
+        ));
+        network.destroy_client(client.id);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (client, restored) = reconnect(&mut network, identity);
+        assert!(!restored);
+        assert!(matches!(
+            network.clients[client.id.0].output.mode,
+            OutputMode::Full
+        ));
+    }
+
+    /// every message which needs a permission
+    fn gated_messages() -> Vec<raphy_protocol::ClientToServerMessage> {
+        use raphy_protocol::ClientToServerMessage as M;
+
+        let task_id = TaskId::generate;
+        vec![
+            M::PerformOperation(task_id(), Operation::Stop, None),
+            M::RunMaintenance(task_id(), Vec::new()),
+            M::SaveWorld(task_id()),
+            M::BackupWorld(task_id()),
+            M::ReloadServer(task_id()),
+            M::Shutdown,
+            M::UpdateConfig(task_id(), Box::default()),
+            M::PatchConfig(task_id(), ConfigPatch::MemoryLimitMb(None)),
+            M::UpdateJar(task_id(), PathBuf::from("server.jar"), false),
+            M::ApplyArgumentPreset(task_id(), "aikar".to_owned(), 4096),
+            M::SetBackupRetention(task_id(), Some(3)),
+            M::TestUser(task_id(), User::Current),
+            M::SetEditingConfig(task_id(), true),
+            M::Input(b"stop\n".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn a_view_only_client_is_refused_every_gated_message() {
+        let (mut network, mut n2s_rx) = network_task();
+        network.remote_permissions = Permissions::VIEW_ONLY;
+        let mut client = add_test_client(&mut network, ClientKind::Tcp);
+
+        for message in gated_messages() {
+            let name = message.name();
+            let permission = message.required_permission().unwrap();
+            let task_id = message.task_id();
+            network.handle_c2s(ClientToServerMessage {
+                id: client.id,
+                data: Incoming::Message(message),
+            });
+
+            let Ok(raphy_protocol::ServerToClientMessage::Error(error, tid)) =
+                client.control.try_recv()
+            else {
+                panic!("{name} wasn't refused");
+            };
+            assert_eq!(tid, task_id);
+            assert_eq!(error.kind(), Some(&ErrorKind::NotPermitted(permission)));
+        }
+        assert!(n2s_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn a_restricted_remote_client_may_still_do_what_it_is_permitted_to() {
+        let (mut network, mut n2s_rx) = network_task();
+        network.remote_permissions = Permissions {
+            input: true,
+            ..Permissions::VIEW_ONLY
+        };
+        let client = add_test_client(&mut network, ClientKind::Tcp);
+
+        network.handle_c2s(ClientToServerMessage {
+            id: client.id,
+            data: Incoming::Message(raphy_protocol::ClientToServerMessage::Input(
+                b"list\n".to_vec(),
+            )),
+        });
+        assert!(n2s_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn sampled_output_counts_the_lines_it_suppresses() {
+        let mut output = ClientOutput {
+            mode: OutputMode::Sampled,
+            ..ClientOutput::default()
+        };
+
+        for _ in 0..SAMPLED_OUTPUT_MESSAGES {
+            assert!(output.admit(b"a\nb\nc\n"));
+        }
+        for _ in 0..10 {
+            assert!(!output.admit(b"a\nb\nc\n"));
+        }
+        // output without a line break still counts as a line
+        assert!(!output.admit(b"partial"));
+
+        assert_eq!(
+            output.next_window(),
+            Some("… 31 lines suppressed …\n".as_bytes().to_vec())
+        );
+        assert!(output.admit(b"a\n"));
+        assert_eq!(output.next_window(), None);
+    }
+
+    #[tokio::test]
+    async fn a_resent_operation_is_only_performed_once() {
+        let (mut network, mut n2s_rx) = network_task();
+        let mut client = add_test_client(&mut network, ClientKind::Unix);
+        let key = IdempotencyKey::generate();
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_perform_operation(client.id, task_id, Operation::Stop, Some(key));
+        let Some(NetworkToServerMessage::PerformOperation(Operation::Stop, op_id, _, ret)) =
+            n2s_rx.recv().await
+        else {
+            panic!("the operation wasn't sent to the server task");
+        };
+
+        // the client times out and sends the same operation again
+        let resent_task_id = TaskId::generate();
+        network.handle_c2s_perform_operation(client.id, resent_task_id, Operation::Stop, Some(key));
+        let Some(NetworkToServerMessage::AwaitOperation(awaited_op_id, await_ret)) =
+            n2s_rx.recv().await
+        else {
+            panic!("the resent operation was performed again");
+        };
+        assert_eq!(awaited_op_id, op_id);
+        assert!(n2s_rx.try_recv().is_err());
+
+        ret.send(Ok(())).unwrap();
+        await_ret.send(Some(Ok(()))).unwrap();
+        let mut performed = Vec::new();
+        while performed.len() < 2 {
+            if let raphy_protocol::ServerToClientMessage::OperationPerformed(_, id, tid) =
+                client.control.recv().await.unwrap()
+            {
+                performed.push((id, tid));
+            }
+        }
+        assert!(performed.contains(&(op_id, Some(task_id))));
+        assert!(performed.contains(&(op_id, Some(resent_task_id))));
+    }
+
+    #[tokio::test]
+    async fn the_others_are_told_about_an_operation_whose_requester_disconnected() {
+        let (mut network, mut n2s_rx) = network_task();
+        let requester = add_test_client(&mut network, ClientKind::Unix);
+        let mut other = add_test_client(&mut network, ClientKind::Unix);
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_perform_operation(requester.id, task_id, Operation::Stop, None);
+        let Some(NetworkToServerMessage::PerformOperation(Operation::Stop, op_id, _, ret)) =
+            n2s_rx.recv().await
+        else {
+            panic!("the operation wasn't sent to the server task");
+        };
+
+        network.destroy_client(requester.id);
+        drop(requester);
+        ret.send(Ok(())).unwrap();
+
+        loop {
+            match other.control.recv().await.unwrap() {
+                raphy_protocol::ServerToClientMessage::OperationPerformed(_, id, tid) => {
+                    assert_eq!(id, op_id);
+                    assert_eq!(tid, None);
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn operations_with_different_keys_are_both_performed() {
+        let (mut network, mut n2s_rx) = network_task();
+        let client = add_test_client(&mut network, ClientKind::Unix);
+
+        for _ in 0..2 {
+            let key = Some(IdempotencyKey::generate());
+            let task_id = TaskId::generate();
+            network.handle_c2s_perform_operation(client.id, task_id, Operation::Start, key);
+        }
+        for _ in 0..2 {
+            let message = n2s_rx.try_recv().unwrap();
+            assert!(matches!(
+                message,
+                NetworkToServerMessage::PerformOperation(Operation::Start, ..)
+            ));
+        }
+    }
+
+    #[test]
+    fn only_clients_which_asked_for_it_get_compressed_output() {
+        let (mut network, _n2s_rx) = network_task();
+        let mut plain = add_test_client(&mut network, ClientKind::Tcp);
+        let mut compressing = add_test_client(&mut network, ClientKind::Tcp);
+        network.clients[compressing.id.0].compress_output = true;
+
+        let output = b"[Server thread/INFO]: Preparing spawn area: 0%\n".repeat(64);
+        let message = raphy_protocol::ServerToClientMessage::Stdout(output.clone());
+        network.broadcast_output(message);
+
+        assert!(matches!(
+            plain.bulk.try_recv(),
+            Ok(raphy_protocol::ServerToClientMessage::Stdout(sent)) if sent == output
+        ));
+        let compressed = compressing.bulk.try_recv().unwrap();
+        assert!(matches!(
+            compressed,
+            raphy_protocol::ServerToClientMessage::StdoutCompressed(_)
+        ));
+        assert!(matches!(
+            compressed.decompressed(),
+            Ok(raphy_protocol::ServerToClientMessage::Stdout(sent)) if sent == output
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_client_may_not_open_more_streams_than_its_limit() {
+        let (mut network, mut n2s_rx) = network_task();
+        network.max_streams_per_client = 2;
+        let mut client = add_test_client(&mut network, ClientKind::Tcp);
+
+        // the backups are kept running by holding on to what the server task was sent
+        let mut backups = Vec::new();
+        for _ in 0..2 {
+            network.handle_c2s_backup_world(client.id, TaskId::generate());
+            let Some(NetworkToServerMessage::BackupWorld(progress_tx, ret)) = n2s_rx.recv().await
+            else {
+                panic!("the backup wasn't sent to the server task");
+            };
+            backups.push((progress_tx, ret));
+        }
+
+        let task_id = TaskId::generate();
+        network.handle_c2s_backup_world(client.id, task_id);
+        assert!(n2s_rx.try_recv().is_err());
+        let Ok(raphy_protocol::ServerToClientMessage::Error(error, Some(refused))) =
+            client.control.try_recv()
+        else {
+            panic!("the client wasn't told that it has too many streams open");
+        };
+        assert_eq!(refused, task_id);
+        assert_eq!(error.kind(), Some(&ErrorKind::TooManyStreams(2)));
+
+        // a stream which finished makes room for another
+        drop(backups.pop());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        network.handle_c2s_backup_world(client.id, TaskId::generate());
+        assert!(matches!(
+            n2s_rx.try_recv(),
+            Ok(NetworkToServerMessage::BackupWorld(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn destroying_a_client_closes_its_streams() {
+        let (mut network, mut n2s_rx) = network_task();
+        let client = add_test_client(&mut network, ClientKind::Tcp);
+
+        network.handle_c2s_backup_world(client.id, TaskId::generate());
+        let Some(NetworkToServerMessage::BackupWorld(progress_tx, _ret)) = n2s_rx.recv().await
+        else {
+            panic!("the backup wasn't sent to the server task");
+        };
+
+        network.destroy_client(client.id);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(progress_tx.is_closed());
+    }
+}
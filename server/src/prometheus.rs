@@ -0,0 +1,80 @@
+use crate::base::MetricsSample;
+use raphy_protocol::ServerState;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// everything that goes into the exported metrics
+pub struct Snapshot {
+    pub sample: MetricsSample,
+    pub state: ServerState,
+    pub clients: usize,
+
+    /// how long the raphy server has been running
+    pub uptime: Duration,
+}
+
+fn metric(text: &mut String, name: &str, kind: &str, help: &str, value: impl Into<f64>) {
+    // writing to a string can't fail
+    writeln!(text, "# HELP {name} {help}").unwrap();
+    writeln!(text, "# TYPE {name} {kind}").unwrap();
+    writeln!(text, "{name} {}", value.into()).unwrap();
+}
+
+/// renders the metrics in the prometheus text exposition format.
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut text = String::new();
+
+    metric(
+        &mut text,
+        "raphy_uptime_seconds",
+        "gauge",
+        "How long the raphy server has been running.",
+        snapshot.uptime.as_secs_f64(),
+    );
+    metric(
+        &mut text,
+        "raphy_clients",
+        "gauge",
+        "How many clients are connected.",
+        snapshot.clients as f64,
+    );
+    metric(
+        &mut text,
+        "raphy_server_running",
+        "gauge",
+        "Whether the minecraft server is running.",
+        u8::from(matches!(snapshot.state, ServerState::Started)),
+    );
+
+    // measurements which aren't known, e.g. because the server is stopped, are left out rather than
+    // reported as zero
+    if let Some(rss_bytes) = snapshot.sample.rss_bytes {
+        metric(
+            &mut text,
+            "raphy_server_resident_memory_bytes",
+            "gauge",
+            "The resident memory of the minecraft server process.",
+            rss_bytes as f64,
+        );
+    }
+    if let Some(cpu_time) = snapshot.sample.cpu_time {
+        metric(
+            &mut text,
+            "raphy_server_cpu_seconds_total",
+            "counter",
+            "The cpu time the minecraft server process used.",
+            cpu_time.as_secs_f64(),
+        );
+    }
+    if let Some(disk_free) = snapshot.sample.disk_free {
+        metric(
+            &mut text,
+            "raphy_disk_free_bytes",
+            "gauge",
+            "The free space on the disk of the working directory.",
+            disk_free as f64,
+        );
+    }
+
+    text
+}
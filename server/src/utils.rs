@@ -1,27 +1,1165 @@
 use anyhow::Context;
+use chrono::{Local, NaiveDateTime};
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use mdns_sd::{ServiceDaemon, ServiceInfo};
+use nix::fcntl::OFlag;
+use nix::sys::statvfs;
+use nix::unistd::{Pid, SysconfVar, Uid, geteuid};
+use raphy_common::ConfigLike;
+use raphy_protocol::config::{Arguments, LaunchMode, User};
+use raphy_protocol::{
+    BackupProgress, BackupUsage, Config, CrashReport, JavaVersion, ResolvedPaths, UserTestOutcome,
+};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
 
-pub fn start_advertising(port: u16) -> anyhow::Result<()> {
-    tracing::info!("create mdns service daemon");
-    let mdns = ServiceDaemon::new().context("Failed to create mDNS service daemon.")?;
-    let service_info = ServiceInfo::new(
+/// how long server state changes are coalesced before the mdns advertisement is updated, unless
+/// overridden with `RAPHY_MDNS_MIN_INTERVAL_MS`
+const DEFAULT_ADVERTISEMENT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// the name of the instance this daemon runs as, if it isn't the default one
+static INSTANCE: OnceLock<Option<String>> = OnceLock::new();
+
+/// reads the instance this daemon runs as from [`raphy_protocol::INSTANCE_ENV_VAR`]. a named
+/// instance needs its own config path, since it would share the default instance's otherwise.
+pub fn init_instance() -> anyhow::Result<()> {
+    let instance = match env::var(raphy_protocol::INSTANCE_ENV_VAR) {
+        Ok(name) => {
+            raphy_protocol::validate_instance_name(&name)?;
+            anyhow::ensure!(
+                env::var_os("RAPHY_CONFIG_PATH").is_some(),
+                "The instance '{name}' needs its own configuration path in `RAPHY_CONFIG_PATH`."
+            );
+            tracing::info!(name, "running as a named instance");
+            Some(name)
+        }
+        Err(_) => None,
+    };
+    INSTANCE.set(instance).ok();
+
+    Ok(())
+}
+
+/// the name of the instance this daemon runs as, or `None` for the default one
+pub fn instance() -> Option<&'static str> {
+    INSTANCE.get().and_then(Option::as_deref)
+}
+
+/// the unix socket of the instance this daemon runs as
+pub fn unix_socket_path() -> PathBuf {
+    raphy_protocol::unix_socket_path(instance())
+}
+
+/// the mdns daemon advertising this server, along with what's needed to re-register it
+static ADVERTISEMENT: OnceLock<Advertisement> = OnceLock::new();
+
+struct Advertisement {
+    mdns: ServiceDaemon,
+    fullname: String,
+    port: u16,
+    game_port: Option<u16>,
+}
+
+/// how long sudo may take when testing a user, in case it waits on something despite `-n`
+const USER_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// checks whether processes can be launched as `user` by running `true` the same way the server is
+/// launched, but with `sudo -n` so that it fails rather than hangs if a password is required.
+pub async fn test_user(user: &User) -> UserTestOutcome {
+    let Some(mut command) = user.make_command() else {
+        return UserTestOutcome::Ok;
+    };
+
+    if let Some(name) = user.resolve() {
+        match nix::unistd::User::from_name(name) {
+            Ok(Some(_)) => {}
+            Ok(None) => return UserTestOutcome::NoSuchUser,
+            Err(error) => tracing::warn!(name, "failed to look up the user: {error}"),
+        }
+    }
+
+    command
+        .args(["-n", "true"])
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = match tokio::time::timeout(USER_TEST_TIMEOUT, command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(error)) => return UserTestOutcome::Failed(format!("Failed to run sudo: {error}")),
+        Err(_) => {
+            return UserTestOutcome::Failed(format!(
+                "sudo didn't finish within {USER_TEST_TIMEOUT:?}."
+            ));
+        }
+    };
+    if output.status.success() {
+        return UserTestOutcome::Ok;
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("password is required") {
+        UserTestOutcome::PasswordRequired
+    } else if stderr.contains("unknown user") {
+        UserTestOutcome::NoSuchUser
+    } else if stderr.contains("not allowed") || stderr.contains("not in the sudoers") {
+        UserTestOutcome::NotPermitted
+    } else {
+        UserTestOutcome::Failed(stderr.trim().to_owned())
+    }
+}
+
+/// runs the backup command in the working directory as the same user the server is launched as,
+/// so that whatever it creates is owned by them.
+pub async fn run_backup(command: &Arguments, user: &User, working_dir: &Path) -> anyhow::Result<()> {
+    let arguments = command.resolve()?;
+    let (program, arguments) = arguments
+        .split_first()
+        .context("The backup command is empty.")?;
+
+    let mut command = match user.make_command() {
+        Some(mut command) => {
+            command.arg(program);
+            command
+        }
+        None => tokio::process::Command::new(program),
+    };
+    let status = command
+        .args(arguments)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .with_context(|| format!("Failed to run the backup command '{program}'."))?;
+
+    tracing::info!(%status, "the backup command exited");
+    anyhow::ensure!(status.success(), "The backup command failed with {status}.");
+    Ok(())
+}
+
+/// the `strftime` format of the time in the name of a world archive
+const ARCHIVE_TIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// how long a time formatted with [`ARCHIVE_TIME_FORMAT`] is
+const ARCHIVE_TIME_LEN: usize = "0000-00-00_00-00-00".len();
+
+/// whether `name` is that of an archive written by [`archive_world`], i.e.
+/// `<world>-<time>.tar.gz`. nothing else in the backup directory is taken to be a backup, so that
+/// pruning can't delete anything else.
+fn is_world_archive(name: &OsStr) -> bool {
+    let Some(stem) = name.to_str().and_then(|name| name.strip_suffix(".tar.gz")) else {
+        return false;
+    };
+    let Some(split) = stem.len().checked_sub(ARCHIVE_TIME_LEN) else {
+        return false;
+    };
+    if !stem.is_char_boundary(split) {
+        return false;
+    }
+
+    // the world's name may contain dashes itself, but the time has a fixed length
+    let (world, time) = stem.split_at(split);
+    world.len() > 1
+        && world.ends_with('-')
+        && NaiveDateTime::parse_from_str(time, ARCHIVE_TIME_FORMAT).is_ok()
+}
+
+/// the world archives in the backup directory, newest first by modification time. directories and
+/// symlinks are never taken to be backups.
+fn backups(backup_dir: &Path) -> anyhow::Result<Vec<(PathBuf, fs::Metadata)>> {
+    let entries = fs::read_dir(backup_dir).with_context(|| {
+        format!("Failed to read the backup directory '{}'.", backup_dir.display())
+    })?;
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry.context("Failed to read an entry of the backup directory.")?;
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("Failed to read '{}'.", entry.path().display()))?;
+        if !file_type.is_file() || !is_world_archive(&entry.file_name()) {
+            continue;
+        }
+
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("Failed to read '{}'.", entry.path().display()))?;
+        backups.push((entry.path(), metadata));
+    }
+
+    // entries without a modification time are taken to be the oldest, so they're pruned first
+    backups.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.modified().ok()));
+    Ok(backups)
+}
+
+/// deletes all but the newest `retention` backups, returning how many were deleted
+pub fn prune_backups(backup_dir: &Path, retention: usize) -> anyhow::Result<usize> {
+    let backups = backups(backup_dir)?;
+    let mut pruned = 0;
+    for (path, _) in backups.into_iter().skip(retention) {
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to delete the backup '{}'.", path.display()))?;
+        tracing::info!(?path, "deleted an old backup");
+        pruned += 1;
+    }
+
+    Ok(pruned)
+}
+
+/// archives the world into a new timestamped `.tar.gz` in `backup_dir`, calling `progress` as its
+/// files are added. returns the path of the archive.
+pub fn archive_world(
+    world_dir: &Path,
+    backup_dir: &Path,
+    progress: impl FnMut(BackupProgress),
+) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(backup_dir).with_context(|| {
+        format!(
+            "Failed to create the backup directory '{}'.",
+            backup_dir.display()
+        )
+    })?;
+
+    // the backups may be kept inside the world, which mustn't end up in each new one
+    let backup_dir_path = fs::canonicalize(backup_dir).with_context(|| {
+        format!(
+            "Failed to resolve the backup directory '{}'.",
+            backup_dir.display()
+        )
+    })?;
+    let mut files = Vec::new();
+    world_files(world_dir, Path::new(""), &backup_dir_path, &mut files).with_context(|| {
+        format!(
+            "Failed to read the world directory '{}'.",
+            world_dir.display()
+        )
+    })?;
+
+    let name = world_dir.file_name().unwrap_or(OsStr::new("world"));
+    let archive_path = backup_dir.join(format!(
+        "{}-{}.tar.gz",
+        name.to_string_lossy(),
+        Local::now().format(ARCHIVE_TIME_FORMAT)
+    ));
+    let result = write_world_archive(world_dir, name, &files, &archive_path, progress);
+
+    // an incomplete archive would be mistaken for a backup
+    if result.is_err() {
+        fs::remove_file(&archive_path).ok();
+    }
+    result.map(|()| archive_path)
+}
+
+/// copies the jar into a new timestamped `.jar` in `backup_dir`, returning the path of the copy.
+/// it isn't a world archive, so pruning leaves it alone.
+pub fn back_up_jar(jar: &Path, backup_dir: &Path) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(backup_dir).with_context(|| {
+        format!(
+            "Failed to create the backup directory '{}'.",
+            backup_dir.display()
+        )
+    })?;
+
+    let name = jar.file_stem().unwrap_or(OsStr::new("server"));
+    let backup = backup_dir.join(format!(
+        "{}-{}.jar",
+        name.to_string_lossy(),
+        Local::now().format(ARCHIVE_TIME_FORMAT)
+    ));
+    fs::copy(jar, &backup)
+        .with_context(|| format!("Failed to back up the server jar '{}'.", jar.display()))?;
+    Ok(backup)
+}
+
+/// the paths of the files in `dir`, relative to the world directory. `backup_dir` is skipped.
+fn world_files(
+    dir: &Path,
+    relative: &Path,
+    backup_dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if fs::canonicalize(entry.path())? == backup_dir {
+                continue;
+            }
+
+            world_files(&entry.path(), &relative, backup_dir, files)?;
+        } else if file_type.is_file() {
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_world_archive(
+    world_dir: &Path,
+    name: &OsStr,
+    files: &[PathBuf],
+    archive_path: &Path,
+    mut progress: impl FnMut(BackupProgress),
+) -> anyhow::Result<()> {
+    let file = fs::File::create(archive_path).with_context(|| {
+        format!("Failed to create the archive '{}'.", archive_path.display())
+    })?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    // reported about every percent, so that large worlds don't flood clients with updates
+    let total_files = files.len() as u64;
+    let step = (total_files / 100).max(1);
+    for (index, file) in files.iter().enumerate() {
+        let path = world_dir.join(file);
+        match archive.append_path_with_name(&path, Path::new(name).join(file)) {
+            Ok(()) => {}
+
+            // e.g. a temporary file the server deleted in the meantime
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                tracing::warn!(?path, "a file of the world was deleted while backing it up");
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to archive '{}'.", path.display()));
+            }
+        }
+
+        let archived_files = index as u64 + 1;
+        if archived_files.is_multiple_of(step) || archived_files == total_files {
+            progress(BackupProgress {
+                archived_files,
+                total_files,
+            });
+        }
+    }
+
+    archive
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .and_then(|file| file.sync_all())
+        .context("Failed to finish the archive.")
+}
+
+/// counts the backups and adds up their sizes
+pub fn backup_usage(backup_dir: &Path) -> anyhow::Result<BackupUsage> {
+    let backups = backups(backup_dir)?;
+    Ok(BackupUsage {
+        count: backups.len(),
+        bytes: backups.iter().map(|(_, metadata)| metadata.len()).sum(),
+    })
+}
+
+/// how long `java -version` may take before giving up on it
+const JAVA_VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the version of the java at `java_path`
+pub async fn java_version(java_path: &Path) -> anyhow::Result<JavaVersion> {
+    let version = raphy_protocol::java_version(java_path);
+    tokio::time::timeout(JAVA_VERSION_TIMEOUT, version)
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!("Java didn't report its version within {JAVA_VERSION_TIMEOUT:?}.")
+        })?
+}
+
+/// how much of a crash report is read at most, which is plenty for its description and stack trace
+const MAX_CRASH_REPORT_LEN: u64 = 64 * 1024;
+
+/// the newest report in the `crash-reports` directory the server writes when it crashes, if any
+pub fn last_crash_report(working_dir: &Path) -> anyhow::Result<Option<CrashReport>> {
+    let dir = working_dir.join("crash-reports");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!("Failed to read the crash reports in '{}'.", dir.display())
+            });
+        }
+    };
+
+    let mut newest: Option<(PathBuf, fs::Metadata)> = None;
+    for entry in entries {
+        let entry = entry.context("Failed to read an entry of the crash reports directory.")?;
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("Failed to read '{}'.", entry.path().display()))?;
+        let is_report = entry.file_name().to_string_lossy().starts_with("crash-");
+        if !metadata.is_file() || !is_report {
+            continue;
+        }
+
+        let is_newer = newest
+            .as_ref()
+            .is_none_or(|(_, newest)| metadata.modified().ok() > newest.modified().ok());
+        if is_newer {
+            newest = Some((entry.path(), metadata));
+        }
+    }
+
+    let Some((path, metadata)) = newest else {
+        return Ok(None);
+    };
+    let mut contents = Vec::new();
+    fs::File::open(&path)
+        .and_then(|file| file.take(MAX_CRASH_REPORT_LEN).read_to_end(&mut contents))
+        .with_context(|| format!("Failed to read the crash report '{}'.", path.display()))?;
+
+    Ok(Some(CrashReport {
+        written_at: metadata.modified().unwrap_or(std::time::UNIX_EPOCH),
+        contents: String::from_utf8_lossy(&contents).into_owned(),
+        truncated: metadata.len() > MAX_CRASH_REPORT_LEN,
+        path,
+    }))
+}
+
+/// the fields of `/proc/<pid>/stat`
+struct ProcStat {
+    path: String,
+    fields: Vec<String>,
+}
+
+impl ProcStat {
+    fn read(pid: Pid) -> anyhow::Result<Self> {
+        let path = format!("/proc/{pid}/stat");
+        let stat =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read '{path}'."))?;
+
+        // the name of the process is in parentheses and may contain spaces, so the fields are
+        // counted from the end of it. the first field after it is the third one.
+        let fields = stat
+            .rsplit_once(')')
+            .map(|(_, fields)| fields.split_whitespace().map(str::to_owned).collect())
+            .unwrap_or_default();
+        Ok(Self { path, fields })
+    }
+
+    fn field(&self, number: usize) -> anyhow::Result<&str> {
+        let path = &self.path;
+        self.fields
+            .get(number - 3)
+            .map(String::as_str)
+            .with_context(|| format!("'{path}' has no field {number}."))
+    }
+
+    fn numeric_field(&self, number: usize) -> anyhow::Result<u64> {
+        let path = &self.path;
+        self.field(number)?
+            .parse()
+            .with_context(|| format!("Failed to parse field {number} of '{path}'."))
+    }
+}
+
+/// the resident memory in bytes and the cpu time a process used so far, read from `/proc`.
+pub fn process_usage(pid: Pid) -> anyhow::Result<(u64, Duration)> {
+    let stat = ProcStat::read(pid)?;
+    let sysconf = |var| -> anyhow::Result<u64> {
+        nix::unistd::sysconf(var)
+            .ok()
+            .flatten()
+            .map(|value| value as u64)
+            .with_context(|| format!("Failed to get {var:?}."))
+    };
+    let ticks_per_second = sysconf(SysconfVar::CLK_TCK)?;
+    let page_size = sysconf(SysconfVar::PAGE_SIZE)?;
+
+    // utime and stime, in clock ticks
+    let ticks = stat.numeric_field(14)? + stat.numeric_field(15)?;
+    let cpu_time = Duration::from_secs_f64(ticks as f64 / ticks_per_second as f64);
+    let rss = stat.numeric_field(24)? * page_size;
+    Ok((rss, cpu_time))
+}
+
+/// identifies a server process across runs of raphy, since its pid alone could belong to an
+/// unrelated process by then
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProcessIdentity {
+    pub pid: Pid,
+
+    /// in clock ticks since boot
+    start_time: u64,
+
+    /// the kernel's id of the boot the process started in, since start times repeat across boots
+    /// if `/tmp` isn't cleared
+    boot_id: String,
+
+    /// tells the server process apart from others which happen to match otherwise
+    cmdline: Vec<u8>,
+}
+
+impl ProcessIdentity {
+    /// reads the identity of a running process from `/proc`
+    pub fn of(pid: Pid) -> anyhow::Result<Self> {
+        let start_time = ProcStat::read(pid)?.numeric_field(22)?;
+        let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .context("Failed to read the boot id.")?
+            .trim()
+            .to_owned();
+        let cmdline_path = format!("/proc/{pid}/cmdline");
+        let cmdline =
+            fs::read(&cmdline_path).with_context(|| format!("Failed to read '{cmdline_path}'."))?;
+
+        Ok(Self {
+            pid,
+            start_time,
+            boot_id,
+            cmdline,
+        })
+    }
+
+    /// whether the process was launched with the command. its arguments have to match, and only be
+    /// preceded by those of an interpreter, in case the program is a script.
+    pub fn launched_with(&self, command: &std::process::Command) -> bool {
+        let expected = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(OsStr::as_bytes)
+            .collect::<Vec<_>>();
+        let args = self
+            .cmdline
+            .strip_suffix(b"\0")
+            .unwrap_or(&self.cmdline)
+            .split(|&byte| byte == 0)
+            .collect::<Vec<_>>();
+        args.ends_with(&expected)
+    }
+
+    /// whether the process is still running. an exited process which wasn't reaped yet doesn't
+    /// count, since its command line is empty.
+    pub fn is_running(&self) -> bool {
+        Self::of(self.pid).is_ok_and(|current| current == *self)
+    }
+}
+
+/// the real user id of a process, read from `/proc/<pid>/status`
+pub fn process_uid(pid: Pid) -> anyhow::Result<Uid> {
+    let path = format!("/proc/{pid}/status");
+    let status = fs::read_to_string(&path).with_context(|| format!("Failed to read '{path}'."))?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|uids| uids.split_whitespace().next())
+        .and_then(|uid| uid.parse().ok())
+        .map(Uid::from_raw)
+        .with_context(|| format!("'{path}' has no user id."))
+}
+
+/// the file the running server process is recorded in, so that the next run of raphy can adopt it.
+/// see [`raphy_protocol::config::ChildExitPolicy::Leave`]. it's kept in the per-user runtime
+/// directory, since anyone could plant a file where raphy would read or write it in a shared one
+/// like `/tmp`.
+fn pid_file_path() -> anyhow::Result<PathBuf> {
+    let dir = raphy_common::runtime_dir()
+        .context("There's no per-user directory to keep the pid file in.")?;
+    let name = match instance() {
+        Some(name) => format!("server-{name}.pid"),
+        None => "server.pid".to_owned(),
+    };
+    Ok(dir.join(name))
+}
+
+/// fails unless the file or directory is owned by this user and others can't change it
+fn check_private(path: &Path, metadata: &fs::Metadata) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        metadata.uid() == geteuid().as_raw() && metadata.mode() & 0o022 == 0,
+        "'{}' isn't owned by this user, or others can change it.",
+        path.display()
+    );
+    Ok(())
+}
+
+/// records the server process. the file holds the pid and start time, the boot id and the command
+/// line, each on its own line.
+pub fn write_pid_file(identity: &ProcessIdentity) -> anyhow::Result<()> {
+    write_pid_file_at(&pid_file_path()?, identity)
+}
+
+fn write_pid_file_at(path: &Path, identity: &ProcessIdentity) -> anyhow::Result<()> {
+    let mut contents = format!(
+        "{} {}\n{}\n",
+        identity.pid, identity.start_time, identity.boot_id
+    )
+    .into_bytes();
+    contents.extend_from_slice(&identity.cmdline);
+
+    let dir = path
+        .parent()
+        .context("The pid file has no parent directory.")?;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create '{}'.", dir.display()))?;
+    let metadata =
+        fs::metadata(dir).with_context(|| format!("Failed to read '{}'.", dir.display()))?;
+    check_private(dir, &metadata)?;
+
+    // a file left behind is replaced rather than written through, since it could be a symlink
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!("Failed to remove the old pid file at '{}'.", path.display())
+            });
+        }
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(&contents))
+        .with_context(|| format!("Failed to write the pid file at '{}'.", path.display()))
+}
+
+pub fn remove_pid_file() {
+    let path = match pid_file_path() {
+        Ok(path) => path,
+        Err(error) => {
+            tracing::warn!("failed to remove the pid file: {error:#}");
+            return;
+        }
+    };
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => tracing::warn!(?path, "failed to remove the pid file: {error}"),
+    }
+}
+
+/// the server process recorded in the pid file, if there is one. it may not be running anymore.
+pub fn read_pid_file() -> anyhow::Result<Option<ProcessIdentity>> {
+    read_pid_file_at(&pid_file_path()?)
+}
+
+fn read_pid_file_at(path: &Path) -> anyhow::Result<Option<ProcessIdentity>> {
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(OFlag::O_NOFOLLOW.bits())
+        .open(path);
+    let mut file = match file {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Failed to read the pid file at '{}'.", path.display()));
+        }
+    };
+
+    // a pid file someone else could have written can't be trusted to name the server
+    let metadata = file
+        .metadata()
+        .with_context(|| format!("Failed to read the pid file at '{}'.", path.display()))?;
+    check_private(path, &metadata)?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)
+        .with_context(|| format!("Failed to read the pid file at '{}'.", path.display()))?;
+
+    // the command line comes last, since it may contain line breaks itself
+    let parse = || -> Option<ProcessIdentity> {
+        let mut lines = contents.splitn(3, |&byte| byte == b'\n');
+        let first = std::str::from_utf8(lines.next()?).ok()?;
+        let boot_id = std::str::from_utf8(lines.next()?).ok()?;
+        let cmdline = lines.next()?;
+
+        let (pid, start_time) = first.split_once(' ')?;
+        Some(ProcessIdentity {
+            pid: Pid::from_raw(pid.parse().ok()?),
+            start_time: start_time.parse().ok()?,
+            boot_id: boot_id.to_owned(),
+            cmdline: cmdline.to_vec(),
+        })
+    };
+    parse()
+        .map(Some)
+        .with_context(|| format!("The pid file at '{}' is malformed.", path.display()))
+}
+
+/// how many bytes are available to unprivileged users on the disk the path is on.
+pub fn disk_free(path: &Path) -> anyhow::Result<u64> {
+    let stat = statvfs::statvfs(path)
+        .with_context(|| format!("Failed to get the file system statistics of '{}'.", path.display()))?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// makes the path absolute, resolving symlinks if it exists.
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// where the config points to, as absolute paths.
+pub fn resolved_paths(config: &Config) -> ResolvedPaths {
+    let (server_jar_path, launch_script) = match &config.launch_mode {
+        LaunchMode::Java => (Some(absolute(&config.server_jar_path)), None),
+        LaunchMode::Script(script) => (None, Some(absolute(script))),
+    };
+
+    ResolvedPaths {
+        working_dir: absolute(config.working_dir()),
+        server_jar_path,
+        launch_script,
+        java_path: config.java_path.resolve().map(|path| absolute(&path)),
+    }
+}
+
+/// hashes the config the way it's written to disk, so that a file which only differs in its
+/// formatting or in fields left at their defaults hashes the same
+fn config_hash(config: &Config) -> Option<u64> {
+    let contents = serde_json::to_string(config).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// whether the config file differs from `config`, the config in use, e.g. because it was edited by
+/// hand. raphy only reads the file when it starts, so such edits aren't applied until then.
+pub async fn config_changed_on_disk(config: Option<&Config>) -> bool {
+    match Config::load().await {
+        Ok(on_disk) => on_disk.as_ref().and_then(config_hash) != config.and_then(config_hash),
+        Err(error) => {
+            // a file which can't be read or parsed can't be what raphy is using
+            tracing::warn!("failed to read the config file to compare it: {error:#}");
+            true
+        }
+    }
+}
+
+/// the magic number a jar starts with, since it's a zip archive
+const JAR_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// resolves a jar given relative to the working directory, making sure it looks like a jar and
+/// doesn't escape the working directory.
+pub fn resolve_jar(working_dir: &Path, relative_path: &Path) -> anyhow::Result<PathBuf> {
+    let working_dir = working_dir.canonicalize().with_context(|| {
+        format!("Failed to resolve the working directory '{}'.", working_dir.display())
+    })?;
+    let path = working_dir
+        .join(relative_path)
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve the jar '{}'.", relative_path.display()))?;
+
+    // the working directory is the one containing the jar, so a jar in a subdirectory would move it
+    if path.parent() != Some(working_dir.as_path()) {
+        anyhow::bail!("The jar must be in the server's working directory.");
+    }
+
+    let mut magic = [0; 4];
+    fs::File::open(&path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .with_context(|| format!("Failed to read the jar '{}'.", path.display()))?;
+    if &magic != JAR_MAGIC {
+        anyhow::bail!("'{}' is not a jar.", path.display());
+    }
+
+    Ok(path)
+}
+
+/// reads the `server-port` property from the `server.properties` file in the working directory.
+pub fn read_game_port(working_dir: &Path) -> Option<u16> {
+    let path = working_dir.join("server.properties");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            tracing::debug!(?path, "failed to read server properties: {error}");
+            return None;
+        }
+    };
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| line.split_once(['=', ':']))
+        .find(|(key, _)| key.trim() == "server-port")
+        .and_then(|(_, value)| match value.trim().parse() {
+            Ok(port) => Some(port),
+            Err(error) => {
+                tracing::warn!(?path, ?value, "failed to parse the server port: {error}");
+                None
+            }
+        })
+}
+
+fn service_info(port: u16, game_port: Option<u16>, running: bool) -> ServiceInfo {
+    let mut properties = HashMap::from([(
+        "state".to_owned(),
+        if running { "running" } else { "stopped" }.to_owned(),
+    )]);
+    if let Some(game_port) = game_port {
+        properties.insert("game_port".to_owned(), game_port.to_string());
+    }
+
+    // named instances are advertised separately, so that they don't conflict with each other
+    let instance_name = match instance() {
+        Some(name) => {
+            properties.insert("instance".to_owned(), name.to_owned());
+            format!("{}-{name}", raphy_protocol::INSTANCE_NAME)
+        }
+        None => raphy_protocol::INSTANCE_NAME.to_owned(),
+    };
+
+    ServiceInfo::new(
         raphy_protocol::SERVICE_TYPE,
-        raphy_protocol::INSTANCE_NAME,
-        &format!(
-            "{}.{}",
-            raphy_protocol::INSTANCE_NAME,
-            raphy_protocol::SERVICE_TYPE
-        ),
+        &instance_name,
+        &format!("{instance_name}.{}", raphy_protocol::SERVICE_TYPE),
         "",
         port,
-        None,
+        properties,
     )
     .expect("service info was invalid")
-    .enable_addr_auto();
+    .enable_addr_auto()
+}
+
+pub fn start_advertising(port: u16, game_port: Option<u16>) -> anyhow::Result<()> {
+    tracing::info!("create mdns service daemon");
+    let mdns = ServiceDaemon::new().context("Failed to create mDNS service daemon.")?;
+    let service_info = service_info(port, game_port, false);
 
     tracing::info!("register service info with mdns");
+    let fullname = service_info.get_fullname().to_owned();
     mdns.register(service_info)
         .context("Failed to register service info with mDNS.")?;
+    ADVERTISEMENT
+        .set(Advertisement {
+            mdns,
+            fullname,
+            port,
+            game_port,
+        })
+        .ok();
 
     Ok(())
 }
+
+/// re-registers the mdns service so that clients see whether the minecraft server is running.
+pub fn update_advertisement(running: bool) -> anyhow::Result<()> {
+    let Some(advertisement) = ADVERTISEMENT.get() else {
+        return Ok(());
+    };
+
+    tracing::debug!(running, "update service info with mdns");
+    advertisement
+        .mdns
+        .register(service_info(
+            advertisement.port,
+            advertisement.game_port,
+            running,
+        ))
+        .context("Failed to update service info with mDNS.")
+}
+
+/// the minimum interval between mdns advertisement updates, from `RAPHY_MDNS_MIN_INTERVAL_MS`.
+pub fn advertisement_interval() -> Duration {
+    let Ok(value) = env::var("RAPHY_MDNS_MIN_INTERVAL_MS") else {
+        return DEFAULT_ADVERTISEMENT_INTERVAL;
+    };
+
+    match value.parse() {
+        Ok(millis) => Duration::from_millis(millis),
+        Err(error) => {
+            tracing::warn!(?value, "failed to parse `RAPHY_MDNS_MIN_INTERVAL_MS`: {error}");
+            DEFAULT_ADVERTISEMENT_INTERVAL
+        }
+    }
+}
+
+/// unregisters the mdns service, if it was registered, so clients stop discovering this server.
+pub fn stop_advertising() {
+    let Some(Advertisement { mdns, fullname, .. }) = ADVERTISEMENT.get() else {
+        return;
+    };
+
+    tracing::info!("unregister service info with mdns");
+    match mdns.unregister(fullname) {
+        // give the daemon a moment to announce that the service is going away
+        Ok(rx) => {
+            rx.recv_timeout(Duration::from_secs(1)).ok();
+        }
+        Err(error) => tracing::warn!("failed to unregister service info with mdns: {error}"),
+    }
+
+    mdns.shutdown().ok();
+}
+
+/// points [`Config::path`] at an empty directory only the tests use, returning the path. the path
+/// is shared by every test, so it's locked until the returned guard is dropped.
+#[cfg(test)]
+pub async fn lock_test_config_path() -> (tokio::sync::MutexGuard<'static, ()>, PathBuf) {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+    let guard = LOCK.lock().await;
+    let path = PATH.get_or_init(|| {
+        let path = env::temp_dir()
+            .join(format!("raphy-test-config-{}", std::process::id()))
+            .join("config.json");
+        // SAFETY: no test reads the config path without holding the lock
+        unsafe { env::set_var(Config::ENV_VAR, &path) };
+        path
+    });
+    if let Some(dir) = path.parent() {
+        fs::remove_dir_all(dir).ok();
+    }
+
+    (guard, path.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::SystemTime;
+
+    /// a fresh directory for a test, which is removed again once it's dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("raphy-test-{name}-{}", std::process::id()));
+            fs::remove_dir_all(&path).ok();
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    /// creates an empty file in `dir`, last modified `age_secs` ago
+    fn create_file(dir: &Path, name: &str, age_secs: u64) -> PathBuf {
+        let path = dir.join(name);
+        let file = fs::File::create(&path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+        path
+    }
+
+    fn server_identity() -> ProcessIdentity {
+        ProcessIdentity {
+            pid: Pid::from_raw(4242),
+            start_time: 1234,
+            boot_id: "6f3b0c1e-0000-4000-8000-000000000000".to_owned(),
+            cmdline: b"java\0-jar\0server.jar\0nogui\0".to_vec(),
+        }
+    }
+
+    #[test]
+    fn identity_matches_the_command_it_was_launched_with() {
+        let mut command = std::process::Command::new("java");
+        command.args(["-jar", "server.jar", "nogui"]);
+        assert!(server_identity().launched_with(&command));
+
+        command.arg("--port=25566");
+        assert!(!server_identity().launched_with(&command));
+
+        // a script is run by its interpreter, which comes first
+        let identity = ProcessIdentity {
+            cmdline: b"/bin/sh\0/srv/minecraft/start.sh\0nogui\0".to_vec(),
+            ..server_identity()
+        };
+        let mut command = std::process::Command::new("/srv/minecraft/start.sh");
+        command.arg("nogui");
+        assert!(identity.launched_with(&command));
+    }
+
+    #[test]
+    fn reads_the_user_of_a_process() {
+        let uid = process_uid(nix::unistd::getpid()).unwrap();
+        assert_eq!(uid, nix::unistd::getuid());
+    }
+
+    #[test]
+    fn pid_file_round_trips() {
+        let dir = TempDir::new("pid-file");
+        let path = dir.0.join("server.pid");
+        write_pid_file_at(&path, &server_identity()).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(read_pid_file_at(&path).unwrap(), Some(server_identity()));
+    }
+
+    #[test]
+    fn pid_file_replaces_a_symlink_rather_than_following_it() {
+        let dir = TempDir::new("pid-file-symlink");
+        let target = create_file(&dir.0, "victim", 0);
+        let path = dir.0.join("server.pid");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        // a planted symlink isn't read through either
+        assert!(read_pid_file_at(&path).is_err());
+
+        write_pid_file_at(&path, &server_identity()).unwrap();
+        assert!(fs::read(&target).unwrap().is_empty());
+        assert!(!fs::symlink_metadata(&path).unwrap().is_symlink());
+    }
+
+    #[test]
+    fn pid_file_others_can_change_is_distrusted() {
+        let dir = TempDir::new("pid-file-writable");
+        let path = dir.0.join("server.pid");
+        write_pid_file_at(&path, &server_identity()).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+
+        assert!(read_pid_file_at(&path).is_err());
+    }
+
+    #[test]
+    fn recognizes_world_archives() {
+        for name in [
+            "world-2026-10-16_14-44-32.tar.gz",
+            "my-world-2026-10-16_14-44-32.tar.gz",
+        ] {
+            assert!(is_world_archive(OsStr::new(name)), "{name} wasn't recognized");
+        }
+
+        for name in [
+            "-2026-10-16_14-44-32.tar.gz",
+            "world2026-10-16_14-44-32.tar.gz",
+            "world-2026-10-16.tar.gz",
+            "world-2026-10-16_14-44-32.zip",
+            "world-2026-13-16_14-44-32.tar.gz",
+            "server.jar",
+        ] {
+            assert!(!is_world_archive(OsStr::new(name)), "{name} was recognized");
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_the_newest_backups() {
+        let dir = TempDir::new("prune");
+        let newest = create_file(&dir.0, "world-2026-10-16_03-00-00.tar.gz", 0);
+        let newer = create_file(&dir.0, "world-2026-10-15_03-00-00.tar.gz", 60);
+        let oldest = create_file(&dir.0, "world-2026-10-14_03-00-00.tar.gz", 120);
+
+        assert_eq!(prune_backups(&dir.0, 2).unwrap(), 1);
+        assert!(newest.exists());
+        assert!(newer.exists());
+        assert!(!oldest.exists());
+
+        let usage = backup_usage(&dir.0).unwrap();
+        assert_eq!(usage.count, 2);
+    }
+
+    #[test]
+    fn pruning_leaves_everything_else_alone() {
+        let dir = TempDir::new("prune-foreign");
+        let jar = create_file(&dir.0, "server.jar", 300);
+        let config = create_file(&dir.0, "config.json", 300);
+        let world = dir.0.join("world");
+        fs::create_dir(&world).unwrap();
+        create_file(&world, "level.dat", 300);
+
+        // named like a backup, but a directory, which is never pruned
+        let archive_dir = dir.0.join("world-2026-10-13_03-00-00.tar.gz");
+        fs::create_dir(&archive_dir).unwrap();
+        let backup = create_file(&dir.0, "world-2026-10-14_03-00-00.tar.gz", 0);
+
+        assert_eq!(prune_backups(&dir.0, 0).unwrap(), 1);
+        assert!(!backup.exists());
+        assert!(jar.exists());
+        assert!(config.exists());
+        assert!(world.join("level.dat").exists());
+        assert!(archive_dir.exists());
+    }
+
+    #[test]
+    fn archiving_the_world_skips_backups_kept_inside_it() {
+        let dir = TempDir::new("world-archive");
+        let world_dir = dir.0.join("world");
+        fs::create_dir_all(world_dir.join("region")).unwrap();
+        fs::write(world_dir.join("level.dat"), b"level").unwrap();
+        fs::write(world_dir.join("region/r.0.0.mca"), b"region").unwrap();
+        let backup_dir = world_dir.join("backups");
+        fs::create_dir(&backup_dir).unwrap();
+        create_file(&backup_dir, "world-2024-01-01_10-00-00.tar.gz", 60);
+
+        let archive = archive_world(&world_dir, &backup_dir, |_| {}).unwrap();
+
+        let file = fs::File::open(archive).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut paths: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                Path::new("world/level.dat"),
+                Path::new("world/region/r.0.0.mca")
+            ]
+        );
+    }
+
+    #[test]
+    fn backing_up_the_jar_keeps_a_copy_which_is_never_pruned() {
+        let dir = TempDir::new("jar-backup");
+        let jar = dir.0.join("paper.jar");
+        fs::write(&jar, b"PK\x03\x04 the old jar").unwrap();
+        let backup_dir = dir.0.join("backups");
+
+        let backup = back_up_jar(&jar, &backup_dir).unwrap();
+        assert_eq!(backup.parent(), Some(backup_dir.as_path()));
+        assert_eq!(fs::read(&backup).unwrap(), fs::read(&jar).unwrap());
+
+        assert_eq!(prune_backups(&backup_dir, 0).unwrap(), 0);
+        assert!(backup.exists());
+    }
+
+    #[test]
+    fn finds_the_newest_crash_report() {
+        let dir = TempDir::new("crash-reports");
+        assert!(last_crash_report(&dir.0).unwrap().is_none());
+
+        let reports = dir.0.join("crash-reports");
+        fs::create_dir(&reports).unwrap();
+        create_file(&reports, "crash-2024-01-01_10.00.00-server.txt", 600);
+        let newest = create_file(&reports, "crash-2024-01-02_10.00.00-server.txt", 60);
+        create_file(&reports, "notes.txt", 0);
+        fs::write(&newest, "---- Minecraft Crash Report ----").unwrap();
+
+        let report = last_crash_report(&dir.0).unwrap().unwrap();
+        assert_eq!(report.path, newest);
+        assert_eq!(report.contents, "---- Minecraft Crash Report ----");
+        assert!(!report.truncated);
+    }
+    #[tokio::test]
+    async fn editing_the_config_file_marks_it_as_changed() {
+        let (_guard, path) = lock_test_config_path().await;
+        let config = Config::default();
+        config.dump().await.unwrap();
+        assert!(!config_changed_on_disk(Some(&config)).await);
+
+        // reformatting alone isn't a change
+        let contents = fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        fs::write(&path, serde_json::to_string_pretty(&value).unwrap()).unwrap();
+        assert!(!config_changed_on_disk(Some(&config)).await);
+
+        let edited = Config {
+            world_dir: Some(PathBuf::from("edited-world")),
+            ..Config::default()
+        };
+        fs::write(&path, serde_json::to_string(&edited).unwrap()).unwrap();
+        assert!(config_changed_on_disk(Some(&config)).await);
+    }
+}